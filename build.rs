@@ -0,0 +1,4 @@
+fn main() {
+    // the migrations are embedded with `embed_migrations!`, rebuild when one is added or changed
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE export_cursors;
+
+ALTER TABLE pool_create_events DROP COLUMN id;
+ALTER TABLE swap_events DROP COLUMN id;
+ALTER TABLE initialization_events DROP COLUMN id;
+ALTER TABLE mint_events DROP COLUMN id;
+ALTER TABLE burn_events DROP COLUMN id;
+ALTER TABLE collect_events DROP COLUMN id;
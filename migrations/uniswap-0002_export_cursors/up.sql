@@ -0,0 +1,23 @@
+-- Migration versions are compared as strings, so follow-up migrations are named
+-- `uniswap-NNNN_*` to sort after the initial `uniswap_pool_events` migration.
+
+-- insertion order ids, used as cursors for exporting new events
+ALTER TABLE pool_create_events ADD COLUMN id BIGSERIAL;
+ALTER TABLE swap_events ADD COLUMN id BIGSERIAL;
+ALTER TABLE initialization_events ADD COLUMN id BIGSERIAL;
+ALTER TABLE mint_events ADD COLUMN id BIGSERIAL;
+ALTER TABLE burn_events ADD COLUMN id BIGSERIAL;
+ALTER TABLE collect_events ADD COLUMN id BIGSERIAL;
+
+CREATE UNIQUE INDEX pool_create_events_id_idx ON pool_create_events(id);
+CREATE UNIQUE INDEX swap_events_id_idx ON swap_events(id);
+CREATE UNIQUE INDEX initialization_events_id_idx ON initialization_events(id);
+CREATE UNIQUE INDEX mint_events_id_idx ON mint_events(id);
+CREATE UNIQUE INDEX burn_events_id_idx ON burn_events(id);
+CREATE UNIQUE INDEX collect_events_id_idx ON collect_events(id);
+
+-- last exported id per event table
+CREATE TABLE export_cursors (
+    event_table TEXT PRIMARY KEY,
+    last_exported_id BIGINT NOT NULL
+);
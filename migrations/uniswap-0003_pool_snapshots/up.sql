@@ -0,0 +1,9 @@
+-- pool state read from the pool contract at a block, to check event-derived state against
+CREATE TABLE pool_snapshots (
+    pool BYTEA NOT NULL,
+    block_number BIGINT NOT NULL,
+    sqrt_price_x96 NUMERIC NOT NULL,
+    tick NUMERIC NOT NULL,
+    liquidity NUMERIC NOT NULL,
+    PRIMARY KEY (pool, block_number)
+);
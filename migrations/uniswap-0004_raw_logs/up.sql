@@ -0,0 +1,10 @@
+-- undecoded logs from tracked addresses matching a user supplied topic
+CREATE TABLE raw_logs (
+    transaction_hash BYTEA NOT NULL REFERENCES transactions(transaction_hash),
+    log_index BIGINT NOT NULL,
+    contract_address BYTEA NOT NULL,
+    topics BYTEA[] NOT NULL,
+    data BYTEA NOT NULL,
+    id BIGSERIAL UNIQUE,
+    PRIMARY KEY (transaction_hash, log_index)
+);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE blocks DROP COLUMN base_fee_per_gas;
@@ -0,0 +1,2 @@
+-- EIP-1559 base fee of the block, NULL for blocks before the fork
+ALTER TABLE blocks ADD COLUMN base_fee_per_gas NUMERIC;
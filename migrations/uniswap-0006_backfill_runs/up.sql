@@ -0,0 +1,10 @@
+-- one row per blocks_from run, end_block is exclusive and times are unix seconds
+CREATE TABLE backfill_runs (
+    id BIGSERIAL PRIMARY KEY,
+    start_block BIGINT NOT NULL,
+    end_block BIGINT NOT NULL,
+    started_at BIGINT NOT NULL,
+    completed_at BIGINT,
+    events_stored BIGINT NOT NULL DEFAULT 0,
+    status TEXT NOT NULL
+);
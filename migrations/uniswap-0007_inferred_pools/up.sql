@@ -0,0 +1,5 @@
+-- pools tracked from their events alone, without a stored PoolCreated
+CREATE TABLE inferred_pools (
+    pool BYTEA PRIMARY KEY,
+    first_seen_block BIGINT NOT NULL REFERENCES blocks(block_number)
+);
@@ -0,0 +1,2 @@
+-- hash of the events stored for the block, set once all of them are committed
+ALTER TABLE blocks ADD COLUMN content_hash BYTEA;
@@ -0,0 +1,9 @@
+-- last consumed event id per consumer, pool and event table. Ids are per event table, so each
+-- table has its own cursor.
+CREATE TABLE consumer_cursors (
+    consumer_id TEXT NOT NULL,
+    pool BYTEA NOT NULL,
+    event_table TEXT NOT NULL,
+    last_consumed_id BIGINT NOT NULL,
+    PRIMARY KEY (consumer_id, pool, event_table)
+);
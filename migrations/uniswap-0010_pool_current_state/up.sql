@@ -0,0 +1,10 @@
+-- each pool's price, tick and liquidity after its latest stored swap or initialization, ordered by
+-- block and log index so backfilling older blocks doesn't overwrite newer state
+CREATE TABLE pool_current_state (
+    pool BYTEA PRIMARY KEY,
+    block_number BIGINT NOT NULL REFERENCES blocks(block_number),
+    log_index BIGINT NOT NULL,
+    sqrt_price_x96 NUMERIC NOT NULL,
+    tick NUMERIC NOT NULL,
+    liquidity NUMERIC NOT NULL
+);
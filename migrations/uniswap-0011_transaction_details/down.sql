@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions DROP COLUMN input_selector;
+ALTER TABLE transactions DROP COLUMN transaction_nonce;
+ALTER TABLE transactions DROP COLUMN transaction_value;
@@ -0,0 +1,5 @@
+-- fields only in the full transaction, stored with --rich-transactions and NULL otherwise
+ALTER TABLE transactions ADD COLUMN transaction_value NUMERIC;
+ALTER TABLE transactions ADD COLUMN transaction_nonce BIGINT;
+-- first 4 bytes of the input, or all of it if shorter
+ALTER TABLE transactions ADD COLUMN input_selector BYTEA;
@@ -0,0 +1,26 @@
+-- swaps of compacted pools joined with their transaction and block, with the price and direction
+-- computed, so analytics queries read one table. Compact mode appends the swaps after the highest
+-- swap_id stored for the pool.
+CREATE TABLE pool_swaps_wide (
+    swap_id BIGINT PRIMARY KEY,
+    pool BYTEA NOT NULL,
+    transaction_hash BYTEA NOT NULL,
+    log_index BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_timestamp BIGINT NOT NULL,
+    transaction_index BIGINT NOT NULL,
+    transaction_sender BYTEA NOT NULL,
+    sender BYTEA NOT NULL,
+    recipient BYTEA NOT NULL,
+    amount0 NUMERIC NOT NULL,
+    amount1 NUMERIC NOT NULL,
+    sqrt_price_x96 NUMERIC NOT NULL,
+    -- token1 per token0 in base units, after the swap
+    price NUMERIC NOT NULL,
+    -- token0 paid in for token1
+    zero_for_one BOOLEAN NOT NULL,
+    liquidity NUMERIC NOT NULL,
+    tick NUMERIC NOT NULL
+);
+
+CREATE INDEX pool_swaps_wide_pool_block_number ON pool_swaps_wide (pool, block_number);
@@ -0,0 +1,7 @@
+-- last block a forward blocks_from run processed on each chain, for --resume. updated_at is unix
+-- seconds.
+CREATE TABLE ingestion_checkpoints (
+    chain_id BIGINT PRIMARY KEY,
+    last_processed_block BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
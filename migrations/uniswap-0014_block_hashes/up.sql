@@ -0,0 +1,4 @@
+-- hash and parent hash of the block, for detecting reorgs. NULL for blocks stored before this
+-- migration, and the parent hash is NULL for blocks fetched without their header.
+ALTER TABLE blocks ADD COLUMN block_hash BYTEA;
+ALTER TABLE blocks ADD COLUMN parent_hash BYTEA;
@@ -0,0 +1,2 @@
+-- whether the transaction succeeded, NULL for transactions stored before this migration
+ALTER TABLE transactions ADD COLUMN status BOOLEAN;
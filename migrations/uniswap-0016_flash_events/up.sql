@@ -0,0 +1,16 @@
+-- flash loans taken from tracked pools
+CREATE TABLE flash_events (
+    transaction_hash BYTEA NOT NULL REFERENCES transactions(transaction_hash),
+    log_index BIGINT NOT NULL,
+    contract_address BYTEA NOT NULL,
+    sender BYTEA NOT NULL,
+    recipient BYTEA NOT NULL,
+    amount0 NUMERIC(78, 0) NOT NULL,
+    amount1 NUMERIC(78, 0) NOT NULL,
+    paid0 NUMERIC(78, 0) NOT NULL,
+    paid1 NUMERIC(78, 0) NOT NULL,
+    id BIGSERIAL UNIQUE,
+    PRIMARY KEY (transaction_hash, log_index)
+);
+
+CREATE INDEX flash_events_contract_address_idx ON flash_events(contract_address);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE set_fee_protocol_events;
+DROP TABLE collect_protocol_events;
@@ -0,0 +1,28 @@
+-- protocol fee withdrawals by the factory owner
+CREATE TABLE collect_protocol_events (
+    transaction_hash BYTEA NOT NULL REFERENCES transactions(transaction_hash),
+    log_index BIGINT NOT NULL,
+    contract_address BYTEA NOT NULL,
+    sender BYTEA NOT NULL,
+    recipient BYTEA NOT NULL,
+    amount0 NUMERIC(78, 0) NOT NULL,
+    amount1 NUMERIC(78, 0) NOT NULL,
+    id BIGSERIAL UNIQUE,
+    PRIMARY KEY (transaction_hash, log_index)
+);
+
+-- protocol fee changes, each fee is the denominator of the share of swap fees taken (0 is off)
+CREATE TABLE set_fee_protocol_events (
+    transaction_hash BYTEA NOT NULL REFERENCES transactions(transaction_hash),
+    log_index BIGINT NOT NULL,
+    contract_address BYTEA NOT NULL,
+    fee_protocol0_old NUMERIC(78, 0) NOT NULL,
+    fee_protocol1_old NUMERIC(78, 0) NOT NULL,
+    fee_protocol0_new NUMERIC(78, 0) NOT NULL,
+    fee_protocol1_new NUMERIC(78, 0) NOT NULL,
+    id BIGSERIAL UNIQUE,
+    PRIMARY KEY (transaction_hash, log_index)
+);
+
+CREATE INDEX collect_protocol_events_contract_address_idx ON collect_protocol_events(contract_address);
+CREATE INDEX set_fee_protocol_events_contract_address_idx ON set_fee_protocol_events(contract_address);
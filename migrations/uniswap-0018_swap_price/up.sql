@@ -0,0 +1,3 @@
+-- token1 per token0 in base units after the swap, exact, NULL for swaps stored before this
+-- migration. Scale by 10^(token0 decimals - token1 decimals) for the price in whole tokens.
+ALTER TABLE swap_events ADD COLUMN price NUMERIC;
@@ -0,0 +1,8 @@
+-- ERC-20 metadata of the tokens of created pools. A field is NULL when the token's call reverted
+-- or returned something that doesn't decode, like the bytes32 symbol of some older tokens.
+CREATE TABLE tokens (
+    address BYTEA PRIMARY KEY,
+    decimals SMALLINT,
+    symbol TEXT,
+    name TEXT
+);
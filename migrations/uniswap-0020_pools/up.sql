@@ -0,0 +1,16 @@
+-- created pools with their pair and fee tier, one row per pool
+CREATE TABLE pools (
+    pool BYTEA PRIMARY KEY,
+    token0 BYTEA NOT NULL,
+    token1 BYTEA NOT NULL,
+    fee NUMERIC(78, 0) NOT NULL,
+    tick_spacing NUMERIC(78, 0) NOT NULL,
+    created_block BIGINT NOT NULL REFERENCES blocks(block_number)
+);
+
+-- pools created before the table existed
+INSERT INTO pools (pool, token0, token1, fee, tick_spacing, created_block)
+SELECT DISTINCT ON (e.pool) e.pool, e.token0, e.token1, e.fee, e.tick_spacing, t.block_number
+FROM pool_create_events e
+JOIN transactions t ON t.transaction_hash = e.transaction_hash
+ORDER BY e.pool, t.block_number, e.log_index;
@@ -1,4 +1,14 @@
-use alloy::sol;
+use alloy::{
+    primitives::{
+        aliases::U24,
+        b256,
+        keccak256,
+        Address,
+        B256,
+        U256,
+    },
+    sol,
+};
 
 sol! {
     #[derive(Debug, PartialEq, Eq)]
@@ -98,5 +108,146 @@ sol! {
             uint128 liquidity,
             int24 tick
         );
+
+        /// @notice Emitted by the pool for any flashes of token0/token1
+        /// @param sender The address that initiated the swap call, and that received the callback
+        /// @param recipient The address that received the tokens from flash
+        /// @param amount0 The amount of token0 that was flashed
+        /// @param amount1 The amount of token1 that was flashed
+        /// @param paid0 The amount of token0 paid for the flash, which can exceed the amount0 plus the fee
+        /// @param paid1 The amount of token1 paid for the flash, which can exceed the amount1 plus the fee
+        event Flash(
+            address indexed sender,
+            address indexed recipient,
+            uint256 amount0,
+            uint256 amount1,
+            uint256 paid0,
+            uint256 paid1
+        );
+
+        /// @notice Emitted when the protocol fee is changed by the pool
+        /// @param feeProtocol0Old The previous value of the token0 protocol fee
+        /// @param feeProtocol1Old The previous value of the token1 protocol fee
+        /// @param feeProtocol0New The updated value of the token0 protocol fee
+        /// @param feeProtocol1New The updated value of the token1 protocol fee
+        event SetFeeProtocol(
+            uint8 feeProtocol0Old,
+            uint8 feeProtocol1Old,
+            uint8 feeProtocol0New,
+            uint8 feeProtocol1New
+        );
+
+        /// @notice Emitted when the collected protocol fees are withdrawn by the factory owner
+        /// @param sender The address that collects the protocol fees
+        /// @param recipient The address that receives the collected protocol fees
+        /// @param amount0 The amount of token0 protocol fees that is withdrawn
+        /// @param amount1 The amount of token1 protocol fees that is withdrawn
+        event CollectProtocol(
+            address indexed sender,
+            address indexed recipient,
+            uint128 amount0,
+            uint128 amount1
+        );
+
+        /// @notice The 0th storage slot in the pool stores many values, and is exposed as a single method to save gas
+        /// when accessed externally.
+        /// @return sqrtPriceX96 The current price of the pool as a sqrt(token1/token0) Q64.96 value
+        /// tick The current tick of the pool, i.e. according to the last tick transition that was run.
+        /// This value may not always be equal to SqrtTickMath.getTickAtSqrtRatio(sqrtPriceX96) if the price is on a tick
+        /// boundary.
+        /// observationIndex The index of the last oracle observation that was written,
+        /// observationCardinality The current maximum number of observations stored in the pool,
+        /// observationCardinalityNext The next maximum number of observations, to be updated when the observation.
+        /// feeProtocol The protocol fee for both tokens of the pool.
+        /// unlocked Whether the pool is currently locked to reentrancy
+        function slot0()
+            external
+            view
+            returns (
+                uint160 sqrtPriceX96,
+                int24 tick,
+                uint16 observationIndex,
+                uint16 observationCardinality,
+                uint16 observationCardinalityNext,
+                uint8 feeProtocol,
+                bool unlocked
+            );
+
+        /// @notice The currently in range liquidity available to the pool
+        /// @dev This value has no relationship to the total liquidity across all ticks
+        function liquidity() external view returns (uint128);
+    }
+}
+
+sol! {
+    #[derive(Debug, PartialEq, Eq)]
+    #[sol(rpc)]
+    interface IERC20 {
+        /// @notice Returns the decimals places of the token
+        function decimals() external view returns (uint8);
+
+        /// @notice Returns the symbol of the token
+        function symbol() external view returns (string);
+
+        /// @notice Returns the name of the token
+        function name() external view returns (string);
+    }
+}
+
+/// Init code hash of the canonical Uniswap V3 pool contract
+const UNISWAP_V3_POOL_INIT_CODE_HASH: B256 =
+    b256!("e34f199b19b2b4f47f68442619d555527d244f78a3297ea89325f843f87b8b54");
+
+/// Pool init code hash of the Uniswap V3 deployment on a chain, if it is a known deployment
+pub fn pool_init_code_hash(chain_id: u64) -> Option<B256> {
+    match chain_id {
+        // Ethereum, OP Mainnet, BNB Chain, Polygon, Base, Arbitrum One
+        1 | 10 | 56 | 137 | 8453 | 42161 => Some(UNISWAP_V3_POOL_INIT_CODE_HASH),
+        _ => None,
+    }
+}
+
+/// Address the factory deploys the pool of a token pair and fee tier to with CREATE2, the salt
+/// being the hash of the abi encoded `(token0, token1, fee)`
+pub fn compute_pool_address(
+    factory: Address,
+    token0: Address,
+    token1: Address,
+    fee: U24,
+    init_code_hash: B256,
+) -> Address {
+    let salt = keccak256(
+        [
+            token0.into_word().as_slice(),
+            token1.into_word().as_slice(),
+            B256::from(U256::from(fee)).as_slice(),
+        ]
+        .concat(),
+    );
+    factory.create2(salt, init_code_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::address;
+
+    use super::*;
+
+    #[test]
+    fn mainnet_init_code_hash_derives_the_usdc_weth_pool() {
+        let factory = address!("1F98431c8aD98523631AE4a59f267346ea31F984");
+        let usdc = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let weth = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+        let pool = compute_pool_address(
+            factory,
+            usdc,
+            weth,
+            U24::from(500),
+            pool_init_code_hash(1).unwrap(),
+        );
+
+        assert_eq!(pool, address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"));
+        assert_eq!(pool_init_code_hash(31_337), None);
     }
 }
@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+use alloy::providers::Provider;
+use diesel::PgConnection;
+use eyre::{
+    bail,
+    Result,
+    WrapErr,
+};
+use futures_util::StreamExt;
+
+use crate::{
+    pool_sql::database_interactions::{
+        establish_connection,
+        find_all_tracked_pools,
+        latest_applied_migration,
+        pending_migrations,
+    },
+    process_blocks::TrackingConfig,
+    rpc::{
+        fetch_chain_id,
+        http_connection,
+        websocket_connection,
+        RetryConfig,
+    },
+    utils::fmt_addr,
+};
+
+/// How long the websocket subscription gets to deliver a new block
+const NEW_BLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Check each part of the setup in turn and print whether it works, fails if any check failed.
+///
+/// Reaching the checks means the environment and flags parsed. Checks that need the database
+/// fail along with the connection.
+pub async fn run_checks(
+    http_url: Option<String>,
+    wss_url: Option<String>,
+    tracking_config: &TrackingConfig,
+    retry_config: &RetryConfig,
+) -> Result<()> {
+    let mut failed = 0;
+    let mut report = |check: &str, result: Result<String>| match result {
+        Ok(detail) => println!("[pass] {}: {}", check, detail),
+        Err(e) => {
+            failed += 1;
+            println!("[fail] {}: {:#}", check, e);
+        }
+    };
+
+    report(
+        "config",
+        Ok(format!(
+            "factory {}, {} pool deployers",
+            fmt_addr(tracking_config.uniswap_v3_factory_address),
+            tracking_config.pool_deployer_addresses.len()
+        )),
+    );
+
+    let mut conn = connect_database();
+    report(
+        "database",
+        conn.as_ref()
+            .map(|_| "connected".to_string())
+            .map_err(|e| eyre::eyre!("{:#}", e)),
+    );
+    report("migrations", with_database(&mut conn, check_migrations));
+    report(
+        "http rpc",
+        check_http_rpc(&http_url, tracking_config, retry_config).await,
+    );
+    report("websocket rpc", check_websocket_rpc(wss_url).await);
+    report(
+        "factory",
+        check_factory_code(&http_url, tracking_config, retry_config).await,
+    );
+    report(
+        "tracked pools",
+        with_database(&mut conn, check_tracked_pools),
+    );
+
+    if failed > 0 {
+        bail!("{} checks failed", failed);
+    }
+    Ok(())
+}
+
+fn connect_database() -> Result<PgConnection> {
+    dotenv::dotenv().ok();
+    std::env::var("DATABASE_URL").wrap_err("DATABASE_URL is not set")?;
+    establish_connection().wrap_err("failed to connect")
+}
+
+fn with_database(
+    conn: &mut Result<PgConnection>,
+    check: impl FnOnce(&mut PgConnection) -> Result<String>,
+) -> Result<String> {
+    match conn {
+        Ok(conn) => check(conn),
+        Err(_) => bail!("no database connection"),
+    }
+}
+
+fn check_migrations(conn: &mut PgConnection) -> Result<String> {
+    let pending = pending_migrations(conn)?;
+    if let Some(newest) = pending.last() {
+        bail!(
+            "{} migrations up to {} aren't applied yet, the other modes apply them on startup",
+            pending.len(),
+            newest
+        );
+    }
+    match latest_applied_migration(conn).wrap_err("failed to read applied migrations")? {
+        Some(applied) => Ok(format!("up to date at {}", applied)),
+        None => bail!("no migrations applied"),
+    }
+}
+
+fn check_tracked_pools(conn: &mut PgConnection) -> Result<String> {
+    let pools = find_all_tracked_pools(conn).wrap_err("failed to load tracked pools")?;
+    if pools.is_empty() {
+        bail!("no tracked pools stored yet, backfill from before the deployers' first pool");
+    }
+    Ok(format!("{} pools", pools.len()))
+}
+
+async fn check_http_rpc(
+    http_url: &Option<String>,
+    tracking_config: &TrackingConfig,
+    retry_config: &RetryConfig,
+) -> Result<String> {
+    let Some(http_url) = http_url else {
+        bail!("HTTP_URL is not set");
+    };
+    let client =
+        http_connection(http_url.clone(), tracking_config.request_id_prefix.clone()).await?;
+    let chain_id = fetch_chain_id(&client, retry_config)
+        .await
+        .wrap_err("failed to fetch the chain id")?;
+    Ok(format!("chain id {}", chain_id))
+}
+
+async fn check_websocket_rpc(wss_url: Option<String>) -> Result<String> {
+    let Some(wss_url) = wss_url else {
+        bail!("WSS_URL is not set");
+    };
+    let provider = websocket_connection(wss_url).await?;
+    let mut block_stream = provider
+        .subscribe_blocks()
+        .await
+        .wrap_err("failed to subscribe to blocks")?
+        .into_stream();
+    match tokio::time::timeout(NEW_BLOCK_TIMEOUT, block_stream.next()).await {
+        Ok(Some(block)) => Ok(format!("received block {}", block.number)),
+        Ok(None) => bail!("block subscription closed"),
+        Err(_) => bail!("no new block within {:?}", NEW_BLOCK_TIMEOUT),
+    }
+}
+
+async fn check_factory_code(
+    http_url: &Option<String>,
+    tracking_config: &TrackingConfig,
+    retry_config: &RetryConfig,
+) -> Result<String> {
+    let Some(http_url) = http_url else {
+        bail!("HTTP_URL is not set");
+    };
+    let client =
+        http_connection(http_url.clone(), tracking_config.request_id_prefix.clone()).await?;
+    let code = client
+        .call_with_failover(
+            |provider| async move {
+                Ok(provider
+                    .get_code_at(tracking_config.uniswap_v3_factory_address)
+                    .await?)
+            },
+            retry_config,
+        )
+        .await
+        .wrap_err("failed to fetch the factory's code")?;
+    if code.is_empty() {
+        bail!(
+            "no code at {}, check UNISWAP_V3_FACTORY_ADDRESS and the chain",
+            fmt_addr(tracking_config.uniswap_v3_factory_address)
+        );
+    }
+    Ok(format!("{} bytes of code", code.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use alloy::primitives::Address;
+    use serde_json::{
+        json,
+        Value,
+    };
+
+    use super::*;
+    use crate::{
+        pool_sql::database_interactions::tests::{
+            insert_pools,
+            test_connection,
+        },
+        rpc::tests::serve_json_rpc,
+    };
+
+    fn tracking_config() -> TrackingConfig {
+        TrackingConfig::new(
+            Address::repeat_byte(0x1f),
+            HashSet::from([Address::repeat_byte(0x42)]),
+        )
+    }
+
+    fn retry_config() -> RetryConfig {
+        RetryConfig::new(1, 1, 1, 1.0, false)
+    }
+
+    /// A Base node with the factory deployed
+    fn base_node(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_chainId" => json!({ "result": "0x2105" }),
+            "eth_getCode" => json!({ "result": "0x6080604052" }),
+            method => panic!("unexpected {}", method),
+        }
+    }
+
+    /// A node of a chain without the factory
+    fn node_without_factory(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_getCode" => json!({ "result": "0x" }),
+            method => panic!("unexpected {}", method),
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_checks_report_the_chain_id_and_factory_code() {
+        let http_url = Some(serve_json_rpc(base_node).await);
+
+        assert_eq!(
+            check_http_rpc(&http_url, &tracking_config(), &retry_config())
+                .await
+                .unwrap(),
+            "chain id 8453"
+        );
+        assert_eq!(
+            check_factory_code(&http_url, &tracking_config(), &retry_config())
+                .await
+                .unwrap(),
+            "5 bytes of code"
+        );
+    }
+
+    #[tokio::test]
+    async fn factory_without_code_fails_its_check() {
+        let http_url = Some(serve_json_rpc(node_without_factory).await);
+
+        let error = check_factory_code(&http_url, &tracking_config(), &retry_config())
+            .await
+            .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("check UNISWAP_V3_FACTORY_ADDRESS"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn rpc_checks_without_an_http_url_fail() {
+        for result in [
+            check_http_rpc(&None, &tracking_config(), &retry_config()).await,
+            check_factory_code(&None, &tracking_config(), &retry_config()).await,
+        ] {
+            assert_eq!(result.unwrap_err().to_string(), "HTTP_URL is not set");
+        }
+        assert_eq!(
+            check_websocket_rpc(None).await.unwrap_err().to_string(),
+            "WSS_URL is not set"
+        );
+    }
+
+    #[test]
+    fn database_checks_fail_without_a_connection() {
+        let mut conn = Err(eyre::eyre!("connection refused"));
+
+        let error = with_database(&mut conn, check_tracked_pools).unwrap_err();
+
+        assert_eq!(error.to_string(), "no database connection");
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn database_checks_pass_on_a_migrated_database_with_pools() {
+        let mut conn = test_connection();
+        insert_pools(
+            5_930,
+            &[(
+                Address::repeat_byte(0x71),
+                Address::repeat_byte(0x01),
+                Address::repeat_byte(0x02),
+                3_000,
+            )],
+            &mut conn,
+        )
+        .unwrap();
+
+        let migrations = check_migrations(&mut conn).unwrap();
+        assert!(migrations.starts_with("up to date at "), "{}", migrations);
+        let pools = check_tracked_pools(&mut conn).unwrap();
+        assert!(pools.ends_with(" pools"), "{}", pools);
+    }
+}
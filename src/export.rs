@@ -0,0 +1,670 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{
+        self,
+        Write,
+    },
+};
+
+use alloy::{
+    hex,
+    primitives::Address,
+};
+use bigdecimal::BigDecimal;
+use diesel::PgConnection;
+use eyre::{
+    Result,
+    WrapErr,
+};
+use serde_json::{
+    json,
+    Value,
+};
+use tracing::debug;
+
+use crate::{
+    pool_sql::{
+        database_interactions::{
+            get_consumer_cursor,
+            get_export_cursor,
+            set_consumer_cursor,
+            set_export_cursor,
+        },
+        types::*,
+    },
+    utils::fmt_addr,
+};
+
+/// Number of rows loaded per query while exporting
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+/// An event table that can be exported as NDJSON
+trait ExportableEvent: Sized {
+    /// Name of the table, used as the `event_type` field and as the cursor key
+    const TABLE: &'static str;
+
+    /// Events after `after_id` in `id` order, only the ones of `pool` if given
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error>;
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value;
+}
+
+/// How token amounts and liquidity are written in exported events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AmountFormat {
+    /// Decimal strings, lossless
+    #[default]
+    String,
+    /// JSON numbers, exact up to 64 bit integers and rounded to the nearest `f64` above that
+    Number,
+}
+
+impl AmountFormat {
+    fn render(self, amount: &BigDecimal) -> Value {
+        let digits = integer_string(amount);
+        match self {
+            AmountFormat::String => json!(digits),
+            AmountFormat::Number => {
+                if let Ok(value) = digits.parse::<i64>() {
+                    json!(value)
+                } else if let Ok(value) = digits.parse::<u64>() {
+                    json!(value)
+                } else {
+                    json!(digits.parse::<f64>().unwrap_or(f64::NAN))
+                }
+            }
+        }
+    }
+}
+
+/// Writes newly stored events as NDJSON, keeping track of what was already written in the
+/// `export_cursors` table.
+///
+/// Events are written per event table in `id` order. The cursor is only moved after the rows
+/// are flushed, so a crash can re-export a batch but never skips one.
+pub struct EventExporter {
+    out: Box<dyn Write + Send>,
+    conn: PgConnection,
+    amount_format: AmountFormat,
+}
+
+impl EventExporter {
+    /// Create an exporter that appends to the file at `out`, or writes to stdout if `out` is `-`
+    pub fn new(out: &str, conn: PgConnection, amount_format: AmountFormat) -> Result<Self> {
+        let out: Box<dyn Write + Send> = if out == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(out)
+                    .wrap_err_with(|| format!("failed to open export file {}", out))?,
+            )
+        };
+
+        Ok(Self {
+            out,
+            conn,
+            amount_format,
+        })
+    }
+
+    /// Export all events stored since the last export, returns the number of exported events
+    pub fn export_new_events(&mut self) -> Result<usize> {
+        let mut exported = 0;
+        exported += self.export_table::<PoolCreateEventRaw>()?;
+        exported += self.export_table::<InitializationEventRaw>()?;
+        exported += self.export_table::<SwapEventRaw>()?;
+        exported += self.export_table::<MintEventRaw>()?;
+        exported += self.export_table::<BurnEventRaw>()?;
+        exported += self.export_table::<CollectEventRaw>()?;
+        exported += self.export_table::<FlashEventRaw>()?;
+        exported += self.export_table::<CollectProtocolEventRaw>()?;
+        exported += self.export_table::<SetFeeProtocolEventRaw>()?;
+        exported += self.export_table::<RawLogRaw>()?;
+        Ok(exported)
+    }
+
+    fn export_table<E: ExportableEvent>(&mut self) -> Result<usize> {
+        let mut cursor = get_export_cursor(E::TABLE, &mut self.conn)?;
+        let mut exported = 0;
+
+        loop {
+            let events = E::find_after_id(cursor, EXPORT_BATCH_SIZE, None, &mut self.conn)?;
+            let Some(&(last_id, _)) = events.last() else {
+                break;
+            };
+
+            for (id, event) in &events {
+                let line = event_line(*id, event, self.amount_format);
+                serde_json::to_writer(&mut self.out, &line)?;
+                self.out.write_all(b"\n")?;
+            }
+            self.out.flush()?;
+
+            set_export_cursor(E::TABLE, last_id, &mut self.conn)?;
+            debug!(
+                "Exported {} {} up to id {}",
+                events.len(),
+                E::TABLE,
+                last_id
+            );
+
+            cursor = last_id;
+            exported += events.len();
+        }
+
+        Ok(exported)
+    }
+}
+
+/// An event as written out, tagged with its table and id
+fn event_line<E: ExportableEvent>(id: i64, event: &E, amount_format: AmountFormat) -> Value {
+    let mut line = event.to_json(amount_format);
+    line["event_type"] = json!(E::TABLE);
+    line["id"] = json!(id);
+    line
+}
+
+/// Events of `pool` stored after the consumer's cursors, up to `limit` per event table.
+///
+/// The events are not marked as consumed, `ack` them once they are handled. A consumer that
+/// fails before acknowledging gets the same events again, so handling has to be idempotent.
+pub fn next_events(
+    consumer_id: &str,
+    pool: Address,
+    limit: i64,
+    amount_format: AmountFormat,
+    conn: &mut PgConnection,
+) -> Result<Vec<Value>> {
+    let mut events = Vec::new();
+    next_table_events::<PoolCreateEventRaw>(
+        consumer_id,
+        pool,
+        limit,
+        amount_format,
+        conn,
+        &mut events,
+    )?;
+    next_table_events::<InitializationEventRaw>(
+        consumer_id,
+        pool,
+        limit,
+        amount_format,
+        conn,
+        &mut events,
+    )?;
+    next_table_events::<SwapEventRaw>(consumer_id, pool, limit, amount_format, conn, &mut events)?;
+    next_table_events::<MintEventRaw>(consumer_id, pool, limit, amount_format, conn, &mut events)?;
+    next_table_events::<BurnEventRaw>(consumer_id, pool, limit, amount_format, conn, &mut events)?;
+    next_table_events::<CollectEventRaw>(
+        consumer_id,
+        pool,
+        limit,
+        amount_format,
+        conn,
+        &mut events,
+    )?;
+    next_table_events::<FlashEventRaw>(consumer_id, pool, limit, amount_format, conn, &mut events)?;
+    next_table_events::<CollectProtocolEventRaw>(
+        consumer_id,
+        pool,
+        limit,
+        amount_format,
+        conn,
+        &mut events,
+    )?;
+    next_table_events::<SetFeeProtocolEventRaw>(
+        consumer_id,
+        pool,
+        limit,
+        amount_format,
+        conn,
+        &mut events,
+    )?;
+    next_table_events::<RawLogRaw>(consumer_id, pool, limit, amount_format, conn, &mut events)?;
+    Ok(events)
+}
+
+fn next_table_events<E: ExportableEvent>(
+    consumer_id: &str,
+    pool: Address,
+    limit: i64,
+    amount_format: AmountFormat,
+    conn: &mut PgConnection,
+    events: &mut Vec<Value>,
+) -> Result<()> {
+    let cursor = get_consumer_cursor(consumer_id, pool, E::TABLE, conn)?;
+    for (id, event) in E::find_after_id(cursor, limit, Some(pool), conn)? {
+        events.push(event_line(id, &event, amount_format));
+    }
+    Ok(())
+}
+
+/// Mark a consumer's events of `pool` in `event_table` up to `up_to_id` as consumed
+pub fn ack(
+    consumer_id: &str,
+    pool: Address,
+    event_table: &str,
+    up_to_id: i64,
+    conn: &mut PgConnection,
+) -> Result<()> {
+    set_consumer_cursor(consumer_id, pool, event_table, up_to_id, conn)
+        .wrap_err_with(|| format!("failed to ack {} up to id {}", event_table, up_to_id))
+}
+
+/// Write a consumer's next events of `pool` to `out` as NDJSON and ack them once flushed,
+/// returns the number of written events
+pub fn consume_events(
+    consumer_id: &str,
+    pool: Address,
+    limit: i64,
+    amount_format: AmountFormat,
+    out: &mut impl Write,
+    conn: &mut PgConnection,
+) -> Result<usize> {
+    let events = next_events(consumer_id, pool, limit, amount_format, conn)?;
+
+    let mut last_ids = BTreeMap::<String, i64>::new();
+    for event in &events {
+        serde_json::to_writer(&mut *out, event)?;
+        out.write_all(b"\n")?;
+        if let (Some(event_type), Some(id)) = (event["event_type"].as_str(), event["id"].as_i64()) {
+            let last_id = last_ids.entry(event_type.to_string()).or_default();
+            *last_id = (*last_id).max(id);
+        }
+    }
+    out.flush()?;
+
+    for (event_table, last_id) in last_ids {
+        ack(consumer_id, pool, &event_table, last_id, conn)?;
+    }
+
+    Ok(events.len())
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn address_string(bytes: &[u8]) -> String {
+    Address::try_from(bytes)
+        .map(fmt_addr)
+        .unwrap_or_else(|_| hex_string(bytes))
+}
+
+impl ExportableEvent for PoolCreateEventRaw {
+    const TABLE: &'static str = "pool_create_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, _amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "token0": address_string(&self.token0),
+            "token1": address_string(&self.token1),
+            "fee": integer_string(&self.fee),
+            "tick_spacing": integer_string(&self.tick_spacing),
+            "pool": address_string(&self.pool),
+        })
+    }
+}
+
+impl ExportableEvent for SwapEventRaw {
+    const TABLE: &'static str = "swap_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "sender": address_string(&self.sender),
+            "recipient": address_string(&self.recipient),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+            "sqrt_price_x96": integer_string(&self.sqrt_price_x96),
+            "liquidity": amount_format.render(&self.liquidity),
+            "tick": integer_string(&self.tick),
+        })
+    }
+}
+
+impl ExportableEvent for InitializationEventRaw {
+    const TABLE: &'static str = "initialization_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, _amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "creator": address_string(&self.creator),
+            "sqrt_price_x96": integer_string(&self.sqrt_price_x96),
+            "tick": integer_string(&self.tick),
+        })
+    }
+}
+
+impl ExportableEvent for MintEventRaw {
+    const TABLE: &'static str = "mint_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "sender": address_string(&self.sender),
+            "owner": address_string(&self.owner),
+            "tick_lower": integer_string(&self.tick_lower),
+            "tick_upper": integer_string(&self.tick_upper),
+            "amount": amount_format.render(&self.amount),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+        })
+    }
+}
+
+impl ExportableEvent for BurnEventRaw {
+    const TABLE: &'static str = "burn_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "owner": address_string(&self.owner),
+            "tick_lower": integer_string(&self.tick_lower),
+            "tick_upper": integer_string(&self.tick_upper),
+            "amount": amount_format.render(&self.amount),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+        })
+    }
+}
+
+impl ExportableEvent for CollectEventRaw {
+    const TABLE: &'static str = "collect_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "owner": address_string(&self.owner),
+            "recipient": address_string(&self.recipient),
+            "tick_lower": integer_string(&self.tick_lower),
+            "tick_upper": integer_string(&self.tick_upper),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+        })
+    }
+}
+
+impl ExportableEvent for FlashEventRaw {
+    const TABLE: &'static str = "flash_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "sender": address_string(&self.sender),
+            "recipient": address_string(&self.recipient),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+            "paid0": amount_format.render(&self.paid0),
+            "paid1": amount_format.render(&self.paid1),
+        })
+    }
+}
+
+impl ExportableEvent for CollectProtocolEventRaw {
+    const TABLE: &'static str = "collect_protocol_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "sender": address_string(&self.sender),
+            "recipient": address_string(&self.recipient),
+            "amount0": amount_format.render(&self.amount0),
+            "amount1": amount_format.render(&self.amount1),
+        })
+    }
+}
+
+impl ExportableEvent for SetFeeProtocolEventRaw {
+    const TABLE: &'static str = "set_fee_protocol_events";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, _amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "fee_protocol0_old": integer_string(&self.fee_protocol0_old),
+            "fee_protocol1_old": integer_string(&self.fee_protocol1_old),
+            "fee_protocol0_new": integer_string(&self.fee_protocol0_new),
+            "fee_protocol1_new": integer_string(&self.fee_protocol1_new),
+        })
+    }
+}
+
+impl ExportableEvent for RawLogRaw {
+    const TABLE: &'static str = "raw_logs";
+
+    fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, diesel::result::Error> {
+        Self::find_after_id(after_id, limit, pool, conn)
+    }
+
+    fn to_json(&self, _amount_format: AmountFormat) -> Value {
+        json!({
+            "transaction_hash": hex_string(&self.transaction_hash),
+            "log_index": self.log_index,
+            "contract_address": address_string(&self.contract_address),
+            "topics": self.topics.iter().map(|topic| hex_string(topic)).collect::<Vec<_>>(),
+            "data": hex_string(&self.data),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use alloy::primitives::{
+        aliases::{
+            I24,
+            U128,
+            U160,
+        },
+        I256,
+        U256,
+    };
+
+    use super::*;
+    use crate::pool_sql::database_interactions::tests::{
+        block,
+        insert_swaps,
+        swap,
+        test_connection,
+        transaction,
+        tx_hash,
+    };
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn only_events_after_the_cursor_are_exported() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x77);
+        insert_swaps(
+            block(5_985),
+            vec![transaction(5_985, 0), transaction(5_985, 1)],
+            vec![
+                swap(5_985, 0, pool, 10, -20, U160::from(1) << 96),
+                swap(5_985, 1, pool, -5, 10, U160::from(1) << 96),
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        let ids: Vec<i64> = SwapEventRaw::find_after_id(0, 10, Some(pool), &mut conn)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        // the first swap was exported before
+        set_export_cursor(SwapEventRaw::TABLE, ids[0], &mut conn).unwrap();
+        let out = std::env::temp_dir().join(format!(
+            "token_tracking_export_{}.ndjson",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&out);
+        let mut exporter =
+            EventExporter::new(out.to_str().unwrap(), conn, AmountFormat::String).unwrap();
+
+        exporter.export_new_events().unwrap();
+        // nothing is stored since, so nothing more is exported
+        assert_eq!(exporter.export_new_events().unwrap(), 0);
+
+        let lines: Vec<Value> = fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|line: &Value| line["contract_address"] == json!(fmt_addr(pool)))
+            .collect();
+        fs::remove_file(&out).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["id"], json!(ids[1]));
+        assert_eq!(lines[0]["event_type"], json!("swap_events"));
+        assert_eq!(
+            lines[0]["transaction_hash"],
+            json!(tx_hash(5_985, 1).to_string())
+        );
+        assert_eq!(
+            get_export_cursor(SwapEventRaw::TABLE, &mut exporter.conn).unwrap(),
+            ids[1]
+        );
+    }
+
+    #[test]
+    fn amounts_are_written_as_strings_or_numbers() {
+        let large = I256::try_from(10).unwrap().pow(U256::from(30));
+        let swap = SwapEventRaw::try_from(SwapEvent {
+            transaction_hash: tx_hash(1, 0),
+            log_index: 0,
+            contract_address: Address::repeat_byte(0x77),
+            sender: Address::repeat_byte(0x42),
+            recipient: Address::repeat_byte(0x42),
+            amount0: PoolDelta(-large),
+            amount1: PoolDelta(I256::try_from(123).unwrap()),
+            sqrt_price_x96: U160::from(1) << 96,
+            liquidity: U128::from(u64::MAX) + U128::from(1),
+            tick: I24::ZERO,
+        })
+        .unwrap();
+
+        let line = swap.to_json(AmountFormat::String);
+        assert_eq!(line["amount0"], json!("-1000000000000000000000000000000"));
+        assert_eq!(line["amount1"], json!("123"));
+        assert_eq!(line["liquidity"], json!("18446744073709551616"));
+
+        // exact while the amount fits 64 bits, rounded above that
+        let line = swap.to_json(AmountFormat::Number);
+        assert_eq!(line["amount0"], json!(-1e30));
+        assert_eq!(line["amount1"], json!(123));
+        assert_eq!(line["liquidity"], json!(18446744073709551616.0));
+    }
+}
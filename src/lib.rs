@@ -0,0 +1,741 @@
+//! Tracks Uniswap V3 pools created by a set of deployers and stores their events in Postgres.
+//!
+//! [`process_single_block`], [`process_block_range`], [`process_live`] and [`process_live_poll`]
+//! run the same processing as the `single-block`, `blocks-from`, `live-track` and `live-poll`
+//! modes of the binary, configured by a [`Config`]. The modules are public for the binary's other
+//! modes, but their API is less settled than the items re-exported here.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use alloy::{
+    network::{
+        AnyNetwork,
+        Ethereum,
+    },
+    primitives::Address,
+};
+use eyre::{
+    bail,
+    eyre,
+    Result,
+    WrapErr,
+};
+use serde::{
+    de,
+    Deserialize,
+    Deserializer,
+};
+use tracing::info;
+
+pub mod abi;
+pub mod doctor;
+pub mod export;
+pub mod ohlc;
+pub mod pool_sql;
+pub mod pricing;
+pub mod process_blocks;
+pub mod pushgateway;
+pub mod rpc;
+pub mod telemetry;
+pub mod timestamps;
+pub mod tokens;
+pub mod utils;
+
+pub use export::EventExporter;
+pub use pool_sql::{
+    database_interactions::{
+        DbPools,
+        TimestampedEvent,
+    },
+    types::{
+        Block,
+        BurnEvent,
+        CollectEvent,
+        CollectProtocolEvent,
+        FlashEvent,
+        InitializationEvent,
+        MintEvent,
+        PoolCreateEvent,
+        Pool,
+        PoolDelta,
+        PoolSnapshot,
+        PoolState,
+        RawLog,
+        SetFeeProtocolEvent,
+        SwapEvent,
+        Transaction,
+    },
+};
+pub use process_blocks::{
+    AnyEvent,
+    BackfillDirection,
+    BackfillReporting,
+    DecodeErrorPolicy,
+    EventHook,
+    TrackingConfig,
+};
+pub use rpc::{
+    BatchConfig,
+    CircuitBreakerConfig,
+    NetworkKind,
+    ReceiptTransport,
+    RetryConfig,
+};
+
+/// Connections and settings shared by the processing entry points
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// HTTP RPC endpoints, comma-separated, only optional for live tracking with receipts fetched
+    /// over the websocket
+    pub http_url: Option<String>,
+    /// Websocket RPC endpoint live tracking subscribes to new heads over, not needed for polling
+    pub wss_url: Option<String>,
+    /// Network types fetched blocks and receipts are deserialized as
+    pub network: NetworkKind,
+    pub tracking: TrackingConfig,
+    pub retry: RetryConfig,
+    pub batch: BatchConfig,
+    /// Transport live tracking fetches blocks and receipts over
+    pub receipt_transport: ReceiptTransport,
+    /// Number of recent heads live tracking remembers to skip repeated notifications
+    pub dedupe_window: usize,
+    /// How often polling live tracking asks for the latest block number
+    pub poll_interval: Duration,
+    /// Maximum number of connections of each database pool
+    pub pool_max_size: u32,
+}
+
+/// Connections per database pool when `POOL_MAX_SIZE` isn't set
+pub const DEFAULT_POOL_MAX_SIZE: u32 = 10;
+
+/// Milliseconds between polls for new blocks when `POLL_INTERVAL_MS` isn't set
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 2_000;
+
+/// How long an RPC endpoint with an open circuit breaker is skipped when
+/// `CIRCUIT_BREAKER_COOLDOWN_MS` isn't set
+pub const DEFAULT_CIRCUIT_BREAKER_COOLDOWN_MS: u64 = 30_000;
+
+/// Settings read from a `--config` TOML file. Keys are the lowercase names of the environment
+/// variables they stand for, e.g. `wss_url` for `WSS_URL`, and all of them are optional.
+///
+/// Precedence, from highest to lowest: command line flags, environment variables, this file.
+/// A variable that is set and not empty replaces the file's value, and `main` applies its flags
+/// on top of the loaded `Config`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub http_url: Option<String>,
+    pub wss_url: Option<String>,
+    #[serde(deserialize_with = "deserialize_addr")]
+    pub uniswap_v3_factory_address: Option<Address>,
+    #[serde(deserialize_with = "deserialize_addrs")]
+    pub pool_deployer_contract_addresses: Option<HashSet<Address>>,
+    pub retry_max_attempts: Option<u32>,
+    pub retry_initial_backoff_ms: Option<u64>,
+    pub retry_max_backoff_ms: Option<u64>,
+    pub retry_backoff_multiplier: Option<f64>,
+    pub retry_jitter: Option<bool>,
+    pub circuit_breaker_failures: Option<u32>,
+    pub circuit_breaker_cooldown_ms: Option<u64>,
+    pub block_from_rpc_delay: Option<u64>,
+    pub pool_max_size: Option<u32>,
+    pub confirmation_blocks: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("failed to parse config file {}", path.display()))
+    }
+}
+
+/// Addresses in the config file go through the same checksum check as the environment's
+fn deserialize_addr<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Address>, D::Error> {
+    let address = String::deserialize(deserializer)?;
+    utils::parse_addr(&address)
+        .map(Some)
+        .map_err(|e| de::Error::custom(format!("{:#}", e)))
+}
+
+fn deserialize_addrs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<HashSet<Address>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|address| utils::parse_addr(address))
+        .collect::<Result<_>>()
+        .map(Some)
+        .map_err(|e| de::Error::custom(format!("{:#}", e)))
+}
+
+impl Config {
+    /// Read the settings set by environment variables, leaving the others at the command line's
+    /// defaults.
+    ///
+    /// Every missing or malformed variable is listed in the returned error, not just the first.
+    pub fn from_env() -> Result<Self> {
+        Self::load(None)
+    }
+
+    /// Like `from_env`, with the values of the config file at `path` used for variables that
+    /// aren't set
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let file = match path {
+            Some(path) => ConfigFile::read(path)?,
+            None => ConfigFile::default(),
+        };
+        let mut env = EnvReader {
+            problems: Vec::new(),
+            has_file: path.is_some(),
+        };
+
+        // empty URLs in the file are unset, like empty variables
+        let wss_url = env.optional(
+            "WSS_URL",
+            file.wss_url.filter(|url| !url.is_empty()),
+            |value| Ok(value.to_string()),
+        );
+        let http_url = env.optional(
+            "HTTP_URL",
+            file.http_url.filter(|url| !url.is_empty()),
+            |value| Ok(value.to_string()),
+        );
+        let uniswap_v3_factory_address = env.required(
+            "UNISWAP_V3_FACTORY_ADDRESS",
+            file.uniswap_v3_factory_address,
+            utils::parse_addr,
+        );
+        let pool_deployer_addresses = env.required(
+            "POOL_DEPLOYER_CONTRACT_ADDRESSES",
+            file.pool_deployer_contract_addresses,
+            |value| value.split(',').map(utils::parse_addr).collect(),
+        );
+        let mut retry = RetryConfig::new(
+            env.required("RETRY_MAX_ATTEMPTS", file.retry_max_attempts, parse_value),
+            env.required(
+                "RETRY_INITIAL_BACKOFF_MS",
+                file.retry_initial_backoff_ms,
+                parse_value,
+            ),
+            env.required(
+                "RETRY_MAX_BACKOFF_MS",
+                file.retry_max_backoff_ms,
+                parse_value,
+            ),
+            env.required(
+                "RETRY_BACKOFF_MULTIPLIER",
+                file.retry_backoff_multiplier,
+                parse_value,
+            ),
+            env.optional("RETRY_JITTER", file.retry_jitter, parse_value)
+                .unwrap_or(true),
+        );
+        // unset or 0 leaves the circuit breaker off
+        let breaker_failures = env
+            .optional(
+                "CIRCUIT_BREAKER_FAILURES",
+                file.circuit_breaker_failures,
+                parse_value,
+            )
+            .unwrap_or(0);
+        let breaker_cooldown_ms = env
+            .optional(
+                "CIRCUIT_BREAKER_COOLDOWN_MS",
+                file.circuit_breaker_cooldown_ms,
+                parse_value,
+            )
+            .unwrap_or(DEFAULT_CIRCUIT_BREAKER_COOLDOWN_MS);
+        retry.circuit_breaker = (breaker_failures > 0).then(|| CircuitBreakerConfig {
+            failure_threshold: breaker_failures,
+            cooldown: Duration::from_millis(breaker_cooldown_ms),
+        });
+        let delay_ms = env.required(
+            "BLOCK_FROM_RPC_DELAY",
+            file.block_from_rpc_delay,
+            parse_value,
+        );
+        let pool_max_size = env
+            .optional("POOL_MAX_SIZE", file.pool_max_size, parse_value)
+            .unwrap_or(DEFAULT_POOL_MAX_SIZE);
+        let confirmations = env
+            .optional("CONFIRMATION_BLOCKS", file.confirmation_blocks, parse_value)
+            .unwrap_or(0);
+        let poll_interval_ms = env
+            .optional("POLL_INTERVAL_MS", file.poll_interval_ms, parse_value)
+            .unwrap_or(DEFAULT_POLL_INTERVAL_MS);
+        if poll_interval_ms == 0 {
+            env.problems
+                .push("POLL_INTERVAL_MS must be at least 1".to_string());
+        }
+
+        if !env.problems.is_empty() {
+            bail!("invalid configuration:\n  {}", env.problems.join("\n  "));
+        }
+
+        let mut tracking = TrackingConfig::new(uniswap_v3_factory_address, pool_deployer_addresses);
+        tracking.confirmations = confirmations;
+        Ok(Self {
+            http_url,
+            wss_url,
+            network: NetworkKind::default(),
+            tracking,
+            retry,
+            batch: BatchConfig {
+                blocks_per_batch: 1,
+                max_request_bytes: 1_000_000,
+                delay: Duration::from_millis(delay_ms),
+            },
+            receipt_transport: ReceiptTransport::default(),
+            dedupe_window: 128,
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            pool_max_size,
+        })
+    }
+
+    /// `HTTP_URL`, an error naming it if it isn't set
+    pub fn http_url(&self) -> Result<String> {
+        self.http_url
+            .clone()
+            .ok_or_else(|| eyre!("HTTP_URL is required"))
+    }
+
+    /// `WSS_URL`, an error naming it if it isn't set
+    pub fn wss_url(&self) -> Result<String> {
+        self.wss_url
+            .clone()
+            .ok_or_else(|| eyre!("WSS_URL is required"))
+    }
+}
+
+/// Reads environment variables for `Config::load`, falling back to the config file's values and
+/// collecting what is wrong with them
+struct EnvReader {
+    problems: Vec<String>,
+    has_file: bool,
+}
+
+impl EnvReader {
+    /// Parse `name` if it is set and not empty, otherwise take the file's value
+    fn optional<T>(
+        &mut self,
+        name: &str,
+        file_value: Option<T>,
+        parse: impl FnOnce(&str) -> Result<T>,
+    ) -> Option<T> {
+        let Some(value) = std::env::var(name).ok().filter(|value| !value.is_empty()) else {
+            return file_value;
+        };
+        match parse(&value) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.problems.push(format!("{} is invalid: {:#}", name, e));
+                None
+            }
+        }
+    }
+
+    /// Like `optional`, recording a problem if neither the variable nor the file sets it. The
+    /// default stands in for values with a problem, which are never returned to the caller of
+    /// `Config::load`.
+    fn required<T: Default>(
+        &mut self,
+        name: &str,
+        file_value: Option<T>,
+        parse: impl FnOnce(&str) -> Result<T>,
+    ) -> T {
+        let is_set = std::env::var(name).is_ok_and(|value| !value.is_empty());
+        if !is_set && file_value.is_none() {
+            if self.has_file {
+                self.problems.push(format!(
+                    "{} is required (or `{}` in the config file)",
+                    name,
+                    name.to_lowercase()
+                ));
+            } else {
+                self.problems.push(format!("{} is required", name));
+            }
+            return T::default();
+        }
+        self.optional(name, file_value, parse).unwrap_or_default()
+    }
+}
+
+fn parse_value<T>(value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.trim().parse().map_err(|e| eyre!("{}", e))
+}
+
+/// Load the pools already tracked in the database, only those with `only_token` if it is set
+fn tracked_pools(config: &Config, db_pools: &DbPools) -> Result<HashSet<Address>> {
+    let conn = &mut *db_pools.main.get()?;
+    match config.tracking.only_token {
+        Some(token) => {
+            let pools =
+                pool_sql::database_interactions::find_tracked_pools_with_token(token, conn)?;
+            info!(
+                "Tracking {} stored pools with token {}",
+                pools.len(),
+                utils::fmt_addr(token)
+            );
+            Ok(pools.into_iter().collect())
+        }
+        None => Ok(
+            pool_sql::database_interactions::find_all_tracked_pools(conn)?
+                .into_iter()
+                .collect(),
+        ),
+    }
+}
+
+/// Fetch, decode and store the events of one block
+pub async fn process_single_block(config: &Config, block_number: u64) -> Result<()> {
+    let http_url = config.http_url()?;
+    let db_pools = DbPools::new(config.pool_max_size)?;
+    let mut pools = tracked_pools(config, &db_pools)?;
+    match config.network {
+        NetworkKind::Any => {
+            process_blocks::single_block::<AnyNetwork>(
+                http_url,
+                block_number,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                &db_pools,
+            )
+            .await
+        }
+        NetworkKind::Ethereum => {
+            process_blocks::single_block::<Ethereum>(
+                http_url,
+                block_number,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                &db_pools,
+            )
+            .await
+        }
+    }
+}
+
+/// Fetch, decode and store the events of the blocks from `start_block` through `end_block`
+pub async fn process_block_range(
+    config: &Config,
+    start_block: u64,
+    end_block: u64,
+    direction: BackfillDirection,
+    reporting: BackfillReporting,
+) -> Result<()> {
+    let http_url = config.http_url()?;
+    let db_pools = DbPools::new(config.pool_max_size)?;
+    let mut pools = tracked_pools(config, &db_pools)?;
+    match config.network {
+        NetworkKind::Any => {
+            process_blocks::blocks_from::<AnyNetwork>(
+                http_url,
+                start_block,
+                end_block,
+                &config.tracking,
+                &mut pools,
+                direction,
+                config.retry.clone(),
+                config.batch.clone(),
+                reporting,
+                &db_pools,
+            )
+            .await
+        }
+        NetworkKind::Ethereum => {
+            process_blocks::blocks_from::<Ethereum>(
+                http_url,
+                start_block,
+                end_block,
+                &config.tracking,
+                &mut pools,
+                direction,
+                config.retry.clone(),
+                config.batch.clone(),
+                reporting,
+                &db_pools,
+            )
+            .await
+        }
+    }
+}
+
+/// Follow the chain head and store the events of each new block, also writing them to `exporter`
+/// if given. Only returns on an error, on Ctrl-C or once `max_runtime` has passed.
+pub async fn process_live(config: &Config, exporter: Option<EventExporter>) -> Result<()> {
+    let db_pools = DbPools::new(config.pool_max_size)?;
+    let mut pools = tracked_pools(config, &db_pools)?;
+    match config.network {
+        NetworkKind::Any => {
+            process_blocks::live_blocks::<AnyNetwork>(
+                config.http_url.clone(),
+                config.wss_url()?,
+                config.receipt_transport,
+                config.dedupe_window,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                exporter,
+                &db_pools,
+            )
+            .await
+        }
+        NetworkKind::Ethereum => {
+            process_blocks::live_blocks::<Ethereum>(
+                config.http_url.clone(),
+                config.wss_url()?,
+                config.receipt_transport,
+                config.dedupe_window,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                exporter,
+                &db_pools,
+            )
+            .await
+        }
+    }
+}
+
+/// Like `process_live`, finding new blocks by polling `HTTP_URL` every `poll_interval` instead of
+/// subscribing over the websocket
+pub async fn process_live_poll(config: &Config, exporter: Option<EventExporter>) -> Result<()> {
+    let db_pools = DbPools::new(config.pool_max_size)?;
+    let mut pools = tracked_pools(config, &db_pools)?;
+    match config.network {
+        NetworkKind::Any => {
+            process_blocks::poll_blocks::<AnyNetwork>(
+                config.http_url()?,
+                config.poll_interval,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                exporter,
+                &db_pools,
+            )
+            .await
+        }
+        NetworkKind::Ethereum => {
+            process_blocks::poll_blocks::<Ethereum>(
+                config.http_url()?,
+                config.poll_interval,
+                &config.tracking,
+                &mut pools,
+                config.retry.clone(),
+                exporter,
+                &db_pools,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        PoisonError,
+    };
+
+    use super::*;
+
+    /// Every variable `Config::load` reads
+    const CONFIG_VARS: [&str; 15] = [
+        "HTTP_URL",
+        "WSS_URL",
+        "UNISWAP_V3_FACTORY_ADDRESS",
+        "POOL_DEPLOYER_CONTRACT_ADDRESSES",
+        "RETRY_MAX_ATTEMPTS",
+        "RETRY_INITIAL_BACKOFF_MS",
+        "RETRY_MAX_BACKOFF_MS",
+        "RETRY_BACKOFF_MULTIPLIER",
+        "RETRY_JITTER",
+        "CIRCUIT_BREAKER_FAILURES",
+        "CIRCUIT_BREAKER_COOLDOWN_MS",
+        "BLOCK_FROM_RPC_DELAY",
+        "POOL_MAX_SIZE",
+        "CONFIRMATION_BLOCKS",
+        "POLL_INTERVAL_MS",
+    ];
+
+    const FACTORY: &str = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+    const DEPLOYER: &str = "0x0000000000000000000000000000000000000042";
+
+    /// The environment is shared by the whole process, so tests setting it take turns
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Run `f` with only `vars` of the config variables set
+    fn with_env<R>(vars: &[(&str, &str)], f: impl FnOnce() -> R) -> R {
+        let _guard = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        for name in CONFIG_VARS {
+            std::env::remove_var(name);
+        }
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let result = f();
+        for (name, _) in vars {
+            std::env::remove_var(name);
+        }
+        result
+    }
+
+    /// Write `contents` to a config file unique to the calling test
+    fn config_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "token_tracking_{}_{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const SAMPLE_FILE: &str = r#"
+http_url = "http://localhost:8545,http://localhost:8546"
+wss_url = "ws://localhost:8546"
+uniswap_v3_factory_address = "0x1F98431c8aD98523631AE4a59f267346ea31F984"
+pool_deployer_contract_addresses = [
+    "0x0000000000000000000000000000000000000042",
+    "0x0000000000000000000000000000000000000043",
+]
+retry_max_attempts = 5
+retry_initial_backoff_ms = 200
+retry_max_backoff_ms = 3000
+retry_backoff_multiplier = 1.5
+retry_jitter = false
+circuit_breaker_failures = 4
+circuit_breaker_cooldown_ms = 60000
+block_from_rpc_delay = 25
+pool_max_size = 4
+confirmation_blocks = 2
+poll_interval_ms = 500
+"#;
+
+    #[test]
+    fn partial_environment_lists_every_missing_key() {
+        let error = with_env(
+            &[
+                ("UNISWAP_V3_FACTORY_ADDRESS", FACTORY),
+                ("RETRY_MAX_ATTEMPTS", "three"),
+            ],
+            Config::from_env,
+        )
+        .unwrap_err()
+        .to_string();
+
+        for missing in [
+            "POOL_DEPLOYER_CONTRACT_ADDRESSES",
+            "RETRY_INITIAL_BACKOFF_MS",
+            "RETRY_MAX_BACKOFF_MS",
+            "RETRY_BACKOFF_MULTIPLIER",
+            "BLOCK_FROM_RPC_DELAY",
+        ] {
+            assert!(
+                error.contains(&format!("{} is required", missing)),
+                "{} is not reported missing in: {}",
+                missing,
+                error
+            );
+        }
+        assert!(error.contains("RETRY_MAX_ATTEMPTS is invalid"), "{}", error);
+        assert!(!error.contains("UNISWAP_V3_FACTORY_ADDRESS"), "{}", error);
+    }
+
+    #[test]
+    fn config_file_sets_each_field() {
+        let path = config_file("round_trip", SAMPLE_FILE);
+        let config = with_env(&[], || Config::load(Some(&path))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            config.http_url.as_deref(),
+            Some("http://localhost:8545,http://localhost:8546")
+        );
+        assert_eq!(config.wss_url.as_deref(), Some("ws://localhost:8546"));
+        assert_eq!(
+            config.tracking.uniswap_v3_factory_address,
+            utils::parse_addr(FACTORY).unwrap()
+        );
+        assert_eq!(
+            config.tracking.pool_deployer_addresses,
+            HashSet::from([
+                Address::with_last_byte(0x42),
+                Address::with_last_byte(0x43)
+            ])
+        );
+        assert_eq!(config.retry.max_attempts, 5);
+        assert_eq!(config.retry.initial_backoff, Duration::from_millis(200));
+        assert_eq!(config.retry.max_backoff, Duration::from_millis(3_000));
+        assert_eq!(config.retry.backoff_multiplier, 1.5);
+        assert!(!config.retry.jitter);
+        let breaker = config.retry.circuit_breaker.unwrap();
+        assert_eq!(breaker.failure_threshold, 4);
+        assert_eq!(breaker.cooldown, Duration::from_secs(60));
+        assert_eq!(config.batch.delay, Duration::from_millis(25));
+        assert_eq!(config.pool_max_size, 4);
+        assert_eq!(config.tracking.confirmations, 2);
+        assert_eq!(config.poll_interval, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let path = config_file("override", SAMPLE_FILE);
+        let config = with_env(
+            &[
+                ("RETRY_MAX_ATTEMPTS", "9"),
+                // 0 turns the file's circuit breaker off
+                ("CIRCUIT_BREAKER_FAILURES", "0"),
+                ("POOL_DEPLOYER_CONTRACT_ADDRESSES", DEPLOYER),
+                // empty variables are unset
+                ("HTTP_URL", ""),
+            ],
+            || Config::load(Some(&path)),
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.retry.max_attempts, 9);
+        assert!(config.retry.circuit_breaker.is_none());
+        assert_eq!(
+            config.tracking.pool_deployer_addresses,
+            HashSet::from([Address::with_last_byte(0x42)])
+        );
+        assert_eq!(
+            config.http_url.as_deref(),
+            Some("http://localhost:8545,http://localhost:8546")
+        );
+        assert_eq!(config.retry.initial_backoff, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn unknown_config_file_keys_are_rejected() {
+        let path = config_file("unknown_key", "retry_attempts = 3\n");
+        let error = with_env(&[], || Config::load(Some(&path))).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            format!("{:#}", error).contains("unknown field `retry_attempts`"),
+            "{:#}",
+            error
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! OHLC candles of a pool's price, from its stored swaps.
+//!
+//! Swaps are bucketed by their block's timestamp. Prices are token1 per token0 in base units,
+//! the same as the `price` column of `swap_events`, see [`crate::pricing`] for whole tokens.
+
+use alloy::primitives::{
+    Address,
+    U256,
+};
+use bigdecimal::{
+    num_bigint::{
+        BigInt,
+        Sign,
+    },
+    BigDecimal,
+    Zero,
+};
+use diesel::PgConnection;
+use eyre::{
+    bail,
+    Result,
+};
+
+use crate::{
+    pool_sql::{
+        database_interactions::{
+            last_swap_before,
+            query_swaps_in_time_range,
+            TimestampedEvent,
+        },
+        types::SwapEvent,
+    },
+    pricing::base_unit_price,
+};
+
+/// Price a candle closes at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PriceSource {
+    /// Price after the bucket's last swap
+    #[default]
+    LastSwap,
+    /// Average price of the bucket's swaps weighted by their token0 volume
+    Vwap,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candle {
+    /// Timestamp the candle's bucket starts at, buckets end where the next one starts
+    pub start_ts: u64,
+    pub open: BigDecimal,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub close: BigDecimal,
+    /// Sum of the absolute token0 amounts of the bucket's swaps, in base units
+    pub volume0: U256,
+    /// Sum of the absolute token1 amounts of the bucket's swaps, in base units
+    pub volume1: U256,
+}
+
+/// Candles of `pool` every `interval_secs` from timestamp `from` up to, but not including, `to`.
+///
+/// A bucket without swaps gets a flat candle at the previous close with no volume. Buckets before
+/// the pool's first swap are left out, as there is no price to carry forward.
+pub fn candles(
+    pool: Address,
+    interval_secs: u64,
+    from: u64,
+    to: u64,
+    price_source: PriceSource,
+    conn: &mut PgConnection,
+) -> Result<Vec<Candle>> {
+    if interval_secs == 0 {
+        bail!("Candle interval must be at least one second");
+    }
+    if from >= to {
+        bail!(
+            "End timestamp {} must be greater than start timestamp {}",
+            to,
+            from
+        );
+    }
+
+    let previous_close =
+        last_swap_before(pool, from, conn)?.map(|swap| base_unit_price(swap.event.sqrt_price_x96));
+    let swaps = query_swaps_in_time_range(pool, from, to, conn)?;
+
+    Ok(bucket_swaps(
+        &swaps,
+        previous_close,
+        interval_secs,
+        from,
+        to,
+        price_source,
+    ))
+}
+
+/// Candles of `swaps`, which are in chain order and within `from..to`
+fn bucket_swaps(
+    swaps: &[TimestampedEvent<SwapEvent>],
+    mut previous_close: Option<BigDecimal>,
+    interval_secs: u64,
+    from: u64,
+    to: u64,
+    price_source: PriceSource,
+) -> Vec<Candle> {
+    let mut candles = Vec::new();
+    let mut swaps = swaps.iter().peekable();
+    for start_ts in (from..to).step_by(interval_secs as usize) {
+        let end_ts = start_ts.saturating_add(interval_secs);
+        let mut bucket = Vec::new();
+        while let Some(swap) = swaps.next_if(|swap| swap.block_timestamp < end_ts) {
+            bucket.push(&swap.event);
+        }
+
+        let candle = match bucket_candle(start_ts, &bucket, price_source) {
+            Some(candle) => candle,
+            None => match &previous_close {
+                Some(close) => Candle {
+                    start_ts,
+                    open: close.clone(),
+                    high: close.clone(),
+                    low: close.clone(),
+                    close: close.clone(),
+                    volume0: U256::ZERO,
+                    volume1: U256::ZERO,
+                },
+                None => continue,
+            },
+        };
+        previous_close = Some(candle.close.clone());
+        candles.push(candle);
+    }
+    candles
+}
+
+/// Candle of a bucket's swaps, `None` if it has none
+fn bucket_candle(start_ts: u64, swaps: &[&SwapEvent], price_source: PriceSource) -> Option<Candle> {
+    let (first, rest) = swaps.split_first()?;
+    let open = base_unit_price(first.sqrt_price_x96);
+    let mut candle = Candle {
+        start_ts,
+        high: open.clone(),
+        low: open.clone(),
+        close: open.clone(),
+        open,
+        volume0: first.amount0.abs_amount(),
+        volume1: first.amount1.abs_amount(),
+    };
+    for swap in rest {
+        let price = base_unit_price(swap.sqrt_price_x96);
+        if price > candle.high {
+            candle.high = price.clone();
+        }
+        if price < candle.low {
+            candle.low = price.clone();
+        }
+        candle.close = price;
+        candle.volume0 = candle.volume0.saturating_add(swap.amount0.abs_amount());
+        candle.volume1 = candle.volume1.saturating_add(swap.amount1.abs_amount());
+    }
+
+    if price_source == PriceSource::Vwap {
+        if let Some(vwap) = volume_weighted_price(swaps) {
+            candle.close = vwap;
+        }
+    }
+    Some(candle)
+}
+
+/// Average price of `swaps` weighted by their token0 volume, `None` if they moved no token0
+fn volume_weighted_price(swaps: &[&SwapEvent]) -> Option<BigDecimal> {
+    let mut weighted_sum = BigDecimal::zero();
+    let mut volume_sum = BigDecimal::zero();
+    for swap in swaps {
+        let volume = BigDecimal::from(BigInt::from_bytes_be(
+            Sign::Plus,
+            &swap.amount0.abs_amount().to_be_bytes::<32>(),
+        ));
+        weighted_sum += base_unit_price(swap.sqrt_price_x96) * &volume;
+        volume_sum += volume;
+    }
+    if volume_sum.is_zero() {
+        return None;
+    }
+    Some(weighted_sum / volume_sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{
+        aliases::{
+            I24,
+            U128,
+            U160,
+        },
+        TxHash,
+        I256,
+    };
+
+    use super::*;
+    use crate::pool_sql::types::PoolDelta;
+
+    /// Swap at `timestamp` moving `amount0` of token0 that leaves the price at `sqrt_price^2`
+    fn swap(timestamp: u64, amount0: i64, sqrt_price: u64) -> TimestampedEvent<SwapEvent> {
+        TimestampedEvent {
+            block_number: timestamp,
+            block_timestamp: timestamp,
+            event: SwapEvent {
+                transaction_hash: TxHash::ZERO,
+                log_index: 0,
+                contract_address: Address::ZERO,
+                sender: Address::ZERO,
+                recipient: Address::ZERO,
+                amount0: PoolDelta(I256::try_from(amount0).unwrap()),
+                amount1: PoolDelta(I256::try_from(-amount0).unwrap()),
+                sqrt_price_x96: U160::from(sqrt_price) << 96,
+                liquidity: U128::from(1),
+                tick: I24::ZERO,
+            },
+        }
+    }
+
+    fn flat(start_ts: u64, price: u64) -> Candle {
+        let price = BigDecimal::from(price);
+        Candle {
+            start_ts,
+            open: price.clone(),
+            high: price.clone(),
+            low: price.clone(),
+            close: price,
+            volume0: U256::ZERO,
+            volume1: U256::ZERO,
+        }
+    }
+
+    #[test]
+    fn swaps_are_bucketed_by_timestamp() {
+        let swaps = [swap(100, 10, 2), swap(105, -30, 3), swap(109, 5, 1)];
+        let candles = bucket_swaps(&swaps, None, 10, 100, 120, PriceSource::LastSwap);
+
+        assert_eq!(
+            candles,
+            [
+                Candle {
+                    start_ts: 100,
+                    open: BigDecimal::from(4),
+                    high: BigDecimal::from(9),
+                    low: BigDecimal::from(1),
+                    close: BigDecimal::from(1),
+                    volume0: U256::from(45),
+                    volume1: U256::from(45),
+                },
+                // no swaps, flat at the previous close
+                flat(110, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn buckets_before_the_first_swap_are_left_out() {
+        let swaps = [swap(125, 10, 2)];
+
+        let candles = bucket_swaps(&swaps, None, 10, 100, 130, PriceSource::LastSwap);
+        assert_eq!(
+            candles
+                .iter()
+                .map(|candle| candle.start_ts)
+                .collect::<Vec<_>>(),
+            [120]
+        );
+
+        // a swap before the range carries its price into the first buckets
+        let candles = bucket_swaps(
+            &swaps,
+            Some(BigDecimal::from(9)),
+            10,
+            100,
+            130,
+            PriceSource::LastSwap,
+        );
+        assert_eq!(candles[..2], [flat(100, 9), flat(110, 9)]);
+        assert_eq!(candles[2].open, BigDecimal::from(4));
+    }
+
+    #[test]
+    fn vwap_closes_at_the_volume_weighted_price() {
+        // 1 token0 at price 4 and 3 token0 at price 9
+        let swaps = [swap(100, 1, 2), swap(101, -3, 3)];
+        let candles = bucket_swaps(&swaps, None, 10, 100, 110, PriceSource::Vwap);
+
+        assert_eq!(candles[0].close, BigDecimal::from(31) / BigDecimal::from(4));
+        assert_eq!(candles[0].open, BigDecimal::from(4));
+    }
+
+    #[test]
+    fn vwap_of_swaps_without_token0_volume_is_none() {
+        let swap = swap(100, 0, 2);
+        assert_eq!(volume_weighted_price(&[&swap.event]), None);
+    }
+}
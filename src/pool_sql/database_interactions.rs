@@ -1,16 +1,68 @@
-use alloy::primitives::Address;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use alloy::primitives::{
+    aliases::{
+        U160,
+        U24,
+    },
+    Address,
+    TxHash,
+    B256,
+    U256,
+};
+use bigdecimal::BigDecimal;
 use diesel::{
+    connection::{
+        AnsiTransactionManager,
+        TransactionManager,
+    },
     pg::PgConnection,
     prelude::*,
-    result::Error,
+    r2d2::{
+        ConnectionManager,
+        Pool,
+    },
+    result::{
+        DatabaseErrorKind,
+        Error,
+    },
+    sql_types::BigInt,
+    ConnectionError,
+};
+use diesel_migrations::{
+    embed_migrations,
+    EmbeddedMigrations,
+    MigrationHarness,
+};
+use eyre::{
+    eyre,
+    Result,
+    WrapErr,
 };
-use eyre::Result;
 use tracing::{
     debug,
     info,
+    warn,
 };
 
-use crate::pool_sql::types::*;
+use crate::{
+    pool_sql::types::*,
+    pricing::{
+        base_unit_price,
+        swap_protocol_fee,
+    },
+    utils::{
+        fmt_addr,
+        fmt_addrs,
+    },
+};
 
 impl BlockRaw {
     pub fn find_by_number(number: i64, conn: &mut PgConnection) -> Result<Option<Self>, Error> {
@@ -21,6 +73,72 @@ impl BlockRaw {
             .optional()
     }
 
+    /// Hash of the block, if it is stored with one
+    pub fn find_block_hash(number: i64, conn: &mut PgConnection) -> Result<Option<B256>, Error> {
+        let stored_hash = Self::find_by_number(number, conn)?.and_then(|block| block.block_hash);
+        Ok(stored_hash.map(|hash| B256::from_slice(&hash)))
+    }
+
+    /// Timestamp of the block, if it is stored with one
+    pub fn find_timestamp(number: i64, conn: &mut PgConnection) -> Result<Option<u64>, Error> {
+        let stored_timestamp = Self::find_by_number(number, conn)?
+            .map(|block| block.block_timestamp as u64)
+            .filter(|&timestamp| timestamp != MISSING_BLOCK_TIMESTAMP);
+        Ok(stored_timestamp)
+    }
+
+    /// Hash of the events stored for the block, if it is stored and they were all committed
+    pub fn find_content_hash(number: i64, conn: &mut PgConnection) -> Result<Option<B256>, Error> {
+        let stored_hash = Self::find_by_number(number, conn)?.and_then(|block| block.content_hash);
+        Ok(stored_hash.map(|hash| B256::from_slice(&hash)))
+    }
+
+    /// Numbers of up to `limit` blocks after `after_number` stored without their timestamp, in
+    /// ascending order
+    pub fn find_missing_timestamp(
+        after_number: i64,
+        limit: i64,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<i64>, Error> {
+        use crate::pool_sql::schema::blocks::dsl::*;
+
+        blocks
+            .filter(block_timestamp.eq(MISSING_BLOCK_TIMESTAMP as i64))
+            .filter(block_number.gt(after_number))
+            .order(block_number.asc())
+            .limit(limit)
+            .select(block_number)
+            .load(conn)
+    }
+
+    pub fn set_header_fields(
+        number: i64,
+        timestamp: i64,
+        base_fee: Option<u64>,
+        conn: &mut PgConnection,
+    ) -> Result<(), Error> {
+        use crate::pool_sql::schema::blocks::dsl::*;
+
+        diesel::update(blocks.filter(block_number.eq(number)))
+            .set((
+                block_timestamp.eq(timestamp),
+                base_fee_per_gas.eq(base_fee.map(BigDecimal::from)),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    pub fn set_content_hash(number: i64, hash: B256, conn: &mut PgConnection) -> Result<(), Error> {
+        use crate::pool_sql::schema::blocks::dsl::*;
+
+        diesel::update(blocks.filter(block_number.eq(number)))
+            .set(content_hash.eq(hash.to_vec()))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
     pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<Self, Error> {
         use crate::pool_sql::schema::blocks::dsl::*;
 
@@ -35,224 +153,699 @@ impl BlockRaw {
 }
 
 impl TransactionRaw {
-    pub fn find_by_hash(hash: &[u8], conn: &mut PgConnection) -> Result<Option<Self>, Error> {
-        use crate::pool_sql::schema::transactions::dsl::*;
-
-        transactions
-            .filter(transaction_hash.eq(hash))
-            .first(conn)
-            .optional()
-    }
-
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<Self, Error> {
+    /// Insert the transactions that aren't already stored
+    pub fn insert_many(
+        transactions_to_insert: Vec<Self>,
+        conn: &mut PgConnection,
+    ) -> Result<(), Error> {
         use crate::pool_sql::schema::transactions::dsl::*;
 
-        // Check if transaction already exists
-        if let Some(existing_tx) = Self::find_by_hash(&self.transaction_hash, conn)? {
-            return Ok(existing_tx);
+        for chunk in insert_chunks(transactions_to_insert) {
+            diesel::insert_into(transactions)
+                .values(chunk)
+                .on_conflict(transaction_hash)
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        // Insert if it doesn't exist
-        diesel::insert_into(transactions)
-            .values(&self)
-            .execute(conn)?;
-
-        Ok(self)
+        Ok(())
     }
 }
 
 impl PoolCreateEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find the creation event of a pool
+    pub fn find_by_pool(
+        pool_address: &[u8],
         conn: &mut PgConnection,
     ) -> Result<Option<Self>, Error> {
         use crate::pool_sql::schema::pool_create_events::dsl::*;
 
         pool_create_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
+            .filter(pool.eq(pool_address))
+            .select(Self::as_select())
             .first(conn)
             .optional()
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::pool_create_events::dsl::*;
 
-        // Check if pool create event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut query = pool_create_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(pool.eq(pool_address.to_vec()));
         }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
+    }
 
-        // Insert if it doesn't exist
-        diesel::insert_into(pool_create_events)
-            .values(self)
-            .execute(conn)?;
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::pool_sql::schema::pool_create_events::dsl::*;
 
-        Ok(())
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(pool_create_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
+        }
+
+        Ok(inserted)
     }
 }
 
 impl SwapEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
         conn: &mut PgConnection,
-    ) -> Result<Option<Self>, Error> {
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::swap_events::dsl::*;
 
-        swap_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
-            .first(conn)
-            .optional()
+        let mut query = swap_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
         use crate::pool_sql::schema::swap_events::dsl::*;
 
-        // Check if swap event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(swap_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        // Insert if it doesn't exist
-        diesel::insert_into(swap_events)
-            .values(self)
-            .execute(conn)?;
-
-        Ok(())
+        Ok(inserted)
     }
 }
 
 impl InitializationEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
         conn: &mut PgConnection,
-    ) -> Result<Option<Self>, Error> {
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::initialization_events::dsl::*;
 
-        initialization_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
-            .first(conn)
-            .optional()
+        let mut query = initialization_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
         use crate::pool_sql::schema::initialization_events::dsl::*;
 
-        // Check if initialization event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(initialization_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        diesel::insert_into(initialization_events)
-            .values(self)
-            .execute(conn)?;
-
-        Ok(())
+        Ok(inserted)
     }
 }
 
 impl MintEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
         conn: &mut PgConnection,
-    ) -> Result<Option<Self>, Error> {
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::mint_events::dsl::*;
 
-        mint_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
-            .first(conn)
-            .optional()
+        let mut query = mint_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
         use crate::pool_sql::schema::mint_events::dsl::*;
 
-        // Check if mint event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(mint_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        diesel::insert_into(mint_events)
-            .values(self)
-            .execute(conn)?;
-
-        Ok(())
+        Ok(inserted)
     }
 }
 
 impl BurnEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
         conn: &mut PgConnection,
-    ) -> Result<Option<Self>, Error> {
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::burn_events::dsl::*;
 
-        burn_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
-            .first(conn)
-            .optional()
+        let mut query = burn_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
         use crate::pool_sql::schema::burn_events::dsl::*;
 
-        // Check if burn event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(burn_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        diesel::insert_into(burn_events)
-            .values(self)
-            .execute(conn)?;
-
-        Ok(())
+        Ok(inserted)
     }
 }
 
 impl CollectEventRaw {
-    pub fn find_by_tx_and_log(
-        tx_hash: &[u8],
-        log_idx: i64,
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
         conn: &mut PgConnection,
-    ) -> Result<Option<Self>, Error> {
+    ) -> Result<Vec<(i64, Self)>, Error> {
         use crate::pool_sql::schema::collect_events::dsl::*;
 
-        collect_events
-            .filter(transaction_hash.eq(tx_hash))
-            .filter(log_index.eq(log_idx))
-            .first(conn)
-            .optional()
+        let mut query = collect_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
     }
 
-    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<(), Error> {
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
         use crate::pool_sql::schema::collect_events::dsl::*;
 
-        // Check if collect event already exists
-        if let Some(_) = Self::find_by_tx_and_log(&self.transaction_hash, self.log_index, conn)? {
-            return Ok(());
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(collect_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+impl FlashEventRaw {
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, Error> {
+        use crate::pool_sql::schema::flash_events::dsl::*;
+
+        let mut query = flash_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
+    }
+
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::pool_sql::schema::flash_events::dsl::*;
+
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(flash_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+impl CollectProtocolEventRaw {
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, Error> {
+        use crate::pool_sql::schema::collect_protocol_events::dsl::*;
+
+        let mut query = collect_protocol_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
+    }
+
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::pool_sql::schema::collect_protocol_events::dsl::*;
+
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(collect_protocol_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+impl SetFeeProtocolEventRaw {
+    /// Find up to `limit` events inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, Error> {
+        use crate::pool_sql::schema::set_fee_protocol_events::dsl::*;
+
+        let mut query = set_fee_protocol_events.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
+    }
+
+    /// Insert the events that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(events: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::pool_sql::schema::set_fee_protocol_events::dsl::*;
+
+        let mut inserted = 0;
+        for chunk in insert_chunks(events) {
+            inserted += diesel::insert_into(set_fee_protocol_events)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+impl RawLogRaw {
+    /// Find up to `limit` logs inserted after `after_id`, in insertion order, only the ones of
+    /// `pool_address` if given
+    pub fn find_after_id(
+        after_id: i64,
+        limit: i64,
+        pool_address: Option<Address>,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<(i64, Self)>, Error> {
+        use crate::pool_sql::schema::raw_logs::dsl::*;
+
+        let mut query = raw_logs.filter(id.gt(after_id)).into_boxed();
+        if let Some(pool_address) = pool_address {
+            query = query.filter(contract_address.eq(pool_address.to_vec()));
+        }
+        query
+            .order(id.asc())
+            .limit(limit)
+            .select((id, Self::as_select()))
+            .load(conn)
+    }
+
+    /// Insert the logs that aren't already stored, returns how many rows were inserted
+    pub fn insert_many(logs: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::pool_sql::schema::raw_logs::dsl::*;
+
+        let mut inserted = 0;
+        for chunk in insert_chunks(logs) {
+            inserted += diesel::insert_into(raw_logs)
+                .values(chunk)
+                .on_conflict((transaction_hash, log_index))
+                .do_nothing()
+                .execute(conn)?;
         }
 
-        diesel::insert_into(collect_events)
+        Ok(inserted)
+    }
+}
+
+impl PoolSnapshotRaw {
+    /// Insert the snapshot unless one is already stored for the pool and block
+    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::pool_sql::schema::pool_snapshots::dsl::*;
+
+        let inserted = diesel::insert_into(pool_snapshots)
             .values(self)
+            .on_conflict((pool, block_number))
+            .do_nothing()
             .execute(conn)?;
 
-        Ok(())
+        Ok(inserted > 0)
     }
 }
 
-pub(crate) fn find_all_tracked_pools(conn: &mut PgConnection) -> Result<Vec<Address>, Error> {
-    use crate::pool_sql::schema::pool_create_events::dsl::*;
+impl InferredPoolRaw {
+    /// Insert the pool unless it is already stored, returns whether a row was inserted
+    pub fn insert_if_not_exists(self, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::pool_sql::schema::inferred_pools::dsl::*;
+
+        let inserted = diesel::insert_into(inferred_pools)
+            .values(self)
+            .on_conflict(pool)
+            .do_nothing()
+            .execute(conn)?;
+
+        Ok(inserted > 0)
+    }
+}
+
+impl PoolRaw {
+    /// Insert the pools, replacing the rows of ones already stored. Returns how many rows were
+    /// written.
+    pub fn upsert_many(rows: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use diesel::upsert::excluded;
+
+        use crate::pool_sql::schema::pools::dsl::*;
+
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        diesel::insert_into(pools)
+            .values(rows)
+            .on_conflict(pool)
+            .do_update()
+            .set((
+                token0.eq(excluded(token0)),
+                token1.eq(excluded(token1)),
+                fee.eq(excluded(fee)),
+                tick_spacing.eq(excluded(tick_spacing)),
+                created_block.eq(excluded(created_block)),
+            ))
+            .execute(conn)
+    }
+}
+
+impl TokenRaw {
+    /// All stored tokens
+    pub fn find_all(conn: &mut PgConnection) -> Result<Vec<Self>, Error> {
+        use crate::pool_sql::schema::tokens::dsl::*;
+
+        tokens.select(Self::as_select()).load(conn)
+    }
+
+    /// Insert the tokens, replacing the metadata of ones already stored. Returns how many rows
+    /// were written.
+    pub fn upsert_many(rows: Vec<Self>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use diesel::upsert::excluded;
+
+        use crate::pool_sql::schema::tokens::dsl::*;
+
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        diesel::insert_into(tokens)
+            .values(rows)
+            .on_conflict(address)
+            .do_update()
+            .set((
+                decimals.eq(excluded(decimals)),
+                symbol.eq(excluded(symbol)),
+                name.eq(excluded(name)),
+            ))
+            .execute(conn)
+    }
+}
 
-    let pool_addresses_raw: Vec<Vec<u8>> = pool_create_events.select(pool).distinct().load(conn)?;
+impl PoolCurrentStateRaw {
+    /// Each pool's state after its last swap or initialization in a block's events
+    pub fn latest_in_block(
+        block_number: i64,
+        swaps: &[SwapEventRaw],
+        initialize_events: &[InitializationEventRaw],
+    ) -> Vec<Self> {
+        let mut latest = HashMap::<Vec<u8>, Self>::new();
+        let states = swaps
+            .iter()
+            .map(|swap| Self {
+                pool: swap.contract_address.clone(),
+                block_number,
+                log_index: swap.log_index,
+                sqrt_price_x96: swap.sqrt_price_x96.clone(),
+                tick: swap.tick.clone(),
+                liquidity: swap.liquidity.clone(),
+            })
+            .chain(initialize_events.iter().map(|initialize| Self {
+                pool: initialize.contract_address.clone(),
+                block_number,
+                log_index: initialize.log_index,
+                sqrt_price_x96: initialize.sqrt_price_x96.clone(),
+                tick: initialize.tick.clone(),
+                // a pool has no liquidity until its first mint, which comes after initialization
+                liquidity: BigDecimal::from(0),
+            }));
+        for state in states {
+            match latest.get(&state.pool) {
+                Some(stored) if stored.log_index > state.log_index => {}
+                _ => {
+                    latest.insert(state.pool.clone(), state);
+                }
+            }
+        }
+        latest.into_values().collect()
+    }
+
+    /// Store the state unless the stored one is from a later event, returns whether it was
+    /// stored
+    pub fn upsert_if_newer(self, conn: &mut PgConnection) -> Result<bool, Error> {
+        use diesel::upsert::excluded;
+
+        use crate::pool_sql::schema::pool_current_state::dsl::*;
+
+        let stored: Option<(i64, i64)> = pool_current_state
+            .filter(pool.eq(&self.pool))
+            .select((block_number, log_index))
+            .first(conn)
+            .optional()?;
+        if stored.is_some_and(|stored| stored >= (self.block_number, self.log_index)) {
+            return Ok(false);
+        }
+
+        let upserted = diesel::insert_into(pool_current_state)
+            .values(self)
+            .on_conflict(pool)
+            .do_update()
+            .set((
+                block_number.eq(excluded(block_number)),
+                log_index.eq(excluded(log_index)),
+                sqrt_price_x96.eq(excluded(sqrt_price_x96)),
+                tick.eq(excluded(tick)),
+                liquidity.eq(excluded(liquidity)),
+            ))
+            .execute(conn)?;
+
+        Ok(upserted > 0)
+    }
+
+    /// The pool's state after its latest stored swap or initialization, `None` if neither is
+    /// stored
+    pub fn latest_stored(pool: &[u8], conn: &mut PgConnection) -> Result<Option<Self>, Error> {
+        use crate::pool_sql::schema::{
+            initialization_events,
+            swap_events,
+            transactions,
+        };
+
+        let swap: Option<(i64, i64, BigDecimal, BigDecimal, BigDecimal)> = swap_events::table
+            .inner_join(transactions::table)
+            .filter(swap_events::contract_address.eq(pool))
+            .order((transactions::block_number.desc(), swap_events::log_index.desc()))
+            .select((
+                transactions::block_number,
+                swap_events::log_index,
+                swap_events::sqrt_price_x96,
+                swap_events::tick,
+                swap_events::liquidity,
+            ))
+            .first(conn)
+            .optional()?;
+        let initialize: Option<(i64, i64, BigDecimal, BigDecimal)> = initialization_events::table
+            .inner_join(transactions::table)
+            .filter(initialization_events::contract_address.eq(pool))
+            .order((
+                transactions::block_number.desc(),
+                initialization_events::log_index.desc(),
+            ))
+            .select((
+                transactions::block_number,
+                initialization_events::log_index,
+                initialization_events::sqrt_price_x96,
+                initialization_events::tick,
+            ))
+            .first(conn)
+            .optional()?;
+
+        let swap = swap.map(|(block_number, log_index, sqrt_price_x96, tick, liquidity)| Self {
+            pool: pool.to_vec(),
+            block_number,
+            log_index,
+            sqrt_price_x96,
+            tick,
+            liquidity,
+        });
+        let initialize = initialize.map(|(block_number, log_index, sqrt_price_x96, tick)| Self {
+            pool: pool.to_vec(),
+            block_number,
+            log_index,
+            sqrt_price_x96,
+            tick,
+            liquidity: BigDecimal::from(0),
+        });
+        Ok(match (swap, initialize) {
+            (Some(swap), Some(initialize))
+                if (initialize.block_number, initialize.log_index)
+                    > (swap.block_number, swap.log_index) =>
+            {
+                Some(initialize)
+            }
+            (Some(swap), _) => Some(swap),
+            (None, initialize) => initialize,
+        })
+    }
+}
+
+/// Current price, tick and liquidity of `pool`, `None` if none of its swaps or initialization were
+/// stored. Without a `pool_current_state` row, e.g. for pools stored without `--track-pool-state`,
+/// the state comes from the pool's latest stored swap or initialization.
+pub fn get_pool_state(pool: Address, conn: &mut PgConnection) -> Result<Option<PoolState>> {
+    use crate::pool_sql::schema::pool_current_state;
+
+    let state = pool_current_state::table
+        .filter(pool_current_state::pool.eq(pool.to_vec()))
+        .select(PoolCurrentStateRaw::as_select())
+        .first(conn)
+        .optional()
+        .wrap_err("failed to query the pool state")?;
+    let state = match state {
+        Some(state) => Some(state),
+        None => PoolCurrentStateRaw::latest_stored(pool.as_slice(), conn)
+            .wrap_err("failed to query the pool's latest swap or initialization")?,
+    };
+
+    state
+        .map(PoolState::try_from)
+        .transpose()
+        .map_err(|e| eyre!("failed to convert pool state: {}", e))
+}
+
+/// Find all created pools with their pair and fee tier, ordered by creation block. Pools inferred
+/// from their events are not included, their pair and fee tier aren't known.
+pub fn find_all_pools_with_metadata(conn: &mut PgConnection) -> Result<Vec<PoolRaw>, Error> {
+    use crate::pool_sql::schema::pools::dsl::*;
+
+    pools
+        .order((created_block.asc(), pool.asc()))
+        .select(PoolRaw::as_select())
+        .load(conn)
+}
+
+/// Find all tracked pools, both created by a tracked deployer and inferred from their events
+pub fn find_all_tracked_pools(conn: &mut PgConnection) -> Result<Vec<Address>, Error> {
+    use crate::pool_sql::schema::{
+        inferred_pools,
+        pool_create_events,
+    };
+
+    let pool_addresses_raw: Vec<Vec<u8>> = pool_create_events::table
+        .select(pool_create_events::pool)
+        .union(inferred_pools::table.select(inferred_pools::pool))
+        .load(conn)?;
 
     let pool_addresses: Vec<Address> = pool_addresses_raw
         .into_iter()
@@ -260,67 +853,3275 @@ pub(crate) fn find_all_tracked_pools(conn: &mut PgConnection) -> Result<Vec<Addr
         .collect();
 
     info!("Found {} tracked pools", pool_addresses.len());
-    debug!("Pool addresses: {:?}", pool_addresses);
+    debug!("Pool addresses: {}", fmt_addrs(&pool_addresses));
 
     Ok(pool_addresses)
 }
 
-// Function to insert a transaction and multiple swap events
-pub(crate) fn insert_block_events(
-    block: BlockRaw,
-    transactions: Vec<TransactionRaw>,
-    pool_create_events: Vec<PoolCreateEventRaw>,
-    swaps: Vec<SwapEventRaw>,
-    initialize_events: Vec<InitializationEventRaw>,
-    mint_events: Vec<MintEventRaw>,
-    burn_events: Vec<BurnEventRaw>,
-    collect_events: Vec<CollectEventRaw>,
+/// Record the start of a `blocks_from` run, returns the run's id
+pub fn start_backfill_run(start: u64, end: u64, conn: &mut PgConnection) -> Result<i64, Error> {
+    use crate::pool_sql::schema::backfill_runs::dsl::*;
+
+    diesel::insert_into(backfill_runs)
+        .values((
+            start_block.eq(start as i64),
+            end_block.eq(end as i64),
+            started_at.eq(unix_now()),
+            status.eq("running"),
+        ))
+        .returning(id)
+        .get_result(conn)
+}
+
+/// Narrow a `blocks_from` run's range to the blocks it got through, for runs stopped early
+pub fn set_backfill_run_range(
+    run_id: i64,
+    start: u64,
+    end: u64,
     conn: &mut PgConnection,
-) -> Result<()> {
-    conn.transaction(|conn| {
-        block.insert_if_not_exists(conn)?;
+) -> Result<(), Error> {
+    use crate::pool_sql::schema::backfill_runs::dsl::*;
 
-        // First ensure the transactions exist
-        for transaction in transactions {
-            transaction.insert_if_not_exists(conn)?;
-        }
+    diesel::update(backfill_runs.filter(id.eq(run_id)))
+        .set((start_block.eq(start as i64), end_block.eq(end as i64)))
+        .execute(conn)?;
 
-        // Then insert all pool create events
-        for pool_create in pool_create_events {
-            pool_create.insert_if_not_exists(conn)?;
-        }
+    Ok(())
+}
 
-        // Then insert all swap events
-        for swap in swaps {
-            swap.insert_if_not_exists(conn)?;
-        }
+/// Record how a `blocks_from` run ended
+pub fn record_backfill_run(
+    run_id: i64,
+    completed: bool,
+    stored: u64,
+    conn: &mut PgConnection,
+) -> Result<(), Error> {
+    use crate::pool_sql::schema::backfill_runs::dsl::*;
 
-        // Then insert all initialize events
-        for initialize in initialize_events {
-            initialize.insert_if_not_exists(conn)?;
-        }
+    diesel::update(backfill_runs.filter(id.eq(run_id)))
+        .set((
+            completed_at.eq(unix_now()),
+            events_stored.eq(stored as i64),
+            status.eq(if completed { "completed" } else { "failed" }),
+        ))
+        .execute(conn)?;
 
-        // Then insert all mint events
-        for mint in mint_events {
-            mint.insert_if_not_exists(conn)?;
-        }
+    Ok(())
+}
 
-        // Then insert all burn events
-        for burn in burn_events {
-            burn.insert_if_not_exists(conn)?;
-        }
+/// Last block a forward `blocks_from` run processed on the chain
+pub fn get_last_processed_block(chain: u64, conn: &mut PgConnection) -> Result<Option<u64>, Error> {
+    use crate::pool_sql::schema::ingestion_checkpoints::dsl::*;
+
+    let block: Option<i64> = ingestion_checkpoints
+        .filter(chain_id.eq(chain as i64))
+        .select(last_processed_block)
+        .first(conn)
+        .optional()?;
+
+    Ok(block.map(|block| block as u64))
+}
+
+/// Record the last block a forward `blocks_from` run processed on the chain
+pub fn set_last_processed_block(
+    chain: u64,
+    block: u64,
+    conn: &mut PgConnection,
+) -> Result<(), Error> {
+    use crate::pool_sql::schema::ingestion_checkpoints::dsl::*;
+
+    diesel::insert_into(ingestion_checkpoints)
+        .values((
+            chain_id.eq(chain as i64),
+            last_processed_block.eq(block as i64),
+            updated_at.eq(unix_now()),
+        ))
+        .on_conflict(chain_id)
+        .do_update()
+        .set((
+            last_processed_block.eq(block as i64),
+            updated_at.eq(unix_now()),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs() as i64)
+}
+
+/// Block ranges covered by completed `blocks_from` runs, all ranges are `[start, end)`
+#[derive(Debug)]
+pub struct CoverageReport {
+    /// Merged ranges of completed runs, in block order
+    pub covered: Vec<(i64, i64)>,
+    /// Ranges between the covered ranges that no completed run covers
+    pub gaps: Vec<(i64, i64)>,
+    /// Runs that failed or are still running
+    pub unfinished: Vec<BackfillRun>,
+}
+
+/// Report which block ranges have been backfilled by completed runs
+pub fn coverage_report(conn: &mut PgConnection) -> Result<CoverageReport, Error> {
+    use crate::pool_sql::schema::backfill_runs::dsl::*;
 
-        // Then insert all collect events
-        for collect in collect_events {
-            collect.insert_if_not_exists(conn)?;
+    let runs: Vec<BackfillRun> = backfill_runs
+        .order(start_block.asc())
+        .select(BackfillRun::as_select())
+        .load(conn)?;
+
+    let mut covered: Vec<(i64, i64)> = Vec::new();
+    let mut unfinished = Vec::new();
+    for run in runs {
+        if run.status != "completed" {
+            unfinished.push(run);
+            continue;
+        }
+        // stopped by the deadline before its first block
+        if run.start_block >= run.end_block {
+            continue;
         }
+        match covered.last_mut() {
+            Some((_, covered_end)) if run.start_block <= *covered_end => {
+                *covered_end = (*covered_end).max(run.end_block);
+            }
+            _ => covered.push((run.start_block, run.end_block)),
+        }
+    }
 
-        Ok(())
+    let gaps = covered
+        .windows(2)
+        .map(|ranges| (ranges[0].1, ranges[1].0))
+        .collect();
+
+    Ok(CoverageReport {
+        covered,
+        gaps,
+        unfinished,
     })
 }
 
-pub(crate) fn establish_connection() -> Result<PgConnection> {
-    dotenv::dotenv().ok();
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set for tests");
-    Ok(PgConnection::establish(&database_url)?)
+/// Find the tracked pools that have `token` as token0 or token1
+pub fn find_tracked_pools_with_token(
+    token: Address,
+    conn: &mut PgConnection,
+) -> Result<Vec<Address>, Error> {
+    use crate::pool_sql::schema::pool_create_events::dsl::*;
+
+    let pool_addresses_raw: Vec<Vec<u8>> = pool_create_events
+        .filter(token0.eq(token.to_vec()).or(token1.eq(token.to_vec())))
+        .select(pool)
+        .distinct()
+        .load(conn)?;
+
+    Ok(pool_addresses_raw
+        .into_iter()
+        .map(|address| Address::from_slice(&address))
+        .collect())
+}
+
+/// Find tracked pools with swap, mint or burn events but no stored `Initialize` event.
+///
+/// A pool is initialized once before any other activity, so these pools were picked up after
+/// their initialization and their stored history is incomplete.
+pub fn pools_missing_initialization(conn: &mut PgConnection) -> Result<Vec<Address>, Error> {
+    use diesel::dsl::{
+        exists,
+        not,
+    };
+
+    use crate::pool_sql::schema::{
+        burn_events,
+        initialization_events,
+        mint_events,
+        pool_create_events,
+        swap_events,
+    };
+
+    let pool_addresses_raw: Vec<Vec<u8>> = pool_create_events::table
+        .filter(
+            exists(
+                swap_events::table
+                    .filter(swap_events::contract_address.eq(pool_create_events::pool)),
+            )
+            .or(exists(mint_events::table.filter(
+                mint_events::contract_address.eq(pool_create_events::pool),
+            )))
+            .or(exists(burn_events::table.filter(
+                burn_events::contract_address.eq(pool_create_events::pool),
+            ))),
+        )
+        .filter(not(exists(initialization_events::table.filter(
+            initialization_events::contract_address.eq(pool_create_events::pool),
+        ))))
+        .select(pool_create_events::pool)
+        .distinct()
+        .load(conn)?;
+
+    Ok(pool_addresses_raw
+        .into_iter()
+        .map(|address| Address::from_slice(&address))
+        .collect())
+}
+
+/// Find the tokens of tracked pools without a `tokens` row, or whose decimals or symbol couldn't
+/// be read, sorted by address.
+///
+/// These tokens' prices can't be put in whole tokens until their metadata is filled in by hand.
+pub fn tokens_missing_metadata(conn: &mut PgConnection) -> Result<Vec<Address>, Error> {
+    use diesel::dsl::{
+        exists,
+        not,
+    };
+
+    use crate::pool_sql::schema::{
+        pool_create_events,
+        tokens,
+    };
+
+    let token0s: Vec<Vec<u8>> = pool_create_events::table
+        .filter(not(exists(
+            tokens::table
+                .filter(tokens::address.eq(pool_create_events::token0))
+                .filter(tokens::decimals.is_not_null())
+                .filter(tokens::symbol.is_not_null()),
+        )))
+        .select(pool_create_events::token0)
+        .distinct()
+        .load(conn)?;
+    let token1s: Vec<Vec<u8>> = pool_create_events::table
+        .filter(not(exists(
+            tokens::table
+                .filter(tokens::address.eq(pool_create_events::token1))
+                .filter(tokens::decimals.is_not_null())
+                .filter(tokens::symbol.is_not_null()),
+        )))
+        .select(pool_create_events::token1)
+        .distinct()
+        .load(conn)?;
+
+    let mut missing: Vec<Address> = token0s
+        .into_iter()
+        .chain(token1s)
+        .map(|address| Address::from_slice(&address))
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    Ok(missing)
+}
+
+/// Average price of a pool's swaps in blocks `start_block..end_block`, weighted by the pool's
+/// liquidity at each swap, or `None` if there were no swaps with liquidity.
+///
+/// Prices are token1 per token0 in base units, like the `price` column of `swap_events`, see
+/// [`pool_token_decimals`] and [`crate::pricing::whole_token_price`] for whole tokens. Swaps
+/// executed at low liquidity move the average less than those at high liquidity.
+pub fn liquidity_weighted_price(
+    pool: Address,
+    start_block: u64,
+    end_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Option<BigDecimal>> {
+    let mut weighted_sum = BigDecimal::from(0);
+    let mut liquidity_sum = BigDecimal::from(0);
+    for batch in swaps_for_pool_iter(pool, SWAP_BATCH_SIZE, conn).in_blocks(start_block, end_block)
+    {
+        for swap in batch? {
+            weighted_sum += stored_swap_price(&swap)? * &swap.liquidity;
+            liquidity_sum += swap.liquidity;
+        }
+    }
+    if liquidity_sum <= BigDecimal::from(0) {
+        return Ok(None);
+    }
+
+    Ok(Some(weighted_sum / liquidity_sum))
+}
+
+/// Decimals of a created pool's token0 and token1, `None` unless both are in `tokens`
+pub fn pool_token_decimals(pool: Address, conn: &mut PgConnection) -> Result<Option<(u8, u8)>> {
+    use crate::pool_sql::schema::{
+        pools,
+        tokens,
+    };
+
+    let Some((token0, token1)): Option<(Vec<u8>, Vec<u8>)> = pools::table
+        .filter(pools::pool.eq(pool.to_vec()))
+        .select((pools::token0, pools::token1))
+        .first(conn)
+        .optional()
+        .wrap_err("failed to query the pool's tokens")?
+    else {
+        return Ok(None);
+    };
+    let mut decimals = |token: Vec<u8>| -> Result<Option<u8>> {
+        let decimals: Option<Option<i16>> = tokens::table
+            .filter(tokens::address.eq(token))
+            .select(tokens::decimals)
+            .first(conn)
+            .optional()
+            .wrap_err("failed to query the token's decimals")?;
+        decimals
+            .flatten()
+            .map(u8::try_from)
+            .transpose()
+            .wrap_err("stored token decimals don't fit in a u8")
+    };
+    Ok(decimals(token0)?.zip(decimals(token1)?))
+}
+
+/// Append the swaps of `pool` that aren't in `pool_swaps_wide` yet, returns the number of swaps
+/// added.
+///
+/// Picks up after the highest `swap_id` stored for the pool, so repeated runs only copy new swaps.
+/// Each batch is inserted in its own transaction, so an interrupted run keeps the batches it
+/// finished.
+pub fn compact_pool_swaps(pool: Address, conn: &mut PgConnection) -> Result<usize> {
+    use crate::pool_sql::schema::{
+        blocks,
+        pool_swaps_wide,
+        swap_events,
+        transactions,
+    };
+
+    let mut after_id: i64 = pool_swaps_wide::table
+        .filter(pool_swaps_wide::pool.eq(pool.to_vec()))
+        .select(diesel::dsl::max(pool_swaps_wide::swap_id))
+        .first::<Option<i64>>(conn)?
+        .unwrap_or(0);
+
+    let mut compacted = 0;
+    loop {
+        let batch: Vec<(i64, SwapEventRaw, i64, i64, Vec<u8>, i64)> = swap_events::table
+            .inner_join(transactions::table.inner_join(blocks::table))
+            .filter(swap_events::contract_address.eq(pool.to_vec()))
+            .filter(swap_events::id.gt(after_id))
+            .order(swap_events::id.asc())
+            .limit(SWAP_BATCH_SIZE)
+            .select((
+                swap_events::id,
+                SwapEventRaw::as_select(),
+                transactions::block_number,
+                transactions::transaction_index,
+                transactions::transaction_sender,
+                blocks::block_timestamp,
+            ))
+            .load(conn)?;
+        let Some((last_id, ..)) = batch.last() else {
+            break;
+        };
+        after_id = *last_id;
+        let batch_len = batch.len();
+
+        let rows: Vec<PoolSwapWideRaw> = batch
+            .into_iter()
+            .map(
+                |(
+                    swap_id,
+                    swap,
+                    block_number,
+                    transaction_index,
+                    transaction_sender,
+                    block_timestamp,
+                )| {
+                    Ok(PoolSwapWideRaw {
+                        swap_id,
+                        price: stored_swap_price(&swap)?,
+                        pool: swap.contract_address,
+                        transaction_hash: swap.transaction_hash,
+                        log_index: swap.log_index,
+                        block_number,
+                        block_timestamp,
+                        transaction_index,
+                        transaction_sender,
+                        sender: swap.sender,
+                        recipient: swap.recipient,
+                        zero_for_one: swap.amount0 > BigDecimal::from(0),
+                        amount0: swap.amount0,
+                        amount1: swap.amount1,
+                        sqrt_price_x96: swap.sqrt_price_x96,
+                        liquidity: swap.liquidity,
+                        tick: swap.tick,
+                    })
+                },
+            )
+            .collect::<Result<_>>()?;
+        compacted += conn.transaction(|conn| {
+            diesel::insert_into(pool_swaps_wide::table)
+                .values(rows)
+                .on_conflict_do_nothing()
+                .execute(conn)
+        })?;
+
+        if (batch_len as i64) < SWAP_BATCH_SIZE {
+            break;
+        }
+    }
+
+    Ok(compacted)
+}
+
+/// token1 per token0 in base units after a stored swap, computed from its `sqrt_price_x96` for
+/// swaps stored before the `price` column
+fn stored_swap_price(swap: &SwapEventRaw) -> Result<BigDecimal> {
+    if let Some(price) = &swap.price {
+        return Ok(price.clone());
+    }
+    let sqrt_price_x96 = U160::from_str(&integer_string(&swap.sqrt_price_x96))
+        .wrap_err("stored sqrt_price_x96 isn't a uint160")?;
+    Ok(base_unit_price(sqrt_price_x96))
+}
+
+/// Number of swaps loaded per query when reading all of a pool's swaps
+const SWAP_BATCH_SIZE: i64 = 10_000;
+
+/// Rows per statement of the `insert_many` functions. Postgres takes at most 65535 bind
+/// parameters per statement, enough for this many rows of the widest event table.
+const INSERT_CHUNK_SIZE: usize = 5_000;
+
+/// Split rows to insert into statements of at most `INSERT_CHUNK_SIZE` rows. The rows are
+/// moved, the event types are only insertable by value.
+fn insert_chunks<T>(mut rows: Vec<T>) -> Vec<Vec<T>> {
+    let mut chunks = Vec::new();
+    while rows.len() > INSERT_CHUNK_SIZE {
+        let rest = rows.split_off(INSERT_CHUNK_SIZE);
+        chunks.push(std::mem::replace(&mut rows, rest));
+    }
+    if !rows.is_empty() {
+        chunks.push(rows);
+    }
+    chunks
+}
+
+/// Swaps of a pool in insertion order, loaded `batch_size` at a time so pools with millions of
+/// swaps can be read without holding them all in memory
+pub struct SwapBatches<'a> {
+    pool: Address,
+    blocks: Option<(u64, u64)>,
+    batch_size: i64,
+    after_id: i64,
+    done: bool,
+    conn: &'a mut PgConnection,
+}
+
+pub fn swaps_for_pool_iter(
+    pool: Address,
+    batch_size: i64,
+    conn: &mut PgConnection,
+) -> SwapBatches<'_> {
+    SwapBatches {
+        pool,
+        blocks: None,
+        batch_size,
+        after_id: 0,
+        done: false,
+        conn,
+    }
+}
+
+impl SwapBatches<'_> {
+    /// Only yield swaps from `start_block` up to, but not including, `end_block`
+    pub fn in_blocks(mut self, start_block: u64, end_block: u64) -> Self {
+        self.blocks = Some((start_block, end_block));
+        self
+    }
+
+    fn next_batch(&mut self) -> Result<Vec<(i64, SwapEventRaw)>, Error> {
+        use crate::pool_sql::schema::{
+            swap_events,
+            transactions,
+        };
+
+        // keyset pagination on the id, so each batch is an index range scan however deep in
+        let mut query = swap_events::table
+            .inner_join(transactions::table)
+            .filter(swap_events::contract_address.eq(self.pool.to_vec()))
+            .filter(swap_events::id.gt(self.after_id))
+            .into_boxed();
+        if let Some((start_block, end_block)) = self.blocks {
+            query = query
+                .filter(transactions::block_number.ge(start_block as i64))
+                .filter(transactions::block_number.lt(end_block as i64));
+        }
+        query
+            .order(swap_events::id.asc())
+            .limit(self.batch_size)
+            .select((swap_events::id, SwapEventRaw::as_select()))
+            .load(self.conn)
+    }
+}
+
+impl Iterator for SwapBatches<'_> {
+    type Item = Result<Vec<SwapEventRaw>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let batch = match self.next_batch() {
+            Ok(batch) => batch,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        // a short batch is the last one, saves a query that would come back empty
+        self.done = (batch.len() as i64) < self.batch_size;
+        let (last_id, _) = batch.last()?;
+        self.after_id = *last_id;
+
+        Some(Ok(batch.into_iter().map(|(_, swap)| swap).collect()))
+    }
+}
+
+/// An event with the number and timestamp of the block it was emitted in
+#[derive(Clone, Debug)]
+pub struct TimestampedEvent<T> {
+    pub block_number: u64,
+    pub block_timestamp: u64,
+    pub event: T,
+}
+
+/// Swaps of `pool` from `from_block` up to, but not including, `to_block`, in chain order
+pub fn query_swaps(
+    pool: Address,
+    from_block: u64,
+    to_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Vec<TimestampedEvent<SwapEvent>>> {
+    use crate::pool_sql::schema::{
+        blocks,
+        swap_events,
+        transactions,
+    };
+
+    let rows: Vec<(SwapEventRaw, i64, i64)> = swap_events::table
+        .inner_join(transactions::table.inner_join(blocks::table))
+        .filter(swap_events::contract_address.eq(pool.to_vec()))
+        .filter(transactions::block_number.ge(from_block as i64))
+        .filter(transactions::block_number.lt(to_block as i64))
+        .order((
+            transactions::block_number.asc(),
+            transactions::transaction_index.asc(),
+            swap_events::log_index.asc(),
+        ))
+        .select((
+            SwapEventRaw::as_select(),
+            transactions::block_number,
+            blocks::block_timestamp,
+        ))
+        .load(conn)
+        .wrap_err("failed to query swaps")?;
+
+    timestamped(rows, "swap")
+}
+
+/// Swaps of `pool` in blocks with a timestamp from `from_timestamp` up to, but not including,
+/// `to_timestamp`, in chain order, ignoring blocks stored without their timestamp
+pub fn query_swaps_in_time_range(
+    pool: Address,
+    from_timestamp: u64,
+    to_timestamp: u64,
+    conn: &mut PgConnection,
+) -> Result<Vec<TimestampedEvent<SwapEvent>>> {
+    use crate::pool_sql::schema::{
+        blocks,
+        swap_events,
+        transactions,
+    };
+
+    let rows: Vec<(SwapEventRaw, i64, i64)> = swap_events::table
+        .inner_join(transactions::table.inner_join(blocks::table))
+        .filter(swap_events::contract_address.eq(pool.to_vec()))
+        .filter(blocks::block_timestamp.ge(from_timestamp as i64))
+        .filter(blocks::block_timestamp.lt(to_timestamp as i64))
+        // blocks stored without their timestamp aren't placed in time yet
+        .filter(blocks::block_timestamp.ne(MISSING_BLOCK_TIMESTAMP as i64))
+        .order((
+            transactions::block_number.asc(),
+            transactions::transaction_index.asc(),
+            swap_events::log_index.asc(),
+        ))
+        .select((
+            SwapEventRaw::as_select(),
+            transactions::block_number,
+            blocks::block_timestamp,
+        ))
+        .load(conn)
+        .wrap_err("failed to query swaps")?;
+
+    timestamped(rows, "swap")
+}
+
+/// Last swap of `pool` in a block with a timestamp before `timestamp`, ignoring blocks stored
+/// without their timestamp
+pub fn last_swap_before(
+    pool: Address,
+    timestamp: u64,
+    conn: &mut PgConnection,
+) -> Result<Option<TimestampedEvent<SwapEvent>>> {
+    use crate::pool_sql::schema::{
+        blocks,
+        swap_events,
+        transactions,
+    };
+
+    let rows: Vec<(SwapEventRaw, i64, i64)> = swap_events::table
+        .inner_join(transactions::table.inner_join(blocks::table))
+        .filter(swap_events::contract_address.eq(pool.to_vec()))
+        .filter(blocks::block_timestamp.lt(timestamp as i64))
+        // blocks stored without their timestamp aren't placed in time yet
+        .filter(blocks::block_timestamp.ne(MISSING_BLOCK_TIMESTAMP as i64))
+        .order((
+            transactions::block_number.desc(),
+            transactions::transaction_index.desc(),
+            swap_events::log_index.desc(),
+        ))
+        .limit(1)
+        .select((
+            SwapEventRaw::as_select(),
+            transactions::block_number,
+            blocks::block_timestamp,
+        ))
+        .load(conn)
+        .wrap_err("failed to query the last swap")?;
+
+    Ok(timestamped(rows, "swap")?.pop())
+}
+
+/// A swap and the protocol fee it paid in token0 and token1 base units
+pub type SwapProtocolFee = (TimestampedEvent<SwapEvent>, (U256, U256));
+
+/// Swaps of `pool` from `from_block` up to, but not including, `to_block`, in chain order, each
+/// with the protocol fee it paid in token0 and token1 base units, see
+/// [`crate::pricing::swap_protocol_fee`]. `None` if the pool isn't in `pools`, since its fee tier
+/// is unknown.
+///
+/// Each swap gets the setting of the pool's last stored `SetFeeProtocol` before it, protocol fees
+/// being off until the first one. Log indices count across the whole block, so a setting changed
+/// earlier in the swap's block applies to it.
+pub fn query_swap_protocol_fees(
+    pool: Address,
+    from_block: u64,
+    to_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Option<Vec<SwapProtocolFee>>> {
+    use crate::pool_sql::schema::{
+        pools,
+        set_fee_protocol_events,
+        transactions,
+    };
+
+    let Some(fee): Option<BigDecimal> = pools::table
+        .filter(pools::pool.eq(pool.to_vec()))
+        .select(pools::fee)
+        .first(conn)
+        .optional()
+        .wrap_err("failed to query the pool's fee")?
+    else {
+        return Ok(None);
+    };
+    let fee = U24::from_str(&integer_string(&fee)).wrap_err("stored pool fee doesn't fit")?;
+
+    let settings: Vec<(SetFeeProtocolEventRaw, i64)> = set_fee_protocol_events::table
+        .inner_join(transactions::table)
+        .filter(set_fee_protocol_events::contract_address.eq(pool.to_vec()))
+        .filter(transactions::block_number.lt(to_block as i64))
+        .order((
+            transactions::block_number.asc(),
+            set_fee_protocol_events::log_index.asc(),
+        ))
+        .select((
+            SetFeeProtocolEventRaw::as_select(),
+            transactions::block_number,
+        ))
+        .load(conn)
+        .wrap_err("failed to query the pool's protocol fee settings")?;
+    let settings = settings
+        .into_iter()
+        .map(|(raw, block_number)| {
+            let event = SetFeeProtocolEvent::try_from(raw)
+                .map_err(|e| eyre!("failed to convert set fee protocol event: {}", e))?;
+            Ok(((block_number as u64, event.log_index), event.new_fee_protocol()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut settings = settings.into_iter().peekable();
+    let mut fee_protocol = FeeProtocol::default();
+    let swaps = query_swaps(pool, from_block, to_block, conn)?;
+    Ok(Some(
+        swaps
+            .into_iter()
+            .map(|swap| {
+                let position = (swap.block_number, swap.event.log_index);
+                while let Some((_, setting)) = settings.next_if(|(at, _)| *at < position) {
+                    fee_protocol = setting;
+                }
+                let protocol_fee = swap_protocol_fee(&swap.event, fee, fee_protocol);
+                (swap, protocol_fee)
+            })
+            .collect(),
+    ))
+}
+
+/// Mints of `pool` from `from_block` up to, but not including, `to_block`, in chain order
+pub fn query_mints(
+    pool: Address,
+    from_block: u64,
+    to_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Vec<TimestampedEvent<MintEvent>>> {
+    use crate::pool_sql::schema::{
+        blocks,
+        mint_events,
+        transactions,
+    };
+
+    let rows: Vec<(MintEventRaw, i64, i64)> = mint_events::table
+        .inner_join(transactions::table.inner_join(blocks::table))
+        .filter(mint_events::contract_address.eq(pool.to_vec()))
+        .filter(transactions::block_number.ge(from_block as i64))
+        .filter(transactions::block_number.lt(to_block as i64))
+        .order((
+            transactions::block_number.asc(),
+            transactions::transaction_index.asc(),
+            mint_events::log_index.asc(),
+        ))
+        .select((
+            MintEventRaw::as_select(),
+            transactions::block_number,
+            blocks::block_timestamp,
+        ))
+        .load(conn)
+        .wrap_err("failed to query mints")?;
+
+    timestamped(rows, "mint")
+}
+
+/// Burns of `pool` from `from_block` up to, but not including, `to_block`, in chain order
+pub fn query_burns(
+    pool: Address,
+    from_block: u64,
+    to_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Vec<TimestampedEvent<BurnEvent>>> {
+    use crate::pool_sql::schema::{
+        blocks,
+        burn_events,
+        transactions,
+    };
+
+    let rows: Vec<(BurnEventRaw, i64, i64)> = burn_events::table
+        .inner_join(transactions::table.inner_join(blocks::table))
+        .filter(burn_events::contract_address.eq(pool.to_vec()))
+        .filter(transactions::block_number.ge(from_block as i64))
+        .filter(transactions::block_number.lt(to_block as i64))
+        .order((
+            transactions::block_number.asc(),
+            transactions::transaction_index.asc(),
+            burn_events::log_index.asc(),
+        ))
+        .select((
+            BurnEventRaw::as_select(),
+            transactions::block_number,
+            blocks::block_timestamp,
+        ))
+        .load(conn)
+        .wrap_err("failed to query burns")?;
+
+    timestamped(rows, "burn")
+}
+
+/// Convert queried `(event, block_number, block_timestamp)` rows
+fn timestamped<R, T>(rows: Vec<(R, i64, i64)>, kind: &str) -> Result<Vec<TimestampedEvent<T>>>
+where
+    T: TryFrom<R, Error = Box<dyn std::error::Error>>,
+{
+    rows.into_iter()
+        .map(|(raw, block_number, block_timestamp)| {
+            Ok(TimestampedEvent {
+                block_number: block_number as u64,
+                block_timestamp: block_timestamp as u64,
+                event: T::try_from(raw)
+                    .map_err(|e| eyre!("failed to convert {} event: {}", kind, e))?,
+            })
+        })
+        .collect()
+}
+
+/// A burn that took a position's liquidity below zero, so some of its mints weren't stored
+#[derive(Clone, Debug)]
+pub struct PositionViolation {
+    pub pool: Address,
+    pub owner: Address,
+    pub tick_lower: BigDecimal,
+    pub tick_upper: BigDecimal,
+    pub block_number: i64,
+    pub transaction_hash: TxHash,
+    /// Liquidity of the position right after the burn, negative
+    pub liquidity: BigDecimal,
+}
+
+/// Check that no position burns more liquidity than was minted into it.
+///
+/// Mints and burns are replayed per `(pool, owner, tick_lower, tick_upper)` in chain order. Each
+/// burn that takes the running liquidity below zero is reported, after which the running
+/// liquidity restarts from zero so one missing mint is only reported once.
+pub fn validate_position_liquidity(
+    conn: &mut PgConnection,
+) -> Result<Vec<PositionViolation>, Error> {
+    use crate::pool_sql::schema::{
+        burn_events,
+        mint_events,
+        transactions,
+    };
+
+    // (pool, owner, tick_lower, tick_upper, amount, block_number, transaction_index, log_index,
+    // transaction_hash)
+    type LiquidityChange = (
+        Vec<u8>,
+        Vec<u8>,
+        BigDecimal,
+        BigDecimal,
+        BigDecimal,
+        i64,
+        i64,
+        i64,
+        Vec<u8>,
+    );
+
+    let mints: Vec<LiquidityChange> = mint_events::table
+        .inner_join(transactions::table)
+        .select((
+            mint_events::contract_address,
+            mint_events::owner,
+            mint_events::tick_lower,
+            mint_events::tick_upper,
+            mint_events::amount,
+            transactions::block_number,
+            transactions::transaction_index,
+            mint_events::log_index,
+            mint_events::transaction_hash,
+        ))
+        .load(conn)?;
+    let burns: Vec<LiquidityChange> = burn_events::table
+        .inner_join(transactions::table)
+        .select((
+            burn_events::contract_address,
+            burn_events::owner,
+            burn_events::tick_lower,
+            burn_events::tick_upper,
+            burn_events::amount,
+            transactions::block_number,
+            transactions::transaction_index,
+            burn_events::log_index,
+            burn_events::transaction_hash,
+        ))
+        .load(conn)?;
+
+    let mut positions = HashMap::<_, Vec<_>>::new();
+    for (pool, owner, tick_lower, tick_upper, amount, block_number, tx_index, log_idx, tx_hash) in
+        mints.into_iter().chain(burns.into_iter().map(|mut burn| {
+            // burns take liquidity out of the position
+            burn.4 = -burn.4;
+            burn
+        }))
+    {
+        positions
+            .entry((pool, owner, tick_lower, tick_upper))
+            .or_default()
+            .push(((block_number, tx_index, log_idx), amount, tx_hash));
+    }
+
+    let mut violations = Vec::new();
+    for ((pool, owner, tick_lower, tick_upper), mut changes) in positions {
+        changes.sort_by_key(|(position_in_chain, ..)| *position_in_chain);
+
+        let mut liquidity = BigDecimal::from(0);
+        for ((block_number, ..), amount, tx_hash) in changes {
+            liquidity += amount;
+            if liquidity < BigDecimal::from(0) {
+                violations.push(PositionViolation {
+                    pool: Address::from_slice(&pool),
+                    owner: Address::from_slice(&owner),
+                    tick_lower: tick_lower.clone(),
+                    tick_upper: tick_upper.clone(),
+                    block_number,
+                    transaction_hash: TxHash::from_slice(&tx_hash),
+                    liquidity,
+                });
+                liquidity = BigDecimal::from(0);
+            }
+        }
+    }
+    violations.sort_by_key(|violation| violation.block_number);
+
+    Ok(violations)
+}
+
+/// A stored transaction with the log indices of its stored events
+#[derive(Clone, Debug)]
+pub struct TransactionEvents {
+    pub transaction_hash: TxHash,
+    pub transaction_index: i64,
+    /// Sorted ascending
+    pub log_indices: Vec<i64>,
+}
+
+/// Get a block's stored transactions in execution order (by `transaction_index`), with the log
+/// indices of their stored events
+pub fn transaction_order(
+    block: i64,
+    conn: &mut PgConnection,
+) -> Result<Vec<TransactionEvents>, Error> {
+    use crate::pool_sql::schema::{
+        burn_events,
+        collect_events,
+        collect_protocol_events,
+        flash_events,
+        initialization_events,
+        mint_events,
+        pool_create_events,
+        raw_logs,
+        set_fee_protocol_events,
+        swap_events,
+        transactions,
+    };
+
+    let block_transactions: Vec<(Vec<u8>, i64)> = transactions::table
+        .filter(transactions::block_number.eq(block))
+        .order(transactions::transaction_index.asc())
+        .select((
+            transactions::transaction_hash,
+            transactions::transaction_index,
+        ))
+        .load(conn)?;
+
+    let mut log_positions = Vec::new();
+    log_positions.extend(
+        pool_create_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((
+                pool_create_events::transaction_hash,
+                pool_create_events::log_index,
+            ))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        swap_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((swap_events::transaction_hash, swap_events::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        initialization_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((
+                initialization_events::transaction_hash,
+                initialization_events::log_index,
+            ))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        mint_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((mint_events::transaction_hash, mint_events::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        burn_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((burn_events::transaction_hash, burn_events::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        collect_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((collect_events::transaction_hash, collect_events::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        flash_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((flash_events::transaction_hash, flash_events::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        collect_protocol_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((
+                collect_protocol_events::transaction_hash,
+                collect_protocol_events::log_index,
+            ))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        set_fee_protocol_events::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((
+                set_fee_protocol_events::transaction_hash,
+                set_fee_protocol_events::log_index,
+            ))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+    log_positions.extend(
+        raw_logs::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_number.eq(block))
+            .select((raw_logs::transaction_hash, raw_logs::log_index))
+            .load::<(Vec<u8>, i64)>(conn)?,
+    );
+
+    let mut log_indices = HashMap::<Vec<u8>, Vec<i64>>::new();
+    for (tx_hash, log_idx) in log_positions {
+        log_indices.entry(tx_hash).or_default().push(log_idx);
+    }
+
+    Ok(block_transactions
+        .into_iter()
+        .map(|(tx_hash, tx_index)| {
+            let mut indices = log_indices.remove(&tx_hash).unwrap_or_default();
+            indices.sort_unstable();
+            TransactionEvents {
+                transaction_hash: TxHash::from_slice(&tx_hash),
+                transaction_index: tx_index,
+                log_indices: indices,
+            }
+        })
+        .collect())
+}
+
+/// Find consecutive transactions (in `transaction_index` order) whose events aren't in log
+/// index order, i.e. the earlier transaction has a log at or after the later one's first log.
+///
+/// Log indices are block wide and assigned in execution order, so any pair returned here means
+/// the provider returned inconsistent indices.
+pub fn log_order_violations(
+    ordered_transactions: &[TransactionEvents],
+) -> Vec<(&TransactionEvents, &TransactionEvents)> {
+    let with_events: Vec<_> = ordered_transactions
+        .iter()
+        .filter(|transaction| !transaction.log_indices.is_empty())
+        .collect();
+
+    with_events
+        .windows(2)
+        .filter(|pair| pair[0].log_indices.last() >= pair[1].log_indices.first())
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+/// Get the last exported event id for an event table, 0 if nothing was exported yet
+pub fn get_export_cursor(table: &str, conn: &mut PgConnection) -> Result<i64, Error> {
+    use crate::pool_sql::schema::export_cursors::dsl::*;
+
+    let cursor = export_cursors
+        .filter(event_table.eq(table))
+        .select(last_exported_id)
+        .first(conn)
+        .optional()?;
+
+    Ok(cursor.unwrap_or(0))
+}
+
+pub fn set_export_cursor(
+    table: &str,
+    exported_id: i64,
+    conn: &mut PgConnection,
+) -> Result<(), Error> {
+    use crate::pool_sql::schema::export_cursors::dsl::*;
+
+    diesel::insert_into(export_cursors)
+        .values((event_table.eq(table), last_exported_id.eq(exported_id)))
+        .on_conflict(event_table)
+        .do_update()
+        .set(last_exported_id.eq(exported_id))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Get the last event id of an event table a consumer acknowledged for a pool, 0 if it hasn't
+/// acknowledged any yet
+pub fn get_consumer_cursor(
+    consumer: &str,
+    pool_address: Address,
+    table: &str,
+    conn: &mut PgConnection,
+) -> Result<i64, Error> {
+    use crate::pool_sql::schema::consumer_cursors::dsl::*;
+
+    let cursor = consumer_cursors
+        .filter(consumer_id.eq(consumer))
+        .filter(pool.eq(pool_address.to_vec()))
+        .filter(event_table.eq(table))
+        .select(last_consumed_id)
+        .first(conn)
+        .optional()?;
+
+    Ok(cursor.unwrap_or(0))
+}
+
+define_sql_function! {
+    /// Postgres `GREATEST`
+    fn greatest(a: BigInt, b: BigInt) -> BigInt;
+}
+
+/// Move a consumer's cursor for a pool's event table up to `up_to_id`. The cursor never moves
+/// back, so acknowledging an older id again is a no-op.
+pub fn set_consumer_cursor(
+    consumer: &str,
+    pool_address: Address,
+    table: &str,
+    up_to_id: i64,
+    conn: &mut PgConnection,
+) -> Result<(), Error> {
+    use diesel::upsert::excluded;
+
+    use crate::pool_sql::schema::consumer_cursors::dsl::*;
+
+    diesel::insert_into(consumer_cursors)
+        .values((
+            consumer_id.eq(consumer),
+            pool.eq(pool_address.to_vec()),
+            event_table.eq(table),
+            last_consumed_id.eq(up_to_id),
+        ))
+        .on_conflict((consumer_id, pool, event_table))
+        .do_update()
+        .set(last_consumed_id.eq(greatest(last_consumed_id, excluded(last_consumed_id))))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Find the tracked pool for a token pair and fee tier.
+///
+/// The factory sorts a pool's tokens by address, so the tokens can be passed in either order.
+pub fn find_pool_by_tokens_and_fee(
+    token_a: Address,
+    token_b: Address,
+    fee_tier: U24,
+    conn: &mut PgConnection,
+) -> Result<Option<Address>, Error> {
+    use crate::pool_sql::schema::pool_create_events::dsl::*;
+
+    let (sorted_token0, sorted_token1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    let pool_address_raw: Option<Vec<u8>> = pool_create_events
+        .filter(token0.eq(sorted_token0.to_vec()))
+        .filter(token1.eq(sorted_token1.to_vec()))
+        .filter(fee.eq(BigDecimal::from(fee_tier.to::<u32>())))
+        .select(pool)
+        .first(conn)
+        .optional()?;
+
+    Ok(pool_address_raw.map(|address| Address::from_slice(&address)))
+}
+
+/// A tracked pool of a token pair, with its activity since some block
+#[derive(Debug)]
+pub struct PoolSummary {
+    pub pool: Address,
+    /// Fee tier in hundredths of a bip
+    pub fee: BigDecimal,
+    pub swap_count: u64,
+    /// Sum of the absolute token0 amounts of the counted swaps, in base units
+    pub volume0: BigDecimal,
+    /// Sum of the absolute token1 amounts of the counted swaps, in base units
+    pub volume1: BigDecimal,
+    /// Liquidity from `pool_current_state`, `None` if the pool's state isn't tracked
+    pub liquidity: Option<BigDecimal>,
+}
+
+/// Summarize every tracked pool of a token pair, one per fee tier, with the volume of their
+/// swaps from `since_block` on and their current liquidity. Pools are sorted by fee tier.
+///
+/// Like `find_pool_by_tokens_and_fee`, the tokens can be passed in either order.
+pub fn pools_for_pair(
+    token_a: Address,
+    token_b: Address,
+    since_block: u64,
+    conn: &mut PgConnection,
+) -> Result<Vec<PoolSummary>, Error> {
+    use crate::pool_sql::schema::{
+        pool_create_events,
+        pool_current_state,
+    };
+
+    let (sorted_token0, sorted_token1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    let pools_raw: Vec<(Vec<u8>, BigDecimal, Option<BigDecimal>)> = pool_create_events::table
+        .left_join(
+            pool_current_state::table.on(pool_current_state::pool.eq(pool_create_events::pool)),
+        )
+        .filter(pool_create_events::token0.eq(sorted_token0.to_vec()))
+        .filter(pool_create_events::token1.eq(sorted_token1.to_vec()))
+        .order(pool_create_events::fee.asc())
+        .select((
+            pool_create_events::pool,
+            pool_create_events::fee,
+            pool_current_state::liquidity.nullable(),
+        ))
+        .load(conn)?;
+
+    let mut summaries = Vec::with_capacity(pools_raw.len());
+    for (pool, fee, liquidity) in pools_raw {
+        let pool = Address::from_slice(&pool);
+        let mut swap_count = 0;
+        let mut volume0 = BigDecimal::from(0);
+        let mut volume1 = BigDecimal::from(0);
+        for batch in
+            swaps_for_pool_iter(pool, SWAP_BATCH_SIZE, conn).in_blocks(since_block, i64::MAX as u64)
+        {
+            for swap in batch? {
+                swap_count += 1;
+                volume0 += swap.amount0.abs();
+                volume1 += swap.amount1.abs();
+            }
+        }
+        summaries.push(PoolSummary {
+            pool,
+            fee,
+            swap_count,
+            volume0,
+            volume1,
+            liquidity,
+        });
+    }
+
+    Ok(summaries)
+}
+
+/// Transaction isolation level used when inserting a block's events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IsolationLevel {
+    /// Postgres' default
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+/// Run `f` in a transaction at the given isolation level
+fn transaction_with_isolation<T>(
+    conn: &mut PgConnection,
+    isolation: IsolationLevel,
+    f: impl FnOnce(&mut PgConnection) -> Result<T>,
+) -> Result<T> {
+    // the isolation level is fixed when a transaction starts, a nested call runs in a savepoint
+    // of the outer transaction
+    if AnsiTransactionManager::transaction_manager_status_mut(conn)
+        .transaction_depth()?
+        .is_some()
+    {
+        return conn.transaction(f);
+    }
+    let transaction = conn.build_transaction();
+    match isolation {
+        IsolationLevel::ReadCommitted => transaction.read_committed().run(f),
+        IsolationLevel::RepeatableRead => transaction.repeatable_read().run(f),
+        IsolationLevel::Serializable => transaction.serializable().run(f),
+    }
+}
+
+/// Number of events handed to `insert_block_events` and how many of them were newly inserted
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InsertCounts {
+    pub inserted: usize,
+    pub total: usize,
+}
+
+impl InsertCounts {
+    fn record_many(&mut self, inserted: usize, total: usize) {
+        self.inserted += inserted;
+        self.total += total;
+    }
+
+    /// Events that were already stored
+    pub fn skipped(&self) -> usize {
+        self.total - self.inserted
+    }
+
+    pub fn add(&mut self, other: InsertCounts) {
+        self.inserted += other.inserted;
+        self.total += other.total;
+    }
+}
+
+/// How a pool's `PoolCreated` compared with what was stored about the pool before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolReconciliation {
+    /// Nothing was stored about the pool
+    New,
+    /// The pool was only tracked as an inferred pool, without its tokens and fee
+    Enriched,
+    /// The stored pool has the same tokens and fee
+    Unchanged,
+    /// The stored pool has other tokens or another fee, replaced by the event's
+    Conflict,
+}
+
+/// Store the pool created by `pool_create_event`, in block `created_block`, in `pools`.
+///
+/// A pool tracked before its `PoolCreated` was seen, like an inferred pool whose creation is in
+/// a block range backfilled later, gets its tokens and fee filled in. The event is authoritative,
+/// so a stored pool with other tokens or another fee is overwritten, with a warning.
+pub fn reconcile_pool(
+    pool_create_event: &PoolCreateEventRaw,
+    created_block: i64,
+    conn: &mut PgConnection,
+) -> Result<PoolReconciliation, Error> {
+    use crate::pool_sql::schema::{
+        inferred_pools,
+        pools,
+    };
+
+    let created = PoolRaw::from_create_event(pool_create_event, created_block);
+    let address = Address::from_slice(&created.pool);
+    let stored: Option<PoolRaw> = pools::table
+        .filter(pools::pool.eq(&created.pool))
+        .select(PoolRaw::as_select())
+        .first(conn)
+        .optional()?;
+    let reconciliation = match stored {
+        Some(stored)
+            if stored.token0 == created.token0
+                && stored.token1 == created.token1
+                && stored.fee == created.fee =>
+        {
+            PoolReconciliation::Unchanged
+        }
+        Some(stored) => {
+            warn!(
+                "Pool {} is stored with tokens {} and {} and fee {}, but its PoolCreated has \
+                 {} and {} and fee {}, replacing them",
+                fmt_addr(address),
+                fmt_addr(Address::from_slice(&stored.token0)),
+                fmt_addr(Address::from_slice(&stored.token1)),
+                integer_string(&stored.fee),
+                fmt_addr(Address::from_slice(&created.token0)),
+                fmt_addr(Address::from_slice(&created.token1)),
+                integer_string(&created.fee),
+            );
+            PoolReconciliation::Conflict
+        }
+        None => {
+            let inferred = diesel::select(diesel::dsl::exists(
+                inferred_pools::table.filter(inferred_pools::pool.eq(&created.pool)),
+            ))
+            .get_result::<bool>(conn)?;
+            if inferred {
+                info!(
+                    "Found the PoolCreated of inferred pool {}, storing its tokens and fee",
+                    fmt_addr(address)
+                );
+                PoolReconciliation::Enriched
+            } else {
+                PoolReconciliation::New
+            }
+        }
+    };
+    PoolRaw::upsert_many(vec![created], conn)?;
+
+    Ok(reconciliation)
+}
+
+/// Attempts at a block's separate swap write before the block fails
+const SWAP_WRITE_ATTEMPTS: u32 = 3;
+
+// Function to insert a transaction and multiple swap events
+//
+// If `swap_conn` is given, the swaps are written on it in their own transaction after the rest of
+// the block is committed (they reference the block's transactions), so a burst of swap writes
+// doesn't hold up the other event tables. The block is then not stored atomically: if the swap
+// write still fails after `SWAP_WRITE_ATTEMPTS`, the rest of the block stays committed without its
+// swaps. Every insert skips rows that are already stored and the block's content hash is only
+// recorded once the swaps are in, so inserting the block again, by the caller's retry or a later
+// run, completes it.
+//
+// `pool_states` are updated along with the swaps, so the current state never runs ahead of the
+// stored swaps.
+#[expect(
+    clippy::too_many_arguments,
+    reason = "one argument per event table, inserted in foreign key order"
+)]
+pub fn insert_block_events(
+    mut block: BlockRaw,
+    transactions: Vec<TransactionRaw>,
+    pool_create_events: Vec<PoolCreateEventRaw>,
+    swaps: Vec<SwapEventRaw>,
+    initialize_events: Vec<InitializationEventRaw>,
+    mint_events: Vec<MintEventRaw>,
+    burn_events: Vec<BurnEventRaw>,
+    collect_events: Vec<CollectEventRaw>,
+    flash_events: Vec<FlashEventRaw>,
+    collect_protocol_events: Vec<CollectProtocolEventRaw>,
+    set_fee_protocol_events: Vec<SetFeeProtocolEventRaw>,
+    raw_logs: Vec<RawLogRaw>,
+    inferred_pools: Vec<InferredPoolRaw>,
+    tokens: Vec<TokenRaw>,
+    pool_states: Vec<PoolCurrentStateRaw>,
+    conn: &mut PgConnection,
+    swap_conn: Option<&mut PgConnection>,
+    isolation: IsolationLevel,
+) -> Result<InsertCounts> {
+    let (swaps, pool_states, separate_swaps) = match swap_conn {
+        Some(swap_conn) => (
+            Vec::new(),
+            Vec::new(),
+            Some((swaps, pool_states, swap_conn)),
+        ),
+        None => (swaps, pool_states, None),
+    };
+    // the hash is recorded last, so a stored hash means every event of the block was committed
+    let block_number = block.block_number;
+    let content_hash = block.content_hash.take();
+
+    let pool_create_len = pool_create_events.len();
+    let swaps_len = swaps.len();
+    let initialize_len = initialize_events.len();
+    let mint_len = mint_events.len();
+    let burn_len = burn_events.len();
+    let collect_len = collect_events.len();
+    let flash_len = flash_events.len();
+    let collect_protocol_len = collect_protocol_events.len();
+    let set_fee_protocol_len = set_fee_protocol_events.len();
+    let raw_logs_len = raw_logs.len();
+
+    let mut counts = transaction_with_isolation(conn, isolation, |conn| {
+        let mut counts = InsertCounts::default();
+
+        block.insert_if_not_exists(conn)?;
+
+        // Pools seen without a PoolCreated reference the block they were first seen in
+        for inferred_pool in inferred_pools {
+            inferred_pool.insert_if_not_exists(conn)?;
+        }
+
+        // First ensure the transactions exist
+        TransactionRaw::insert_many(transactions, conn)?;
+
+        // Created pools and their tokens, refreshed if they were stored before
+        for pool_create_event in &pool_create_events {
+            reconcile_pool(pool_create_event, block_number, conn)?;
+        }
+        TokenRaw::upsert_many(tokens, conn)?;
+
+        // Then insert the events, one statement per table
+        counts.record_many(
+            PoolCreateEventRaw::insert_many(pool_create_events, conn)?,
+            pool_create_len,
+        );
+        counts.record_many(SwapEventRaw::insert_many(swaps, conn)?, swaps_len);
+        counts.record_many(
+            InitializationEventRaw::insert_many(initialize_events, conn)?,
+            initialize_len,
+        );
+        counts.record_many(MintEventRaw::insert_many(mint_events, conn)?, mint_len);
+        counts.record_many(BurnEventRaw::insert_many(burn_events, conn)?, burn_len);
+        counts.record_many(
+            CollectEventRaw::insert_many(collect_events, conn)?,
+            collect_len,
+        );
+        counts.record_many(FlashEventRaw::insert_many(flash_events, conn)?, flash_len);
+        counts.record_many(
+            CollectProtocolEventRaw::insert_many(collect_protocol_events, conn)?,
+            collect_protocol_len,
+        );
+        counts.record_many(
+            SetFeeProtocolEventRaw::insert_many(set_fee_protocol_events, conn)?,
+            set_fee_protocol_len,
+        );
+        counts.record_many(RawLogRaw::insert_many(raw_logs, conn)?, raw_logs_len);
+
+        // Then move the pools' current state forward
+        for pool_state in pool_states {
+            pool_state.upsert_if_newer(conn)?;
+        }
+
+        Ok(counts)
+    })?;
+
+    if let Some((swaps, pool_states, swap_conn)) = separate_swaps {
+        let mut attempts = 0;
+        let swap_counts = loop {
+            attempts += 1;
+            let result = transaction_with_isolation(swap_conn, isolation, |swap_conn| {
+                let mut swap_counts = InsertCounts::default();
+                let swaps_len = swaps.len();
+                swap_counts.record_many(
+                    SwapEventRaw::insert_many(swaps.clone(), swap_conn)?,
+                    swaps_len,
+                );
+                for pool_state in pool_states.clone() {
+                    pool_state.upsert_if_newer(swap_conn)?;
+                }
+                Ok(swap_counts)
+            });
+            match result {
+                Ok(swap_counts) => break swap_counts,
+                // a lost connection is left to the caller, which reconnects
+                Err(e) if attempts < SWAP_WRITE_ATTEMPTS && !is_connection_error(&e) => {
+                    warn!(
+                        "Failed to insert the swaps of block {} (attempt {}/{}), retrying: {}",
+                        block_number, attempts, SWAP_WRITE_ATTEMPTS, e
+                    );
+                }
+                Err(e) => {
+                    return Err(e.wrap_err(format!(
+                        "block {} is stored without its swaps",
+                        block_number
+                    )))
+                }
+            }
+        };
+        counts.add(swap_counts);
+    }
+
+    if let Some(content_hash) = content_hash {
+        BlockRaw::set_content_hash(block_number, B256::from_slice(&content_hash), conn)?;
+    }
+
+    Ok(counts)
+}
+
+/// What rolling back an orphaned block removed
+#[derive(Debug, Default)]
+pub struct RolledBackBlock {
+    /// Number of events deleted
+    pub events: usize,
+    /// Pools created or first seen in the block, they are no longer stored
+    pub pools: Vec<Address>,
+}
+
+/// Delete a block and everything stored for it, for blocks orphaned by a reorg.
+///
+/// Swaps are deleted through `swap_conn` when swaps are written through a separate connection.
+/// Pools whose current state came from the block are moved back to their latest surviving swap or
+/// initialization, or lose their state if there is none.
+pub fn delete_block_events(
+    block: u64,
+    conn: &mut PgConnection,
+    swap_conn: Option<&mut PgConnection>,
+) -> Result<RolledBackBlock, Error> {
+    use crate::pool_sql::schema::{
+        blocks,
+        burn_events,
+        collect_events,
+        collect_protocol_events,
+        flash_events,
+        inferred_pools,
+        initialization_events,
+        mint_events,
+        pool_create_events,
+        pool_current_state,
+        pool_swaps_wide,
+        pools,
+        raw_logs,
+        set_fee_protocol_events,
+        swap_events,
+        transactions,
+    };
+
+    let block = block as i64;
+    let block_tx_hashes = |conn: &mut PgConnection| {
+        transactions::table
+            .filter(transactions::block_number.eq(block))
+            .select(transactions::transaction_hash)
+            .load::<Vec<u8>>(conn)
+    };
+
+    // the swaps reference the block's transactions, so they go first
+    let mut swaps_deleted = 0;
+    if let Some(swap_conn) = swap_conn {
+        swaps_deleted = swap_conn.transaction(|swap_conn| {
+            let tx_hashes = block_tx_hashes(swap_conn)?;
+            diesel::delete(
+                swap_events::table.filter(swap_events::transaction_hash.eq_any(&tx_hashes)),
+            )
+            .execute(swap_conn)
+        })?;
+    }
+
+    conn.transaction(|conn| {
+        let tx_hashes = block_tx_hashes(conn)?;
+        let rolled_back_states: Vec<Vec<u8>> = pool_current_state::table
+            .filter(pool_current_state::block_number.eq(block))
+            .select(pool_current_state::pool)
+            .load(conn)?;
+        let mut pools_deleted: Vec<Vec<u8>> = pools::table
+            .filter(pools::created_block.eq(block))
+            .select(pools::pool)
+            .load(conn)?;
+        pools_deleted.extend(
+            inferred_pools::table
+                .filter(inferred_pools::first_seen_block.eq(block))
+                .select(inferred_pools::pool)
+                .load::<Vec<u8>>(conn)?,
+        );
+
+        // events first, they reference the transactions
+        let mut deleted = swaps_deleted;
+        deleted += diesel::delete(
+            pool_create_events::table
+                .filter(pool_create_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            swap_events::table.filter(swap_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            initialization_events::table
+                .filter(initialization_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            mint_events::table.filter(mint_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            burn_events::table.filter(burn_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            collect_events::table.filter(collect_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            flash_events::table.filter(flash_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            collect_protocol_events::table
+                .filter(collect_protocol_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted += diesel::delete(
+            set_fee_protocol_events::table
+                .filter(set_fee_protocol_events::transaction_hash.eq_any(&tx_hashes)),
+        )
+        .execute(conn)?;
+        deleted +=
+            diesel::delete(raw_logs::table.filter(raw_logs::transaction_hash.eq_any(&tx_hashes)))
+                .execute(conn)?;
+
+        // then what refers to the block
+        diesel::delete(pool_swaps_wide::table.filter(pool_swaps_wide::block_number.eq(block)))
+            .execute(conn)?;
+        diesel::delete(
+            pool_current_state::table.filter(pool_current_state::block_number.eq(block)),
+        )
+        .execute(conn)?;
+        // the block's events are gone, so this is the state before the block
+        for pool in &rolled_back_states {
+            if let Some(state) = PoolCurrentStateRaw::latest_stored(pool, conn)? {
+                state.upsert_if_newer(conn)?;
+            }
+        }
+        diesel::delete(inferred_pools::table.filter(inferred_pools::first_seen_block.eq(block)))
+            .execute(conn)?;
+        diesel::delete(pools::table.filter(pools::created_block.eq(block))).execute(conn)?;
+        diesel::delete(transactions::table.filter(transactions::block_number.eq(block)))
+            .execute(conn)?;
+        diesel::delete(blocks::table.filter(blocks::block_number.eq(block))).execute(conn)?;
+
+        Ok(RolledBackBlock {
+            events: deleted,
+            pools: pools_deleted
+                .iter()
+                .map(|pool| Address::from_slice(pool))
+                .collect(),
+        })
+    })
+}
+
+diesel::table! {
+    /// Applied migrations, kept by diesel
+    __diesel_schema_migrations (version) {
+        version -> VarChar,
+    }
+}
+
+/// The `migrations` directory, compiled into the binary
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Apply the migrations the database doesn't have yet, in version order. Returns how many were
+/// applied, none if the schema is up to date.
+pub fn run_migrations(conn: &mut PgConnection) -> Result<usize> {
+    let applied = conn
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(|e| eyre!("failed to run migrations: {}", e))?;
+    match applied.last() {
+        Some(latest) => info!("Applied {} migrations, up to {}", applied.len(), latest),
+        None => info!("Database schema is up to date"),
+    }
+    Ok(applied.len())
+}
+
+/// Versions of the embedded migrations the database doesn't have yet. A version is the part of
+/// the migration's directory name before the first `_`, without dashes.
+pub fn pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>> {
+    let pending = conn
+        .pending_migrations(MIGRATIONS)
+        .map_err(|e| eyre!("failed to read pending migrations: {}", e))?;
+    Ok(pending
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect())
+}
+
+/// Version of the newest migration applied to the database. Versions sort as strings.
+pub fn latest_applied_migration(conn: &mut PgConnection) -> Result<Option<String>, Error> {
+    use self::__diesel_schema_migrations::dsl::*;
+
+    __diesel_schema_migrations
+        .select(diesel::dsl::max(version))
+        .first(conn)
+}
+
+/// Connection to `DATABASE_URL`, read from the environment or `.env`
+pub fn establish_connection() -> Result<PgConnection> {
+    dotenv::dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").wrap_err("DATABASE_URL must be set")?;
+    PgConnection::establish(&database_url).wrap_err("failed to connect to DATABASE_URL")
+}
+
+/// Connection to `SWAP_DATABASE_URL`, `None` if swaps are stored through `DATABASE_URL`
+pub fn establish_swap_connection() -> Result<Option<PgConnection>> {
+    dotenv::dotenv().ok();
+    match std::env::var("SWAP_DATABASE_URL") {
+        Ok(database_url) if !database_url.is_empty() => Ok(Some(
+            PgConnection::establish(&database_url)
+                .wrap_err("failed to connect to SWAP_DATABASE_URL")?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Check if an error came from losing the connection to the database, rather than from the
+/// data being inserted. Only these are worth retrying on a fresh connection.
+pub fn is_connection_error(error: &eyre::Report) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<ConnectionError>().is_some()
+            || matches!(
+                cause.downcast_ref::<Error>(),
+                Some(
+                    Error::DatabaseError(
+                        DatabaseErrorKind::ClosedConnection
+                            | DatabaseErrorKind::UnableToSendCommand,
+                        _,
+                    ) | Error::BrokenTransactionManager
+                )
+            )
+    })
+}
+
+/// Pool of connections to one database
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+/// Connection pools of the block processing modes, built once per run
+pub struct DbPools {
+    pub main: DbPool,
+    /// Pool dedicated to swap inserts, if `SWAP_DATABASE_URL` is set
+    pub swap: Option<DbPool>,
+}
+
+impl DbPools {
+    /// Pools of up to `max_size` connections each
+    pub fn new(max_size: u32) -> Result<Self> {
+        dotenv::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").wrap_err("DATABASE_URL must be set")?;
+        let main = Pool::builder()
+            .max_size(max_size)
+            .build(ConnectionManager::new(database_url))
+            .wrap_err("failed to build the database pool")?;
+        let swap = match std::env::var("SWAP_DATABASE_URL") {
+            Ok(database_url) if !database_url.is_empty() => Some(
+                Pool::builder()
+                    .max_size(max_size)
+                    .build(ConnectionManager::new(database_url))
+                    .wrap_err("failed to build the swap database pool")?,
+            ),
+            _ => None,
+        };
+
+        Ok(Self {
+            main,
+            swap,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Once;
+
+    use alloy::primitives::{
+        aliases::{
+            I24,
+            I256,
+            U128,
+            U160,
+        },
+        U256,
+    };
+
+    use super::*;
+
+    /// Migrate the database at `DATABASE_URL` once per test run
+    pub(crate) fn migrate_once() {
+        static MIGRATE: Once = Once::new();
+        MIGRATE.call_once(|| {
+            let mut conn = establish_connection().expect("DATABASE_URL should be set for tests");
+            run_migrations(&mut conn).expect("migrations should apply");
+        });
+    }
+
+    /// Connection to the database at `DATABASE_URL` with the schema migrated, inside a
+    /// transaction that is never committed
+    pub(crate) fn test_connection() -> PgConnection {
+        migrate_once();
+
+        let mut conn = establish_connection().expect("DATABASE_URL should be set for tests");
+        conn.begin_test_transaction()
+            .expect("test transaction should start");
+        conn
+    }
+
+    /// Pools of a single connection to the database at `DATABASE_URL`, inside a transaction that
+    /// is never committed, so everything written through them is seen by later reads
+    pub(crate) fn test_db_pools() -> DbPools {
+        migrate_once();
+
+        dotenv::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
+        let main = Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(diesel::r2d2::TestCustomizer))
+            .build(ConnectionManager::new(database_url))
+            .expect("test pool should build");
+        DbPools { main, swap: None }
+    }
+
+    /// Hash of the `index`th test transaction of `block`
+    pub(crate) fn tx_hash(block: u64, index: u64) -> TxHash {
+        TxHash::from(U256::from((block << 16) | index))
+    }
+
+    /// Stored block `number`, with hashes derived from its number
+    pub(crate) fn block(number: u64) -> BlockRaw {
+        BlockRaw::try_from(Block::new(
+            number,
+            B256::from(U256::from(number)),
+            Some(B256::from(U256::from(number - 1))),
+            1_700_000_000 + number * 12,
+            None,
+        ))
+        .unwrap()
+    }
+
+    pub(crate) fn transaction(block: u64, index: u64) -> TransactionRaw {
+        TransactionRaw::try_from(Transaction {
+            transaction_hash: tx_hash(block, index),
+            block_number: block,
+            transaction_index: index,
+            transaction_sender: Address::repeat_byte(0x42),
+            details: None,
+            status: Some(true),
+        })
+        .unwrap()
+    }
+
+    /// Swap of `pool` in the `index`th transaction of `block`, leaving the pool at
+    /// `sqrt_price_x96`
+    pub(crate) fn swap(
+        block: u64,
+        index: u64,
+        pool: Address,
+        amount0: i64,
+        amount1: i64,
+        sqrt_price_x96: U160,
+    ) -> SwapEventRaw {
+        SwapEventRaw::try_from(SwapEvent {
+            transaction_hash: tx_hash(block, index),
+            log_index: index,
+            contract_address: pool,
+            sender: Address::repeat_byte(0x42),
+            recipient: Address::repeat_byte(0x42),
+            amount0: PoolDelta(I256::try_from(amount0).unwrap()),
+            amount1: PoolDelta(I256::try_from(amount1).unwrap()),
+            sqrt_price_x96,
+            liquidity: U128::from(1_000_000),
+            tick: I24::ZERO,
+        })
+        .unwrap()
+    }
+
+    /// Insert a block with `transactions` and `swaps` and nothing else
+    pub(crate) fn insert_swaps(
+        block: BlockRaw,
+        transactions: Vec<TransactionRaw>,
+        swaps: Vec<SwapEventRaw>,
+        pool_states: Vec<PoolCurrentStateRaw>,
+        conn: &mut PgConnection,
+        swap_conn: Option<&mut PgConnection>,
+    ) -> Result<InsertCounts> {
+        insert_block_events(
+            block,
+            transactions,
+            Vec::new(),
+            swaps,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            pool_states,
+            conn,
+            swap_conn,
+            IsolationLevel::default(),
+        )
+    }
+
+    /// Insert block `number` creating each `(pool, token0, token1, fee)` of `pools` in a
+    /// transaction of its own
+    pub(crate) fn insert_pools(
+        number: u64,
+        pools: &[(Address, Address, Address, u32)],
+        conn: &mut PgConnection,
+    ) -> Result<InsertCounts> {
+        let pool_create_events = pools
+            .iter()
+            .zip(0..)
+            .map(|(&(pool, token0, token1, fee), index)| {
+                PoolCreateEventRaw::try_from(PoolCreateEvent {
+                    transaction_hash: tx_hash(number, index),
+                    log_index: index,
+                    token0,
+                    token1,
+                    fee: U24::from(fee),
+                    tick_spacing: I24::try_from(60).unwrap(),
+                    pool,
+                })
+                .unwrap()
+            })
+            .collect();
+        insert_block_events(
+            block(number),
+            (0..pools.len() as u64)
+                .map(|index| transaction(number, index))
+                .collect(),
+            pool_create_events,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            conn,
+            None,
+            IsolationLevel::default(),
+        )
+    }
+
+    #[test]
+    fn insert_chunks_splits_at_the_chunk_size() {
+        let sizes = |rows: usize| -> Vec<usize> {
+            insert_chunks(vec![(); rows])
+                .iter()
+                .map(Vec::len)
+                .collect()
+        };
+        assert_eq!(sizes(0), Vec::<usize>::new());
+        assert_eq!(sizes(INSERT_CHUNK_SIZE), [INSERT_CHUNK_SIZE]);
+        assert_eq!(
+            sizes(2 * INSERT_CHUNK_SIZE + 1),
+            [INSERT_CHUNK_SIZE, INSERT_CHUNK_SIZE, 1]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn reinserted_events_are_skipped() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let price = U160::from(1) << 96;
+
+        let counts = insert_swaps(
+            block(2_000),
+            vec![transaction(2_000, 0), transaction(2_000, 1)],
+            vec![
+                swap(2_000, 0, pool, 10, -20, price),
+                swap(2_000, 1, pool, -5, 10, price),
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        assert_eq!((counts.inserted, counts.total), (2, 2));
+
+        // the block again with one more swap, only the new one is inserted
+        let counts = insert_swaps(
+            block(2_000),
+            vec![
+                transaction(2_000, 0),
+                transaction(2_000, 1),
+                transaction(2_000, 2),
+            ],
+            vec![
+                swap(2_000, 0, pool, 10, -20, price),
+                swap(2_000, 1, pool, -5, 10, price),
+                swap(2_000, 2, pool, 7, -14, price),
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        assert_eq!((counts.inserted, counts.skipped()), (1, 2));
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn insert_many_spans_several_statements() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let rows = INSERT_CHUNK_SIZE as u64 + 1;
+        let transactions: Vec<TransactionRaw> =
+            (0..rows).map(|index| transaction(3_000, index)).collect();
+        let swaps: Vec<SwapEventRaw> = (0..rows)
+            .map(|index| swap(3_000, index, pool, 1, -1, U160::from(1) << 96))
+            .collect();
+        block(3_000).insert_if_not_exists(&mut conn).unwrap();
+        TransactionRaw::insert_many(transactions, &mut conn).unwrap();
+
+        assert_eq!(
+            SwapEventRaw::insert_many(swaps.clone(), &mut conn).unwrap(),
+            rows as usize
+        );
+        assert_eq!(SwapEventRaw::insert_many(swaps, &mut conn).unwrap(), 0);
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn rolled_back_block_restores_the_pool_state() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let inferred = Address::repeat_byte(0x12);
+        let before = U160::from(1) << 96;
+        let after = U160::from(2) << 96;
+        for (number, price) in [(5_000, before), (5_001, after)] {
+            let swaps = vec![swap(number, 0, pool, 10, -20, price)];
+            let pool_states = PoolCurrentStateRaw::latest_in_block(number as i64, &swaps, &[]);
+            insert_swaps(
+                block(number),
+                vec![transaction(number, 0)],
+                swaps,
+                pool_states,
+                &mut conn,
+                None,
+            )
+            .unwrap();
+        }
+        InferredPoolRaw {
+            pool: inferred.to_vec(),
+            first_seen_block: 5_001,
+        }
+        .insert_if_not_exists(&mut conn)
+        .unwrap();
+        let state = get_pool_state(pool, &mut conn).unwrap().unwrap();
+        assert_eq!((state.last_updated_block, state.sqrt_price_x96), (5_001, after));
+
+        let rolled_back = delete_block_events(5_001, &mut conn, None).unwrap();
+        assert_eq!(rolled_back.events, 1);
+        assert_eq!(rolled_back.pools, [inferred]);
+        let state = get_pool_state(pool, &mut conn).unwrap().unwrap();
+        assert_eq!((state.last_updated_block, state.sqrt_price_x96), (5_000, before));
+
+        delete_block_events(5_000, &mut conn, None).unwrap();
+        assert!(get_pool_state(pool, &mut conn).unwrap().is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn pool_state_falls_back_to_the_latest_stored_swap() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let price = U160::from(3) << 96;
+        // stored without `--track-pool-state`
+        insert_swaps(
+            block(5_100),
+            vec![transaction(5_100, 0), transaction(5_100, 1)],
+            vec![
+                swap(5_100, 0, pool, 10, -20, U160::from(1) << 96),
+                swap(5_100, 1, pool, 10, -20, price),
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+
+        let state = get_pool_state(pool, &mut conn).unwrap().unwrap();
+        assert_eq!(
+            (state.last_updated_block, state.log_index, state.sqrt_price_x96),
+            (5_100, 1, price)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn weighted_price_in_base_and_whole_tokens() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let (token0, token1) = (Address::repeat_byte(0x21), Address::repeat_byte(0x22));
+        let mut swaps = vec![
+            swap(5_200, 0, pool, 10, -20, U160::from(1) << 96),
+            swap(5_200, 1, pool, 10, -20, U160::from(2) << 96),
+        ];
+        // stored before the `price` column
+        swaps[1].price = None;
+        insert_swaps(
+            block(5_200),
+            vec![transaction(5_200, 0), transaction(5_200, 1)],
+            swaps,
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+
+        // prices 1 and 4 at the same liquidity
+        let price = liquidity_weighted_price(pool, 5_200, 5_201, &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(price, BigDecimal::from_str("2.5").unwrap());
+        assert_eq!(pool_token_decimals(pool, &mut conn).unwrap(), None);
+
+        PoolRaw::upsert_many(
+            vec![PoolRaw {
+                pool: pool.to_vec(),
+                token0: token0.to_vec(),
+                token1: token1.to_vec(),
+                fee: BigDecimal::from(3000),
+                tick_spacing: BigDecimal::from(60),
+                created_block: 5_200,
+            }],
+            &mut conn,
+        )
+        .unwrap();
+        let token = |address: Address, decimals| TokenRaw {
+            address: address.to_vec(),
+            decimals: Some(decimals),
+            symbol: None,
+            name: None,
+        };
+        TokenRaw::upsert_many(vec![token(token0, 8), token(token1, 6)], &mut conn).unwrap();
+        let (decimals0, decimals1) = pool_token_decimals(pool, &mut conn).unwrap().unwrap();
+        assert_eq!(
+            crate::pricing::whole_token_price(&price, decimals0, decimals1),
+            BigDecimal::from(250)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn tokens_without_stored_metadata_are_reported() {
+        let mut conn = test_connection();
+        let known = Address::repeat_byte(0x31);
+        let absent = Address::repeat_byte(0x32);
+        let without_symbol = Address::repeat_byte(0x33);
+        let pool_create = |index, token0, token1, pool| {
+            PoolCreateEventRaw::try_from(PoolCreateEvent {
+                transaction_hash: tx_hash(5_600, index),
+                log_index: index,
+                token0,
+                token1,
+                fee: U24::from(3000),
+                tick_spacing: I24::try_from(60).unwrap(),
+                pool,
+            })
+            .unwrap()
+        };
+        insert_block_events(
+            block(5_600),
+            vec![transaction(5_600, 0), transaction(5_600, 1)],
+            vec![
+                pool_create(0, known, absent, Address::repeat_byte(0x34)),
+                pool_create(1, without_symbol, known, Address::repeat_byte(0x35)),
+            ],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                TokenRaw {
+                    address: known.to_vec(),
+                    decimals: Some(18),
+                    symbol: Some("KNOWN".to_string()),
+                    name: None,
+                },
+                TokenRaw {
+                    address: without_symbol.to_vec(),
+                    decimals: Some(18),
+                    symbol: None,
+                    name: Some("No Symbol".to_string()),
+                },
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+            IsolationLevel::default(),
+        )
+        .unwrap();
+
+        let missing = tokens_missing_metadata(&mut conn).unwrap();
+        let ours: Vec<Address> = missing
+            .into_iter()
+            .filter(|token| [known, absent, without_symbol].contains(token))
+            .collect();
+        assert_eq!(ours, [absent, without_symbol]);
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn time_range_skips_blocks_without_a_timestamp() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        for number in [5_300, 5_301] {
+            let mut stored_block = block(number);
+            if number == 5_300 {
+                stored_block.block_timestamp = MISSING_BLOCK_TIMESTAMP as i64;
+            }
+            insert_swaps(
+                stored_block,
+                vec![transaction(number, 0)],
+                vec![swap(number, 0, pool, 10, -20, U160::from(1) << 96)],
+                Vec::new(),
+                &mut conn,
+                None,
+            )
+            .unwrap();
+        }
+
+        let swaps = query_swaps_in_time_range(pool, 0, u64::MAX >> 1, &mut conn).unwrap();
+        assert_eq!(
+            swaps
+                .iter()
+                .map(|swap| swap.block_number)
+                .collect::<Vec<_>>(),
+            [5_301]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn range_queries_return_the_pools_events_in_chain_order() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x11);
+        let other_pool = Address::repeat_byte(0x22);
+        let mint = |block, index| {
+            MintEventRaw::try_from(MintEvent {
+                transaction_hash: tx_hash(block, index),
+                log_index: index,
+                contract_address: pool,
+                sender: Address::repeat_byte(0x42),
+                owner: Address::repeat_byte(0x42),
+                tick_lower: I24::try_from(-60).unwrap(),
+                tick_upper: I24::try_from(60).unwrap(),
+                amount: U128::from(1_000),
+                amount0: U256::from(10),
+                amount1: U256::from(20),
+            })
+            .unwrap()
+        };
+        let burn = |block, index| {
+            BurnEventRaw::try_from(BurnEvent {
+                transaction_hash: tx_hash(block, index),
+                log_index: index,
+                contract_address: pool,
+                owner: Address::repeat_byte(0x42),
+                tick_lower: I24::try_from(-60).unwrap(),
+                tick_upper: I24::try_from(60).unwrap(),
+                amount: U128::from(1_000),
+                amount0: U256::from(10),
+                amount1: U256::from(20),
+            })
+            .unwrap()
+        };
+
+        for number in [5_400, 5_401, 5_402] {
+            // the later transaction's events come first, the queries order them
+            let (mints, burns) = match number {
+                5_400 => (vec![mint(number, 1)], Vec::new()),
+                5_401 => (Vec::new(), vec![burn(number, 1), burn(number, 0)]),
+                _ => (vec![mint(number, 0)], vec![burn(number, 1)]),
+            };
+            insert_block_events(
+                block(number),
+                vec![transaction(number, 1), transaction(number, 0)],
+                Vec::new(),
+                vec![
+                    swap(number, 1, pool, 10, -20, U160::from(1) << 96),
+                    swap(number, 0, pool, 10, -20, U160::from(1) << 96),
+                    swap(number, 0, other_pool, 10, -20, U160::from(1) << 96),
+                ],
+                Vec::new(),
+                mints,
+                burns,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                &mut conn,
+                None,
+                IsolationLevel::default(),
+            )
+            .unwrap();
+        }
+
+        let positions = |events: Vec<(u64, u64, TxHash)>| {
+            events
+                .into_iter()
+                .map(|(block_number, block_timestamp, hash)| {
+                    assert_eq!(block_timestamp, 1_700_000_000 + block_number * 12);
+                    let index = (0..2).find(|&index| tx_hash(block_number, index) == hash);
+                    (block_number, index.unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the range ends before `to_block`
+        let swaps = query_swaps(pool, 5_400, 5_402, &mut conn).unwrap();
+        assert!(swaps.iter().all(|swap| swap.event.contract_address == pool));
+        assert_eq!(
+            positions(
+                swaps
+                    .iter()
+                    .map(|swap| (swap.block_number, swap.block_timestamp, swap.event.transaction_hash))
+                    .collect()
+            ),
+            [(5_400, 0), (5_400, 1), (5_401, 0), (5_401, 1)]
+        );
+
+        let mints = query_mints(pool, 5_400, 5_403, &mut conn).unwrap();
+        assert_eq!(
+            positions(
+                mints
+                    .iter()
+                    .map(|mint| (mint.block_number, mint.block_timestamp, mint.event.transaction_hash))
+                    .collect()
+            ),
+            [(5_400, 1), (5_402, 0)]
+        );
+        assert_eq!(mints[0].event.tick_lower, I24::try_from(-60).unwrap());
+        assert_eq!(mints[0].event.amount, U128::from(1_000));
+
+        let burns = query_burns(pool, 5_401, 5_403, &mut conn).unwrap();
+        assert_eq!(
+            positions(
+                burns
+                    .iter()
+                    .map(|burn| (burn.block_number, burn.block_timestamp, burn.event.transaction_hash))
+                    .collect()
+            ),
+            [(5_401, 0), (5_401, 1), (5_402, 1)]
+        );
+
+        assert!(query_mints(other_pool, 5_400, 5_403, &mut conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Deletes a committed block when dropped, so a failed test doesn't leave it behind
+    pub(crate) struct CommittedBlock(pub(crate) u64);
+
+    impl Drop for CommittedBlock {
+        fn drop(&mut self) {
+            let mut conn = establish_connection().unwrap();
+            delete_block_events(self.0, &mut conn, None).unwrap();
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn protocol_fees_use_the_setting_in_effect_at_each_swap() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x57);
+        // 10,000 USDC for 2.5 WETH and back, through a 0.05% pool
+        let usdc = 10_000_000_000;
+        let weth = 2_500_000_000_000_000_000;
+        let price = U160::from(1) << 96;
+        let set_fee_protocol = SetFeeProtocolEventRaw::try_from(SetFeeProtocolEvent {
+            transaction_hash: tx_hash(5_700, 2),
+            log_index: 2,
+            contract_address: pool,
+            fee_protocol0_old: 0,
+            fee_protocol1_old: 0,
+            fee_protocol0_new: 4,
+            fee_protocol1_new: 5,
+        })
+        .unwrap();
+        let pool_create = PoolCreateEventRaw::try_from(PoolCreateEvent {
+            transaction_hash: tx_hash(5_700, 0),
+            log_index: 0,
+            token0: Address::repeat_byte(0x01),
+            token1: Address::repeat_byte(0x02),
+            fee: U24::from(500),
+            tick_spacing: I24::try_from(10).unwrap(),
+            pool,
+        })
+        .unwrap();
+
+        for number in [5_700, 5_701] {
+            let (transactions, pool_create_events, swaps, set_fee_protocol_events) = match number {
+                5_700 => (
+                    (0..4).map(|index| transaction(number, index)).collect(),
+                    vec![pool_create.clone()],
+                    vec![
+                        swap(number, 1, pool, usdc, -weth, price),
+                        swap(number, 3, pool, usdc, -weth, price),
+                    ],
+                    vec![set_fee_protocol.clone()],
+                ),
+                _ => (
+                    vec![transaction(number, 0)],
+                    Vec::new(),
+                    vec![swap(number, 0, pool, -usdc, weth, price)],
+                    Vec::new(),
+                ),
+            };
+            insert_block_events(
+                block(number),
+                transactions,
+                pool_create_events,
+                swaps,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                set_fee_protocol_events,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                &mut conn,
+                None,
+                IsolationLevel::default(),
+            )
+            .unwrap();
+        }
+
+        let fees: Vec<(u64, (U256, U256))> = query_swap_protocol_fees(pool, 5_700, 5_702, &mut conn)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|(swap, fee)| (swap.event.log_index, fee))
+            .collect();
+        assert_eq!(
+            fees,
+            [
+                // before the fee switch
+                (1, (U256::ZERO, U256::ZERO)),
+                // 5 USDC of fees, a quarter of it to the protocol
+                (3, (U256::from(1_250_000), U256::ZERO)),
+                // 0.00125 WETH of fees, a fifth of it to the protocol
+                (0, (U256::ZERO, U256::from(250_000_000_000_000u64))),
+            ]
+        );
+        assert!(
+            query_swap_protocol_fees(Address::repeat_byte(0x58), 5_700, 5_702, &mut conn)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn inferred_pools_are_enriched_by_their_pool_created() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x61);
+        let pool_create = |token1| {
+            PoolCreateEventRaw::try_from(PoolCreateEvent {
+                transaction_hash: tx_hash(5_800, 0),
+                log_index: 0,
+                token0: Address::repeat_byte(0x01),
+                token1,
+                fee: U24::from(3000),
+                tick_spacing: I24::try_from(60).unwrap(),
+                pool,
+            })
+            .unwrap()
+        };
+        let stored_pool = |conn: &mut PgConnection| {
+            use crate::pool_sql::schema::pools;
+
+            pools::table
+                .find(pool.to_vec())
+                .select(PoolRaw::as_select())
+                .first(conn)
+                .optional()
+                .unwrap()
+        };
+
+        // live tracking saw the pool's events before a backfill reached its creation
+        insert_swaps(block(5_800), Vec::new(), Vec::new(), Vec::new(), &mut conn, None).unwrap();
+        insert_block_events(
+            block(5_801),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![InferredPoolRaw {
+                pool: pool.to_vec(),
+                first_seen_block: 5_801,
+            }],
+            Vec::new(),
+            Vec::new(),
+            &mut conn,
+            None,
+            IsolationLevel::default(),
+        )
+        .unwrap();
+        assert!(stored_pool(&mut conn).is_none());
+
+        let created = pool_create(Address::repeat_byte(0x02));
+        assert_eq!(
+            reconcile_pool(&created, 5_800, &mut conn).unwrap(),
+            PoolReconciliation::Enriched
+        );
+        let stored = stored_pool(&mut conn).unwrap();
+        assert_eq!(stored.token0, created.token0);
+        assert_eq!(stored.token1, created.token1);
+        assert_eq!(stored.fee, BigDecimal::from(3000));
+        assert_eq!(
+            reconcile_pool(&created, 5_800, &mut conn).unwrap(),
+            PoolReconciliation::Unchanged
+        );
+
+        // the event wins over a stored pool with other tokens
+        let conflicting = pool_create(Address::repeat_byte(0x03));
+        assert_eq!(
+            reconcile_pool(&conflicting, 5_800, &mut conn).unwrap(),
+            PoolReconciliation::Conflict
+        );
+        assert_eq!(stored_pool(&mut conn).unwrap().token1, conflicting.token1);
+
+        let mut unknown = pool_create(Address::repeat_byte(0x02));
+        unknown.pool = Address::repeat_byte(0x62).to_vec();
+        assert_eq!(
+            reconcile_pool(&unknown, 5_800, &mut conn).unwrap(),
+            PoolReconciliation::New
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn separate_swap_connection_commits_the_swaps_on_their_own() {
+        // the swap connection has to see the committed transactions, so nothing here runs in a
+        // test transaction
+        migrate_once();
+        let mut conn = establish_connection().unwrap();
+        let mut swap_conn = establish_connection().unwrap();
+        let number = 4_000_000_000;
+        let _cleanup = CommittedBlock(number);
+        let pool = Address::repeat_byte(0x11);
+        let mut stored_block = block(number);
+        stored_block.content_hash = Some(B256::repeat_byte(0xcc).to_vec());
+
+        // a swap of a transaction that isn't stored fails its own write only
+        let orphan_swap = swap(number, 1, pool, 10, -20, U160::from(1) << 96);
+        let error = insert_swaps(
+            stored_block.clone(),
+            vec![transaction(number, 0)],
+            vec![orphan_swap],
+            Vec::new(),
+            &mut conn,
+            Some(&mut swap_conn),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("stored without its swaps"));
+        assert!(BlockRaw::find_by_number(number as i64, &mut conn)
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            BlockRaw::find_content_hash(number as i64, &mut conn).unwrap(),
+            None
+        );
+
+        // inserting the block again skips what is stored and completes it
+        let counts = insert_swaps(
+            stored_block,
+            vec![transaction(number, 0)],
+            vec![swap(number, 0, pool, 10, -20, U160::from(1) << 96)],
+            Vec::new(),
+            &mut conn,
+            Some(&mut swap_conn),
+        )
+        .unwrap();
+        assert_eq!((counts.inserted, counts.total), (1, 1));
+        assert_eq!(
+            BlockRaw::find_content_hash(number as i64, &mut conn).unwrap(),
+            Some(B256::repeat_byte(0xcc))
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn migrations_are_idempotent() {
+        let mut conn = test_connection();
+
+        assert_eq!(run_migrations(&mut conn).unwrap(), 0);
+        assert!(pending_migrations(&mut conn).unwrap().is_empty());
+        assert!(latest_applied_migration(&mut conn).unwrap().is_some());
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn pool_is_found_with_its_tokens_in_either_order() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x61);
+        let token0 = Address::repeat_byte(0x62);
+        let token1 = Address::repeat_byte(0x63);
+        insert_pools(5_960, &[(pool, token0, token1, 3000)], &mut conn).unwrap();
+
+        for (token_a, token_b) in [(token0, token1), (token1, token0)] {
+            assert_eq!(
+                find_pool_by_tokens_and_fee(token_a, token_b, U24::from(3000), &mut conn).unwrap(),
+                Some(pool)
+            );
+        }
+        assert_eq!(
+            find_pool_by_tokens_and_fee(token0, token1, U24::from(500), &mut conn).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn pair_is_broken_down_by_fee_tier() {
+        let mut conn = test_connection();
+        let medium = Address::repeat_byte(0x64);
+        let low = Address::repeat_byte(0x65);
+        let token0 = Address::repeat_byte(0x66);
+        let token1 = Address::repeat_byte(0x67);
+        let price = U160::from(1) << 96;
+        insert_pools(
+            5_961,
+            &[(medium, token0, token1, 3000), (low, token0, token1, 500)],
+            &mut conn,
+        )
+        .unwrap();
+        // before the summarized range
+        insert_swaps(
+            block(5_962),
+            vec![transaction(5_962, 0)],
+            vec![swap(5_962, 0, medium, 1_000, -1_000, price)],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        let swaps = vec![
+            swap(5_963, 0, low, 10, -20, price),
+            swap(5_963, 1, low, -5, 8, price),
+        ];
+        let pool_states = PoolCurrentStateRaw::latest_in_block(5_963, &swaps, &[]);
+        insert_swaps(
+            block(5_963),
+            vec![transaction(5_963, 0), transaction(5_963, 1)],
+            swaps,
+            pool_states,
+            &mut conn,
+            None,
+        )
+        .unwrap();
+
+        let summaries = pools_for_pair(token1, token0, 5_963, &mut conn).unwrap();
+        let summaries: Vec<_> = summaries
+            .iter()
+            .map(|summary| {
+                (
+                    summary.pool,
+                    summary.fee.to_string(),
+                    summary.swap_count,
+                    summary.volume0.to_string(),
+                    summary.volume1.to_string(),
+                    summary.liquidity.as_ref().map(ToString::to_string),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summaries,
+            [
+                (
+                    low,
+                    "500".to_string(),
+                    2,
+                    "15".to_string(),
+                    "28".to_string(),
+                    Some("1000000".to_string())
+                ),
+                (
+                    medium,
+                    "3000".to_string(),
+                    0,
+                    "0".to_string(),
+                    "0".to_string(),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn compaction_appends_the_new_swaps_with_their_block_fields() {
+        use crate::pool_sql::schema::pool_swaps_wide;
+
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x68);
+        insert_swaps(
+            block(5_964),
+            vec![transaction(5_964, 0), transaction(5_964, 1)],
+            vec![
+                swap(5_964, 0, pool, 10, -20, U160::from(1) << 96),
+                swap(5_964, 1, pool, -5, 10, U160::from(2) << 96),
+            ],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        assert_eq!(compact_pool_swaps(pool, &mut conn).unwrap(), 2);
+        assert_eq!(compact_pool_swaps(pool, &mut conn).unwrap(), 0);
+
+        // only the swaps after the last compacted one are appended
+        insert_swaps(
+            block(5_965),
+            vec![transaction(5_965, 0)],
+            vec![swap(5_965, 0, pool, 3, -6, U160::from(1) << 96)],
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        assert_eq!(compact_pool_swaps(pool, &mut conn).unwrap(), 1);
+
+        let rows: Vec<PoolSwapWideRaw> = pool_swaps_wide::table
+            .filter(pool_swaps_wide::pool.eq(pool.to_vec()))
+            .order(pool_swaps_wide::swap_id.asc())
+            .select(PoolSwapWideRaw::as_select())
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|row| (
+                    TxHash::from_slice(&row.transaction_hash),
+                    row.block_number,
+                    row.block_timestamp,
+                    row.transaction_index,
+                    row.zero_for_one,
+                    row.amount0.to_string(),
+                    row.price.to_string(),
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    tx_hash(5_964, 0),
+                    5_964,
+                    1_700_071_568,
+                    0,
+                    true,
+                    "10".to_string(),
+                    "1".to_string()
+                ),
+                (
+                    tx_hash(5_964, 1),
+                    5_964,
+                    1_700_071_568,
+                    1,
+                    false,
+                    "-5".to_string(),
+                    "4".to_string()
+                ),
+                (
+                    tx_hash(5_965, 0),
+                    5_965,
+                    1_700_071_580,
+                    0,
+                    true,
+                    "3".to_string(),
+                    "1".to_string()
+                ),
+            ]
+        );
+        assert!(rows.iter().all(|row| row.transaction_sender == [0x42; 20]));
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn consumers_keep_their_own_cursors() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x69);
+        let other_pool = Address::repeat_byte(0x6a);
+
+        set_consumer_cursor("indexer", pool, "swap_events", 10, &mut conn).unwrap();
+        set_consumer_cursor("dashboard", pool, "swap_events", 4, &mut conn).unwrap();
+        // acknowledging an older id doesn't move the cursor back
+        set_consumer_cursor("indexer", pool, "swap_events", 7, &mut conn).unwrap();
+
+        let cursor = |consumer, pool, table, conn: &mut PgConnection| {
+            get_consumer_cursor(consumer, pool, table, conn).unwrap()
+        };
+        assert_eq!(cursor("indexer", pool, "swap_events", &mut conn), 10);
+        assert_eq!(cursor("dashboard", pool, "swap_events", &mut conn), 4);
+        assert_eq!(cursor("indexer", pool, "mint_events", &mut conn), 0);
+        assert_eq!(cursor("indexer", other_pool, "swap_events", &mut conn), 0);
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn pool_swaps_are_read_completely_in_batches() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x6b);
+        let other_pool = Address::repeat_byte(0x6c);
+        let rows = 2_500;
+        insert_swaps(
+            block(5_966),
+            (0..rows).map(|index| transaction(5_966, index)).collect(),
+            (0..rows)
+                .map(|index| {
+                    // every tenth swap is another pool's
+                    let swap_pool = if index % 10 == 9 { other_pool } else { pool };
+                    swap(5_966, index, swap_pool, 1, -1, U160::from(1) << 96)
+                })
+                .collect(),
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+
+        let batches: Vec<Vec<SwapEventRaw>> = swaps_for_pool_iter(pool, 1_000, &mut conn)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            [1_000, 1_000, 250]
+        );
+        let log_indices: Vec<i64> = batches
+            .iter()
+            .flatten()
+            .map(|swap| swap.log_index)
+            .collect();
+        let expected: Vec<i64> = (0..rows as i64).filter(|index| index % 10 != 9).collect();
+        assert_eq!(log_indices, expected);
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn burning_more_than_was_minted_is_a_violation() {
+        let mut conn = test_connection();
+        let valid_pool = Address::repeat_byte(0x6d);
+        let over_burned_pool = Address::repeat_byte(0x6e);
+        let mint = |block, index, pool, amount| {
+            MintEventRaw::try_from(MintEvent {
+                transaction_hash: tx_hash(block, index),
+                log_index: index,
+                contract_address: pool,
+                sender: Address::repeat_byte(0x42),
+                owner: Address::repeat_byte(0x42),
+                tick_lower: I24::try_from(-60).unwrap(),
+                tick_upper: I24::try_from(60).unwrap(),
+                amount: U128::from(amount),
+                amount0: U256::from(10),
+                amount1: U256::from(20),
+            })
+            .unwrap()
+        };
+        let burn = |block, index, pool, amount| {
+            BurnEventRaw::try_from(BurnEvent {
+                transaction_hash: tx_hash(block, index),
+                log_index: index,
+                contract_address: pool,
+                owner: Address::repeat_byte(0x42),
+                tick_lower: I24::try_from(-60).unwrap(),
+                tick_upper: I24::try_from(60).unwrap(),
+                amount: U128::from(amount),
+                amount0: U256::from(10),
+                amount1: U256::from(20),
+            })
+            .unwrap()
+        };
+
+        for number in [5_967, 5_968] {
+            let (mints, burns) = match number {
+                5_967 => (
+                    vec![mint(number, 0, valid_pool, 1_000)],
+                    vec![burn(number, 1, valid_pool, 600)],
+                ),
+                _ => (
+                    vec![mint(number, 0, over_burned_pool, 500)],
+                    vec![
+                        burn(number, 1, valid_pool, 400),
+                        burn(number, 2, over_burned_pool, 800),
+                    ],
+                ),
+            };
+            insert_block_events(
+                block(number),
+                (0..3).map(|index| transaction(number, index)).collect(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                mints,
+                burns,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                &mut conn,
+                None,
+                IsolationLevel::default(),
+            )
+            .unwrap();
+        }
+
+        let violations: Vec<PositionViolation> = validate_position_liquidity(&mut conn)
+            .unwrap()
+            .into_iter()
+            .filter(|violation| [valid_pool, over_burned_pool].contains(&violation.pool))
+            .collect();
+        assert_eq!(violations.len(), 1);
+        let violation = &violations[0];
+        assert_eq!(violation.pool, over_burned_pool);
+        assert_eq!(
+            (violation.block_number, violation.transaction_hash),
+            (5_968, tx_hash(5_968, 2))
+        );
+        assert_eq!(violation.liquidity, BigDecimal::from(-300));
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn pools_with_swaps_but_no_initialize_are_reported() {
+        let mut conn = test_connection();
+        let initialized = Address::repeat_byte(0x6f);
+        let uninitialized = Address::repeat_byte(0x70);
+        let inactive = Address::repeat_byte(0x71);
+        let token0 = Address::repeat_byte(0x72);
+        let token1 = Address::repeat_byte(0x73);
+        insert_pools(
+            5_969,
+            &[
+                (initialized, token0, token1, 500),
+                (uninitialized, token0, token1, 3000),
+                (inactive, token0, token1, 10000),
+            ],
+            &mut conn,
+        )
+        .unwrap();
+        let price = U160::from(1) << 96;
+        insert_block_events(
+            block(5_970),
+            (0..3).map(|index| transaction(5_970, index)).collect(),
+            Vec::new(),
+            vec![
+                swap(5_970, 1, initialized, 10, -20, price),
+                swap(5_970, 2, uninitialized, 10, -20, price),
+            ],
+            vec![InitializationEventRaw::try_from(InitializationEvent {
+                transaction_hash: tx_hash(5_970, 0),
+                log_index: 0,
+                contract_address: initialized,
+                creator: Address::repeat_byte(0x42),
+                sqrt_price_x96: price,
+                tick: I24::ZERO,
+            })
+            .unwrap()],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            &mut conn,
+            None,
+            IsolationLevel::default(),
+        )
+        .unwrap();
+
+        let missing: Vec<Address> = pools_missing_initialization(&mut conn)
+            .unwrap()
+            .into_iter()
+            .filter(|pool| [initialized, uninitialized, inactive].contains(pool))
+            .collect();
+        assert_eq!(missing, [uninitialized]);
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn transactions_with_out_of_order_logs_are_reported() {
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x74);
+        // the `index`th transaction's swap at block wide log index `log_index`
+        let swap_at = |number, index, log_index| {
+            let mut swap = swap(number, index, pool, 10, -20, U160::from(1) << 96);
+            swap.log_index = log_index;
+            swap
+        };
+        for (number, log_indices) in [(5_971, [0, 1, 2]), (5_972, [1, 3, 2])] {
+            // the last transaction has no events
+            insert_swaps(
+                block(number),
+                (0..3).map(|index| transaction(number, index)).collect(),
+                vec![
+                    swap_at(number, 0, log_indices[0]),
+                    swap_at(number, 0, log_indices[1]),
+                    swap_at(number, 1, log_indices[2]),
+                ],
+                Vec::new(),
+                &mut conn,
+                None,
+            )
+            .unwrap();
+        }
+
+        let ordered = transaction_order(5_971, &mut conn).unwrap();
+        assert_eq!(
+            ordered
+                .iter()
+                .map(|transaction| (
+                    transaction.transaction_hash,
+                    transaction.transaction_index,
+                    transaction.log_indices.clone()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (tx_hash(5_971, 0), 0, vec![0, 1]),
+                (tx_hash(5_971, 1), 1, vec![2]),
+                (tx_hash(5_971, 2), 2, vec![]),
+            ]
+        );
+        assert!(log_order_violations(&ordered).is_empty());
+
+        // the first transaction's last log comes after the second one's first
+        let ordered = transaction_order(5_972, &mut conn).unwrap();
+        let violations = log_order_violations(&ordered);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|(earlier, later)| (earlier.transaction_index, later.transaction_index))
+                .collect::<Vec<_>>(),
+            [(0, 1)]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn completed_backfill_runs_are_reported_as_covered() {
+        let mut conn = test_connection();
+        // far past any block another run could have covered
+        let start = 6_000_000_000;
+        let ours = |range: &(i64, i64)| range.0 >= start as i64;
+
+        let first = start_backfill_run(start, start + 100, &mut conn).unwrap();
+        let report = coverage_report(&mut conn).unwrap();
+        assert!(!report.covered.iter().any(ours));
+        assert!(report
+            .unfinished
+            .iter()
+            .any(|run| run.id == first && run.status == "running"));
+
+        record_backfill_run(first, true, 42, &mut conn).unwrap();
+        let second = start_backfill_run(start + 200, start + 300, &mut conn).unwrap();
+        record_backfill_run(second, true, 7, &mut conn).unwrap();
+        let failed = start_backfill_run(start + 100, start + 200, &mut conn).unwrap();
+        record_backfill_run(failed, false, 0, &mut conn).unwrap();
+
+        let report = coverage_report(&mut conn).unwrap();
+        let start = start as i64;
+        assert_eq!(
+            report.covered.into_iter().filter(ours).collect::<Vec<_>>(),
+            [(start, start + 100), (start + 200, start + 300)]
+        );
+        assert_eq!(
+            report.gaps.into_iter().filter(ours).collect::<Vec<_>>(),
+            [(start + 100, start + 200)]
+        );
+        let unfinished: Vec<&BackfillRun> = report
+            .unfinished
+            .iter()
+            .filter(|run| [first, second, failed].contains(&run.id))
+            .collect();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(
+            (unfinished[0].id, unfinished[0].status.as_str()),
+            (failed, "failed")
+        );
+
+        let completed: BackfillRun = {
+            use crate::pool_sql::schema::backfill_runs::dsl::*;
+
+            backfill_runs
+                .filter(id.eq(first))
+                .select(BackfillRun::as_select())
+                .first(&mut conn)
+                .unwrap()
+        };
+        assert_eq!(completed.events_stored, 42);
+        assert!(completed.completed_at.is_some());
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn transactions_run_at_the_configured_isolation_level() {
+        use diesel::{
+            dsl::sql,
+            sql_types::Text,
+        };
+
+        let current_isolation = |conn: &mut PgConnection| -> Result<String> {
+            Ok(diesel::select(sql::<Text>("current_setting('transaction_isolation')"))
+                .get_result(conn)?)
+        };
+
+        // a test transaction would turn these into savepoints, so this one isn't in one
+        migrate_once();
+        let mut conn = establish_connection().unwrap();
+        for (isolation, expected) in [
+            (IsolationLevel::ReadCommitted, "read committed"),
+            (IsolationLevel::RepeatableRead, "repeatable read"),
+            (IsolationLevel::Serializable, "serializable"),
+        ] {
+            assert_eq!(
+                transaction_with_isolation(&mut conn, isolation, current_isolation).unwrap(),
+                expected
+            );
+        }
+
+        // a nested call keeps the outer transaction's level
+        let mut conn = test_connection();
+        assert_eq!(
+            transaction_with_isolation(&mut conn, IsolationLevel::Serializable, current_isolation)
+                .unwrap(),
+            "read committed"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    fn latest_swap_in_a_block_sets_the_pool_state() {
+        use crate::pool_sql::schema::pool_current_state;
+
+        let mut conn = test_connection();
+        let pool = Address::repeat_byte(0x75);
+        let other_pool = Address::repeat_byte(0x76);
+        let latest = U160::from(5) << 96;
+        // the provider's order isn't the log order
+        let swaps = vec![
+            swap(5_973, 2, pool, 10, -20, latest),
+            swap(5_973, 0, pool, 10, -20, U160::from(1) << 96),
+            swap(5_973, 1, other_pool, 10, -20, U160::from(2) << 96),
+        ];
+        let mut pool_states = PoolCurrentStateRaw::latest_in_block(5_973, &swaps, &[]);
+        pool_states.sort_by_key(|state| state.pool.clone());
+        assert_eq!(
+            pool_states
+                .iter()
+                .map(|state| (Address::from_slice(&state.pool), state.log_index))
+                .collect::<Vec<_>>(),
+            [(pool, 2), (other_pool, 1)]
+        );
+        insert_swaps(
+            block(5_973),
+            (0..3).map(|index| transaction(5_973, index)).collect(),
+            swaps,
+            pool_states,
+            &mut conn,
+            None,
+        )
+        .unwrap();
+
+        let stored: PoolCurrentStateRaw = pool_current_state::table
+            .filter(pool_current_state::pool.eq(pool.to_vec()))
+            .select(PoolCurrentStateRaw::as_select())
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!((stored.block_number, stored.log_index), (5_973, 2));
+        assert_eq!(
+            stored.sqrt_price_x96,
+            BigDecimal::from_str(&latest.to_string()).unwrap()
+        );
+
+        // a state from an earlier log doesn't replace it
+        let earlier = PoolCurrentStateRaw {
+            log_index: 0,
+            ..stored
+        };
+        assert!(!earlier.upsert_if_newer(&mut conn).unwrap());
+    }
 }
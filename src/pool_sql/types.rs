@@ -1,5 +1,6 @@
 use std::{
     convert::TryFrom,
+    fmt,
     str::FromStr,
 };
 
@@ -14,8 +15,10 @@ use alloy::{
             U256,
         },
         Address,
+        Bytes,
         Log as AbiLog,
         TxHash,
+        B256,
     },
     rpc::types::Log,
 };
@@ -35,27 +38,58 @@ use crate::{
         UniswapV3Pool::{
             Burn,
             Collect,
+            CollectProtocol,
+            Flash,
             Initialize,
             Mint,
+            SetFeeProtocol,
             Swap,
         },
     },
     pool_sql::schema::*,
+    pricing::{
+        base_unit_price,
+        price_from_sqrt_price_x96,
+    },
 };
 
+/// Render an integral `BigDecimal` as plain decimal digits.
+///
+/// `BigDecimal::to_string` can switch to scientific notation (e.g. `1e+20`) depending on how the
+/// value is scaled, which the alloy integer parsers reject. All stored numerics are integers, so
+/// any fractional part is dropped.
+pub fn integer_string(value: &BigDecimal) -> String {
+    value.with_scale(0).into_bigint_and_exponent().0.to_string()
+}
+
 #[derive(Debug, Queryable, Selectable, Insertable)]
 #[diesel(table_name = blocks)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct BlockRaw {
+pub struct BlockRaw {
     pub block_number: i64,
     pub block_timestamp: i64,
+    pub base_fee_per_gas: Option<BigDecimal>,
+    pub content_hash: Option<Vec<u8>>,
+    pub block_hash: Option<Vec<u8>>,
+    pub parent_hash: Option<Vec<u8>>,
 }
 
-#[derive(Debug)]
-pub(crate) struct Block {
+/// Timestamp stored for blocks fetched without their header, filled in by `backfill-timestamps`
+pub const MISSING_BLOCK_TIMESTAMP: u64 = 0;
+
+#[derive(Clone, Debug)]
+pub struct Block {
     pub block_number: u64,
     pub block_timestamp: u64,
+    /// `None` for blocks before EIP-1559
+    pub base_fee_per_gas: Option<u64>,
+    /// Hash of the events stored for the block, `None` until all of them are committed
+    pub content_hash: Option<B256>,
+    /// `None` for blocks stored before block hashes were
+    pub block_hash: Option<B256>,
+    /// `None` for blocks fetched without their header
+    pub parent_hash: Option<B256>,
 }
 
 impl TryFrom<BlockRaw> for Block {
@@ -65,6 +99,22 @@ impl TryFrom<BlockRaw> for Block {
         Ok(Self {
             block_number: raw.block_number as u64,
             block_timestamp: raw.block_timestamp as u64,
+            base_fee_per_gas: raw
+                .base_fee_per_gas
+                .map(|base_fee| integer_string(&base_fee).parse())
+                .transpose()?,
+            content_hash: raw
+                .content_hash
+                .map(|hash| B256::try_from(hash.as_slice()))
+                .transpose()?,
+            block_hash: raw
+                .block_hash
+                .map(|hash| B256::try_from(hash.as_slice()))
+                .transpose()?,
+            parent_hash: raw
+                .parent_hash
+                .map(|hash| B256::try_from(hash.as_slice()))
+                .transpose()?,
         })
     }
 }
@@ -76,27 +126,64 @@ impl TryFrom<Block> for BlockRaw {
         Ok(Self {
             block_number: block.block_number as i64,
             block_timestamp: block.block_timestamp as i64,
+            base_fee_per_gas: block.base_fee_per_gas.map(BigDecimal::from),
+            content_hash: block.content_hash.map(|hash| hash.to_vec()),
+            block_hash: block.block_hash.map(|hash| hash.to_vec()),
+            parent_hash: block.parent_hash.map(|hash| hash.to_vec()),
         })
     }
 }
 
+/// A `blocks_from` run over `[start_block, end_block)`, times are unix seconds
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = backfill_runs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct BackfillRun {
+    pub id: i64,
+    pub start_block: i64,
+    pub end_block: i64,
+    pub started_at: i64,
+    pub completed_at: Option<i64>,
+    pub events_stored: i64,
+    /// One of `running`, `completed` or `failed`
+    pub status: String,
+}
+
 #[derive(Debug, Queryable, Selectable, Insertable)]
 #[diesel(table_name = transactions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct TransactionRaw {
+pub struct TransactionRaw {
     pub transaction_hash: Vec<u8>,
     pub block_number: i64,
     pub transaction_index: i64,
     pub transaction_sender: Vec<u8>,
+    pub transaction_value: Option<BigDecimal>,
+    pub transaction_nonce: Option<i64>,
+    pub input_selector: Option<Vec<u8>>,
+    pub status: Option<bool>,
 }
 
-#[derive(Debug)]
-pub(crate) struct Transaction {
+#[derive(Clone, Debug)]
+pub struct Transaction {
     pub transaction_hash: TxHash,
     pub block_number: u64,
     pub transaction_index: u64,
     pub transaction_sender: Address,
+    /// Only known when the block was fetched with its full transactions
+    pub details: Option<TransactionDetails>,
+    /// Whether the transaction succeeded, `None` for transactions stored before it was recorded
+    pub status: Option<bool>,
+}
+
+/// Fields of a transaction that its receipt doesn't have
+#[derive(Clone, Debug)]
+pub struct TransactionDetails {
+    pub value: U256,
+    pub nonce: u64,
+    /// First 4 bytes of the input, or all of it if shorter
+    pub input_selector: Bytes,
 }
 
 impl TryFrom<TransactionRaw> for Transaction {
@@ -116,11 +203,28 @@ impl TryFrom<TransactionRaw> for Transaction {
         let transaction_sender = Address::try_from(raw.transaction_sender.as_slice())
             .map_err(|_| "Failed to convert sender address")?;
 
+        // the details are stored together, so they're either all there or all missing
+        let details = match (
+            raw.transaction_value,
+            raw.transaction_nonce,
+            raw.input_selector,
+        ) {
+            (Some(value), Some(nonce), Some(input_selector)) => Some(TransactionDetails {
+                value: U256::from_str(&integer_string(&value))
+                    .map_err(|_| "Failed to convert transaction value")?,
+                nonce: nonce as u64,
+                input_selector: input_selector.into(),
+            }),
+            _ => None,
+        };
+
         Ok(Self {
             transaction_hash,
             block_number: raw.block_number as u64,
             transaction_index: raw.transaction_index as u64,
             transaction_sender,
+            details,
+            status: raw.status,
         })
     }
 }
@@ -139,6 +243,15 @@ impl TryFrom<Transaction> for TransactionRaw {
             block_number: tx.block_number as i64,
             transaction_index: tx.transaction_index as i64,
             transaction_sender: tx.transaction_sender.to_vec(),
+            transaction_value: tx
+                .details
+                .as_ref()
+                .map(|details| BigDecimal::from_str(&details.value.to_string()))
+                .transpose()
+                .map_err(|_| "Failed to convert transaction value")?,
+            transaction_nonce: tx.details.as_ref().map(|details| details.nonce as i64),
+            input_selector: tx.details.map(|details| details.input_selector.to_vec()),
+            status: tx.status,
         })
     }
 }
@@ -147,7 +260,7 @@ impl TryFrom<Transaction> for TransactionRaw {
 #[diesel(table_name = pool_create_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct PoolCreateEventRaw {
+pub struct PoolCreateEventRaw {
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
     pub token0: Vec<u8>,
@@ -157,8 +270,8 @@ pub(crate) struct PoolCreateEventRaw {
     pub pool: Vec<u8>,
 }
 
-#[derive(Debug)]
-pub(crate) struct PoolCreateEvent {
+#[derive(Clone, Debug)]
+pub struct PoolCreateEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub token0: Address,
@@ -177,8 +290,8 @@ impl TryFrom<PoolCreateEventRaw> for PoolCreateEvent {
             log_index: raw.log_index as u64,
             token0: Address::try_from(raw.token0.as_slice())?,
             token1: Address::try_from(raw.token1.as_slice())?,
-            fee: U24::from_str(&raw.fee.to_string())?,
-            tick_spacing: I24::from_str(&raw.tick_spacing.to_string())?,
+            fee: U24::from_str(&integer_string(&raw.fee))?,
+            tick_spacing: I24::from_str(&integer_string(&raw.tick_spacing))?,
             pool: Address::try_from(raw.pool.as_slice())?,
         })
     }
@@ -190,7 +303,7 @@ impl TryFrom<PoolCreateEvent> for PoolCreateEventRaw {
     fn try_from(event: PoolCreateEvent) -> Result<Self, Self::Error> {
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             token0: event.token0.to_vec(),
             token1: event.token1.to_vec(),
             fee: BigDecimal::from_str(&event.fee.to_string())?,
@@ -204,7 +317,7 @@ impl TryFrom<PoolCreateEvent> for PoolCreateEventRaw {
 #[diesel(table_name = swap_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct SwapEventRaw {
+pub struct SwapEventRaw {
     #[diesel(serialize_as = Vec<u8>)]
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
@@ -219,17 +332,50 @@ pub(crate) struct SwapEventRaw {
     pub sqrt_price_x96: BigDecimal,
     pub liquidity: BigDecimal,
     pub tick: BigDecimal,
+    /// token1 per token0 in base units after the swap, `None` for swaps stored before the column
+    /// was added
+    pub price: Option<BigDecimal>,
+}
+
+/// Change of a pool's balance of one token in a swap, seen from the pool.
+///
+/// Positive means the token went into the pool (the swapper paid it), negative means it left
+/// the pool (the swapper received it). Stored as the signed value the pool emitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolDelta(pub I256);
+
+impl PoolDelta {
+    /// The pool received the token
+    pub fn is_inflow(self) -> bool {
+        self.0.is_positive()
+    }
+
+    /// The pool paid out the token
+    pub fn is_outflow(self) -> bool {
+        self.0.is_negative()
+    }
+
+    /// Amount of the token that moved, in either direction
+    pub fn abs_amount(self) -> U256 {
+        self.0.unsigned_abs()
+    }
 }
 
-#[derive(Debug)]
-pub(crate) struct SwapEvent {
+impl fmt::Display for PoolDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SwapEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub contract_address: Address,
     pub sender: Address,
     pub recipient: Address,
-    pub amount0: I256,
-    pub amount1: I256,
+    pub amount0: PoolDelta,
+    pub amount1: PoolDelta,
     pub sqrt_price_x96: U160,
     pub liquidity: U128,
     pub tick: I24,
@@ -257,18 +403,18 @@ impl TryFrom<SwapEventRaw> for SwapEvent {
             .map_err(|e| format!("Failed to convert recipient address: {}", e))?;
 
         // Convert BigDecimal to specific numeric types
-        let amount0 = I256::from_dec_str(&raw.amount0.to_string())
+        let amount0 = I256::from_dec_str(&integer_string(&raw.amount0))
             .map_err(|e| format!("Failed to convert amount0: {}", e))?;
-        let amount1 = I256::from_dec_str(&raw.amount1.to_string())
+        let amount1 = I256::from_dec_str(&integer_string(&raw.amount1))
             .map_err(|e| format!("Failed to convert amount1: {}", e))?;
 
-        let sqrt_price_x96 = U160::from_str(&raw.sqrt_price_x96.to_string())
+        let sqrt_price_x96 = U160::from_str(&integer_string(&raw.sqrt_price_x96))
             .map_err(|e| format!("Failed to convert sqrt_price_x96: {}", e))?;
 
-        let liquidity = U128::from_str(&raw.liquidity.to_string())
+        let liquidity = U128::from_str(&integer_string(&raw.liquidity))
             .map_err(|e| format!("Failed to convert liquidity: {}", e))?;
 
-        let tick = I24::from_dec_str(&raw.tick.to_string())
+        let tick = I24::from_dec_str(&integer_string(&raw.tick))
             .map_err(|e| format!("Failed to convert tick: {}", e))?;
 
         Ok(Self {
@@ -277,8 +423,8 @@ impl TryFrom<SwapEventRaw> for SwapEvent {
             contract_address,
             sender,
             recipient,
-            amount0,
-            amount1,
+            amount0: PoolDelta(amount0),
+            amount1: PoolDelta(amount1),
             sqrt_price_x96,
             liquidity,
             tick,
@@ -297,7 +443,7 @@ impl TryFrom<SwapEvent> for SwapEventRaw {
 
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             contract_address: event.contract_address.to_vec(),
             sender: event.sender.to_vec(),
             recipient: event.recipient.to_vec(),
@@ -311,6 +457,7 @@ impl TryFrom<SwapEvent> for SwapEventRaw {
                 .map_err(|e| format!("Failed to convert liquidity: {}", e))?,
             tick: BigDecimal::from_str(&event.tick.to_string())
                 .map_err(|e| format!("Failed to convert tick: {}", e))?,
+            price: Some(base_unit_price(event.sqrt_price_x96)),
         })
     }
 }
@@ -319,7 +466,7 @@ impl TryFrom<SwapEvent> for SwapEventRaw {
 #[diesel(table_name = initialization_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct InitializationEventRaw {
+pub struct InitializationEventRaw {
     #[diesel(serialize_as = Vec<u8>)]
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
@@ -331,8 +478,8 @@ pub(crate) struct InitializationEventRaw {
     pub tick: BigDecimal,
 }
 
-#[derive(Debug)]
-pub(crate) struct InitializationEvent {
+#[derive(Clone, Debug)]
+pub struct InitializationEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub contract_address: Address,
@@ -350,8 +497,8 @@ impl TryFrom<InitializationEventRaw> for InitializationEvent {
             log_index: raw.log_index as u64,
             contract_address: Address::try_from(raw.contract_address.as_slice())?,
             creator: Address::try_from(raw.creator.as_slice())?,
-            sqrt_price_x96: U160::from_str(&raw.sqrt_price_x96.to_string())?,
-            tick: I24::from_dec_str(&raw.tick.to_string())?,
+            sqrt_price_x96: U160::from_str(&integer_string(&raw.sqrt_price_x96))?,
+            tick: I24::from_dec_str(&integer_string(&raw.tick))?,
         })
     }
 }
@@ -362,7 +509,7 @@ impl TryFrom<InitializationEvent> for InitializationEventRaw {
     fn try_from(event: InitializationEvent) -> Result<Self, Self::Error> {
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             contract_address: event.contract_address.to_vec(),
             creator: event.creator.to_vec(),
             sqrt_price_x96: BigDecimal::from_str(&event.sqrt_price_x96.to_string())?,
@@ -375,7 +522,7 @@ impl TryFrom<InitializationEvent> for InitializationEventRaw {
 #[diesel(table_name = mint_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct MintEventRaw {
+pub struct MintEventRaw {
     #[diesel(serialize_as = Vec<u8>)]
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
@@ -392,8 +539,8 @@ pub(crate) struct MintEventRaw {
     pub amount1: BigDecimal,
 }
 
-#[derive(Debug)]
-pub(crate) struct MintEvent {
+#[derive(Clone, Debug)]
+pub struct MintEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub contract_address: Address,
@@ -416,11 +563,11 @@ impl TryFrom<MintEventRaw> for MintEvent {
             contract_address: Address::try_from(raw.contract_address.as_slice())?,
             sender: Address::try_from(raw.sender.as_slice())?,
             owner: Address::try_from(raw.owner.as_slice())?,
-            tick_lower: I24::from_dec_str(&raw.tick_lower.to_string())?,
-            tick_upper: I24::from_dec_str(&raw.tick_upper.to_string())?,
-            amount: U128::from_str(&raw.amount.to_string())?,
-            amount0: U256::from_str(&raw.amount0.to_string())?,
-            amount1: U256::from_str(&raw.amount1.to_string())?,
+            tick_lower: I24::from_dec_str(&integer_string(&raw.tick_lower))?,
+            tick_upper: I24::from_dec_str(&integer_string(&raw.tick_upper))?,
+            amount: U128::from_str(&integer_string(&raw.amount))?,
+            amount0: U256::from_str(&integer_string(&raw.amount0))?,
+            amount1: U256::from_str(&integer_string(&raw.amount1))?,
         })
     }
 }
@@ -431,7 +578,7 @@ impl TryFrom<MintEvent> for MintEventRaw {
     fn try_from(event: MintEvent) -> Result<Self, Self::Error> {
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             contract_address: event.contract_address.to_vec(),
             sender: event.sender.to_vec(),
             owner: event.owner.to_vec(),
@@ -448,7 +595,7 @@ impl TryFrom<MintEvent> for MintEventRaw {
 #[diesel(table_name = burn_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct BurnEventRaw {
+pub struct BurnEventRaw {
     #[diesel(serialize_as = Vec<u8>)]
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
@@ -463,8 +610,8 @@ pub(crate) struct BurnEventRaw {
     pub amount1: BigDecimal,
 }
 
-#[derive(Debug)]
-pub(crate) struct BurnEvent {
+#[derive(Clone, Debug)]
+pub struct BurnEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub contract_address: Address,
@@ -485,11 +632,11 @@ impl TryFrom<BurnEventRaw> for BurnEvent {
             log_index: raw.log_index as u64,
             contract_address: Address::try_from(raw.contract_address.as_slice())?,
             owner: Address::try_from(raw.owner.as_slice())?,
-            tick_lower: I24::from_dec_str(&raw.tick_lower.to_string())?,
-            tick_upper: I24::from_dec_str(&raw.tick_upper.to_string())?,
-            amount: U128::from_str(&raw.amount.to_string())?,
-            amount0: U256::from_str(&raw.amount0.to_string())?,
-            amount1: U256::from_str(&raw.amount1.to_string())?,
+            tick_lower: I24::from_dec_str(&integer_string(&raw.tick_lower))?,
+            tick_upper: I24::from_dec_str(&integer_string(&raw.tick_upper))?,
+            amount: U128::from_str(&integer_string(&raw.amount))?,
+            amount0: U256::from_str(&integer_string(&raw.amount0))?,
+            amount1: U256::from_str(&integer_string(&raw.amount1))?,
         })
     }
 }
@@ -500,7 +647,7 @@ impl TryFrom<BurnEvent> for BurnEventRaw {
     fn try_from(event: BurnEvent) -> Result<Self, Self::Error> {
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             contract_address: event.contract_address.to_vec(),
             owner: event.owner.to_vec(),
             tick_lower: BigDecimal::from_str(&event.tick_lower.to_string())?,
@@ -516,7 +663,7 @@ impl TryFrom<BurnEvent> for BurnEventRaw {
 #[diesel(table_name = collect_events)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone)]
-pub(crate) struct CollectEventRaw {
+pub struct CollectEventRaw {
     #[diesel(serialize_as = Vec<u8>)]
     pub transaction_hash: Vec<u8>,
     pub log_index: i64,
@@ -532,8 +679,8 @@ pub(crate) struct CollectEventRaw {
     pub amount1: BigDecimal,
 }
 
-#[derive(Debug)]
-pub(crate) struct CollectEvent {
+#[derive(Clone, Debug)]
+pub struct CollectEvent {
     pub transaction_hash: TxHash,
     pub log_index: u64,
     pub contract_address: Address,
@@ -541,8 +688,8 @@ pub(crate) struct CollectEvent {
     pub recipient: Address,
     pub tick_lower: I24,
     pub tick_upper: I24,
-    pub amount0: U256,
-    pub amount1: U256,
+    pub amount0: U128,
+    pub amount1: U128,
 }
 
 impl TryFrom<CollectEventRaw> for CollectEvent {
@@ -555,10 +702,11 @@ impl TryFrom<CollectEventRaw> for CollectEvent {
             contract_address: Address::try_from(raw.contract_address.as_slice())?,
             owner: Address::try_from(raw.owner.as_slice())?,
             recipient: Address::try_from(raw.recipient.as_slice())?,
-            tick_lower: I24::from_dec_str(&raw.tick_lower.to_string())?,
-            tick_upper: I24::from_dec_str(&raw.tick_upper.to_string())?,
-            amount0: U256::from_str(&raw.amount0.to_string())?,
-            amount1: U256::from_str(&raw.amount1.to_string())?,
+            tick_lower: I24::from_dec_str(&integer_string(&raw.tick_lower))?,
+            tick_upper: I24::from_dec_str(&integer_string(&raw.tick_upper))?,
+            // `uint128` in the event, so a larger stored value fails instead of truncating
+            amount0: U128::from_str(&integer_string(&raw.amount0))?,
+            amount1: U128::from_str(&integer_string(&raw.amount1))?,
         })
     }
 }
@@ -569,7 +717,7 @@ impl TryFrom<CollectEvent> for CollectEventRaw {
     fn try_from(event: CollectEvent) -> Result<Self, Self::Error> {
         Ok(Self {
             transaction_hash: event.transaction_hash.to_vec(),
-            log_index: event.log_index as i64,
+            log_index: i64::try_from(event.log_index)?,
             contract_address: event.contract_address.to_vec(),
             owner: event.owner.to_vec(),
             recipient: event.recipient.to_vec(),
@@ -581,17 +729,540 @@ impl TryFrom<CollectEvent> for CollectEventRaw {
     }
 }
 
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = flash_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct FlashEventRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub contract_address: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub sender: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub recipient: Vec<u8>,
+    pub amount0: BigDecimal,
+    pub amount1: BigDecimal,
+    pub paid0: BigDecimal,
+    pub paid1: BigDecimal,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlashEvent {
+    pub transaction_hash: TxHash,
+    pub log_index: u64,
+    pub contract_address: Address,
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount0: U256,
+    pub amount1: U256,
+    /// Amount of token0 paid back, the flashed amount plus the fee or more
+    pub paid0: U256,
+    pub paid1: U256,
+}
+
+impl TryFrom<FlashEventRaw> for FlashEvent {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: FlashEventRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: TxHash::try_from(raw.transaction_hash.as_slice())?,
+            log_index: raw.log_index as u64,
+            contract_address: Address::try_from(raw.contract_address.as_slice())?,
+            sender: Address::try_from(raw.sender.as_slice())?,
+            recipient: Address::try_from(raw.recipient.as_slice())?,
+            amount0: U256::from_str(&integer_string(&raw.amount0))?,
+            amount1: U256::from_str(&integer_string(&raw.amount1))?,
+            paid0: U256::from_str(&integer_string(&raw.paid0))?,
+            paid1: U256::from_str(&integer_string(&raw.paid1))?,
+        })
+    }
+}
+
+impl TryFrom<FlashEvent> for FlashEventRaw {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(event: FlashEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: event.transaction_hash.to_vec(),
+            log_index: i64::try_from(event.log_index)?,
+            contract_address: event.contract_address.to_vec(),
+            sender: event.sender.to_vec(),
+            recipient: event.recipient.to_vec(),
+            amount0: BigDecimal::from_str(&event.amount0.to_string())?,
+            amount1: BigDecimal::from_str(&event.amount1.to_string())?,
+            paid0: BigDecimal::from_str(&event.paid0.to_string())?,
+            paid1: BigDecimal::from_str(&event.paid1.to_string())?,
+        })
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = collect_protocol_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct CollectProtocolEventRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub contract_address: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub sender: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub recipient: Vec<u8>,
+    pub amount0: BigDecimal,
+    pub amount1: BigDecimal,
+}
+
+#[derive(Clone, Debug)]
+pub struct CollectProtocolEvent {
+    pub transaction_hash: TxHash,
+    pub log_index: u64,
+    pub contract_address: Address,
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount0: U128,
+    pub amount1: U128,
+}
+
+impl TryFrom<CollectProtocolEventRaw> for CollectProtocolEvent {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: CollectProtocolEventRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: TxHash::try_from(raw.transaction_hash.as_slice())?,
+            log_index: raw.log_index as u64,
+            contract_address: Address::try_from(raw.contract_address.as_slice())?,
+            sender: Address::try_from(raw.sender.as_slice())?,
+            recipient: Address::try_from(raw.recipient.as_slice())?,
+            amount0: U128::from_str(&integer_string(&raw.amount0))?,
+            amount1: U128::from_str(&integer_string(&raw.amount1))?,
+        })
+    }
+}
+
+impl TryFrom<CollectProtocolEvent> for CollectProtocolEventRaw {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(event: CollectProtocolEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: event.transaction_hash.to_vec(),
+            log_index: i64::try_from(event.log_index)?,
+            contract_address: event.contract_address.to_vec(),
+            sender: event.sender.to_vec(),
+            recipient: event.recipient.to_vec(),
+            amount0: BigDecimal::from_str(&event.amount0.to_string())?,
+            amount1: BigDecimal::from_str(&event.amount1.to_string())?,
+        })
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = set_fee_protocol_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct SetFeeProtocolEventRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub contract_address: Vec<u8>,
+    pub fee_protocol0_old: BigDecimal,
+    pub fee_protocol1_old: BigDecimal,
+    pub fee_protocol0_new: BigDecimal,
+    pub fee_protocol1_new: BigDecimal,
+}
+
+/// A change of the pool's protocol fees. Each fee is the denominator of the share of swap fees
+/// the protocol takes, 0 meaning none.
+#[derive(Clone, Debug)]
+pub struct SetFeeProtocolEvent {
+    pub transaction_hash: TxHash,
+    pub log_index: u64,
+    pub contract_address: Address,
+    pub fee_protocol0_old: u8,
+    pub fee_protocol1_old: u8,
+    pub fee_protocol0_new: u8,
+    pub fee_protocol1_new: u8,
+}
+
+impl TryFrom<SetFeeProtocolEventRaw> for SetFeeProtocolEvent {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: SetFeeProtocolEventRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: TxHash::try_from(raw.transaction_hash.as_slice())?,
+            log_index: raw.log_index as u64,
+            contract_address: Address::try_from(raw.contract_address.as_slice())?,
+            fee_protocol0_old: integer_string(&raw.fee_protocol0_old).parse()?,
+            fee_protocol1_old: integer_string(&raw.fee_protocol1_old).parse()?,
+            fee_protocol0_new: integer_string(&raw.fee_protocol0_new).parse()?,
+            fee_protocol1_new: integer_string(&raw.fee_protocol1_new).parse()?,
+        })
+    }
+}
+
+impl TryFrom<SetFeeProtocolEvent> for SetFeeProtocolEventRaw {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(event: SetFeeProtocolEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: event.transaction_hash.to_vec(),
+            log_index: i64::try_from(event.log_index)?,
+            contract_address: event.contract_address.to_vec(),
+            fee_protocol0_old: BigDecimal::from(event.fee_protocol0_old),
+            fee_protocol1_old: BigDecimal::from(event.fee_protocol1_old),
+            fee_protocol0_new: BigDecimal::from(event.fee_protocol0_new),
+            fee_protocol1_new: BigDecimal::from(event.fee_protocol1_new),
+        })
+    }
+}
+
+/// Protocol fee setting of a pool, for swaps paying in token0 and in token1. Each is the
+/// denominator of the share of swap fees the protocol takes, 0 meaning none, which is how pools
+/// start out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeProtocol {
+    pub token0: u8,
+    pub token1: u8,
+}
+
+impl SetFeeProtocolEvent {
+    /// The setting the event put in effect
+    pub fn new_fee_protocol(&self) -> FeeProtocol {
+        FeeProtocol {
+            token0: self.fee_protocol0_new,
+            token1: self.fee_protocol1_new,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = raw_logs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct RawLogRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub contract_address: Vec<u8>,
+    pub topics: Vec<Vec<u8>>,
+    pub data: Vec<u8>,
+}
+
+/// A log that isn't decoded, captured because its first topic was configured as an extra topic
+#[derive(Clone, Debug)]
+pub struct RawLog {
+    pub transaction_hash: TxHash,
+    pub log_index: u64,
+    pub contract_address: Address,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+}
+
+impl TryFrom<RawLogRaw> for RawLog {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: RawLogRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: TxHash::try_from(raw.transaction_hash.as_slice())?,
+            log_index: raw.log_index as u64,
+            contract_address: Address::try_from(raw.contract_address.as_slice())?,
+            topics: raw
+                .topics
+                .iter()
+                .map(|topic| B256::try_from(topic.as_slice()))
+                .collect::<Result<_, _>>()?,
+            data: Bytes::from(raw.data),
+        })
+    }
+}
+
+impl TryFrom<RawLog> for RawLogRaw {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(event: RawLog) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_hash: event.transaction_hash.to_vec(),
+            log_index: i64::try_from(event.log_index)?,
+            contract_address: event.contract_address.to_vec(),
+            topics: event.topics.iter().map(|topic| topic.to_vec()).collect(),
+            data: event.data.to_vec(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = inferred_pools)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct InferredPoolRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub pool: Vec<u8>,
+    pub first_seen_block: i64,
+}
+
+/// A created pool with its pair and fee tier
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pools)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PoolRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub pool: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub token0: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub token1: Vec<u8>,
+    pub fee: BigDecimal,
+    pub tick_spacing: BigDecimal,
+    pub created_block: i64,
+}
+
+impl PoolRaw {
+    /// The pool created by `event`, in block `created_block`
+    pub fn from_create_event(event: &PoolCreateEventRaw, created_block: i64) -> Self {
+        Self {
+            pool: event.pool.clone(),
+            token0: event.token0.clone(),
+            token1: event.token1.clone(),
+            fee: event.fee.clone(),
+            tick_spacing: event.tick_spacing.clone(),
+            created_block,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pool {
+    pub pool: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: U24,
+    pub tick_spacing: I24,
+    pub created_block: u64,
+}
+
+impl TryFrom<PoolRaw> for Pool {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: PoolRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pool: Address::try_from(raw.pool.as_slice())?,
+            token0: Address::try_from(raw.token0.as_slice())?,
+            token1: Address::try_from(raw.token1.as_slice())?,
+            fee: U24::from_str(&integer_string(&raw.fee))?,
+            tick_spacing: I24::from_str(&integer_string(&raw.tick_spacing))?,
+            created_block: u64::try_from(raw.created_block)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TokenRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub address: Vec<u8>,
+    pub decimals: Option<i16>,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+}
+
+/// ERC-20 metadata of a token, each field `None` if the token's call for it reverted or returned
+/// something that doesn't decode
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub address: Address,
+    pub decimals: Option<u8>,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+}
+
+impl TryFrom<TokenRaw> for Token {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: TokenRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            address: Address::try_from(raw.address.as_slice())?,
+            decimals: raw.decimals.map(u8::try_from).transpose()?,
+            symbol: raw.symbol,
+            name: raw.name,
+        })
+    }
+}
+
+impl From<Token> for TokenRaw {
+    fn from(token: Token) -> Self {
+        Self {
+            address: token.address.to_vec(),
+            decimals: token.decimals.map(i16::from),
+            symbol: token.symbol,
+            name: token.name,
+        }
+    }
+}
+
+/// A pool's state after its latest stored swap or initialization
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pool_current_state)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PoolCurrentStateRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub pool: Vec<u8>,
+    pub block_number: i64,
+    pub log_index: i64,
+    pub sqrt_price_x96: BigDecimal,
+    pub tick: BigDecimal,
+    pub liquidity: BigDecimal,
+}
+
+/// A pool's price, tick and liquidity after its latest stored swap or initialization
+#[derive(Clone, Debug)]
+pub struct PoolState {
+    pub pool: Address,
+    pub sqrt_price_x96: U160,
+    pub tick: I24,
+    pub liquidity: U128,
+    /// Block of the swap or initialization the state is from
+    pub last_updated_block: u64,
+    pub log_index: u64,
+}
+
+impl TryFrom<PoolCurrentStateRaw> for PoolState {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: PoolCurrentStateRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pool: Address::try_from(raw.pool.as_slice())?,
+            sqrt_price_x96: U160::from_str(&integer_string(&raw.sqrt_price_x96))?,
+            tick: I24::from_dec_str(&integer_string(&raw.tick))?,
+            liquidity: U128::from_str(&integer_string(&raw.liquidity))?,
+            last_updated_block: raw.block_number as u64,
+            log_index: raw.log_index as u64,
+        })
+    }
+}
+
+impl PoolState {
+    /// token1 per token0 in whole tokens, given the decimals of the pool's tokens
+    pub fn price(&self, decimals0: u8, decimals1: u8) -> BigDecimal {
+        price_from_sqrt_price_x96(self.sqrt_price_x96, decimals0, decimals1)
+    }
+}
+
+/// A swap of a compacted pool with its transaction and block fields and its derived price
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pool_swaps_wide)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PoolSwapWideRaw {
+    /// `id` of the swap in `swap_events`
+    pub swap_id: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub pool: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_hash: Vec<u8>,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub block_timestamp: i64,
+    pub transaction_index: i64,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub transaction_sender: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub sender: Vec<u8>,
+    #[diesel(serialize_as = Vec<u8>)]
+    pub recipient: Vec<u8>,
+    pub amount0: BigDecimal,
+    pub amount1: BigDecimal,
+    pub sqrt_price_x96: BigDecimal,
+    /// token1 per token0 in base units after the swap
+    pub price: BigDecimal,
+    /// Whether token0 was paid in for token1
+    pub zero_for_one: bool,
+    pub liquidity: BigDecimal,
+    pub tick: BigDecimal,
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pool_snapshots)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone)]
+pub struct PoolSnapshotRaw {
+    #[diesel(serialize_as = Vec<u8>)]
+    pub pool: Vec<u8>,
+    pub block_number: i64,
+    pub sqrt_price_x96: BigDecimal,
+    pub tick: BigDecimal,
+    pub liquidity: BigDecimal,
+}
+
+/// Pool state read from the pool contract at the end of a block
+#[derive(Clone, Debug)]
+pub struct PoolSnapshot {
+    pub pool: Address,
+    pub block_number: u64,
+    pub sqrt_price_x96: U160,
+    pub tick: I24,
+    pub liquidity: U128,
+}
+
+impl TryFrom<PoolSnapshotRaw> for PoolSnapshot {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(raw: PoolSnapshotRaw) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pool: Address::try_from(raw.pool.as_slice())?,
+            block_number: raw.block_number as u64,
+            sqrt_price_x96: U160::from_str(&integer_string(&raw.sqrt_price_x96))?,
+            tick: I24::from_dec_str(&integer_string(&raw.tick))?,
+            liquidity: U128::from_str(&integer_string(&raw.liquidity))?,
+        })
+    }
+}
+
+impl TryFrom<PoolSnapshot> for PoolSnapshotRaw {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(snapshot: PoolSnapshot) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pool: snapshot.pool.to_vec(),
+            block_number: snapshot.block_number as i64,
+            sqrt_price_x96: BigDecimal::from_str(&snapshot.sqrt_price_x96.to_string())?,
+            tick: BigDecimal::from_str(&snapshot.tick.to_string())?,
+            liquidity: BigDecimal::from_str(&snapshot.liquidity.to_string())?,
+        })
+    }
+}
+
 impl Block {
-    pub(crate) fn new(block_number: u64, block_timestamp: u64) -> Self {
+    pub fn new(
+        block_number: u64,
+        block_hash: B256,
+        parent_hash: Option<B256>,
+        block_timestamp: u64,
+        base_fee_per_gas: Option<u64>,
+    ) -> Self {
         Self {
             block_number,
             block_timestamp,
+            base_fee_per_gas,
+            content_hash: None,
+            block_hash: Some(block_hash),
+            parent_hash,
         }
     }
 }
 
 impl Transaction {
-    pub(crate) fn new(sender: Address, log: Log) -> Result<Self> {
+    /// Some providers leave `transaction_index` out of logs, the receipt's is used then
+    pub fn new(
+        sender: Address,
+        receipt_transaction_index: Option<u64>,
+        status: bool,
+        log: Log,
+    ) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -599,14 +1270,31 @@ impl Transaction {
             block_number: log.block_number.wrap_err("block_number is missing")?,
             transaction_index: log
                 .transaction_index
-                .wrap_err("transaction_index is missing")?,
+                .or(receipt_transaction_index)
+                .wrap_err("transaction_index is missing from the log and its receipt")?,
             transaction_sender: sender,
+            details: None,
+            status: Some(status),
+        })
+    }
+}
+
+impl RawLog {
+    pub fn new(log: &Log) -> Result<Self> {
+        Ok(Self {
+            transaction_hash: log
+                .transaction_hash
+                .wrap_err("transaction_hash is missing")?,
+            log_index: log.log_index.wrap_err("log_index is missing")?,
+            contract_address: log.address(),
+            topics: log.topics().to_vec(),
+            data: log.data().data.clone(),
         })
     }
 }
 
 impl PoolCreateEvent {
-    pub(crate) fn new(log: Log, pool_create_event: AbiLog<PoolCreated>) -> Result<Self> {
+    pub fn new(log: Log, pool_create_event: AbiLog<PoolCreated>) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -622,7 +1310,7 @@ impl PoolCreateEvent {
 }
 
 impl SwapEvent {
-    pub(crate) fn new(log: Log, swap_event: AbiLog<Swap>) -> Result<Self> {
+    pub fn new(log: Log, swap_event: AbiLog<Swap>) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -631,17 +1319,22 @@ impl SwapEvent {
             contract_address: swap_event.address,
             sender: swap_event.sender,
             recipient: swap_event.recipient,
-            amount0: swap_event.amount0,
-            amount1: swap_event.amount1,
+            amount0: PoolDelta(swap_event.amount0),
+            amount1: PoolDelta(swap_event.amount1),
             sqrt_price_x96: swap_event.sqrtPriceX96,
             liquidity: U128::from(swap_event.liquidity),
             tick: swap_event.tick,
         })
     }
+
+    /// token1 per token0 in whole tokens after the swap, given the decimals of the pool's tokens
+    pub fn price(&self, decimals0: u8, decimals1: u8) -> BigDecimal {
+        price_from_sqrt_price_x96(self.sqrt_price_x96, decimals0, decimals1)
+    }
 }
 
 impl InitializationEvent {
-    pub(crate) fn new(
+    pub fn new(
         log: Log,
         initialization_event: AbiLog<Initialize>,
         creator: Address,
@@ -660,7 +1353,7 @@ impl InitializationEvent {
 }
 
 impl MintEvent {
-    pub(crate) fn new(log: Log, mint_event: AbiLog<Mint>) -> Result<Self> {
+    pub fn new(log: Log, mint_event: AbiLog<Mint>) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -679,7 +1372,7 @@ impl MintEvent {
 }
 
 impl BurnEvent {
-    pub(crate) fn new(log: Log, burn_event: AbiLog<Burn>) -> Result<Self> {
+    pub fn new(log: Log, burn_event: AbiLog<Burn>) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -697,7 +1390,7 @@ impl BurnEvent {
 }
 
 impl CollectEvent {
-    pub(crate) fn new(log: Log, collect_event: AbiLog<Collect>) -> Result<Self> {
+    pub fn new(log: Log, collect_event: AbiLog<Collect>) -> Result<Self> {
         Ok(Self {
             transaction_hash: log
                 .transaction_hash
@@ -708,8 +1401,243 @@ impl CollectEvent {
             recipient: collect_event.recipient,
             tick_lower: collect_event.tickLower,
             tick_upper: collect_event.tickUpper,
-            amount0: U256::from_str(&collect_event.amount0.to_string())?,
-            amount1: U256::from_str(&collect_event.amount1.to_string())?,
+            amount0: U128::from(collect_event.amount0),
+            amount1: U128::from(collect_event.amount1),
+        })
+    }
+}
+
+impl FlashEvent {
+    pub fn new(log: Log, flash_event: AbiLog<Flash>) -> Result<Self> {
+        Ok(Self {
+            transaction_hash: log
+                .transaction_hash
+                .wrap_err("transaction_hash is missing")?,
+            log_index: log.log_index.wrap_err("log_index is missing")?,
+            contract_address: flash_event.address,
+            sender: flash_event.sender,
+            recipient: flash_event.recipient,
+            amount0: flash_event.amount0,
+            amount1: flash_event.amount1,
+            paid0: flash_event.paid0,
+            paid1: flash_event.paid1,
         })
     }
 }
+
+impl CollectProtocolEvent {
+    pub fn new(log: Log, collect_protocol_event: AbiLog<CollectProtocol>) -> Result<Self> {
+        Ok(Self {
+            transaction_hash: log
+                .transaction_hash
+                .wrap_err("transaction_hash is missing")?,
+            log_index: log.log_index.wrap_err("log_index is missing")?,
+            contract_address: collect_protocol_event.address,
+            sender: collect_protocol_event.sender,
+            recipient: collect_protocol_event.recipient,
+            amount0: U128::from(collect_protocol_event.amount0),
+            amount1: U128::from(collect_protocol_event.amount1),
+        })
+    }
+}
+
+impl SetFeeProtocolEvent {
+    pub fn new(log: Log, set_fee_protocol_event: AbiLog<SetFeeProtocol>) -> Result<Self> {
+        Ok(Self {
+            transaction_hash: log
+                .transaction_hash
+                .wrap_err("transaction_hash is missing")?,
+            log_index: log.log_index.wrap_err("log_index is missing")?,
+            contract_address: set_fee_protocol_event.address,
+            fee_protocol0_old: set_fee_protocol_event.feeProtocol0Old,
+            fee_protocol1_old: set_fee_protocol_event.feeProtocol1Old,
+            fee_protocol0_new: set_fee_protocol_event.feeProtocol0New,
+            fee_protocol1_new: set_fee_protocol_event.feeProtocol1New,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        primitives::LogData,
+        sol_types::SolEvent,
+    };
+
+    use super::*;
+
+    /// A pool's log with `data`, as the RPC returns it
+    fn pool_log(data: LogData) -> Log {
+        Log {
+            inner: AbiLog {
+                address: Address::repeat_byte(0x11),
+                data,
+            },
+            transaction_hash: Some(TxHash::repeat_byte(0xaa)),
+            log_index: Some(7),
+            ..Default::default()
+        }
+    }
+
+    fn block_raw(block_hash: Option<Vec<u8>>) -> BlockRaw {
+        BlockRaw {
+            block_number: 100,
+            block_timestamp: 1_700_000_000,
+            base_fee_per_gas: None,
+            content_hash: None,
+            block_hash,
+            parent_hash: None,
+        }
+    }
+
+    #[test]
+    fn block_hash_round_trips() {
+        let hash = B256::repeat_byte(0xab);
+        let block = Block::new(100, hash, Some(B256::repeat_byte(0xcd)), 1_700_000_000, None);
+
+        let raw = BlockRaw::try_from(block).unwrap();
+        assert_eq!(raw.block_hash, Some(hash.to_vec()));
+
+        let block = Block::try_from(raw).unwrap();
+        assert_eq!(block.block_hash, Some(hash));
+        assert_eq!(block.parent_hash, Some(B256::repeat_byte(0xcd)));
+    }
+
+    #[test]
+    fn block_stored_before_hashes_has_none() {
+        let block = Block::try_from(block_raw(None)).unwrap();
+        assert_eq!(block.block_hash, None);
+    }
+
+    #[test]
+    fn malformed_block_hash_is_an_error() {
+        assert!(Block::try_from(block_raw(Some(vec![0xab; 31]))).is_err());
+    }
+
+    #[test]
+    fn flash_event_round_trips() {
+        let log = pool_log(
+            Flash {
+                sender: Address::repeat_byte(0x21),
+                recipient: Address::repeat_byte(0x22),
+                amount0: U256::MAX,
+                amount1: U256::from(1_000),
+                paid0: U256::MAX,
+                paid1: U256::from(1_003),
+            }
+            .encode_log_data(),
+        );
+        let decoded = Flash::decode_log(&log.inner, true).unwrap();
+        let event = FlashEvent::new(log, decoded).unwrap();
+        assert_eq!(event.contract_address, Address::repeat_byte(0x11));
+        assert_eq!((event.log_index, event.paid1), (7, U256::from(1_003)));
+
+        let raw = FlashEventRaw::try_from(event.clone()).unwrap();
+        assert_eq!(raw.amount0.to_string(), U256::MAX.to_string());
+        let round_tripped = FlashEvent::try_from(raw).unwrap();
+        assert_eq!(format!("{:?}", round_tripped), format!("{:?}", event));
+    }
+
+    #[test]
+    fn collect_protocol_event_round_trips() {
+        let log = pool_log(
+            CollectProtocol {
+                sender: Address::repeat_byte(0x21),
+                recipient: Address::repeat_byte(0x22),
+                amount0: u128::MAX,
+                amount1: 5,
+            }
+            .encode_log_data(),
+        );
+        let decoded = CollectProtocol::decode_log(&log.inner, true).unwrap();
+        let event = CollectProtocolEvent::new(log, decoded).unwrap();
+        assert_eq!(event.amount0, U128::MAX);
+
+        let raw = CollectProtocolEventRaw::try_from(event.clone()).unwrap();
+        assert_eq!(raw.amount0.to_string(), u128::MAX.to_string());
+        let round_tripped = CollectProtocolEvent::try_from(raw).unwrap();
+        assert_eq!(format!("{:?}", round_tripped), format!("{:?}", event));
+    }
+
+    #[test]
+    fn collect_event_round_trips_max_amounts() {
+        let log = pool_log(
+            Collect {
+                owner: Address::repeat_byte(0x21),
+                recipient: Address::repeat_byte(0x22),
+                tickLower: I24::try_from(-60).unwrap(),
+                tickUpper: I24::try_from(60).unwrap(),
+                amount0: u128::MAX,
+                amount1: u128::MAX,
+            }
+            .encode_log_data(),
+        );
+        let decoded = Collect::decode_log(&log.inner, true).unwrap();
+        let event = CollectEvent::new(log, decoded).unwrap();
+        assert_eq!((event.amount0, event.amount1), (U128::MAX, U128::MAX));
+
+        let raw = CollectEventRaw::try_from(event.clone()).unwrap();
+        assert_eq!(raw.amount0.to_string(), u128::MAX.to_string());
+        assert_eq!(raw.amount1.to_string(), u128::MAX.to_string());
+        let round_tripped = CollectEvent::try_from(raw).unwrap();
+        assert_eq!(format!("{:?}", round_tripped), format!("{:?}", event));
+    }
+
+    #[test]
+    fn set_fee_protocol_event_round_trips() {
+        let log = pool_log(
+            SetFeeProtocol {
+                feeProtocol0Old: 0,
+                feeProtocol1Old: 0,
+                feeProtocol0New: 4,
+                feeProtocol1New: 10,
+            }
+            .encode_log_data(),
+        );
+        let decoded = SetFeeProtocol::decode_log(&log.inner, true).unwrap();
+        let event = SetFeeProtocolEvent::new(log, decoded).unwrap();
+        assert_eq!((event.fee_protocol0_new, event.fee_protocol1_new), (4, 10));
+
+        let raw = SetFeeProtocolEventRaw::try_from(event.clone()).unwrap();
+        let round_tripped = SetFeeProtocolEvent::try_from(raw).unwrap();
+        assert_eq!(format!("{:?}", round_tripped), format!("{:?}", event));
+    }
+
+    #[test]
+    fn positive_deltas_flow_into_the_pool() {
+        let paid_in = PoolDelta(I256::try_from(1_000).unwrap());
+        assert!(paid_in.is_inflow());
+        assert!(!paid_in.is_outflow());
+        assert_eq!(paid_in.abs_amount(), U256::from(1_000));
+
+        let paid_out = PoolDelta(I256::try_from(-250).unwrap());
+        assert!(paid_out.is_outflow());
+        assert!(!paid_out.is_inflow());
+        assert_eq!(paid_out.abs_amount(), U256::from(250));
+
+        let untouched = PoolDelta(I256::ZERO);
+        assert!(!untouched.is_inflow() && !untouched.is_outflow());
+        // the most negative value still has an absolute amount
+        assert_eq!(PoolDelta(I256::MIN).abs_amount(), U256::from(1) << 255);
+    }
+
+    #[test]
+    fn integer_string_has_no_exponent() {
+        // large numbers with a negative scale render in exponent notation
+        let scaled = BigDecimal::new(1.into(), -20);
+        assert_eq!(scaled.to_string(), "1e+20");
+        assert_eq!(integer_string(&scaled), "100000000000000000000");
+        assert_eq!(
+            U256::from_str(&integer_string(&scaled)).unwrap(),
+            U256::from(10).pow(U256::from(20))
+        );
+
+        assert_eq!(integer_string(&BigDecimal::from_str("-42").unwrap()), "-42");
+        assert_eq!(integer_string(&BigDecimal::from_str("7.000").unwrap()), "7");
+        assert_eq!(integer_string(&BigDecimal::from_str("7.9").unwrap()), "7");
+        assert_eq!(
+            integer_string(&BigDecimal::from_str(&U256::MAX.to_string()).unwrap()),
+            U256::MAX.to_string()
+        );
+    }
+}
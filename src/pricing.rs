@@ -0,0 +1,201 @@
+//! Prices of Uniswap V3 pools from their `sqrtPriceX96`.
+//!
+//! A pool orders its tokens by address, `token0` being the lower one, and quotes its price as
+//! token1 per token0: how much of token1 one token0 is worth. The inverse price is `1 / price`.
+//!
+//! Also the protocol's share of a swap's fee, see [`swap_protocol_fee`].
+
+use alloy::primitives::{
+    aliases::{
+        U160,
+        U24,
+    },
+    U256,
+};
+use bigdecimal::{
+    num_bigint::{
+        BigInt,
+        Sign,
+    },
+    BigDecimal,
+};
+
+use crate::pool_sql::types::{
+    FeeProtocol,
+    SwapEvent,
+};
+
+/// Fractional bits of the Q64.96 fixed point `sqrtPriceX96`
+const Q96_BITS: u32 = 96;
+
+/// Fee tiers are in hundredths of a basis point, 3000 is 0.3%
+const FEE_DENOMINATOR: u64 = 1_000_000;
+
+/// Price of token0 in token1 from a pool's `sqrtPriceX96`, in whole tokens given the decimals of
+/// each token.
+///
+/// `sqrtPriceX96` is `sqrt(price) * 2^96` with the price in base units, so the price is
+/// `sqrtPriceX96^2 / 2^192`, and `10^(decimals0 - decimals1)` times that in whole tokens. The
+/// division is done as `sqrtPriceX96^2 * 5^192 / 10^192`, which only moves the decimal point, so
+/// the result is exact.
+pub fn price_from_sqrt_price_x96(sqrt_price_x96: U160, decimals0: u8, decimals1: u8) -> BigDecimal {
+    let sqrt_price = BigInt::from_bytes_be(Sign::Plus, &sqrt_price_x96.to_be_bytes::<20>());
+    let digits = sqrt_price.pow(2) * BigInt::from(5).pow(2 * Q96_BITS);
+    let base_unit_price = BigDecimal::new(digits, i64::from(2 * Q96_BITS));
+    whole_token_price(&base_unit_price, decimals0, decimals1)
+}
+
+/// Price of token0 in token1 in base units, before adjusting for the tokens' decimals
+pub fn base_unit_price(sqrt_price_x96: U160) -> BigDecimal {
+    price_from_sqrt_price_x96(sqrt_price_x96, 0, 0)
+}
+
+/// Price in whole tokens from a price in base units, like the `price` column of `swap_events`,
+/// given the decimals of each token. Multiplying by `10^(decimals0 - decimals1)` only moves the
+/// decimal point, so the result is exact.
+pub fn whole_token_price(base_unit_price: &BigDecimal, decimals0: u8, decimals1: u8) -> BigDecimal {
+    let (digits, scale) = base_unit_price.as_bigint_and_exponent();
+    let scale = scale + i64::from(decimals1) - i64::from(decimals0);
+    let price = BigDecimal::new(digits, scale).normalized();
+    // integers normalize to a negative scale, keep them as plain digits
+    if price.fractional_digit_count() < 0 {
+        price.with_scale(0)
+    } else {
+        price
+    }
+}
+
+/// Protocol fee taken from `swap`, in token0 and token1 base units, given the pool's fee tier and
+/// the protocol fee setting in effect when the swap executed.
+///
+/// The swapper pays the fee in the token going into the pool, so at most one of the two is
+/// nonzero, and the protocol keeps `1 / fee_protocol` of it, using the setting of that token:
+///
+/// `protocol_fee = amount_in * fee / 1_000_000 / fee_protocol`
+///
+/// where `amount_in` is the swap's positive amount, which includes the fee. The pool charges the
+/// fee per tick range the swap crosses and rounds each part, so this can be a few base units off
+/// the pool's own `protocolFees`.
+pub fn swap_protocol_fee(swap: &SwapEvent, fee: U24, fee_protocol: FeeProtocol) -> (U256, U256) {
+    let protocol_fee = |amount_in: U256, fee_protocol: u8| {
+        if fee_protocol == 0 {
+            return U256::ZERO;
+        }
+        amount_in.saturating_mul(U256::from(fee))
+            / U256::from(FEE_DENOMINATOR)
+            / U256::from(fee_protocol)
+    };
+    if swap.amount0.is_inflow() {
+        (
+            protocol_fee(swap.amount0.abs_amount(), fee_protocol.token0),
+            U256::ZERO,
+        )
+    } else if swap.amount1.is_inflow() {
+        (
+            U256::ZERO,
+            protocol_fee(swap.amount1.abs_amount(), fee_protocol.token1),
+        )
+    } else {
+        (U256::ZERO, U256::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use alloy::primitives::I256;
+
+    use super::*;
+    use crate::pool_sql::types::PoolDelta;
+
+    /// `sqrtPriceX96` of a 1:1 price in base units
+    fn one() -> U160 {
+        U160::from(1) << 96
+    }
+
+    #[test]
+    fn one_to_one_price_is_one() {
+        assert_eq!(base_unit_price(one()), BigDecimal::from(1));
+        assert_eq!(base_unit_price(one()).to_string(), "1");
+    }
+
+    #[test]
+    fn price_is_the_square_of_the_sqrt_price() {
+        assert_eq!(base_unit_price(one() * U160::from(3)), BigDecimal::from(9));
+        // half the sqrt price is a quarter of the price, exactly
+        assert_eq!(
+            base_unit_price(one() >> 1),
+            BigDecimal::from_str("0.25").unwrap()
+        );
+        assert_eq!(base_unit_price(U160::ZERO), BigDecimal::from(0));
+    }
+
+    #[test]
+    fn decimals_move_the_decimal_point() {
+        // 1 base unit of an 18 decimals token0 per base unit of a 6 decimals token1 is 10^12
+        // whole token1 per whole token0
+        assert_eq!(
+            price_from_sqrt_price_x96(one(), 18, 6),
+            BigDecimal::from(1_000_000_000_000u64)
+        );
+        assert_eq!(
+            price_from_sqrt_price_x96(one(), 6, 18),
+            BigDecimal::from_str("0.000000000001").unwrap()
+        );
+        assert_eq!(
+            whole_token_price(&base_unit_price(one() >> 1), 2, 0),
+            BigDecimal::from(25)
+        );
+    }
+
+    #[test]
+    fn protocol_fee_is_a_share_of_the_input_tokens_fee() {
+        let swap = SwapEvent {
+            transaction_hash: Default::default(),
+            log_index: 0,
+            contract_address: Default::default(),
+            sender: Default::default(),
+            recipient: Default::default(),
+            amount0: PoolDelta(I256::try_from(-1_000).unwrap()),
+            amount1: PoolDelta(I256::try_from(2_000_000).unwrap()),
+            sqrt_price_x96: one(),
+            liquidity: Default::default(),
+            tick: Default::default(),
+        };
+        let fee = U24::from(3_000);
+
+        // 0.3% of the token1 paid in is 6000, the protocol takes a sixth
+        let fee_protocol = FeeProtocol {
+            token0: 4,
+            token1: 6,
+        };
+        assert_eq!(
+            swap_protocol_fee(&swap, fee, fee_protocol),
+            (U256::ZERO, U256::from(1_000))
+        );
+        // 0 turns the protocol fee off
+        let fee_protocol = FeeProtocol {
+            token0: 4,
+            token1: 0,
+        };
+        assert_eq!(
+            swap_protocol_fee(&swap, fee, fee_protocol),
+            (U256::ZERO, U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn usdc_weth_pool_price() {
+        // USDC (6 decimals) / WETH (18 decimals) at about 4000 USDC per WETH, token1 per token0
+        // is about 1 / 4000 WETH per USDC
+        let sqrt_price_x96 = U160::from_str("1252707241875239613573034342875136").unwrap();
+        let price = price_from_sqrt_price_x96(sqrt_price_x96, 6, 18);
+        let usdc_per_weth = BigDecimal::from(1) / price;
+        assert!(
+            usdc_per_weth > BigDecimal::from(3_999) && usdc_per_weth < BigDecimal::from(4_001),
+            "{}",
+            usdc_per_weth
+        );
+    }
+}
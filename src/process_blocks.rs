@@ -1,115 +1,562 @@
 use std::{
     collections::{
+        hash_map::Entry,
+        BTreeMap,
         HashMap,
         HashSet,
+        VecDeque,
     },
+    fmt::Display,
+    fs,
+    io,
+    ops::Range,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
     time::Duration,
 };
 
 use alloy::{
-    consensus::TxReceipt,
+    consensus::{
+        BlockHeader,
+        // named like the stored `Transaction`, only needed for its methods
+        Transaction as _,
+    },
     network::{
+        primitives::HeaderResponse,
         AnyNetwork,
-        AnyReceiptEnvelope,
+        BlockResponse,
         Network,
+        ReceiptResponse,
+        TransactionResponse,
     },
     primitives::{
+        aliases::{
+            U128,
+            U24,
+            U256,
+        },
+        keccak256,
         Address,
+        Bloom,
+        BloomInput,
+        Keccak256,
         Log as AbiLog,
         TxHash,
+        B256,
+    },
+    providers::{
+        Provider,
+        RootProvider,
     },
-    providers::Provider,
+    pubsub::PubSubFrontend,
     rpc::types::{
-        serde_helpers::WithOtherFields,
+        BlockId,
         Log,
-        TransactionReceipt,
     },
     sol_types::SolEvent,
 };
 use diesel::PgConnection;
 use eyre::{
     bail,
+    eyre,
     Result,
     WrapErr,
 };
-use futures_util::StreamExt;
+use futures_util::{
+    Stream,
+    StreamExt,
+};
+use serde::Serialize;
+use tokio::{
+    sync::watch,
+    time::{
+        Instant,
+        MissedTickBehavior,
+    },
+};
 use tracing::{
     debug,
     info,
+    info_span,
+    warn,
+    Instrument,
 };
 use UniswapV3Pool::{
     Burn,
     Collect,
+    CollectProtocol,
+    Flash,
     Initialize,
     Mint,
+    SetFeeProtocol,
     Swap,
 };
 
 use crate::{
     abi::{
+        compute_pool_address,
         IUniswapV3Factory::PoolCreated,
         UniswapV3Pool,
     },
+    export::EventExporter,
     pool_sql::{
         database_interactions::{
+            delete_block_events,
             establish_connection,
             insert_block_events,
+            is_connection_error,
+            record_backfill_run,
+            set_backfill_run_range,
+            set_last_processed_block,
+            start_backfill_run,
+            DbPools,
+            InsertCounts,
+            IsolationLevel,
         },
         types::{
+            integer_string,
             Block,
+            BlockRaw,
             BurnEvent,
+            BurnEventRaw,
             CollectEvent,
+            CollectEventRaw,
+            CollectProtocolEvent,
+            CollectProtocolEventRaw,
+            FlashEvent,
+            FlashEventRaw,
+            InferredPoolRaw,
             InitializationEvent,
+            InitializationEventRaw,
             MintEvent,
+            MintEventRaw,
             PoolCreateEvent,
+            PoolCreateEventRaw,
+            PoolCurrentStateRaw,
+            PoolDelta,
+            PoolSnapshot,
+            PoolSnapshotRaw,
+            RawLog,
+            RawLogRaw,
+            SetFeeProtocolEvent,
+            SetFeeProtocolEventRaw,
             SwapEvent,
+            SwapEventRaw,
+            Token,
+            TokenRaw,
             Transaction,
+            TransactionDetails,
+            TransactionRaw,
+            MISSING_BLOCK_TIMESTAMP,
         },
     },
+    pushgateway::{
+        BackfillMetrics,
+        Pushgateway,
+    },
     rpc::{
+        check_receipts_block_number,
+        count_rpc_calls,
         fetch_block_data_batched,
+        fetch_block_data_over,
+        fetch_blocks_batched,
+        fetch_block_number,
+        fetch_blocks_data_batched,
+        fetch_chain_id,
+        fetch_logs,
+        fetch_receipts_batched,
+        fetch_transaction_receipts_batched,
         http_connection,
+        retry_with_backoff,
         websocket_connection,
+        BatchConfig,
+        HttpClient,
+        ReceiptTransport,
         RetryConfig,
+        TrackedNetwork,
     },
+    telemetry::PIPELINE_TARGET,
+    tokens::TokenMetadataCache,
+    utils::fmt_addr,
 };
 
-pub(crate) async fn single_block(
+/// Settings that decide which pools and events get tracked, and how they're stored
+#[derive(Clone, Debug)]
+pub struct TrackingConfig {
+    pub uniswap_v3_factory_address: Address,
+    pub pool_deployer_addresses: HashSet<Address>,
+    /// Also track pools created by calling the factory directly (`tx.to == factory`)
+    pub accept_direct_factory_creates: bool,
+    /// Fraction of swap events to keep, in `[0, 1]`. Other event types are always kept.
+    pub swap_sample_rate: f64,
+    /// Drop swaps moving less than this much token0, in base units
+    pub min_swap_amount0: Option<U256>,
+    /// Drop swaps moving less than this much token1, in base units
+    pub min_swap_amount1: Option<U256>,
+    /// Skip `PoolCreated` events for tracked pools whose tokens or fee differ from the stored pool
+    pub strict_factory: bool,
+    /// Isolation level of the transaction each block's events are inserted in
+    pub db_isolation: IsolationLevel,
+    /// Directory to dump a block's receipts to when processing it fails
+    pub save_failed_receipts: Option<PathBuf>,
+    /// First topics of logs from tracked addresses to store undecoded in `raw_logs`
+    pub extra_topics: HashSet<B256>,
+    /// Log per block how many logs with a tracked event signature were decoded
+    pub qa_stats: bool,
+    /// Only track pools with this token as token0 or token1
+    pub only_token: Option<Address>,
+    /// Decode pool events from any address and track unknown emitters as inferred pools
+    pub decode_all_pool_events: bool,
+    /// Retry a block's insert on a fresh connection after losing the database connection,
+    /// instead of failing the run
+    pub db_retry: Option<RetryConfig>,
+    /// Skip writing blocks whose stored content hash matches the events they would store
+    pub skip_unchanged_blocks: bool,
+    /// Pool init code hash to check created pools' addresses against, `None` to not check them
+    pub pool_init_code_hash: Option<B256>,
+    /// Keep each pool's latest price, tick and liquidity in `pool_current_state`
+    pub track_pool_state: bool,
+    /// Only fetch receipts in `blocks_from`, storing blocks without their timestamp and base fee
+    pub skip_timestamp_fetch: bool,
+    /// Fetch blocks with their full transactions and store the transactions' value, nonce and
+    /// input selector
+    pub rich_transactions: bool,
+    /// Prefix of the ids of JSON-RPC requests sent over HTTP, to find them in provider logs
+    pub request_id_prefix: Option<String>,
+    /// What to do with logs from tracked addresses that can't be decoded into events
+    pub decode_error_policy: DecodeErrorPolicy,
+    /// Fetch the headers of each batch first and only fetch receipts of blocks whose logs bloom
+    /// matches a tracked address
+    pub bloom_filter: bool,
+    /// Fetch `blocks_from` batches with `eth_getLogs` filtered by the tracked addresses and event
+    /// signatures, and then only the receipts of the matching transactions
+    pub fetch_logs: bool,
+    /// Log every log of the processed blocks with how it was classified and decoded
+    pub trace_logs: bool,
+    /// Stop `blocks_from` and live tracking between blocks once they have run this long
+    pub max_runtime: Option<Duration>,
+    /// Blocks live modes stay behind the head, 0 processes each head as it arrives
+    pub confirmations: u64,
+    /// Deepest reorg live modes roll back. A deeper one stops them without deleting anything, for
+    /// an operator to look into.
+    pub reorg_depth_limit: usize,
+    /// Shortest time between two reorg rollbacks of live modes. A reorg sooner after the last one
+    /// pauses ingestion until the time is up, `None` rolls back every reorg right away.
+    pub reorg_cooldown: Option<Duration>,
+    /// Callbacks run on each decoded event before its block is stored, see
+    /// [`TrackingConfig::on_event`]
+    pub event_hooks: EventHooks,
+}
+
+/// A decoded event of any of the tracked types
+#[derive(Clone, Copy, Debug)]
+pub enum AnyEvent<'a> {
+    PoolCreate(&'a PoolCreateEvent),
+    Initialize(&'a InitializationEvent),
+    Swap(&'a SwapEvent),
+    Mint(&'a MintEvent),
+    Burn(&'a BurnEvent),
+    Collect(&'a CollectEvent),
+    Flash(&'a FlashEvent),
+    CollectProtocol(&'a CollectProtocolEvent),
+    SetFeeProtocol(&'a SetFeeProtocolEvent),
+}
+
+/// Callback on a decoded event and the block it is in
+pub type EventHook = Arc<dyn Fn(&AnyEvent<'_>, &Block) + Send + Sync>;
+
+/// Callbacks registered with [`TrackingConfig::on_event`], in registration order
+#[derive(Clone, Default)]
+pub struct EventHooks(Vec<EventHook>);
+
+impl EventHooks {
+    fn run(&self, event: AnyEvent<'_>, block: &Block) {
+        for hook in &self.0 {
+            hook(&event, block);
+        }
+    }
+}
+
+impl std::fmt::Debug for EventHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EventHooks({} registered)", self.0.len())
+    }
+}
+
+/// How a log from a tracked address that fails to decode, or to be turned into an event, is
+/// handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodeErrorPolicy {
+    /// Log a warning and skip the log
+    #[default]
+    Continue,
+    /// Fail the block
+    Fail,
+}
+
+/// What a log's emitter is to the tracker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AddressRole {
+    /// The tracked factory, only its `PoolCreated` events are decoded
+    Factory,
+    /// A tracked pool, only its pool events are decoded
+    Pool,
+    Untracked,
+}
+
+impl TrackingConfig {
+    /// Track the pools `pool_deployer_addresses` create through the factory, with every other
+    /// setting at the command line's default
+    pub fn new(
+        uniswap_v3_factory_address: Address,
+        pool_deployer_addresses: HashSet<Address>,
+    ) -> Self {
+        Self {
+            uniswap_v3_factory_address,
+            pool_deployer_addresses,
+            accept_direct_factory_creates: false,
+            swap_sample_rate: 1.0,
+            min_swap_amount0: None,
+            min_swap_amount1: None,
+            strict_factory: false,
+            db_isolation: IsolationLevel::default(),
+            save_failed_receipts: None,
+            extra_topics: HashSet::new(),
+            qa_stats: false,
+            only_token: None,
+            decode_all_pool_events: false,
+            db_retry: None,
+            skip_unchanged_blocks: false,
+            pool_init_code_hash: None,
+            track_pool_state: false,
+            skip_timestamp_fetch: false,
+            rich_transactions: false,
+            request_id_prefix: None,
+            decode_error_policy: DecodeErrorPolicy::default(),
+            bloom_filter: false,
+            fetch_logs: false,
+            trace_logs: false,
+            max_runtime: None,
+            confirmations: 0,
+            reorg_depth_limit: DEFAULT_REORG_DEPTH_LIMIT,
+            reorg_cooldown: None,
+            event_hooks: EventHooks::default(),
+        }
+    }
+
+    /// Run `hook` on every event decoded from now on, e.g. to alert on large swaps.
+    ///
+    /// Hooks see the events that are about to be stored, after sampling and the other filters,
+    /// in the order they were emitted. They run synchronously in the decode loop, before the
+    /// block is stored, so they're meant for lightweight filtering and alerting: a slow hook
+    /// holds up processing, and anything heavier should be handed off to another task. A block
+    /// that fails and is processed again runs the hooks on its events again.
+    pub fn on_event(&mut self, hook: impl Fn(&AnyEvent<'_>, &Block) + Send + Sync + 'static) {
+        self.event_hooks.0.push(Arc::new(hook));
+    }
+
+    /// Classify a log's emitter. The factory wins over the pool set, so pool events are never
+    /// decoded from the factory even if it ended up in `pools`.
+    fn classify_address(&self, address: Address, pools: &HashSet<Address>) -> AddressRole {
+        if address == self.uniswap_v3_factory_address {
+            AddressRole::Factory
+        } else if pools.contains(&address) {
+            AddressRole::Pool
+        } else {
+            AddressRole::Untracked
+        }
+    }
+
+    /// Apply the decode error policy to the result of decoding `log` or building an event from
+    /// it. Returns `None` if the log should be skipped.
+    fn on_bad_log<T, E: Display>(
+        &self,
+        result: std::result::Result<T, E>,
+        action: &str,
+        log: &Log,
+    ) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => match self.decode_error_policy {
+                DecodeErrorPolicy::Continue => {
+                    warn!(
+                        "Failed to {} from log {:?} of {}, skipping: {}",
+                        action,
+                        log.log_index,
+                        fmt_addr(log.address()),
+                        e
+                    );
+                    Ok(None)
+                }
+                DecodeErrorPolicy::Fail => bail!("Failed to {} from: {:?}: {}", action, log, e),
+            },
+        }
+    }
+
+    /// Whether a block with `logs_bloom` might have logs from tracked addresses. Blooms have false
+    /// positives but no false negatives, so a `false` means the block has nothing to store.
+    ///
+    /// Some providers return headers with an all-zero bloom instead of the block's, which would
+    /// rule out every block, so an empty bloom always counts as a match.
+    fn might_have_tracked_logs(&self, logs_bloom: &Bloom, pools: &HashSet<Address>) -> bool {
+        if logs_bloom.is_zero() {
+            return true;
+        }
+        let contains = |input: &[u8]| logs_bloom.contains_input(BloomInput::Raw(input));
+        contains(self.uniswap_v3_factory_address.as_slice())
+            || pools.iter().any(|pool| contains(pool.as_slice()))
+            // any emitter of a pool event is taken to be a pool
+            || (self.decode_all_pool_events
+                && TRACKED_EVENT_SIGNATURES
+                    .iter()
+                    .any(|signature| contains(signature.as_slice())))
+    }
+
+    /// Check if a `PoolCreated` from a transaction sent to `tx_to` came from a tracked deployer.
+    ///
+    /// Pools are normally created through one of the deployer contracts, which then calls into
+    /// the factory. When `accept_direct_factory_creates` is set, transactions sent straight to
+    /// the factory are accepted as well.
+    fn is_tracked_deployer(&self, tx_to: Option<Address>) -> bool {
+        match tx_to {
+            Some(to) => {
+                self.pool_deployer_addresses.contains(&to)
+                    || (self.accept_direct_factory_creates && to == self.uniswap_v3_factory_address)
+            }
+            None => false,
+        }
+    }
+
+    /// Check if a pool with these tokens passes the `only_token` filter
+    fn is_tracked_token_pair(&self, token0: Address, token1: Address) -> bool {
+        self.only_token
+            .is_none_or(|token| token == token0 || token == token1)
+    }
+
+    /// Check if a created pool is at the factory's CREATE2 address for its tokens and fee, when
+    /// pool addresses are verified
+    fn is_expected_pool_address(
+        &self,
+        token0: Address,
+        token1: Address,
+        fee: U24,
+        pool: Address,
+    ) -> bool {
+        self.pool_init_code_hash.is_none_or(|init_code_hash| {
+            compute_pool_address(
+                self.uniswap_v3_factory_address,
+                token0,
+                token1,
+                fee,
+                init_code_hash,
+            ) == pool
+        })
+    }
+
+    /// Check if a swap moves at least the configured minimum amount of both tokens
+    fn meets_min_swap_amounts(&self, swap_event: &SwapEvent) -> bool {
+        let meets_min = |amount: PoolDelta, min_amount: Option<U256>| {
+            min_amount.is_none_or(|min_amount| amount.abs_amount() >= min_amount)
+        };
+
+        meets_min(swap_event.amount0, self.min_swap_amount0)
+            && meets_min(swap_event.amount1, self.min_swap_amount1)
+    }
+}
+
+/// Deterministically decide if a swap is kept when sampling swaps at `sample_rate`.
+///
+/// The decision only depends on the swap's `(transaction_hash, log_index)`, so re-running the
+/// same range keeps exactly the same swaps.
+fn keep_sampled_swap(transaction_hash: TxHash, log_index: u64, sample_rate: f64) -> bool {
+    if sample_rate >= 1.0 {
+        return true;
+    }
+
+    let mut preimage = transaction_hash.to_vec();
+    preimage.extend_from_slice(&log_index.to_be_bytes());
+    let hash = keccak256(preimage);
+    let bucket = u64::from_be_bytes(hash[..8].try_into().expect("keccak hash is 32 bytes"));
+
+    (bucket as f64) < sample_rate * u64::MAX as f64
+}
+
+/// Check a `PoolCreated` for an already tracked pool against the stored pool record.
+///
+/// A pool's address is derived from its tokens and fee, so a mismatch points at a spoofed event
+/// or a bug rather than a legitimate re-creation.
+fn matches_stored_pool(
+    pool_create_event: &PoolCreateEvent,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    let Some(stored_pool) =
+        PoolCreateEventRaw::find_by_pool(pool_create_event.pool.as_slice(), conn)?
+    else {
+        return Ok(true);
+    };
+
+    Ok(stored_pool.token0 == pool_create_event.token0.as_slice()
+        && stored_pool.token1 == pool_create_event.token1.as_slice()
+        && integer_string(&stored_pool.fee) == pool_create_event.fee.to_string())
+}
+
+pub async fn single_block<N: TrackedNetwork>(
     http_url: String,
     block_number: u64,
-    uniswap_v3_factory_address: Address,
-    pool_deployer_addresses: &HashSet<Address>,
+    tracking_config: &TrackingConfig,
     pools: &mut HashSet<Address>,
     retry_config: RetryConfig,
+    db_pools: &DbPools,
 ) -> Result<()> {
-    let client = http_connection(http_url)
+    let client = http_connection(http_url, tracking_config.request_id_prefix.clone())
         .await
         .wrap_err("failed to build http")?;
+    let token_metadata = TokenMetadataCache::new(
+        client.clone(),
+        retry_config.clone(),
+        &mut *db_pools.main.get()?,
+    )?;
+
+    let block_span = block_span(block_number);
 
     // fetch block data
-    let (receipts, block) =
-        match fetch_block_data_batched(&client, block_number, &retry_config).await {
-            Ok((receipts, block)) => {
-                debug!(
-                    "Successfully grabbed receipts for block {}, receipts length: {}",
-                    block_number,
-                    receipts.len()
-                );
-                (receipts, block)
-            }
-            Err(e) => {
-                bail!("Failed to grab data for block {}: {}", block_number, e);
-            }
-        };
+    let (receipts, block) = match fetch_block_data_batched::<N>(
+        &client,
+        block_number,
+        tracking_config.rich_transactions,
+        &retry_config,
+    )
+    .instrument(info_span!(target: PIPELINE_TARGET, parent: &block_span, "fetch"))
+    .await
+    {
+        Ok((receipts, block)) => {
+            debug!(
+                "Successfully grabbed receipts for block {}, receipts length: {}",
+                block_number,
+                receipts.len()
+            );
+            (receipts, block)
+        }
+        Err(e) => {
+            bail!("Failed to grab data for block {}: {}", block_number, e);
+        }
+    };
 
     // process block for desired events
-    match get_and_store_events(
-        pool_deployer_addresses,
+    let block = BlockInfo::from_block::<N>(&block);
+    match get_and_store_events::<N>(
+        tracking_config,
         pools,
-        uniswap_v3_factory_address,
         receipts,
         block,
+        true,
+        db_pools,
+        Some(&token_metadata),
     )
+    .instrument(block_span)
     .await
     {
         Ok(_) => {}
@@ -125,465 +572,4401 @@ pub(crate) async fn single_block(
     Ok(())
 }
 
-pub(crate) async fn blocks_from(
+/// Check that a `blocks_from` range has blocks in it. The end block is processed too, so a range
+/// ending at its start block is that one block.
+pub fn check_block_range(start_block: u64, end_block: u64) -> Result<()> {
+    if start_block > end_block {
+        bail!(
+            "End block {} is before start block {} (use --end-block {} to process only block {})",
+            end_block,
+            start_block,
+            start_block,
+            start_block
+        );
+    }
+    Ok(())
+}
+
+/// Watch that turns true on Ctrl-C, for runs to stop between blocks instead of in the middle of
+/// one. A second Ctrl-C exits right away.
+fn watch_ctrl_c() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            warn!("Failed to listen for Ctrl-C, it will stop the run in the middle of a block");
+            return;
+        }
+        info!("Received Ctrl-C, stopping after the current block, press it again to exit now");
+        let _ = sender.send(true);
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    receiver
+}
+
+/// How often `blocks_from` logs its progress when per-block logging is off
+const QUIET_PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How `blocks_from` reports its progress
+pub struct BackfillReporting {
+    /// Only log periodic progress instead of a summary for every block
+    pub quiet: bool,
+    /// Where to push the run's metrics to on completion
+    pub pushgateway: Option<Pushgateway>,
+    /// Also push the metrics every this many blocks
+    pub push_every_blocks: Option<u64>,
+}
+
+/// Order in which `blocks_from` walks its range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackfillDirection {
+    /// Oldest block first
+    #[default]
+    Forward,
+    /// Newest block first. A pool is only tracked from the block it was created in, so the swaps
+    /// of a pool created within the range, which come in newer blocks, are processed before the
+    /// pool is known and are skipped. Only pools that are already stored or created before the
+    /// range are fully covered.
+    Backward,
+}
+
+impl BackfillDirection {
+    /// Blocks of `start_block..=end_block` in the order they are processed
+    fn block_order(self, start_block: u64, end_block: u64) -> Vec<u64> {
+        let mut block_numbers: Vec<u64> = (start_block..=end_block).collect();
+        if self == BackfillDirection::Backward {
+            block_numbers.reverse();
+        }
+        block_numbers
+    }
+}
+
+#[expect(
+    clippy::too_many_arguments,
+    reason = "range, direction and the per-run configs are all independent"
+)]
+pub async fn blocks_from<N: TrackedNetwork>(
     http_url: String,
     start_block: u64,
     end_block: u64,
-    uniswap_v3_factory_address: Address,
-    pool_deployer_addresses: &HashSet<Address>,
+    tracking_config: &TrackingConfig,
     pools: &mut HashSet<Address>,
+    direction: BackfillDirection,
     retry_config: RetryConfig,
-    delay_ms: u64,
+    batch_config: BatchConfig,
+    reporting: BackfillReporting,
+    db_pools: &DbPools,
 ) -> Result<()> {
-    if start_block > end_block {
-        bail!("Start block must be less than end block");
-    }
+    check_block_range(start_block, end_block)?;
 
-    let client = http_connection(http_url)
+    let client = http_connection(http_url, tracking_config.request_id_prefix.clone())
         .await
         .wrap_err("failed to build http")?;
+    let token_metadata = TokenMetadataCache::new(
+        client.clone(),
+        retry_config.clone(),
+        &mut *db_pools.main.get()?,
+    )?;
+    // checkpoints are per chain, in case several chains share the database
+    let chain_id = fetch_chain_id(&client, &retry_config).await?;
 
     info!(
-        "Processing blocks from {} to {} ({} blocks)",
+        "Processing blocks from {} to {} ({} blocks, {:?})",
         start_block,
         end_block,
-        end_block.saturating_sub(start_block)
+        end_block - start_block + 1,
+        direction
     );
 
-    for block_number in start_block..end_block {
-        // fetch block data
-        let (receipts, block) =
-            match fetch_block_data_batched(&client, block_number, &retry_config).await {
-                Ok((receipts, block)) => {
+    if direction == BackfillDirection::Backward {
+        warn!(
+            "Processing newest block first, swaps of pools created within the range are skipped"
+        );
+    }
+    let block_numbers = direction.block_order(start_block, end_block);
+    let mut metrics = BackfillMetrics {
+        blocks_total: block_numbers.len() as u64,
+        ..Default::default()
+    };
+    // backfill runs are stored with the block after the range as their end
+    let run_end = end_block + 1;
+    let run_id = start_backfill_run(start_block, run_end, &mut *db_pools.main.get()?)?;
+
+    let mut receipt_fetches_skipped = 0;
+    let deadline = tracking_config
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    let shutdown = watch_ctrl_c();
+    let should_stop =
+        || deadline.is_some_and(|deadline| Instant::now() >= deadline) || *shutdown.borrow();
+    let result = async {
+        let mut last_progress_log = Instant::now();
+        'batches: for batch in block_numbers.chunks(batch_config.blocks_per_batch as usize) {
+            if should_stop() {
+                break;
+            }
+
+            // fetch block data
+            let filtered_pools = pools.len();
+            let blocks_data = match fetch_blocks_info::<N>(
+                &client,
+                batch,
+                batch_config.max_request_bytes,
+                &retry_config,
+                tracking_config,
+                pools,
+            )
+            .instrument(info_span!(
+                target: PIPELINE_TARGET,
+                "fetch",
+                batch.first = batch[0] as i64,
+                batch.last = batch[batch.len() - 1] as i64
+            ))
+            .await
+            {
+                Ok(blocks_data) => blocks_data,
+                Err(e) => {
+                    bail!(
+                        "Failed to grab data for blocks {} to {}: {}",
+                        batch[0],
+                        batch[batch.len() - 1],
+                        e
+                    );
+                }
+            };
+
+            for (&block_number, batch_block) in batch.iter().zip(blocks_data) {
+                // blocks are only ever left between their inserts
+                if should_stop() {
+                    break 'batches;
+                }
+
+                let block_span = block_span(block_number);
+                let receipts = match (batch_block.receipts, batch_block.logs_bloom) {
+                    (Some(receipts), _) => Some(receipts),
+                    // a pool created earlier in the batch can make a filtered out block relevant,
+                    // going backward the remaining blocks are older than the pool
+                    (None, Some(logs_bloom))
+                        if direction == BackfillDirection::Forward
+                            && pools.len() > filtered_pools
+                            && tracking_config.might_have_tracked_logs(&logs_bloom, pools) =>
+                    {
+                        fetch_receipts_batched::<N>(
+                            &client,
+                            &[block_number],
+                            batch_config.max_request_bytes,
+                            &retry_config,
+                        )
+                        .instrument(info_span!(
+                            target: PIPELINE_TARGET,
+                            parent: &block_span,
+                            "fetch"
+                        ))
+                        .await
+                        .wrap_err_with(|| {
+                            format!("Failed to grab receipts for block {}", block_number)
+                        })?
+                        .pop()
+                    }
+                    (None, _) => None,
+                };
+
+                if let Some(receipts) = receipts {
                     debug!(
                         "Successfully grabbed receipts for block {}, receipts length: {}",
                         block_number,
                         receipts.len()
                     );
-                    (receipts, block)
+
+                    // process block for desired events
+                    match get_and_store_events::<N>(
+                        tracking_config,
+                        pools,
+                        receipts,
+                        batch_block.block,
+                        !reporting.quiet,
+                        db_pools,
+                        Some(&token_metadata),
+                    )
+                    .instrument(block_span)
+                    .await
+                    {
+                        Ok(counts) => metrics.events.add(counts),
+                        Err(e) => {
+                            bail!(
+                                "Failed to process block's position activity {}: {}",
+                                block_number,
+                                e
+                            );
+                        }
+                    }
+                } else {
+                    debug!("Block {} has no tracked logs", block_number);
+                    receipt_fetches_skipped += 1;
                 }
-                Err(e) => {
-                    bail!("Failed to grab data for block {}: {}", block_number, e);
+                metrics.blocks_processed += 1;
+                metrics.last_block = block_number;
+                // a backward run leaves the blocks before the one it reached unprocessed
+                if direction == BackfillDirection::Forward {
+                    set_last_processed_block(chain_id, block_number, &mut *db_pools.main.get()?)?;
                 }
-            };
 
-        // process block for desired events
-        match get_and_store_events(
-            pool_deployer_addresses,
-            pools,
-            uniswap_v3_factory_address,
-            receipts,
-            block,
-        )
-        .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                bail!(
-                    "Failed to process block's position activity {}: {}",
-                    block_number,
-                    e
+                if let (Some(pushgateway), Some(push_every_blocks)) =
+                    (&reporting.pushgateway, reporting.push_every_blocks)
+                {
+                    if metrics.blocks_processed.is_multiple_of(push_every_blocks) {
+                        push_metrics(pushgateway, &metrics).await;
+                    }
+                }
+            }
+            if reporting.quiet && last_progress_log.elapsed() >= QUIET_PROGRESS_INTERVAL {
+                info!(
+                    "Processed {} of {} blocks, inserted {} of {} events so far",
+                    metrics.blocks_processed,
+                    metrics.blocks_total,
+                    metrics.events.inserted,
+                    metrics.events.total
                 );
+                last_progress_log = Instant::now();
+            }
+            if !batch_config.delay.is_zero() {
+                tokio::time::sleep(batch_config.delay).await;
             }
         }
-        if delay_ms > 0 {
-            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
-        }
+        Ok(())
     }
-    info!(
-        "Successfully processed blocks from {} to {}",
-        start_block, end_block
-    );
-    Ok(())
-}
+    .await;
 
-pub(crate) async fn live_blocks(
-    http_url: String,
-    wss_url: String,
-    uniswap_v3_factory_address: Address,
-    pool_deployer_addresses: &HashSet<Address>,
-    pools: &mut HashSet<Address>,
-    retry_config: RetryConfig,
-) -> Result<()> {
-    let client = http_connection(http_url)
-        .await
-        .wrap_err("failed to build http")?;
+    // a run stopped by the deadline or Ctrl-C only covers the blocks it processed, the covered
+    // range ends after its last block like the stored run, the remaining one at its last block
+    let processed = metrics.blocks_processed;
+    let (covered, remaining) = match direction {
+        BackfillDirection::Forward => (
+            (start_block, start_block + processed),
+            (start_block + processed, end_block),
+        ),
+        BackfillDirection::Backward => (
+            (run_end - processed, run_end),
+            (start_block, end_block - processed),
+        ),
+    };
+    let stopped_early = result.is_ok() && processed < metrics.blocks_total;
+    if stopped_early {
+        set_backfill_run_range(run_id, covered.0, covered.1, &mut *db_pools.main.get()?)?;
+    }
 
-    let provider = websocket_connection(wss_url).await?;
+    // failed runs are recorded too, with the events stored before the failure
+    record_backfill_run(
+        run_id,
+        result.is_ok(),
+        metrics.events.inserted as u64,
+        &mut *db_pools.main.get()?,
+    )?;
+    result?;
 
-    info!("Connected to provider, subscribing to blocks...");
-    let mut block_stream = provider
-        .subscribe_blocks()
-        .await
-        .context("Failed to subscribe to blocks")?
-        .into_stream();
+    if stopped_early {
+        info!(
+            "{} after processing {} of {} blocks, inserted {} of {} events. Blocks {} to {} are \
+             left",
+            if *shutdown.borrow() {
+                "Shut down by Ctrl-C"
+            } else {
+                "Reached the max runtime"
+            },
+            processed,
+            metrics.blocks_total,
+            metrics.events.inserted,
+            metrics.events.total,
+            remaining.0,
+            remaining.1
+        );
+    } else {
+        info!(
+            "Successfully processed blocks from {} to {}, inserted {} of {} events ({} duplicates \
+             skipped)",
+            start_block,
+            end_block,
+            metrics.events.inserted,
+            metrics.events.total,
+            metrics.events.skipped()
+        );
+    }
+    if tracking_config.bloom_filter {
+        info!(
+            "Bloom filter skipped the receipts of {} of {} blocks",
+            receipt_fetches_skipped, metrics.blocks_total
+        );
+    } else if tracking_config.fetch_logs {
+        info!(
+            "Found no tracked logs in {} of {} blocks",
+            receipt_fetches_skipped, metrics.blocks_total
+        );
+    }
+    if let Some(pushgateway) = &reporting.pushgateway {
+        push_metrics(pushgateway, &metrics).await;
+    }
+    Ok(())
+}
 
-    info!("Successfully subscribed to block stream");
+/// A block of a `blocks_from` batch
+struct BatchBlock<R> {
+    block: BlockInfo,
+    /// `None` if the bloom filter ruled the block out or it has no logs the log filter matched
+    receipts: Option<Vec<R>>,
+    /// Logs bloom of a block the bloom filter ruled out, to check it again against pools created
+    /// earlier in the batch
+    logs_bloom: Option<Bloom>,
+}
 
-    while let Some(block) = block_stream.next().await {
-        let block_number = block.number;
-        // fetch block data
-        let (receipts, block) =
-            match fetch_block_data_batched(&client, block_number, &retry_config).await {
-                Ok((receipts, block)) => {
-                    debug!(
-                        "Successfully grabbed receipts for block {}, receipts length: {}",
-                        block_number,
-                        receipts.len()
-                    );
-                    (receipts, block)
-                }
-                Err(e) => {
-                    bail!("Failed to grab data for block {}: {}", block_number, e);
+/// Fetch the receipts of a batch of blocks along with their headers, or with the header fields
+/// left unknown when `skip_timestamp_fetch` is set.
+///
+/// With `bloom_filter` set, the headers are fetched in one batch first and only blocks whose logs
+/// bloom might contain `pools` or the factory get their receipts fetched. With `fetch_logs` set,
+/// see `fetch_blocks_logs`.
+async fn fetch_blocks_info<N: TrackedNetwork>(
+    client: &HttpClient,
+    block_numbers: &[u64],
+    max_request_bytes: usize,
+    retry_config: &RetryConfig,
+    tracking_config: &TrackingConfig,
+    pools: &HashSet<Address>,
+) -> Result<Vec<BatchBlock<N::ReceiptResponse>>> {
+    if tracking_config.fetch_logs {
+        return fetch_blocks_logs::<N>(
+            client,
+            block_numbers,
+            max_request_bytes,
+            retry_config,
+            tracking_config,
+            pools,
+        )
+        .await;
+    }
+
+    if tracking_config.bloom_filter {
+        let blocks =
+            fetch_blocks_batched::<N>(client, block_numbers, max_request_bytes, retry_config)
+                .await?;
+        let matching_blocks: Vec<u64> = blocks
+            .iter()
+            .map(|block| block.header())
+            .filter(|header| tracking_config.might_have_tracked_logs(&header.logs_bloom(), pools))
+            .map(|header| header.number())
+            .collect();
+        let mut matching_receipts: HashMap<u64, Vec<N::ReceiptResponse>> = matching_blocks
+            .iter()
+            .copied()
+            .zip(
+                fetch_receipts_batched::<N>(
+                    client,
+                    &matching_blocks,
+                    max_request_bytes,
+                    retry_config,
+                )
+                .await?,
+            )
+            .collect();
+        return Ok(blocks
+            .iter()
+            .map(|block| {
+                let receipts = matching_receipts.remove(&block.header().number());
+                BatchBlock {
+                    block: BlockInfo::from_block::<N>(block),
+                    logs_bloom: receipts.is_none().then(|| block.header().logs_bloom()),
+                    receipts,
                 }
-            };
+            })
+            .collect());
+    }
 
-        // process block for desired events
-        match get_and_store_events(
-            pool_deployer_addresses,
-            pools,
-            uniswap_v3_factory_address,
-            receipts,
-            block,
+    if !tracking_config.skip_timestamp_fetch {
+        let blocks_data = fetch_blocks_data_batched::<N>(
+            client,
+            block_numbers,
+            max_request_bytes,
+            tracking_config.rich_transactions,
+            retry_config,
         )
-        .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                bail!(
-                    "Failed to process block's position activity {}: {}",
-                    block_number,
-                    e
-                );
-            }
-        }
+        .await?;
+        return Ok(blocks_data
+            .into_iter()
+            .map(|(receipts, block)| BatchBlock {
+                block: BlockInfo::from_block::<N>(&block),
+                receipts: Some(receipts),
+                logs_bloom: None,
+            })
+            .collect());
     }
 
-    Ok(())
+    let blocks_receipts =
+        fetch_receipts_batched::<N>(client, block_numbers, max_request_bytes, retry_config).await?;
+    block_numbers
+        .iter()
+        .zip(blocks_receipts)
+        .map(|(&block_number, receipts)| {
+            Ok(BatchBlock {
+                block: BlockInfo::from_receipts::<N>(block_number, &receipts)?,
+                receipts: Some(receipts),
+                logs_bloom: None,
+            })
+        })
+        .collect()
 }
 
-// TODO: refactor this to be more modular
-async fn get_and_store_events(
-    pool_deployer_addresses: &HashSet<Address>,
-    pools: &mut HashSet<Address>,
-    uniswap_v3_factory_address: Address,
-    block_receipts: Vec<WithOtherFields<TransactionReceipt<AnyReceiptEnvelope<Log>>>>,
-    block: <AnyNetwork as Network>::BlockResponse,
-) -> Result<()> {
-    // Filter receipts that interact with target pool contracts
-    let filtered_receipts: Vec<_> = block_receipts
-        .into_iter()
-        .filter(
-            |receipt: &WithOtherFields<TransactionReceipt<AnyReceiptEnvelope<Log>>>| {
-                // Check if any logs are from our target contract
-                receipt.inner.inner.inner.logs().iter().any(|log| {
-                    pools.contains(&log.address()) || log.address() == uniswap_v3_factory_address
-                })
-            },
+/// Most addresses an `eth_getLogs` filter is sent with. Providers cap the size of a filter, so
+/// with more tracked addresses the logs of every emitter are fetched and matched afterwards.
+const MAX_LOG_FILTER_ADDRESSES: usize = 1_000;
+
+/// Addresses to filter an `eth_getLogs` request by, none if there are too many to send
+fn log_address_filter(addresses: Option<&HashSet<Address>>) -> Option<Vec<Address>> {
+    addresses
+        .filter(|addresses| addresses.len() <= MAX_LOG_FILTER_ADDRESSES)
+        .map(|addresses| addresses.iter().copied().collect())
+}
+
+/// Fetch the tracked logs of a batch of blocks with `eth_getLogs`, and the receipts of only the
+/// transactions they are in. The receipts are still needed for the transactions' sender,
+/// recipient and index. Headers are only fetched for blocks with logs, unless
+/// `skip_timestamp_fetch` is set.
+///
+/// The factory's `PoolCreated` logs are fetched first, so pools created in the batch are in the
+/// filter for the rest of it. Pools that processing doesn't end up tracking only add logs it
+/// skips.
+async fn fetch_blocks_logs<N: TrackedNetwork>(
+    client: &HttpClient,
+    block_numbers: &[u64],
+    max_request_bytes: usize,
+    retry_config: &RetryConfig,
+    tracking_config: &TrackingConfig,
+    pools: &HashSet<Address>,
+) -> Result<Vec<BatchBlock<N::ReceiptResponse>>> {
+    let (Some(&from_block), Some(&to_block)) =
+        (block_numbers.iter().min(), block_numbers.iter().max())
+    else {
+        return Ok(Vec::new());
+    };
+
+    // any emitter of a pool event is taken to be a pool, so no address is filtered out
+    let addresses: Option<HashSet<Address>> = if tracking_config.decode_all_pool_events {
+        None
+    } else {
+        let created_pools = fetch_logs(
+            client,
+            from_block,
+            to_block,
+            Some(vec![tracking_config.uniswap_v3_factory_address]),
+            vec![PoolCreated::SIGNATURE_HASH],
+            retry_config,
         )
+        .await?
+        .into_iter()
+        .filter_map(|log| PoolCreated::decode_log(&log.inner, true).ok())
+        .map(|pool_created| pool_created.pool);
+        let mut addresses: HashSet<Address> = pools.iter().copied().chain(created_pools).collect();
+        addresses.insert(tracking_config.uniswap_v3_factory_address);
+        Some(addresses)
+    };
+    let topics = TRACKED_EVENT_SIGNATURES
+        .into_iter()
+        .chain(tracking_config.extra_topics.iter().copied())
         .collect();
+    let mut logs = fetch_logs(
+        client,
+        from_block,
+        to_block,
+        log_address_filter(addresses.as_ref()),
+        topics,
+        retry_config,
+    )
+    .await?;
+    // too many addresses to send are matched here instead
+    if let Some(addresses) = &addresses {
+        logs.retain(|log| addresses.contains(&log.address()));
+    }
 
-    let block = Block::new(block.inner.header.number, block.inner.header.timestamp);
-    let mut transactions = HashMap::<TxHash, Transaction>::new();
-    let mut pool_create_events = Vec::<PoolCreateEvent>::new();
-    let mut swaps = Vec::<SwapEvent>::new();
-    let mut initialize_events = Vec::<InitializationEvent>::new();
-    let mut mint_events = Vec::<MintEvent>::new();
-    let mut burn_events = Vec::<BurnEvent>::new();
-    let mut collect_events = Vec::<CollectEvent>::new();
-    // Process the receipts to search for transfers from the
-    for tx in filtered_receipts {
-        for log in tx.inner.inner.inner.logs() {
-            if log.inner.topics().is_empty()
-                || (log.inner.topics()[0] != Swap::SIGNATURE_HASH
-                    && log.inner.topics()[0] != Mint::SIGNATURE_HASH
-                    && log.inner.topics()[0] != Burn::SIGNATURE_HASH
-                    && log.inner.topics()[0] != Collect::SIGNATURE_HASH
-                    && log.inner.topics()[0] != Initialize::SIGNATURE_HASH
-                    && log.inner.topics()[0] != PoolCreated::SIGNATURE_HASH)
-            {
-                continue;
-            }
+    // transactions with tracked logs by block, in the order of their first log
+    let mut block_transactions = BTreeMap::<u64, Vec<TxHash>>::new();
+    for log in &logs {
+        let (Some(block_number), Some(transaction_hash)) = (log.block_number, log.transaction_hash)
+        else {
+            bail!("log without a block number or transaction hash: {:?}", log);
+        };
+        let transactions = block_transactions.entry(block_number).or_default();
+        if !transactions.contains(&transaction_hash) {
+            transactions.push(transaction_hash);
+        }
+    }
+    let transaction_hashes: Vec<TxHash> = block_transactions.values().flatten().copied().collect();
+    let mut receipts = fetch_transaction_receipts_batched::<N>(
+        client,
+        &transaction_hashes,
+        max_request_bytes,
+        retry_config,
+    )
+    .await?
+    .into_iter();
+    let mut blocks_receipts = HashMap::<u64, Vec<N::ReceiptResponse>>::new();
+    for (&block_number, transactions) in &block_transactions {
+        let block_receipts: Vec<N::ReceiptResponse> =
+            receipts.by_ref().take(transactions.len()).collect();
+        // a reorg between the two requests can move a transaction to another block
+        check_receipts_block_number::<N>(block_number, &block_receipts)?;
+        blocks_receipts.insert(block_number, block_receipts);
+    }
 
-            // create log object and processes events
-            if let Some(abi_log) = AbiLog::new(
-                log.address(),
-                log.topics().to_vec(),
-                log.data().data.clone(),
-            ) {
-                match log.inner.topics()[0] {
-                    PoolCreated::SIGNATURE_HASH => {
-                        if let Ok(pool_create_event) = PoolCreated::decode_log(&abi_log, true) {
-                            if log.address() != uniswap_v3_factory_address {
-                                // event not from target factory
-                                continue;
-                            }
-                            if let Some(pool_address) = tx.inner.to {
-                                if !pool_deployer_addresses.contains(&pool_address) {
-                                    // pool not from target deployers
-                                    continue;
-                                }
-                            } else {
-                                // pool not from target deployers
-                                continue;
-                            }
+    let matching_blocks: Vec<u64> = block_transactions.into_keys().collect();
+    let mut blocks_info = HashMap::<u64, BlockInfo>::new();
+    if tracking_config.skip_timestamp_fetch {
+        for &block_number in &matching_blocks {
+            let block =
+                BlockInfo::from_receipts::<N>(block_number, &blocks_receipts[&block_number])?;
+            blocks_info.insert(block_number, block);
+        }
+    } else {
+        let blocks =
+            fetch_blocks_batched::<N>(client, &matching_blocks, max_request_bytes, retry_config)
+                .await?;
+        for block in &blocks {
+            blocks_info.insert(block.header().number(), BlockInfo::from_block::<N>(block));
+        }
+    }
 
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            debug!("pool_create_event: {:?}", pool_create_event);
-                            let pool_create_event =
-                                PoolCreateEvent::new(log.clone(), pool_create_event);
-
-                            if let Ok(pool_create_event) = pool_create_event {
-                                // track pool in pools set
-                                pools.insert(pool_create_event.pool);
-
-                                // add to pool create events
-                                pool_create_events.push(pool_create_event);
-                            } else {
-                                bail!("Failed to create pool create event from: {:?}", log);
-                            }
-                        }
-                    }
-                    Initialize::SIGNATURE_HASH => {
-                        if let Ok(initialize_event) = Initialize::decode_log(&abi_log, true) {
-                            if !pools.contains(&log.address()) {
-                                continue;
-                            }
-                            debug!("initialize_event: {:?}", initialize_event);
-
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            // build initialization event
-                            let initialize_event = InitializationEvent::new(
-                                log.clone(),
-                                initialize_event,
-                                tx.inner.from,
-                            );
-                            if let Ok(initialize_event) = initialize_event {
-                                initialize_events.push(initialize_event);
-                            } else {
-                                bail!("Failed to create initialize event from: {:?}", log);
-                            }
-                        }
-                    }
-                    Swap::SIGNATURE_HASH => {
-                        if let Ok(swap_event) = Swap::decode_log(&abi_log, true) {
-                            if !pools.contains(&log.address()) {
-                                continue;
-                            }
-                            debug!("swap_event: {:?}", swap_event);
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            // build swap event
-                            let swap_event = SwapEvent::new(log.clone(), swap_event);
-                            if let Ok(swap_event) = swap_event {
-                                swaps.push(swap_event);
-                            } else {
-                                bail!("Failed to create swap event from: {:?}", log);
-                            }
-                        }
-                    }
-                    Mint::SIGNATURE_HASH => {
-                        if let Ok(mint_event) = Mint::decode_log(&abi_log, true) {
-                            if !pools.contains(&log.address()) {
-                                continue;
-                            }
-                            debug!("mint_event: {:?}", mint_event);
-
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            // build mint event
-                            let mint_event = MintEvent::new(log.clone(), mint_event);
-                            if let Ok(mint_event) = mint_event {
-                                mint_events.push(mint_event);
-                            } else {
-                                bail!("Failed to create mint event from: {:?}", log);
-                            }
-                        }
-                    }
-                    Burn::SIGNATURE_HASH => {
-                        if let Ok(burn_event) = Burn::decode_log(&abi_log, true) {
-                            if !pools.contains(&log.address()) {
-                                continue;
-                            }
-                            debug!("burn_event: {:?}", burn_event);
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            // build burn event
-                            let burn_event = BurnEvent::new(log.clone(), burn_event);
-                            if let Ok(burn_event) = burn_event {
-                                burn_events.push(burn_event);
-                            } else {
-                                bail!("Failed to create burn event from: {:?}", log);
-                            }
-                        }
-                    }
-                    Collect::SIGNATURE_HASH => {
-                        if let Ok(collect_event) = Collect::decode_log(&abi_log, true) {
-                            if !pools.contains(&log.address()) {
-                                continue;
-                            }
-                            debug!("collect_event: {:?}", collect_event);
-
-                            // build transaction data struct if not already in map
-                            transactions.entry(tx.inner.transaction_hash).or_insert({
-                                let transaction_data = Transaction::new(tx.inner.from, log.clone());
-                                if let Ok(transaction_data) = transaction_data {
-                                    transaction_data
-                                } else {
-                                    bail!("Failed to create transaction data from: {:?}", log);
-                                }
-                            });
-
-                            // build collect event
-                            let collect_event = CollectEvent::new(log.clone(), collect_event);
-                            if let Ok(collect_event) = collect_event {
-                                collect_events.push(collect_event);
-                            } else {
-                                bail!("Failed to create collect event from: {:?}", log);
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
+    block_numbers
+        .iter()
+        .map(|&block_number| {
+            Ok(match blocks_info.remove(&block_number) {
+                Some(block) => BatchBlock {
+                    block,
+                    receipts: blocks_receipts.remove(&block_number),
+                    logs_bloom: None,
+                },
+                // never stored, the block has nothing to process
+                None => BatchBlock {
+                    block: BlockInfo::from_receipts::<N>(block_number, &[])?,
+                    receipts: None,
+                    logs_bloom: None,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Fill in the timestamps and base fees of blocks stored by `--skip-timestamp-fetch` runs,
+/// returns the number of blocks filled in
+pub async fn backfill_timestamps<N: TrackedNetwork>(
+    http_url: String,
+    request_id_prefix: Option<String>,
+    retry_config: RetryConfig,
+    batch_config: BatchConfig,
+) -> Result<u64> {
+    let client = http_connection(http_url, request_id_prefix)
+        .await
+        .wrap_err("failed to build http")?;
     let mut db_connection = establish_connection()?;
 
-    // insert events into db if swaps exist
-    if !swaps.is_empty()
-        || !initialize_events.is_empty()
-        || !mint_events.is_empty()
-        || !burn_events.is_empty()
-        || !collect_events.is_empty()
-    {
-        info!(
-            "Found in block {}:\n  pool_create_events: {}\n  swaps: {}\n  mint_events: {}\n  \
-             burn_events: {}\n  collect_events: {}\n  initialize_events: {}",
-            block.block_number,
-            pool_create_events.len(),
-            swaps.len(),
-            mint_events.len(),
-            burn_events.len(),
-            collect_events.len(),
-            initialize_events.len()
-        );
-        let result = put_events_into_db(
-            block,
-            transactions,
-            pool_create_events,
-            swaps,
-            initialize_events,
-            mint_events,
-            burn_events,
-            collect_events,
+    let mut filled = 0;
+    let mut after_block = -1;
+    loop {
+        let block_numbers: Vec<u64> = BlockRaw::find_missing_timestamp(
+            after_block,
+            batch_config.blocks_per_batch as i64,
             &mut db_connection,
+        )?
+        .into_iter()
+        .map(|block_number| block_number as u64)
+        .collect();
+        let Some(&last_block) = block_numbers.last() else {
+            break;
+        };
+        after_block = last_block as i64;
+
+        let blocks = fetch_blocks_batched::<N>(
+            &client,
+            &block_numbers,
+            batch_config.max_request_bytes,
+            &retry_config,
+        )
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "Failed to grab blocks {} to {}",
+                block_numbers[0], last_block
+            )
+        })?;
+        for block in blocks {
+            let header = block.header();
+            BlockRaw::set_header_fields(
+                header.number() as i64,
+                header.timestamp() as i64,
+                header.base_fee_per_gas(),
+                &mut db_connection,
+            )?;
+        }
+
+        filled += block_numbers.len() as u64;
+        info!(
+            "Filled in timestamps of {} blocks, up to block {}",
+            filled, last_block
         );
-        if result.is_err() {
-            bail!(
-                "Failed to put swap events into db: {}",
-                result.err().unwrap()
+        if !batch_config.delay.is_zero() {
+            tokio::time::sleep(batch_config.delay).await;
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Push backfill metrics, a failed push is only logged so it doesn't stop the backfill
+async fn push_metrics(pushgateway: &Pushgateway, metrics: &BackfillMetrics) {
+    match pushgateway.push(metrics).await {
+        Ok(()) => debug!("Pushed metrics at block {}", metrics.last_block),
+        Err(e) => warn!("Failed to push metrics: {}", e),
+    }
+}
+
+#[expect(
+    clippy::too_many_arguments,
+    reason = "connection settings and the per-run configs are all independent"
+)]
+pub async fn live_blocks<N: TrackedNetwork>(
+    http_url: Option<String>,
+    wss_url: String,
+    receipt_transport: ReceiptTransport,
+    dedupe_window: usize,
+    tracking_config: &TrackingConfig,
+    pools: &mut HashSet<Address>,
+    retry_config: RetryConfig,
+    exporter: Option<EventExporter>,
+    db_pools: &DbPools,
+) -> Result<()> {
+    let http_client = match http_url {
+        Some(http_url) => Some(
+            http_connection(http_url, tracking_config.request_id_prefix.clone())
+                .await
+                .wrap_err("failed to build http")?,
+        ),
+        None => None,
+    };
+
+    // token metadata is read over http, so it is left out without HTTP_URL
+    let token_metadata = http_client
+        .clone()
+        .map(|client| {
+            TokenMetadataCache::new(client, retry_config.clone(), &mut *db_pools.main.get()?)
+        })
+        .transpose()?;
+    if token_metadata.is_none() {
+        warn!("HTTP_URL is not set, the metadata of new pools' tokens won't be stored");
+    }
+
+    // without an http client, receipts are fetched over the subscription's websocket
+    let client = match receipt_transport {
+        ReceiptTransport::Http => {
+            if http_client.is_none() {
+                bail!("HTTP_URL is required to fetch receipts over http");
+            }
+            http_client
+        }
+        ReceiptTransport::Ws => None,
+    };
+
+    let mut tracker = LiveTracker::new(
+        tracking_config,
+        pools,
+        &retry_config,
+        exporter,
+        db_pools,
+        token_metadata,
+        dedupe_window,
+    )?;
+
+    let (mut provider, mut block_stream) = subscribe_blocks(wss_url.clone()).await?;
+
+    info!("Successfully subscribed to block stream");
+
+    let deadline = tracking_config
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    let mut shutdown = watch_ctrl_c();
+    // set when the subscription was re-established, until the first head after it arrives
+    let mut reconnected = false;
+    'heads: loop {
+        let head = tokio::select! {
+            head = block_stream.next() => head,
+            Ok(_) = shutdown.wait_for(|&requested| requested) => break,
+        };
+        let Some(head) = head else {
+            warn!("Block subscription ended, reconnecting");
+            (provider, block_stream) =
+                retry_with_backoff(|| subscribe_blocks(wss_url.clone()), &retry_config)
+                    .await
+                    .wrap_err("failed to reconnect to the block subscription")?;
+            info!("Reconnected to the block subscription");
+            reconnected = true;
+            continue;
+        };
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            info!(
+                "Reached the max runtime, stopping before block {}",
+                head.number
             );
+            break;
+        }
+        let missed_blocks = tracker.missed_blocks(head.number);
+        if std::mem::take(&mut reconnected) {
+            info!(
+                "Backfilling {} blocks missed while reconnecting, before block {}",
+                missed_blocks.clone().count(),
+                head.number
+            );
+        } else if !missed_blocks.is_empty() {
+            warn!(
+                "Subscription skipped blocks {} to {}, fetching them before block {}",
+                missed_blocks.start,
+                missed_blocks.end - 1,
+                head.number
+            );
+        }
+        if tracker.is_redelivered(head.number, head.hash) {
+            debug!("Skipping re-delivered block {}", head.number);
+            continue;
+        }
+
+        for block_number in tracker.confirmed_blocks(missed_blocks, head.number) {
+            // blocks are only ever left between their inserts
+            if *shutdown.borrow() {
+                break 'heads;
+            }
+            let source = match &client {
+                Some(client) => BlockSource::Http(client),
+                None => BlockSource::Ws(&provider),
+            };
+            tracker.process_block::<N>(source, block_number).await?;
         }
-    } else {
-        info!("No events found in block {}", block.block_number);
+    }
+    if *shutdown.borrow() {
+        info!("Shut down by Ctrl-C");
     }
 
     Ok(())
 }
 
-fn put_events_into_db(
-    block: Block,
-    transactions: HashMap<TxHash, Transaction>,
-    pool_create_events: Vec<PoolCreateEvent>,
-    swap_events: Vec<SwapEvent>,
-    initialize_events: Vec<InitializationEvent>,
-    mint_events: Vec<MintEvent>,
-    burn_events: Vec<BurnEvent>,
-    collect_events: Vec<CollectEvent>,
-    db_connection: &mut PgConnection,
+/// Like `live_blocks`, finding new blocks by polling the HTTP endpoint's block number every
+/// `poll_interval` instead of subscribing to them, for providers without websockets
+pub async fn poll_blocks<N: TrackedNetwork>(
+    http_url: String,
+    poll_interval: Duration,
+    tracking_config: &TrackingConfig,
+    pools: &mut HashSet<Address>,
+    retry_config: RetryConfig,
+    exporter: Option<EventExporter>,
+    db_pools: &DbPools,
 ) -> Result<()> {
-    // convert swapevents to swapeventraw
-    let block_raw = block.try_into().unwrap();
-    let pool_create_events_raw = pool_create_events
-        .into_iter()
-        .map(|pool_create_event| pool_create_event.try_into().unwrap())
-        .collect();
-    let swap_events_raw = swap_events
-        .into_iter()
-        .map(|swap_event| swap_event.try_into().unwrap())
-        .collect();
-    let transactions_raw = transactions
-        .into_iter()
-        .map(|(_, transaction)| transaction.try_into().unwrap())
-        .collect();
-    let initialize_events_raw = initialize_events
-        .into_iter()
-        .map(|initialize_event| initialize_event.try_into().unwrap())
-        .collect();
-    let mint_events_raw = mint_events
-        .into_iter()
-        .map(|mint_event| mint_event.try_into().unwrap())
-        .collect();
-    let burn_events_raw = burn_events
-        .into_iter()
-        .map(|burn_event| burn_event.try_into().unwrap())
-        .collect();
-    let collect_events_raw = collect_events
-        .into_iter()
-        .map(|collect_event| collect_event.try_into().unwrap())
-        .collect();
-    insert_block_events(
-        block_raw,
-        transactions_raw,
-        pool_create_events_raw,
-        swap_events_raw,
-        initialize_events_raw,
-        mint_events_raw,
-        burn_events_raw,
-        collect_events_raw,
-        db_connection,
-    )
+    let client = http_connection(http_url, tracking_config.request_id_prefix.clone())
+        .await
+        .wrap_err("failed to build http")?;
+    let token_metadata = TokenMetadataCache::new(
+        client.clone(),
+        retry_config.clone(),
+        &mut *db_pools.main.get()?,
+    )?;
+
+    // polled numbers are never delivered twice, so there is nothing to dedupe
+    let mut tracker = LiveTracker::new(
+        tracking_config,
+        pools,
+        &retry_config,
+        exporter,
+        db_pools,
+        Some(token_metadata),
+        0,
+    )?;
+
+    info!("Polling for new blocks every {:?}", poll_interval);
+
+    let deadline = tracking_config
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    let mut shutdown = watch_ctrl_c();
+    // a slow poll delays the next one instead of being followed by a burst of them
+    let mut polls = tokio::time::interval(poll_interval);
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    'polls: loop {
+        tokio::select! {
+            _ = polls.tick() => {}
+            Ok(_) = shutdown.wait_for(|&requested| requested) => break,
+        }
+        let head_number = fetch_block_number(&client, &retry_config)
+            .await
+            .wrap_err("failed to fetch the latest block number")?;
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            info!(
+                "Reached the max runtime, stopping before block {}",
+                head_number
+            );
+            break;
+        }
+        if tracker
+            .last_block()
+            .is_some_and(|last_block| head_number <= last_block)
+        {
+            continue;
+        }
+        let missed_blocks = tracker.missed_blocks(head_number);
+        if !missed_blocks.is_empty() {
+            debug!(
+                "Blocks {} to {} appeared since the last poll",
+                missed_blocks.start, head_number
+            );
+        }
+
+        for block_number in tracker.confirmed_blocks(missed_blocks, head_number) {
+            // blocks are only ever left between their inserts
+            if *shutdown.borrow() {
+                break 'polls;
+            }
+            tracker
+                .process_block::<N>(BlockSource::Http(&client), block_number)
+                .await?;
+        }
+    }
+    if *shutdown.borrow() {
+        info!("Shut down by Ctrl-C");
+    }
+
+    Ok(())
+}
+
+/// Number of processed blocks live modes remember the hashes of. Older blocks are checked against
+/// the hashes stored in `blocks`.
+const REORG_WINDOW: usize = 64;
+
+/// Deepest reorg live modes roll back when `--reorg-depth-limit` isn't given
+pub const DEFAULT_REORG_DEPTH_LIMIT: usize = 64;
+
+/// Spaces out the reorg rollbacks of live modes, so a provider flapping between two chains can't
+/// make them delete and re-insert the same blocks over and over
+#[derive(Debug)]
+struct ReorgCooldown {
+    cooldown: Option<Duration>,
+    last_rollback: Option<Instant>,
+}
+
+impl ReorgCooldown {
+    fn new(cooldown: Option<Duration>) -> Self {
+        Self {
+            cooldown,
+            last_rollback: None,
+        }
+    }
+
+    /// Time left until the next rollback is allowed, `None` if it is allowed now
+    fn remaining(&self) -> Option<Duration> {
+        let elapsed = self.last_rollback?.elapsed();
+        self.cooldown?
+            .checked_sub(elapsed)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    fn record_rollback(&mut self) {
+        self.last_rollback = Some(Instant::now());
+    }
+}
+
+/// Blocks live modes have seen and processed, and what they need to process the next ones
+struct LiveTracker<'a> {
+    tracking_config: &'a TrackingConfig,
+    pools: &'a mut HashSet<Address>,
+    retry_config: &'a RetryConfig,
+    exporter: Option<EventExporter>,
+    db_pools: &'a DbPools,
+    /// `None` when there is no HTTP endpoint to read token metadata from
+    token_metadata: Option<TokenMetadataCache>,
+    dedupe_window: usize,
+    /// (number, hash) of the last `dedupe_window` processed blocks, oldest first
+    recent_blocks: VecDeque<(u64, B256)>,
+    /// (number, hash) of the last `REORG_WINDOW` processed blocks, to check new heads' parents
+    processed_heads: VecDeque<(u64, B256)>,
+    /// numbers of the blocks seen at the head that aren't `confirmations` deep yet, oldest first
+    pending_blocks: VecDeque<u64>,
+    /// Number of blocks the deepest reorg of the run orphaned
+    deepest_reorg: usize,
+    reorg_cooldown: ReorgCooldown,
+}
+
+impl<'a> LiveTracker<'a> {
+    /// Catches `exporter` up with the events stored before this run
+    fn new(
+        tracking_config: &'a TrackingConfig,
+        pools: &'a mut HashSet<Address>,
+        retry_config: &'a RetryConfig,
+        mut exporter: Option<EventExporter>,
+        db_pools: &'a DbPools,
+        token_metadata: Option<TokenMetadataCache>,
+        dedupe_window: usize,
+    ) -> Result<Self> {
+        if let Some(exporter) = exporter.as_mut() {
+            let exported = exporter
+                .export_new_events()
+                .wrap_err("failed to export stored events")?;
+            info!("Exported {} previously stored events", exported);
+        }
+
+        Ok(Self {
+            tracking_config,
+            pools,
+            retry_config,
+            exporter,
+            db_pools,
+            token_metadata,
+            dedupe_window,
+            recent_blocks: VecDeque::with_capacity(dedupe_window),
+            processed_heads: VecDeque::with_capacity(REORG_WINDOW),
+            pending_blocks: VecDeque::new(),
+            deepest_reorg: 0,
+            reorg_cooldown: ReorgCooldown::new(tracking_config.reorg_cooldown),
+        })
+    }
+
+    /// Number of the last block seen at the head, processed or waiting for confirmations
+    fn last_block(&self) -> Option<u64> {
+        self.pending_blocks
+            .back()
+            .copied()
+            .or_else(|| self.processed_heads.back().map(|&(number, _)| number))
+    }
+
+    /// Blocks between the last block seen and a new head. They were skipped by the source of new
+    /// heads, or came while it was down, and are fetched by number before the head so the stored
+    /// blocks stay contiguous.
+    fn missed_blocks(&self, head_number: u64) -> Range<u64> {
+        self.last_block()
+            .map_or(0..0, |last_block| last_block + 1..head_number)
+    }
+
+    /// Whether a head was processed already with the same hash, remembering it otherwise. Only
+    /// heads processed as they arrive are remembered, within the last `dedupe_window` blocks.
+    fn is_redelivered(&mut self, head_number: u64, head_hash: B256) -> bool {
+        if self.tracking_config.confirmations > 0 || self.dedupe_window == 0 {
+            return false;
+        }
+        match self
+            .recent_blocks
+            .iter()
+            .position(|&(number, _)| number == head_number)
+        {
+            Some(i) if self.recent_blocks[i].1 == head_hash => return true,
+            Some(i) => {
+                // a new hash for a processed number is a reorg, the new block is processed
+                warn!(
+                    "Block {} was delivered again with a different hash ({} then {}), \
+                     processing it again",
+                    head_number, self.recent_blocks[i].1, head_hash
+                );
+                self.recent_blocks.remove(i);
+            }
+            None => {}
+        }
+        if self.recent_blocks.len() == self.dedupe_window {
+            self.recent_blocks.pop_front();
+        }
+        self.recent_blocks.push_back((head_number, head_hash));
+        false
+    }
+
+    /// Blocks to process for a new head, oldest first: the missed blocks and the head itself, or
+    /// with `confirmations` the queued blocks that are now deep enough
+    fn confirmed_blocks(&mut self, missed_blocks: Range<u64>, head_number: u64) -> Vec<u64> {
+        let confirmations = self.tracking_config.confirmations;
+        if confirmations == 0 {
+            return missed_blocks.chain([head_number]).collect();
+        }
+
+        // blocks are fetched by number once they are deep enough, so a head delivered again
+        // adds nothing and the missed blocks are queued with the new head
+        let next_block = self
+            .pending_blocks
+            .back()
+            .map_or(head_number, |&number| number + 1);
+        self.pending_blocks.extend(next_block..=head_number);
+        let mut confirmed_blocks = Vec::new();
+        while let Some(&number) = self.pending_blocks.front() {
+            if number + confirmations > head_number {
+                break;
+            }
+            confirmed_blocks.push(number);
+            self.pending_blocks.pop_front();
+        }
+        confirmed_blocks
+    }
+
+    /// Fetch and store a block, first rolling back the blocks it orphaned, and export its events
+    #[tracing::instrument(
+        target = "pipeline",
+        name = "block",
+        skip_all,
+        fields(block.number = block_number as i64)
+    )]
+    async fn process_block<N: TrackedNetwork>(
+        &mut self,
+        source: BlockSource<'_>,
+        block_number: u64,
+    ) -> Result<()> {
+        let tracking_config = self.tracking_config;
+        let db_pools = self.db_pools;
+
+        // a reorg within the cooldown of the last rollback waits it out, then the chain is
+        // checked again from the new block
+        let (receipts, block, mut orphaned, canonical_blocks) = loop {
+            // fetch block data
+            let block_data =
+                fetch_live_block_data::<N>(source, block_number, tracking_config, self.retry_config)
+                    .instrument(info_span!(target: PIPELINE_TARGET, "fetch"))
+                    .await;
+            let (receipts, block) = match block_data {
+                Ok((receipts, block)) => {
+                    debug!(
+                        "Successfully grabbed receipts for block {}, receipts length: {}",
+                        block_number,
+                        receipts.len()
+                    );
+                    (receipts, block)
+                }
+                Err(e) => {
+                    bail!("Failed to grab data for block {}: {}", block_number, e);
+                }
+            };
+            let block = BlockInfo::from_block::<N>(&block);
+
+            // roll back the blocks the new block's chain replaced, the new block's number and
+            // above if it has a new hash, and below it until the parent hashes match again
+            let mut orphaned = Vec::new();
+            if !self.processed_heads.contains(&(block_number, block.hash)) {
+                orphaned.extend(
+                    self.processed_heads
+                        .iter()
+                        .filter(|&&(number, _)| number >= block_number),
+                );
+            }
+            let mut canonical_blocks = Vec::new();
+            let mut parent = (block_number.saturating_sub(1), block.parent_hash);
+            loop {
+                let known_hash = match self
+                    .processed_heads
+                    .iter()
+                    .find(|&&(number, _)| number == parent.0)
+                {
+                    Some(&(_, hash)) => Some(hash),
+                    None => {
+                        BlockRaw::find_block_hash(parent.0 as i64, &mut *db_pools.main.get()?)?
+                    }
+                };
+                let Some(known_hash) = known_hash
+                    .filter(|&hash| parent.1.is_some_and(|parent_hash| hash != parent_hash))
+                else {
+                    break;
+                };
+                // nothing is deleted until the whole reorg is known, so a halt leaves the
+                // database as it was
+                if orphaned.len() >= tracking_config.reorg_depth_limit {
+                    bail!(
+                        "Reorg at block {} is deeper than the reorg depth limit of {} blocks, \
+                         halting without rolling anything back. Check that the provider serves \
+                         the right chain before restarting, or raise --reorg-depth-limit.",
+                        block_number,
+                        tracking_config.reorg_depth_limit
+                    );
+                }
+                orphaned.push((parent.0, known_hash));
+
+                let (receipts, canonical_block) =
+                    fetch_live_block_data::<N>(source, parent.0, tracking_config, self.retry_config)
+                        .instrument(info_span!(
+                            target: PIPELINE_TARGET,
+                            "fetch",
+                            block.number = parent.0 as i64
+                        ))
+                        .await
+                        .wrap_err_with(|| format!("Failed to grab canonical block {}", parent.0))?;
+                parent = (
+                    parent.0.saturating_sub(1),
+                    Some(canonical_block.header().parent_hash()),
+                );
+                canonical_blocks.push((receipts, canonical_block));
+            }
+            if !orphaned.is_empty() {
+                if let Some(remaining) = self.reorg_cooldown.remaining() {
+                    warn!(
+                        "Reorg at block {} within the reorg cooldown of the last rollback, the \
+                         provider may be flapping between chains. Pausing ingestion for {:?} \
+                         before checking the chain again.",
+                        block_number, remaining
+                    );
+                    tokio::time::sleep(remaining).await;
+                    continue;
+                }
+            }
+            break (receipts, block, orphaned, canonical_blocks);
+        };
+        if !orphaned.is_empty() {
+            orphaned.sort_unstable();
+            self.reorg_cooldown.record_rollback();
+            self.deepest_reorg = self.deepest_reorg.max(orphaned.len());
+            warn!(
+                "Reorg at block {}, rolling back {} orphaned blocks from block {} (deepest this \
+                 run: {})",
+                block_number,
+                orphaned.len(),
+                orphaned[0].0,
+                self.deepest_reorg
+            );
+            for &(number, _) in orphaned.iter().rev() {
+                let mut swap_conn = db_pools.swap.as_ref().map(|swap| swap.get()).transpose()?;
+                let rolled_back = delete_block_events(
+                    number,
+                    &mut *db_pools.main.get()?,
+                    swap_conn.as_deref_mut(),
+                )?;
+                info!(
+                    "Deleted {} events of orphaned block {}",
+                    rolled_back.events, number
+                );
+                for pool in &rolled_back.pools {
+                    self.pools.remove(pool);
+                }
+            }
+            self.processed_heads.retain(|head| !orphaned.contains(head));
+            // an orphaned block delivered again has to be processed again
+            self.recent_blocks.retain(|head| !orphaned.contains(head));
+
+            // events exported before the rollback stay exported, the canonical ones follow
+            for (receipts, canonical_block) in canonical_blocks.into_iter().rev() {
+                let canonical_block = BlockInfo::from_block::<N>(&canonical_block);
+                let head = (canonical_block.number, canonical_block.hash);
+                get_and_store_events::<N>(
+                    tracking_config,
+                    self.pools,
+                    receipts,
+                    canonical_block,
+                    true,
+                    db_pools,
+                    self.token_metadata.as_ref(),
+                )
+                .instrument(block_span(head.0))
+                .await
+                .wrap_err_with(|| format!("Failed to process canonical block {}", head.0))?;
+                remember_head(&mut self.processed_heads, REORG_WINDOW, head);
+            }
+        }
+
+        // process block for desired events
+        let head = (block.number, block.hash);
+        match get_and_store_events::<N>(
+            tracking_config,
+            self.pools,
+            receipts,
+            block,
+            true,
+            db_pools,
+            self.token_metadata.as_ref(),
+        )
+        .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                bail!(
+                    "Failed to process block's position activity {}: {}",
+                    block_number,
+                    e
+                );
+            }
+        }
+        remember_head(&mut self.processed_heads, REORG_WINDOW, head);
+
+        // export the events committed for this block
+        if let Some(exporter) = self.exporter.as_mut() {
+            let exported = exporter
+                .export_new_events()
+                .wrap_err("failed to export new events")?;
+            debug!(
+                "Exported {} new events from block {}",
+                exported, block_number
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Add a processed block to a window of the last `capacity` ones
+fn remember_head(heads: &mut VecDeque<(u64, B256)>, capacity: usize, head: (u64, B256)) {
+    if heads.len() == capacity {
+        heads.pop_front();
+    }
+    heads.push_back(head);
+}
+
+/// Connect to the websocket provider and subscribe to new heads
+async fn subscribe_blocks(
+    wss_url: String,
+) -> Result<(
+    Arc<RootProvider<PubSubFrontend, AnyNetwork>>,
+    impl Stream<Item = <AnyNetwork as Network>::HeaderResponse>,
+)> {
+    let provider = websocket_connection(wss_url).await?;
+
+    info!("Connected to provider, subscribing to blocks...");
+    count_rpc_calls("eth_subscribe", 1);
+    let block_stream = provider
+        .subscribe_blocks()
+        .await
+        .context("Failed to subscribe to blocks")?
+        .into_stream();
+
+    Ok((provider, block_stream))
+}
+
+/// Connection live modes fetch blocks over
+#[derive(Clone, Copy)]
+enum BlockSource<'a> {
+    Http(&'a HttpClient),
+    /// The subscription's websocket
+    Ws(&'a RootProvider<PubSubFrontend, AnyNetwork>),
+}
+
+/// Fetch a block for live modes
+async fn fetch_live_block_data<N: TrackedNetwork>(
+    source: BlockSource<'_>,
+    block_number: u64,
+    tracking_config: &TrackingConfig,
+    retry_config: &RetryConfig,
+) -> Result<(Vec<N::ReceiptResponse>, N::BlockResponse)> {
+    match source {
+        BlockSource::Http(client) => {
+            fetch_block_data_batched::<N>(
+                client,
+                block_number,
+                tracking_config.rich_transactions,
+                retry_config,
+            )
+            .await
+        }
+        BlockSource::Ws(provider) => {
+            fetch_block_data_over::<N, _>(
+                provider.client(),
+                block_number,
+                tracking_config.rich_transactions,
+                retry_config,
+            )
+            .await
+        }
+    }
+}
+
+/// Read a pool's `slot0` and `liquidity` at the end of `block_number` and store them as a
+/// snapshot
+pub async fn snapshot_pool(
+    client: &HttpClient,
+    pool: Address,
+    block_number: u64,
+    retry_config: RetryConfig,
+) -> Result<PoolSnapshot> {
+    let block_id = BlockId::number(block_number);
+
+    let (slot0, liquidity) = client
+        .call_with_failover(
+            |provider| async move {
+                let pool_contract = UniswapV3Pool::new(pool, provider);
+                count_rpc_calls("eth_call", 2);
+                let slot0 = pool_contract.slot0().block(block_id).call().await?;
+                let liquidity = pool_contract.liquidity().block(block_id).call().await?;
+                Ok((slot0, liquidity._0))
+            },
+            &retry_config,
+        )
+        .await
+        .wrap_err_with(|| format!("failed to read pool state at block {}", block_number))?;
+
+    let snapshot = PoolSnapshot {
+        pool,
+        block_number,
+        sqrt_price_x96: slot0.sqrtPriceX96,
+        tick: slot0.tick,
+        liquidity: U128::from(liquidity),
+    };
+    debug!("Read pool snapshot {:?}", snapshot);
+
+    let snapshot_raw: PoolSnapshotRaw = match PoolSnapshot::try_into(snapshot.clone()) {
+        Ok(snapshot_raw) => snapshot_raw,
+        Err(e) => bail!("Failed to convert pool snapshot: {}", e),
+    };
+    let mut db_connection = establish_connection()?;
+    if !snapshot_raw.insert_if_not_exists(&mut db_connection)? {
+        debug!(
+            "Snapshot of {} at block {} was already stored",
+            fmt_addr(pool),
+            block_number
+        );
+    }
+
+    Ok(snapshot)
+}
+
+/// Span of a block's processing, see [`crate::telemetry`]. The number is recorded as an `i64`,
+/// OpenTelemetry has no unsigned integers and would record a `u64` as a string.
+fn block_span(block_number: u64) -> tracing::Span {
+    info_span!(target: PIPELINE_TARGET, "block", block.number = block_number as i64)
+}
+
+/// Process a block, dumping its receipts to `save_failed_receipts` if processing fails.
+///
+/// Pools the block adds to `pools` are removed again if it fails, so the set never tracks a
+/// pool the database has no record of.
+async fn get_and_store_events<N: TrackedNetwork>(
+    tracking_config: &TrackingConfig,
+    pools: &mut HashSet<Address>,
+    block_receipts: Vec<N::ReceiptResponse>,
+    block: BlockInfo,
+    log_per_block: bool,
+    db_pools: &DbPools,
+    token_metadata: Option<&TokenMetadataCache>,
+) -> Result<InsertCounts> {
+    let block_number = block.number;
+    let receipts_dump = tracking_config
+        .save_failed_receipts
+        .as_ref()
+        .map(|dump_dir| (dump_dir, block_receipts.clone()));
+    let mut added_pools = Vec::new();
+    let result = extract_and_store_events::<N>(
+        tracking_config,
+        pools,
+        &mut added_pools,
+        block_receipts,
+        block,
+        log_per_block,
+        db_pools,
+        token_metadata,
+    )
+    .await;
+    if result.is_err() {
+        for pool in &added_pools {
+            pools.remove(pool);
+        }
+        if !added_pools.is_empty() {
+            warn!(
+                "Stopped tracking {} pools added by failed block {}",
+                added_pools.len(),
+                block_number
+            );
+        }
+
+        if let Some((dump_dir, receipts_dump)) = receipts_dump {
+            match save_receipts(dump_dir, block_number, &receipts_dump) {
+                Ok(path) => info!(
+                    "Saved receipts of failed block {} to {}",
+                    block_number,
+                    path.display()
+                ),
+                Err(e) => warn!(
+                    "Failed to save receipts of failed block {}: {}",
+                    block_number, e
+                ),
+            }
+        }
+    }
+    result
+}
+
+/// The parts of a block that are stored or hashed
+#[derive(Clone, Debug)]
+struct BlockInfo {
+    number: u64,
+    hash: B256,
+    /// `None` when the header wasn't fetched
+    parent_hash: Option<B256>,
+    timestamp: u64,
+    /// `None` for blocks before EIP-1559 or when the header wasn't fetched
+    base_fee_per_gas: Option<u64>,
+    /// Details of the block's transactions by hash, empty unless the block was fetched with its
+    /// full transactions
+    transaction_details: HashMap<TxHash, TransactionDetails>,
+}
+
+impl BlockInfo {
+    fn from_block<N: TrackedNetwork>(block: &N::BlockResponse) -> Self {
+        let header = block.header();
+        let transaction_details = block
+            .transactions()
+            .txns()
+            .map(|tx| {
+                let input = tx.input();
+                let details = TransactionDetails {
+                    value: tx.value(),
+                    nonce: tx.nonce(),
+                    input_selector: input.slice(..input.len().min(4)),
+                };
+                (tx.tx_hash(), details)
+            })
+            .collect();
+        Self {
+            number: header.number(),
+            hash: header.hash(),
+            parent_hash: Some(header.parent_hash()),
+            timestamp: header.timestamp(),
+            base_fee_per_gas: header.base_fee_per_gas(),
+            transaction_details,
+        }
+    }
+
+    /// Info of a block fetched without its header. The hash is taken from the receipts and the
+    /// timestamp is left as `MISSING_BLOCK_TIMESTAMP` for `backfill_timestamps` to fill in.
+    fn from_receipts<N: TrackedNetwork>(
+        number: u64,
+        receipts: &[N::ReceiptResponse],
+    ) -> Result<Self> {
+        // a block without receipts has no events to store, so its hash isn't needed
+        let hash = match receipts.first() {
+            Some(receipt) => match receipt.block_hash() {
+                Some(hash) => hash,
+                None => bail!("receipts of block {} have no block hash", number),
+            },
+            None => B256::ZERO,
+        };
+        Ok(Self {
+            number,
+            hash,
+            parent_hash: None,
+            timestamp: MISSING_BLOCK_TIMESTAMP,
+            base_fee_per_gas: None,
+            transaction_details: HashMap::new(),
+        })
+    }
+}
+
+/// Hash identifying what a block stores: the block hash, which commits to all of its logs, and
+/// the log indexes stored in each event table.
+///
+/// A re-run over the same chain with the same filtering gets the same hash, while a reorg or a
+/// change in which events are kept changes it.
+fn block_content_hash(block_hash: B256, stored_log_indexes: &[Vec<u64>]) -> B256 {
+    let mut hasher = Keccak256::new();
+    hasher.update(block_hash);
+    for log_indexes in stored_log_indexes {
+        // the count keeps the tables apart
+        hasher.update((log_indexes.len() as u64).to_be_bytes());
+        for log_index in log_indexes {
+            hasher.update(log_index.to_be_bytes());
+        }
+    }
+    hasher.finalize()
+}
+
+/// Signatures of the events that are decoded and stored
+const TRACKED_EVENT_SIGNATURES: [B256; 9] = [
+    PoolCreated::SIGNATURE_HASH,
+    Swap::SIGNATURE_HASH,
+    Initialize::SIGNATURE_HASH,
+    Mint::SIGNATURE_HASH,
+    Burn::SIGNATURE_HASH,
+    Collect::SIGNATURE_HASH,
+    Flash::SIGNATURE_HASH,
+    CollectProtocol::SIGNATURE_HASH,
+    SetFeeProtocol::SIGNATURE_HASH,
+];
+
+/// Log how many of a block's logs with a tracked event signature ended up decoded and stored.
+///
+/// Logs with a tracked signature from an untracked address are expected (other Uniswap V3
+/// pools), but a drop in the decoded share over a range points at a filtering bug or a stale
+/// pool set.
+fn log_qa_stats<N: TrackedNetwork>(
+    block_number: u64,
+    block_receipts: &[N::ReceiptResponse],
+    pools: &HashSet<Address>,
+    tracking_config: &TrackingConfig,
+    decoded: usize,
+) {
+    let stats = QaStats::for_block::<N>(block_receipts, pools, tracking_config, decoded);
+    info!(
+        "QA block {}: {} logs scanned, {} with a tracked event signature, {} decoded ({:.1}%), {} \
+         from untracked addresses",
+        block_number,
+        stats.scanned,
+        stats.signature_matches,
+        stats.decoded,
+        stats.decoded_percent(),
+        stats.untracked
+    );
+}
+
+/// Counts of a block's logs logged by `log_qa_stats`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct QaStats {
+    scanned: usize,
+    /// Logs with a tracked event signature
+    signature_matches: usize,
+    decoded: usize,
+    /// Logs with a tracked event signature from an untracked address
+    untracked: usize,
+}
+
+impl QaStats {
+    fn for_block<N: TrackedNetwork>(
+        block_receipts: &[N::ReceiptResponse],
+        pools: &HashSet<Address>,
+        tracking_config: &TrackingConfig,
+        decoded: usize,
+    ) -> Self {
+        let mut stats = Self {
+            scanned: 0,
+            signature_matches: 0,
+            decoded,
+            untracked: 0,
+        };
+        for log in block_receipts
+            .iter()
+            .flat_map(|receipt| N::receipt_logs(receipt))
+        {
+            stats.scanned += 1;
+            if log
+                .topics()
+                .first()
+                .is_some_and(|topic| TRACKED_EVENT_SIGNATURES.contains(topic))
+            {
+                stats.signature_matches += 1;
+                if tracking_config.classify_address(log.address(), pools) == AddressRole::Untracked
+                {
+                    stats.untracked += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Share of the logs with a tracked event signature that were decoded, 100% if there are none
+    fn decoded_percent(&self) -> f64 {
+        if self.signature_matches == 0 {
+            100.0
+        } else {
+            self.decoded as f64 / self.signature_matches as f64 * 100.0
+        }
+    }
+}
+
+/// Log what happened to a log of a traced block: its emitter's role, how its first topic was
+/// recognized, and whether it was decoded or why not
+fn trace_log(
+    tracking_config: &TrackingConfig,
+    transaction_hash: TxHash,
+    log: &Log,
+    role: AddressRole,
+    outcome: &str,
+) {
+    let (topic0, topic_kind) = match log.topics().first() {
+        Some(topic) if tracking_config.extra_topics.contains(topic) => {
+            (topic.to_string(), "extra topic")
+        }
+        Some(topic) if TRACKED_EVENT_SIGNATURES.contains(topic) => {
+            (topic.to_string(), "tracked event signature")
+        }
+        Some(topic) => (topic.to_string(), "unknown topic"),
+        None => ("none".to_string(), "no topics"),
+    };
+    info!(
+        "Trace log {:?} of {}: address {} ({:?}), topic0 {} ({}): {}",
+        log.log_index,
+        transaction_hash,
+        fmt_addr(log.address()),
+        role,
+        topic0,
+        topic_kind,
+        outcome
+    );
+}
+
+/// Write a block's receipts as JSON to `<dir>/<block_number>.json`
+fn save_receipts<R: Serialize>(dir: &Path, block_number: u64, receipts: &[R]) -> Result<PathBuf> {
+    fs::create_dir_all(dir).wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.json", block_number));
+    let file =
+        fs::File::create(&path).wrap_err_with(|| format!("failed to create {}", path.display()))?;
+    serde_json::to_writer_pretty(io::BufWriter::new(file), receipts)?;
+    Ok(path)
+}
+
+// TODO: refactor this to be more modular
+#[expect(
+    clippy::too_many_arguments,
+    reason = "the block's context is passed through from get_and_store_events"
+)]
+async fn extract_and_store_events<N: TrackedNetwork>(
+    tracking_config: &TrackingConfig,
+    pools: &mut HashSet<Address>,
+    added_pools: &mut Vec<Address>,
+    mut block_receipts: Vec<N::ReceiptResponse>,
+    block: BlockInfo,
+    log_per_block: bool,
+    db_pools: &DbPools,
+    token_metadata: Option<&TokenMetadataCache>,
+) -> Result<InsertCounts> {
+    // Events are processed in execution order, so a pool created earlier in the block is
+    // already tracked when its first events are reached
+    block_receipts.sort_by_key(|receipt| receipt.transaction_index());
+
+    let mut db_connection = db_pools.main.get()?;
+    let mut swap_db_connection = db_pools.swap.as_ref().map(|pool| pool.get()).transpose()?;
+
+    let block_hash = block.hash;
+    let transaction_details = block.transaction_details;
+    let mut block = Block::new(
+        block.number,
+        block.hash,
+        block.parent_hash,
+        block.timestamp,
+        block.base_fee_per_gas,
+    );
+    let mut transactions = HashMap::<TxHash, Transaction>::new();
+    let mut pool_create_events = Vec::<PoolCreateEvent>::new();
+    let mut swaps = Vec::<SwapEvent>::new();
+    let mut initialize_events = Vec::<InitializationEvent>::new();
+    let mut mint_events = Vec::<MintEvent>::new();
+    let mut burn_events = Vec::<BurnEvent>::new();
+    let mut collect_events = Vec::<CollectEvent>::new();
+    let mut flash_events = Vec::<FlashEvent>::new();
+    let mut collect_protocol_events = Vec::<CollectProtocolEvent>::new();
+    let mut set_fee_protocol_events = Vec::<SetFeeProtocolEvent>::new();
+    let mut raw_logs = Vec::<RawLog>::new();
+    let mut inferred_pools = Vec::<Address>::new();
+    let mut reverted_transactions = 0;
+    let decode_span = info_span!(target: PIPELINE_TARGET, "decode").entered();
+    // Process the receipts to search for transfers from the
+    for tx in &block_receipts {
+        // a reverted transaction's logs were rolled back with it
+        if !tx.status() {
+            reverted_transactions += 1;
+            continue;
+        }
+
+        // Skip receipts that don't interact with target pool contracts
+        let mut logs: Vec<&Log> = N::receipt_logs(tx).iter().collect();
+        if !logs.iter().any(|log| {
+            tracking_config.classify_address(log.address(), pools) != AddressRole::Untracked
+                || (tracking_config.decode_all_pool_events
+                    && log
+                        .inner
+                        .topics()
+                        .first()
+                        .is_some_and(|topic| TRACKED_EVENT_SIGNATURES.contains(topic)))
+        }) {
+            if tracking_config.trace_logs {
+                for log in logs {
+                    trace_log(
+                        tracking_config,
+                        tx.transaction_hash(),
+                        log,
+                        AddressRole::Untracked,
+                        "no log of its transaction is from a tracked address",
+                    );
+                }
+            }
+            continue;
+        }
+        logs.sort_by_key(|log| log.log_index);
+
+        for log in logs {
+            let mut role = tracking_config.classify_address(log.address(), pools);
+            let outcome = 'log: {
+                // store logs with a configured extra topic as is
+                if log
+                    .inner
+                    .topics()
+                    .first()
+                    .is_some_and(|topic| tracking_config.extra_topics.contains(topic))
+                    && role != AddressRole::Untracked
+                {
+                    debug!("raw_log: {:?}", log);
+                    let Some(raw_log) =
+                        tracking_config.on_bad_log(RawLog::new(log), "create raw log", log)?
+                    else {
+                        break 'log "failed to build the raw log";
+                    };
+
+                    // build transaction data struct if not already in map
+                    if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                        let Some(transaction_data) = tracking_config.on_bad_log(
+                            Transaction::new(
+                                tx.from(),
+                                tx.transaction_index(),
+                                tx.status(),
+                                log.clone(),
+                            ),
+                            "create transaction data",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the transaction";
+                        };
+                        entry.insert(transaction_data);
+                    }
+                    raw_logs.push(raw_log);
+                    break 'log "stored as a raw log";
+                }
+
+                if log.inner.topics().is_empty()
+                    || !TRACKED_EVENT_SIGNATURES.contains(&log.inner.topics()[0])
+                {
+                    break 'log "no tracked event signature";
+                }
+
+                // any emitter of a pool event is taken to be a pool
+                if tracking_config.decode_all_pool_events
+                    && log.inner.topics()[0] != PoolCreated::SIGNATURE_HASH
+                    && role == AddressRole::Untracked
+                {
+                    debug!("inferred pool: {}", fmt_addr(log.address()));
+                    pools.insert(log.address());
+                    added_pools.push(log.address());
+                    inferred_pools.push(log.address());
+                    role = AddressRole::Pool;
+                }
+
+                // only the factory's `PoolCreated` and the pools' other events are decoded
+                let expected_role = if log.inner.topics()[0] == PoolCreated::SIGNATURE_HASH {
+                    AddressRole::Factory
+                } else {
+                    AddressRole::Pool
+                };
+                if role != expected_role {
+                    break 'log if expected_role == AddressRole::Factory {
+                        "not from the tracked factory"
+                    } else {
+                        "not from a tracked pool"
+                    };
+                }
+
+                // create log object and processes events
+                let Some(abi_log) = tracking_config.on_bad_log(
+                    AbiLog::new(
+                        log.address(),
+                        log.topics().to_vec(),
+                        log.data().data.clone(),
+                    )
+                    .ok_or("more than 4 topics"),
+                    "decode",
+                    log,
+                )?
+                else {
+                    break 'log "failed to decode";
+                };
+                match log.inner.topics()[0] {
+                    PoolCreated::SIGNATURE_HASH => {
+                        let Some(pool_create_event) = tracking_config.on_bad_log(
+                            PoolCreated::decode_log(&abi_log, true),
+                            "decode pool create event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        if !tracking_config.is_tracked_deployer(tx.to()) {
+                            // pool not from target deployers
+                            break 'log "pool not from a tracked deployer";
+                        }
+                        if !tracking_config.is_tracked_token_pair(
+                            pool_create_event.token0,
+                            pool_create_event.token1,
+                        ) {
+                            // pool without the target token
+                            break 'log "pool without the tracked token";
+                        }
+                        if !tracking_config.is_expected_pool_address(
+                            pool_create_event.token0,
+                            pool_create_event.token1,
+                            pool_create_event.fee,
+                            pool_create_event.pool,
+                        ) {
+                            warn!(
+                                "PoolCreated for {} is not at the CREATE2 address of its tokens \
+                                 and fee, skipping",
+                                fmt_addr(pool_create_event.pool)
+                            );
+                            break 'log "pool not at its CREATE2 address";
+                        }
+
+                        debug!("pool_create_event: {:?}", pool_create_event);
+                        let Some(pool_create_event) = tracking_config.on_bad_log(
+                            PoolCreateEvent::new(log.clone(), pool_create_event),
+                            "create pool create event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // an already tracked pool must match its stored record
+                        if pools.contains(&pool_create_event.pool)
+                            && !matches_stored_pool(&pool_create_event, &mut db_connection)?
+                        {
+                            warn!(
+                                "PoolCreated for tracked pool {} has different tokens or fee than \
+                                 the stored pool",
+                                fmt_addr(pool_create_event.pool)
+                            );
+                            if tracking_config.strict_factory {
+                                break 'log "pool differs from the stored pool";
+                            }
+                        }
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        // track pool in pools set
+                        if pools.insert(pool_create_event.pool) {
+                            added_pools.push(pool_create_event.pool);
+                        }
+
+                        // add to pool create events
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::PoolCreate(&pool_create_event), &block);
+                        pool_create_events.push(pool_create_event);
+                        "decoded"
+                    }
+                    Initialize::SIGNATURE_HASH => {
+                        let Some(initialize_event) = tracking_config.on_bad_log(
+                            Initialize::decode_log(&abi_log, true),
+                            "decode initialize event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("initialize_event: {:?}", initialize_event);
+
+                        // build initialization event
+                        let Some(initialize_event) = tracking_config.on_bad_log(
+                            InitializationEvent::new(log.clone(), initialize_event, tx.from()),
+                            "create initialize event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Initialize(&initialize_event), &block);
+                        initialize_events.push(initialize_event);
+                        "decoded"
+                    }
+                    Swap::SIGNATURE_HASH => {
+                        let Some(swap_event) = tracking_config.on_bad_log(
+                            Swap::decode_log(&abi_log, true),
+                            "decode swap event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("swap_event: {:?}", swap_event);
+
+                        // build swap event
+                        let Some(swap_event) = tracking_config.on_bad_log(
+                            SwapEvent::new(log.clone(), swap_event),
+                            "create swap event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // a real swap pays one token into the pool for the other
+                        let (amount0, amount1) = (swap_event.amount0, swap_event.amount1);
+                        if (amount0.is_inflow() && amount1.is_inflow())
+                            || (amount0.is_outflow() && amount1.is_outflow())
+                        {
+                            warn!(
+                                "Swap at log {} of pool {} moves both tokens the same way ({}, {})",
+                                swap_event.log_index,
+                                fmt_addr(swap_event.contract_address),
+                                amount0,
+                                amount1
+                            );
+                        }
+
+                        // drop swaps that fall outside of the configured sample
+                        if !keep_sampled_swap(
+                            swap_event.transaction_hash,
+                            swap_event.log_index,
+                            tracking_config.swap_sample_rate,
+                        ) {
+                            break 'log "swap not in the sample";
+                        }
+
+                        // drop dust swaps below the configured minimum amounts
+                        if !tracking_config.meets_min_swap_amounts(&swap_event) {
+                            break 'log "swap below the minimum amounts";
+                        }
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Swap(&swap_event), &block);
+                        swaps.push(swap_event);
+                        "decoded"
+                    }
+                    Mint::SIGNATURE_HASH => {
+                        let Some(mint_event) = tracking_config.on_bad_log(
+                            Mint::decode_log(&abi_log, true),
+                            "decode mint event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("mint_event: {:?}", mint_event);
+
+                        // build mint event
+                        let Some(mint_event) = tracking_config.on_bad_log(
+                            MintEvent::new(log.clone(), mint_event),
+                            "create mint event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Mint(&mint_event), &block);
+                        mint_events.push(mint_event);
+                        "decoded"
+                    }
+                    Burn::SIGNATURE_HASH => {
+                        let Some(burn_event) = tracking_config.on_bad_log(
+                            Burn::decode_log(&abi_log, true),
+                            "decode burn event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("burn_event: {:?}", burn_event);
+
+                        // build burn event
+                        let Some(burn_event) = tracking_config.on_bad_log(
+                            BurnEvent::new(log.clone(), burn_event),
+                            "create burn event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Burn(&burn_event), &block);
+                        burn_events.push(burn_event);
+                        "decoded"
+                    }
+                    Collect::SIGNATURE_HASH => {
+                        let Some(collect_event) = tracking_config.on_bad_log(
+                            Collect::decode_log(&abi_log, true),
+                            "decode collect event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("collect_event: {:?}", collect_event);
+
+                        // build collect event
+                        let Some(collect_event) = tracking_config.on_bad_log(
+                            CollectEvent::new(log.clone(), collect_event),
+                            "create collect event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Collect(&collect_event), &block);
+                        collect_events.push(collect_event);
+                        "decoded"
+                    }
+                    Flash::SIGNATURE_HASH => {
+                        let Some(flash_event) = tracking_config.on_bad_log(
+                            Flash::decode_log(&abi_log, true),
+                            "decode flash event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("flash_event: {:?}", flash_event);
+
+                        // build flash event
+                        let Some(flash_event) = tracking_config.on_bad_log(
+                            FlashEvent::new(log.clone(), flash_event),
+                            "create flash event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::Flash(&flash_event), &block);
+                        flash_events.push(flash_event);
+                        "decoded"
+                    }
+                    CollectProtocol::SIGNATURE_HASH => {
+                        let Some(collect_protocol_event) = tracking_config.on_bad_log(
+                            CollectProtocol::decode_log(&abi_log, true),
+                            "decode collect protocol event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("collect_protocol_event: {:?}", collect_protocol_event);
+
+                        // build collect protocol event
+                        let Some(collect_protocol_event) = tracking_config.on_bad_log(
+                            CollectProtocolEvent::new(log.clone(), collect_protocol_event),
+                            "create collect protocol event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::CollectProtocol(&collect_protocol_event), &block);
+                        collect_protocol_events.push(collect_protocol_event);
+                        "decoded"
+                    }
+                    SetFeeProtocol::SIGNATURE_HASH => {
+                        let Some(set_fee_protocol_event) = tracking_config.on_bad_log(
+                            SetFeeProtocol::decode_log(&abi_log, true),
+                            "decode set fee protocol event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to decode";
+                        };
+                        debug!("set_fee_protocol_event: {:?}", set_fee_protocol_event);
+
+                        // build set fee protocol event
+                        let Some(set_fee_protocol_event) = tracking_config.on_bad_log(
+                            SetFeeProtocolEvent::new(log.clone(), set_fee_protocol_event),
+                            "create set fee protocol event",
+                            log,
+                        )?
+                        else {
+                            break 'log "failed to build the event";
+                        };
+
+                        // build transaction data struct if not already in map
+                        if let Entry::Vacant(entry) = transactions.entry(tx.transaction_hash()) {
+                            let Some(transaction_data) = tracking_config.on_bad_log(
+                                Transaction::new(
+                                    tx.from(),
+                                    tx.transaction_index(),
+                                    tx.status(),
+                                    log.clone(),
+                                ),
+                                "create transaction data",
+                                log,
+                            )?
+                            else {
+                                break 'log "failed to build the transaction";
+                            };
+                            entry.insert(transaction_data);
+                        }
+
+                        tracking_config
+                            .event_hooks
+                            .run(AnyEvent::SetFeeProtocol(&set_fee_protocol_event), &block);
+                        set_fee_protocol_events.push(set_fee_protocol_event);
+                        "decoded"
+                    }
+                    _ => "no tracked event signature",
+                }
+            };
+            if tracking_config.trace_logs {
+                trace_log(tracking_config, tx.transaction_hash(), log, role, outcome);
+            }
+        }
+    }
+
+    // join the stored transactions to the full transactions fetched with the block
+    if tracking_config.rich_transactions {
+        for (transaction_hash, transaction) in &mut transactions {
+            let Some(details) = transaction_details.get(transaction_hash) else {
+                bail!(
+                    "Block {} has no full transaction {}",
+                    block.block_number,
+                    transaction_hash
+                );
+            };
+            transaction.details = Some(details.clone());
+        }
+    }
+
+    drop(decode_span);
+
+    if reverted_transactions > 0 {
+        debug!(
+            "Skipped {} reverted transactions in block {}",
+            reverted_transactions, block.block_number
+        );
+    }
+
+    if tracking_config.qa_stats {
+        let decoded = pool_create_events.len()
+            + swaps.len()
+            + initialize_events.len()
+            + mint_events.len()
+            + burn_events.len()
+            + collect_events.len()
+            + flash_events.len()
+            + collect_protocol_events.len()
+            + set_fee_protocol_events.len();
+        log_qa_stats::<N>(
+            block.block_number,
+            &block_receipts,
+            pools,
+            tracking_config,
+            decoded,
+        );
+    }
+
+    // insert events into db if any tracked event exists, a block can hold only a pool's creation
+    if !pool_create_events.is_empty()
+        || !swaps.is_empty()
+        || !initialize_events.is_empty()
+        || !mint_events.is_empty()
+        || !burn_events.is_empty()
+        || !collect_events.is_empty()
+        || !flash_events.is_empty()
+        || !collect_protocol_events.is_empty()
+        || !set_fee_protocol_events.is_empty()
+        || !raw_logs.is_empty()
+    {
+        if log_per_block {
+            info!(
+                "Found in block {}:\n  pool_create_events: {}\n  swaps: {}\n  mint_events: {}\n  \
+                 burn_events: {}\n  collect_events: {}\n  flash_events: {}\n  \
+                 collect_protocol_events: {}\n  set_fee_protocol_events: {}\n  \
+                 initialize_events: {}\n  raw_logs: {}\n  inferred_pools: {}",
+                block.block_number,
+                pool_create_events.len(),
+                swaps.len(),
+                mint_events.len(),
+                burn_events.len(),
+                collect_events.len(),
+                flash_events.len(),
+                collect_protocol_events.len(),
+                set_fee_protocol_events.len(),
+                initialize_events.len(),
+                raw_logs.len(),
+                inferred_pools.len()
+            );
+        }
+        let block_number = block.block_number;
+        let content_hash = block_content_hash(
+            block_hash,
+            &[
+                pool_create_events
+                    .iter()
+                    .map(|event| event.log_index)
+                    .collect(),
+                swaps.iter().map(|event| event.log_index).collect(),
+                initialize_events
+                    .iter()
+                    .map(|event| event.log_index)
+                    .collect(),
+                mint_events.iter().map(|event| event.log_index).collect(),
+                burn_events.iter().map(|event| event.log_index).collect(),
+                collect_events.iter().map(|event| event.log_index).collect(),
+                flash_events.iter().map(|event| event.log_index).collect(),
+                collect_protocol_events
+                    .iter()
+                    .map(|event| event.log_index)
+                    .collect(),
+                set_fee_protocol_events
+                    .iter()
+                    .map(|event| event.log_index)
+                    .collect(),
+                raw_logs.iter().map(|raw_log| raw_log.log_index).collect(),
+            ],
+        );
+        if tracking_config.skip_unchanged_blocks
+            && BlockRaw::find_content_hash(block_number as i64, &mut db_connection)?
+                == Some(content_hash)
+        {
+            if log_per_block {
+                info!(
+                    "Block {} is unchanged since it was stored, skipping",
+                    block_number
+                );
+            }
+            return Ok(InsertCounts {
+                inserted: 0,
+                total: pool_create_events.len()
+                    + swaps.len()
+                    + initialize_events.len()
+                    + mint_events.len()
+                    + burn_events.len()
+                    + collect_events.len()
+                    + flash_events.len()
+                    + collect_protocol_events.len()
+                    + set_fee_protocol_events.len()
+                    + raw_logs.len(),
+            });
+        }
+        block.content_hash = Some(content_hash);
+
+        // read before the insert, so a token that can't be read fails the block before any of it
+        // is stored
+        let tokens = match token_metadata {
+            Some(token_metadata) => {
+                token_metadata
+                    .tokens(
+                        pool_create_events
+                            .iter()
+                            .flat_map(|event| [event.token0, event.token1]),
+                    )
+                    .await?
+            }
+            None => Vec::new(),
+        };
+
+        let mut attempts = 0;
+        let mut backoff = tracking_config
+            .db_retry
+            .as_ref()
+            .map(|retry| retry.initial_backoff)
+            .unwrap_or_default();
+        let counts = loop {
+            attempts += 1;
+            // inserts skip stored rows, so an attempt that stored part of the block is completed
+            let store_span = info_span!(target: PIPELINE_TARGET, "store", attempt = attempts);
+            let result = store_span.in_scope(|| {
+                put_events_into_db(
+                    block.clone(),
+                    transactions.clone(),
+                    pool_create_events.clone(),
+                    swaps.clone(),
+                    initialize_events.clone(),
+                    mint_events.clone(),
+                    burn_events.clone(),
+                    collect_events.clone(),
+                    flash_events.clone(),
+                    collect_protocol_events.clone(),
+                    set_fee_protocol_events.clone(),
+                    raw_logs.clone(),
+                    inferred_pools.clone(),
+                    tokens.clone(),
+                    tracking_config.track_pool_state,
+                    &mut db_connection,
+                    swap_db_connection.as_deref_mut(),
+                    tracking_config.db_isolation,
+                )
+            });
+            match result {
+                Ok(counts) => break counts,
+                Err(e) => {
+                    let Some(retry) = tracking_config.db_retry.as_ref() else {
+                        bail!("Failed to put swap events into db: {}", e);
+                    };
+                    if !is_connection_error(&e) || attempts >= retry.max_attempts {
+                        bail!("Failed to put swap events into db: {}", e);
+                    }
+
+                    warn!(
+                        "Lost database connection inserting block {} (attempt {}/{}), \
+                         reconnecting in {:?}: {}",
+                        block_number, attempts, retry.max_attempts, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = Duration::from_secs_f64(
+                        (backoff.as_secs_f64() * retry.backoff_multiplier)
+                            .min(retry.max_backoff.as_secs_f64()),
+                    );
+
+                    // if the database is still down, the next attempt fails as a connection error
+                    // again and is retried
+                    match (
+                        db_pools.main.get(),
+                        db_pools.swap.as_ref().map(|pool| pool.get()).transpose(),
+                    ) {
+                        (Ok(connection), Ok(swap_connection)) => {
+                            db_connection = connection;
+                            swap_db_connection = swap_connection;
+                        }
+                        (Err(e), _) | (_, Err(e)) => {
+                            warn!("Failed to reconnect to the database: {}", e);
+                        }
+                    }
+                }
+            }
+        };
+        if log_per_block {
+            info!(
+                "Block {}: inserted {} of {} events ({} duplicates skipped)",
+                block_number,
+                counts.inserted,
+                counts.total,
+                counts.skipped()
+            );
+        }
+        Ok(counts)
+    } else {
+        if log_per_block {
+            info!("No events found in block {}", block.block_number);
+        }
+        Ok(InsertCounts::default())
+    }
+}
+
+#[expect(
+    clippy::too_many_arguments,
+    reason = "one argument per event table, mirrors insert_block_events"
+)]
+fn put_events_into_db(
+    block: Block,
+    transactions: HashMap<TxHash, Transaction>,
+    pool_create_events: Vec<PoolCreateEvent>,
+    swap_events: Vec<SwapEvent>,
+    initialize_events: Vec<InitializationEvent>,
+    mint_events: Vec<MintEvent>,
+    burn_events: Vec<BurnEvent>,
+    collect_events: Vec<CollectEvent>,
+    flash_events: Vec<FlashEvent>,
+    collect_protocol_events: Vec<CollectProtocolEvent>,
+    set_fee_protocol_events: Vec<SetFeeProtocolEvent>,
+    raw_logs: Vec<RawLog>,
+    inferred_pools: Vec<Address>,
+    tokens: Vec<Token>,
+    track_pool_state: bool,
+    db_connection: &mut PgConnection,
+    swap_db_connection: Option<&mut PgConnection>,
+    db_isolation: IsolationLevel,
+) -> Result<InsertCounts> {
+    // convert swapevents to swapeventraw
+    let inferred_pools_raw = inferred_pools
+        .into_iter()
+        .map(|pool| InferredPoolRaw {
+            pool: pool.to_vec(),
+            first_seen_block: block.block_number as i64,
+        })
+        .collect();
+    let block_number = block.block_number as i64;
+    let block_raw =
+        BlockRaw::try_from(block).map_err(|e| eyre!("failed to convert block: {}", e))?;
+    let pool_create_events_raw: Vec<PoolCreateEventRaw> = pool_create_events
+        .into_iter()
+        .map(PoolCreateEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert pool create event: {}", e))?;
+    let swap_events_raw: Vec<SwapEventRaw> = swap_events
+        .into_iter()
+        .map(SwapEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert swap event: {}", e))?;
+    let transactions_raw = transactions
+        .into_values()
+        .map(TransactionRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert transaction: {}", e))?;
+    let initialize_events_raw: Vec<InitializationEventRaw> = initialize_events
+        .into_iter()
+        .map(InitializationEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert initialize event: {}", e))?;
+    let pool_states = if track_pool_state {
+        PoolCurrentStateRaw::latest_in_block(block_number, &swap_events_raw, &initialize_events_raw)
+    } else {
+        Vec::new()
+    };
+    let mint_events_raw = mint_events
+        .into_iter()
+        .map(MintEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert mint event: {}", e))?;
+    let burn_events_raw = burn_events
+        .into_iter()
+        .map(BurnEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert burn event: {}", e))?;
+    let collect_events_raw = collect_events
+        .into_iter()
+        .map(CollectEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert collect event: {}", e))?;
+    let flash_events_raw = flash_events
+        .into_iter()
+        .map(FlashEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert flash event: {}", e))?;
+    let collect_protocol_events_raw = collect_protocol_events
+        .into_iter()
+        .map(CollectProtocolEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert collect protocol event: {}", e))?;
+    let set_fee_protocol_events_raw = set_fee_protocol_events
+        .into_iter()
+        .map(SetFeeProtocolEventRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert set fee protocol event: {}", e))?;
+    let raw_logs_raw = raw_logs
+        .into_iter()
+        .map(RawLogRaw::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|e| eyre!("failed to convert raw log: {}", e))?;
+    insert_block_events(
+        block_raw,
+        transactions_raw,
+        pool_create_events_raw,
+        swap_events_raw,
+        initialize_events_raw,
+        mint_events_raw,
+        burn_events_raw,
+        collect_events_raw,
+        flash_events_raw,
+        collect_protocol_events_raw,
+        set_fee_protocol_events_raw,
+        raw_logs_raw,
+        inferred_pools_raw,
+        tokens.into_iter().map(TokenRaw::from).collect(),
+        pool_states,
+        db_connection,
+        swap_db_connection,
+        db_isolation,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        consensus::{
+            Eip658Value,
+            Receipt,
+            ReceiptEnvelope,
+            ReceiptWithBloom,
+        },
+        network::Ethereum,
+        primitives::{
+            address,
+            aliases::{
+                I24,
+                U160,
+            },
+            Bloom,
+            Bytes,
+            LogData,
+            I256,
+        },
+        rpc::types::TransactionReceipt,
+    };
+    use bigdecimal::BigDecimal;
+    use diesel::r2d2::{
+        ConnectionManager,
+        Pool,
+    };
+    use serde_json::{
+        json,
+        Value,
+    };
+
+    use super::*;
+    use crate::{
+        pool_sql::database_interactions::{
+            establish_connection,
+            find_all_pools_with_metadata,
+            find_tracked_pools_with_token,
+            get_last_processed_block,
+            tests::{
+                block as stored_block,
+                insert_swaps,
+                migrate_once,
+                test_db_pools,
+                CommittedBlock,
+            },
+            transaction_order,
+        },
+        rpc::{
+            http_connection,
+            tests::{
+                closed_endpoint,
+                serve_json_rpc,
+            },
+        },
+        telemetry::tests::RecordedSpans,
+    };
+
+    const FACTORY: Address = address!("33128a8fC17869897dcE68Ed026d694621f6FDfD");
+    const DEPLOYER: Address = address!("375C15db32D28cEcdcAB5C03Ab889bf15cbD2c5E");
+    const POOL: Address = address!("1000000000000000000000000000000000000001");
+    const TOKEN0: Address = address!("2000000000000000000000000000000000000002");
+    const TOKEN1: Address = address!("3000000000000000000000000000000000000003");
+
+    fn tracking_config() -> TrackingConfig {
+        TrackingConfig::new(FACTORY, HashSet::from([DEPLOYER]))
+    }
+
+    fn block_info(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            hash: B256::with_last_byte(number as u8),
+            parent_hash: Some(B256::with_last_byte(number as u8 - 1)),
+            timestamp: 1_700_000_000 + number * 2,
+            base_fee_per_gas: None,
+            transaction_details: HashMap::new(),
+        }
+    }
+
+    fn pool_created(pool: Address) -> LogData {
+        PoolCreated {
+            token0: TOKEN0,
+            token1: TOKEN1,
+            fee: U24::from(10_000),
+            tickSpacing: I24::try_from(200).unwrap(),
+            pool,
+        }
+        .encode_log_data()
+    }
+
+    fn swap(amount0: i64, amount1: i64) -> LogData {
+        Swap {
+            sender: DEPLOYER,
+            recipient: DEPLOYER,
+            amount0: I256::try_from(amount0).unwrap(),
+            amount1: I256::try_from(amount1).unwrap(),
+            sqrtPriceX96: U160::from(1) << 96,
+            liquidity: 1_000,
+            tick: I24::ZERO,
+        }
+        .encode_log_data()
+    }
+
+    /// Successful receipt of a transaction sent to `to` in `block`, emitting `logs`
+    fn receipt(
+        block: &BlockInfo,
+        transaction_index: u64,
+        to: Address,
+        logs: Vec<(Address, LogData)>,
+    ) -> TransactionReceipt {
+        let transaction_hash = B256::with_last_byte(transaction_index as u8 + 1);
+        let logs = logs
+            .into_iter()
+            .enumerate()
+            .map(|(log_index, (address, data))| Log {
+                inner: alloy::primitives::Log { address, data },
+                block_hash: Some(block.hash),
+                block_number: Some(block.number),
+                block_timestamp: None,
+                transaction_hash: Some(transaction_hash),
+                transaction_index: Some(transaction_index),
+                log_index: Some(log_index as u64),
+                removed: false,
+            })
+            .collect();
+        TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(ReceiptWithBloom {
+                receipt: Receipt {
+                    status: Eip658Value::Eip658(true),
+                    cumulative_gas_used: 100_000,
+                    logs,
+                },
+                logs_bloom: Bloom::default(),
+            }),
+            transaction_hash,
+            transaction_index: Some(transaction_index),
+            block_hash: Some(block.hash),
+            block_number: Some(block.number),
+            gas_used: 100_000,
+            effective_gas_price: 1,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from: address!("4000000000000000000000000000000000000004"),
+            to: Some(to),
+            contract_address: None,
+            authorization_list: None,
+        }
+    }
+
+    #[test]
+    fn backward_processes_the_newest_block_first() {
+        assert_eq!(
+            BackfillDirection::Forward.block_order(10, 13),
+            [10, 11, 12, 13]
+        );
+        assert_eq!(
+            BackfillDirection::Backward.block_order(10, 13),
+            [13, 12, 11, 10]
+        );
+    }
+
+    #[test]
+    fn block_range_includes_its_end_block() {
+        check_block_range(10, 10).unwrap();
+        assert_eq!(BackfillDirection::Forward.block_order(10, 10), [10]);
+        check_block_range(10, 11).unwrap();
+        assert_eq!(BackfillDirection::Forward.block_order(10, 11), [10, 11]);
+
+        let error = check_block_range(11, 10).unwrap_err();
+        assert!(
+            error.to_string().contains("End block 10 is before start block 11"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn emitters_are_classified_with_the_factory_first() {
+        let tracking_config = tracking_config();
+        let pools = HashSet::from([POOL]);
+        assert_eq!(
+            tracking_config.classify_address(FACTORY, &pools),
+            AddressRole::Factory
+        );
+        assert_eq!(
+            tracking_config.classify_address(POOL, &pools),
+            AddressRole::Pool
+        );
+        assert_eq!(
+            tracking_config.classify_address(TOKEN0, &pools),
+            AddressRole::Untracked
+        );
+
+        // a factory that ended up in the pool set is still the factory
+        let pools = HashSet::from([POOL, FACTORY]);
+        assert_eq!(
+            tracking_config.classify_address(FACTORY, &pools),
+            AddressRole::Factory
+        );
+    }
+
+    #[test]
+    fn log_filter_leaves_out_too_many_addresses() {
+        let few = HashSet::from([FACTORY, POOL]);
+        let mut filtered = log_address_filter(Some(&few)).unwrap();
+        filtered.sort();
+        assert_eq!(filtered, [POOL, FACTORY]);
+
+        let many: HashSet<Address> = (0..=MAX_LOG_FILTER_ADDRESSES as u64)
+            .map(|index| Address::from_word(U256::from(index).into()))
+            .collect();
+        assert_eq!(
+            log_address_filter(Some(&many)).map(|addresses| addresses.len()),
+            None
+        );
+        assert_eq!(
+            log_address_filter(Some(&many.iter().skip(1).copied().collect()))
+                .map(|addresses| addresses.len()),
+            Some(MAX_LOG_FILTER_ADDRESSES)
+        );
+        assert_eq!(log_address_filter(None), None);
+    }
+
+    #[test]
+    fn pools_are_created_through_a_deployer_or_directly_with_the_flag() {
+        let mut tracking_config = tracking_config();
+        assert!(tracking_config.is_tracked_deployer(Some(DEPLOYER)));
+        assert!(!tracking_config.is_tracked_deployer(Some(FACTORY)));
+        assert!(!tracking_config.is_tracked_deployer(Some(TOKEN0)));
+        // a contract creation has no recipient
+        assert!(!tracking_config.is_tracked_deployer(None));
+
+        tracking_config.accept_direct_factory_creates = true;
+        assert!(tracking_config.is_tracked_deployer(Some(DEPLOYER)));
+        assert!(tracking_config.is_tracked_deployer(Some(FACTORY)));
+        assert!(!tracking_config.is_tracked_deployer(Some(TOKEN0)));
+    }
+
+    #[test]
+    fn qa_stats_count_the_blocks_tracked_signatures() {
+        let block = block_info(1);
+        let untracked_pool = address!("1000000000000000000000000000000000000002");
+        let transfer = LogData::new_unchecked(vec![B256::repeat_byte(0xdd)], Bytes::new());
+        let receipts = vec![
+            receipt(
+                &block,
+                0,
+                POOL,
+                vec![(POOL, swap(10, -20)), (TOKEN0, transfer)],
+            ),
+            receipt(
+                &block,
+                1,
+                untracked_pool,
+                vec![
+                    (untracked_pool, swap(10, -20)),
+                    (POOL, LogData::new_unchecked(Vec::new(), Bytes::new())),
+                ],
+            ),
+        ];
+
+        let stats = QaStats::for_block::<Ethereum>(
+            &receipts,
+            &HashSet::from([POOL]),
+            &tracking_config(),
+            1,
+        );
+
+        assert_eq!(
+            stats,
+            QaStats {
+                scanned: 4,
+                signature_matches: 2,
+                decoded: 1,
+                untracked: 1,
+            }
+        );
+        assert_eq!(stats.decoded_percent(), 50.0);
+        let empty = QaStats::for_block::<Ethereum>(&[], &HashSet::new(), &tracking_config(), 0);
+        assert_eq!(empty.decoded_percent(), 100.0);
+    }
+
+    #[test]
+    fn blocks_with_an_empty_bloom_are_fetched() {
+        let tracking_config = tracking_config();
+        let pools = HashSet::from([POOL]);
+        assert!(tracking_config.might_have_tracked_logs(&Bloom::ZERO, &pools));
+
+        let mut logs_bloom = Bloom::ZERO;
+        logs_bloom.accrue(BloomInput::Raw(TOKEN0.as_slice()));
+        assert!(!tracking_config.might_have_tracked_logs(&logs_bloom, &pools));
+        logs_bloom.accrue(BloomInput::Raw(POOL.as_slice()));
+        assert!(tracking_config.might_have_tracked_logs(&logs_bloom, &pools));
+    }
+
+    /// `(method, block number)` of the requests `bloom_chain` answered, in the order received
+    static BLOOM_CHAIN_REQUESTS: std::sync::Mutex<Vec<(String, u64)>> =
+        std::sync::Mutex::new(Vec::new());
+
+    /// Answers for a provider where only block 11's logs bloom has `POOL`, the other blocks only
+    /// have `TOKEN0`
+    fn bloom_chain(request: &Value) -> Value {
+        let method = request["method"].as_str().unwrap();
+        let number = request["params"][0].as_str().unwrap();
+        let number = u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap();
+        BLOOM_CHAIN_REQUESTS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push((method.to_string(), number));
+        match method {
+            "eth_getBlockByNumber" => {
+                let mut logs_bloom = Bloom::ZERO;
+                let emitter = if number == 11 { POOL } else { TOKEN0 };
+                logs_bloom.accrue(BloomInput::Raw(emitter.as_slice()));
+                let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+                    header: alloy::rpc::types::Header {
+                        inner: alloy::consensus::Header {
+                            number,
+                            logs_bloom,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                json!({ "result": block })
+            }
+            _ => {
+                let block = block_info(number);
+                json!({ "result": [receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))])] })
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bloom_filter_only_fetches_receipts_of_matching_blocks() {
+        let client = http_connection(serve_json_rpc(bloom_chain).await, None)
+            .await
+            .unwrap();
+        let mut tracking_config = tracking_config();
+        tracking_config.bloom_filter = true;
+        let pools = HashSet::from([POOL]);
+
+        let blocks = fetch_blocks_info::<Ethereum>(
+            &client,
+            &[10, 11, 12],
+            usize::MAX,
+            &RetryConfig::new(1, 1, 1, 1.0, false),
+            &tracking_config,
+            &pools,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|block| (block.block.number, block.receipts.is_some()))
+                .collect::<Vec<_>>(),
+            [(10, false), (11, true), (12, false)]
+        );
+        // all the headers come first, then the receipts of the one block they let through
+        let requests = BLOOM_CHAIN_REQUESTS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        assert_eq!(
+            requests
+                .iter()
+                .map(|(method, number)| (method.as_str(), *number))
+                .collect::<Vec<_>>(),
+            [
+                ("eth_getBlockByNumber", 10),
+                ("eth_getBlockByNumber", 11),
+                ("eth_getBlockByNumber", 12),
+                ("eth_getBlockReceipts", 11),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn block_with_only_a_pool_creation_is_stored() {
+        let db_pools = test_db_pools();
+        let block = block_info(1_000);
+        let receipts = vec![receipt(
+            &block,
+            0,
+            DEPLOYER,
+            vec![(FACTORY, pool_created(POOL))],
+        )];
+        let mut pools = HashSet::new();
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config(),
+            &mut pools,
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        assert!(pools.contains(&POOL));
+        let mut conn = db_pools.main.get().unwrap();
+        assert!(BlockRaw::find_by_number(1_000, &mut conn).unwrap().is_some());
+        let stored_pools = find_all_pools_with_metadata(&mut conn).unwrap();
+        assert!(stored_pools
+            .iter()
+            .any(|pool| pool.pool == POOL.to_vec() && pool.created_block == 1_000));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn block_is_traced_with_decode_and_store_spans() {
+        let recorded = RecordedSpans::start();
+        let db_pools = test_db_pools();
+        let block = block_info(1_002);
+        let receipts = vec![receipt(
+            &block,
+            0,
+            DEPLOYER,
+            vec![(FACTORY, pool_created(POOL))],
+        )];
+
+        get_and_store_events::<Ethereum>(
+            &tracking_config(),
+            &mut HashSet::new(),
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .instrument(block_span(1_002))
+        .await
+        .unwrap();
+
+        let spans = recorded.finished();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+        assert_eq!(names, ["decode", "store", "block"]);
+        let block_span = &spans[2];
+        assert!(block_span
+            .attributes
+            .iter()
+            .any(|attribute| attribute.key.as_str() == "block.number"
+                && attribute.value == 1_002_i64.into()));
+        for child in &spans[..2] {
+            assert_eq!(child.parent_span_id, block_span.span_context.span_id());
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn failed_block_stops_tracking_its_pools() {
+        let db_pools = test_db_pools();
+        // the block has no full transactions to join, so it fails after its pool is decoded
+        let mut tracking_config = tracking_config();
+        tracking_config.rich_transactions = true;
+        let dump_dir = std::env::temp_dir().join(format!(
+            "token_tracking_failed_receipts_{}",
+            std::process::id()
+        ));
+        tracking_config.save_failed_receipts = Some(dump_dir.clone());
+        let block = block_info(1_001);
+        let receipts = vec![receipt(
+            &block,
+            0,
+            DEPLOYER,
+            vec![(FACTORY, pool_created(POOL))],
+        )];
+        let mut pools = HashSet::new();
+
+        let result = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut pools,
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(pools.is_empty());
+        let mut conn = db_pools.main.get().unwrap();
+        assert!(BlockRaw::find_by_number(1_001, &mut conn).unwrap().is_none());
+        // the receipts are dumped to be replayed
+        let dumped = fs::read_to_string(dump_dir.join("1001.json")).unwrap();
+        fs::remove_dir_all(&dump_dir).unwrap();
+        let dumped: Vec<TransactionReceipt> = serde_json::from_str(&dumped).unwrap();
+        assert_eq!(dumped.len(), 1);
+        assert_eq!(dumped[0].block_number, Some(1_001));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn strict_factory_skips_a_conflicting_recreation() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        let mut pools = HashSet::new();
+        // (block, fee, strict_factory, expected (inserted, total))
+        let creations = [
+            (5_980, 10_000, false, (1, 1)),
+            // the same pool with another fee is passed on, and skipped as already stored
+            (5_981, 500, false, (0, 1)),
+            (5_982, 500, true, (0, 0)),
+            // a re-creation matching the stored pool isn't a conflict
+            (5_983, 10_000, true, (0, 1)),
+        ];
+        for (number, fee, strict_factory, expected) in creations {
+            tracking_config.strict_factory = strict_factory;
+            let pool_created = PoolCreated {
+                fee: U24::from(fee),
+                ..PoolCreated::decode_log_data(&pool_created(POOL), true).unwrap()
+            };
+            let block = block_info(number);
+            let receipts = vec![receipt(
+                &block,
+                0,
+                DEPLOYER,
+                vec![(FACTORY, pool_created.encode_log_data())],
+            )];
+
+            let counts = get_and_store_events::<Ethereum>(
+                &tracking_config,
+                &mut pools,
+                receipts,
+                block,
+                false,
+                &db_pools,
+                None,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                (counts.inserted, counts.total),
+                expected,
+                "block {}",
+                number
+            );
+        }
+
+        let mut conn = db_pools.main.get().unwrap();
+        let stored = PoolCreateEventRaw::find_by_pool(POOL.as_slice(), &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.fee, BigDecimal::from(10_000));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn only_pools_with_the_tracked_token_are_tracked() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.only_token = Some(TOKEN0);
+        let other_pool = address!("1000000000000000000000000000000000000002");
+        let other_token = address!("5000000000000000000000000000000000000005");
+        let without_token = PoolCreated {
+            token0: TOKEN1,
+            token1: other_token,
+            fee: U24::from(10_000),
+            tickSpacing: I24::try_from(200).unwrap(),
+            pool: other_pool,
+        }
+        .encode_log_data();
+        let block = block_info(5_984);
+        let receipts = vec![
+            receipt(&block, 0, DEPLOYER, vec![(FACTORY, without_token)]),
+            receipt(&block, 1, DEPLOYER, vec![(FACTORY, pool_created(POOL))]),
+        ];
+        let mut pools = HashSet::new();
+
+        get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut pools,
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(pools, HashSet::from([POOL]));
+        let mut conn = db_pools.main.get().unwrap();
+        assert_eq!(
+            find_tracked_pools_with_token(TOKEN0, &mut conn).unwrap(),
+            [POOL]
+        );
+        assert!(find_tracked_pools_with_token(other_token, &mut conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn swaps_below_the_minimum_amounts_are_dropped() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.min_swap_amount0 = Some(U256::from(100));
+        tracking_config.min_swap_amount1 = Some(U256::from(100));
+        let block = block_info(5_979);
+        let receipts = vec![
+            // enough token1, but dust of token0
+            receipt(&block, 0, POOL, vec![(POOL, swap(-5, 1_000))]),
+            receipt(&block, 1, POOL, vec![(POOL, swap(-500, 1_000))]),
+        ];
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        let swaps =
+            SwapEventRaw::find_after_id(0, 10, Some(POOL), &mut db_pools.main.get().unwrap())
+                .unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(
+            TxHash::from_slice(&swaps[0].1.transaction_hash),
+            B256::with_last_byte(2)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn logs_with_an_extra_topic_are_stored_raw() {
+        let db_pools = test_db_pools();
+        let extra_topic = B256::repeat_byte(0xee);
+        let mut tracking_config = tracking_config();
+        tracking_config.extra_topics = HashSet::from([extra_topic]);
+        let block = block_info(5_978);
+        let log = |topic| LogData::new_unchecked(vec![topic], Bytes::from_static(&[1, 2]));
+        let receipts = vec![receipt(
+            &block,
+            0,
+            POOL,
+            vec![
+                (POOL, log(extra_topic)),
+                (POOL, log(B256::repeat_byte(0xdd))),
+                // not from a tracked address
+                (TOKEN0, log(extra_topic)),
+            ],
+        )];
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        let mut conn = db_pools.main.get().unwrap();
+        let raw_logs = RawLogRaw::find_after_id(0, 10, Some(POOL), &mut conn).unwrap();
+        assert_eq!(raw_logs.len(), 1);
+        let (_, raw_log) = &raw_logs[0];
+        assert_eq!(raw_log.log_index, 0);
+        assert_eq!(raw_log.topics, [extra_topic.to_vec()]);
+        assert_eq!(raw_log.data, [1, 2]);
+        assert!(RawLogRaw::find_after_id(0, 10, Some(TOKEN0), &mut conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn base_fee_is_stored_for_blocks_after_london_only() {
+        let db_pools = test_db_pools();
+        for (number, base_fee_per_gas) in [(5_976, Some(7)), (5_977, None)] {
+            let header: alloy::rpc::types::Block = alloy::rpc::types::Block {
+                header: alloy::rpc::types::Header {
+                    hash: B256::with_last_byte(number as u8),
+                    inner: alloy::consensus::Header {
+                        number,
+                        base_fee_per_gas,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let block = BlockInfo::from_block::<Ethereum>(&header);
+            assert_eq!(block.base_fee_per_gas, base_fee_per_gas);
+            let receipts = vec![receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))])];
+            get_and_store_events::<Ethereum>(
+                &tracking_config(),
+                &mut HashSet::from([POOL]),
+                receipts,
+                block,
+                false,
+                &db_pools,
+                None,
+            )
+            .await
+            .unwrap();
+
+            let stored = BlockRaw::find_by_number(number as i64, &mut db_pools.main.get().unwrap())
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                stored.base_fee_per_gas,
+                base_fee_per_gas.map(BigDecimal::from)
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn rich_transactions_store_the_full_transactions_details() {
+        use diesel::prelude::*;
+
+        use crate::pool_sql::schema::transactions;
+
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.rich_transactions = true;
+        let mut block = block_info(5_975);
+        let swap_receipt = receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))]);
+        // the full transaction of the receipt and one without tracked logs
+        for (transaction_hash, nonce) in [(swap_receipt.transaction_hash, 7), (B256::ZERO, 8)] {
+            block.transaction_details.insert(
+                transaction_hash,
+                TransactionDetails {
+                    value: U256::from(1_000),
+                    nonce,
+                    input_selector: Bytes::from_static(&[0x12, 0x34, 0x56, 0x78]),
+                },
+            );
+        }
+
+        get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            vec![swap_receipt],
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let stored: Vec<TransactionRaw> = transactions::table
+            .filter(transactions::block_number.eq(5_975))
+            .select(TransactionRaw::as_select())
+            .load(&mut db_pools.main.get().unwrap())
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(
+            (
+                stored[0].transaction_value.clone(),
+                stored[0].transaction_nonce,
+                stored[0].input_selector.clone()
+            ),
+            (
+                Some(BigDecimal::from(1_000)),
+                Some(7),
+                Some(vec![0x12, 0x34, 0x56, 0x78])
+            )
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn event_that_does_not_fit_its_row_fails_the_block() {
+        let db_pools = test_db_pools();
+        let block = block_info(5_900);
+        let mut swap_receipt = receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))]);
+        let ReceiptEnvelope::Eip1559(inner) = &mut swap_receipt.inner else {
+            unreachable!()
+        };
+        // too large for the `log_index` column
+        inner.receipt.logs[0].log_index = Some(u64::MAX);
+
+        let error = get_and_store_events::<Ethereum>(
+            &tracking_config(),
+            &mut HashSet::from([POOL]),
+            vec![swap_receipt],
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("failed to convert swap event"),
+            "{}",
+            error
+        );
+        let mut conn = db_pools.main.get().unwrap();
+        assert!(BlockRaw::find_by_number(5_900, &mut conn).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn log_without_a_transaction_index_takes_its_receipts() {
+        let db_pools = test_db_pools();
+        let block = block_info(5_974);
+        let mut swap_receipt = receipt(&block, 2, POOL, vec![(POOL, swap(10, -20))]);
+        let ReceiptEnvelope::Eip1559(inner) = &mut swap_receipt.inner else {
+            unreachable!()
+        };
+        inner.receipt.logs[0].transaction_index = None;
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config(),
+            &mut HashSet::from([POOL]),
+            vec![swap_receipt],
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        let transactions = transaction_order(5_974, &mut db_pools.main.get().unwrap()).unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|transaction| (transaction.transaction_hash, transaction.transaction_index))
+                .collect::<Vec<_>>(),
+            [(B256::with_last_byte(3), 2)]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn pool_created_earlier_in_the_block_keeps_its_initialize() {
+        let db_pools = test_db_pools();
+        let block = block_info(5_950);
+        let initialize = Initialize {
+            sqrtPriceX96: U160::from(1) << 96,
+            tick: I24::ZERO,
+        }
+        .encode_log_data();
+        // the provider returns the initializing transaction's receipt first
+        let receipts = vec![
+            receipt(&block, 1, POOL, vec![(POOL, initialize)]),
+            receipt(&block, 0, DEPLOYER, vec![(FACTORY, pool_created(POOL))]),
+        ];
+        let mut pools = HashSet::new();
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config(),
+            &mut pools,
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 2);
+        assert!(pools.contains(&POOL));
+        let initialized = InitializationEventRaw::find_after_id(
+            0,
+            10,
+            Some(POOL),
+            &mut db_pools.main.get().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(initialized.len(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn unchanged_block_is_skipped_when_processed_again() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.skip_unchanged_blocks = true;
+        let block = block_info(5_951);
+        let receipts = vec![
+            receipt(&block, 0, DEPLOYER, vec![(FACTORY, pool_created(POOL))]),
+            receipt(&block, 1, POOL, vec![(POOL, swap(10, -20))]),
+        ];
+        let mut pools = HashSet::new();
+
+        // a second write would insert nothing as well, so the store span shows it is skipped
+        for (expected_inserted, expected_spans) in [(2, &["decode", "store"][..]), (0, &["decode"])]
+        {
+            let recorded = RecordedSpans::start();
+            let counts = get_and_store_events::<Ethereum>(
+                &tracking_config,
+                &mut pools,
+                receipts.clone(),
+                block.clone(),
+                false,
+                &db_pools,
+                None,
+            )
+            .await
+            .unwrap();
+            assert_eq!((counts.inserted, counts.total), (expected_inserted, 2));
+            let spans = recorded.finished();
+            let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+            assert_eq!(names, expected_spans);
+        }
+    }
+
+    /// Receipts of `block` with a swap that doesn't decode, a swap whose log can't be turned into
+    /// a transaction and a valid swap, in that order
+    fn receipts_with_bad_logs(block: &BlockInfo) -> Vec<TransactionReceipt> {
+        let mut undecodable = swap(10, -20);
+        undecodable.data = Bytes::from_static(&[0; 10]);
+        let mut without_block_number = receipt(block, 1, POOL, vec![(POOL, swap(10, -20))]);
+        let ReceiptEnvelope::Eip1559(inner) = &mut without_block_number.inner else {
+            unreachable!()
+        };
+        inner.receipt.logs[0].block_number = None;
+        vec![
+            receipt(block, 0, POOL, vec![(POOL, undecodable)]),
+            without_block_number,
+            receipt(block, 2, POOL, vec![(POOL, swap(30, -40))]),
+        ]
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn continue_on_decode_error_skips_only_the_bad_logs() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.decode_error_policy = DecodeErrorPolicy::Continue;
+        let block = block_info(5_940);
+
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            receipts_with_bad_logs(&block),
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        let stored =
+            SwapEventRaw::find_after_id(0, 10, Some(POOL), &mut db_pools.main.get().unwrap())
+                .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].1.amount0, BigDecimal::from(30));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn fail_on_decode_error_fails_the_block() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.decode_error_policy = DecodeErrorPolicy::Fail;
+        let block = block_info(5_941);
+
+        let error = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            receipts_with_bad_logs(&block),
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("Failed to decode swap event"),
+            "{}",
+            error
+        );
+        let mut conn = db_pools.main.get().unwrap();
+        assert!(BlockRaw::find_by_number(5_941, &mut conn)
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn hooks_observe_every_decoded_event_once() {
+        let db_pools = test_db_pools();
+        let observed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut tracking_config = tracking_config();
+        let hook_observed = observed.clone();
+        tracking_config.on_event(move |event, block| {
+            let (kind, log_index) = match event {
+                AnyEvent::PoolCreate(event) => ("pool_create", event.log_index),
+                AnyEvent::Swap(event) => ("swap", event.log_index),
+                AnyEvent::Mint(event) => ("mint", event.log_index),
+                _ => ("other", 0),
+            };
+            hook_observed
+                .lock()
+                .unwrap()
+                .push((block.block_number, kind, log_index));
+        });
+        let block = block_info(1_003);
+        let swap = swap(10, -20);
+        let mint = Mint {
+            sender: DEPLOYER,
+            owner: DEPLOYER,
+            tickLower: I24::try_from(-200).unwrap(),
+            tickUpper: I24::try_from(200).unwrap(),
+            amount: 1_000,
+            amount0: U256::from(10),
+            amount1: U256::from(20),
+        }
+        .encode_log_data();
+        let receipts = vec![
+            receipt(&block, 0, DEPLOYER, vec![(FACTORY, pool_created(POOL))]),
+            // the untracked emitter's swap isn't decoded
+            receipt(
+                &block,
+                1,
+                POOL,
+                vec![(POOL, mint), (TOKEN0, swap.clone()), (POOL, swap)],
+            ),
+        ];
+
+        get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::new(),
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *observed.lock().unwrap(),
+            [
+                (1_003, "pool_create", 0),
+                (1_003, "mint", 0),
+                (1_003, "swap", 2)
+            ]
+        );
+    }
+
+    /// Log output of the current thread, captured until dropped
+    struct CapturedLogs {
+        output: Arc<std::sync::Mutex<Vec<u8>>>,
+        _guard: tracing::subscriber::DefaultGuard,
+    }
+
+    /// Writer appending to a `CapturedLogs`' output
+    struct CapturedWriter(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl CapturedLogs {
+        fn start() -> Self {
+            let output = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let writer_output = output.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .with_writer(move || CapturedWriter(writer_output.clone()))
+                .with_ansi(false)
+                .without_time()
+                .finish();
+            Self {
+                output,
+                _guard: tracing::subscriber::set_default(subscriber),
+            }
+        }
+
+        /// Captured lines containing `pattern`
+        fn lines_with(&self, pattern: &str) -> Vec<String> {
+            String::from_utf8(self.output.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .filter(|line| line.contains(pattern))
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn traced_block_reports_what_happened_to_each_log() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        tracking_config.trace_logs = true;
+        let block = block_info(5_986);
+        let unknown = LogData::new_unchecked(vec![B256::repeat_byte(0x99)], Bytes::new());
+        let receipts = vec![
+            receipt(&block, 0, DEPLOYER, vec![(FACTORY, pool_created(POOL))]),
+            receipt(
+                &block,
+                1,
+                POOL,
+                vec![
+                    (POOL, swap(10, -20)),
+                    (POOL, unknown),
+                    (TOKEN0, swap(1, -2)),
+                ],
+            ),
+            receipt(&block, 2, TOKEN1, vec![(TOKEN1, swap(3, -4))]),
+        ];
+
+        let captured = CapturedLogs::start();
+        get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::new(),
+            receipts,
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let traced: Vec<String> = captured
+            .lines_with("Trace log")
+            .iter()
+            .map(|line| line.split_once("Trace log ").unwrap().1.to_string())
+            .collect();
+        let (created, swapped) = (PoolCreated::SIGNATURE_HASH, Swap::SIGNATURE_HASH);
+        let (first, second, third) = (
+            B256::with_last_byte(1),
+            B256::with_last_byte(2),
+            B256::with_last_byte(3),
+        );
+        assert_eq!(
+            traced,
+            [
+                format!(
+                    "Some(0) of {}: address {} (Factory), topic0 {} (tracked event signature): \
+                     decoded",
+                    first,
+                    fmt_addr(FACTORY),
+                    created
+                ),
+                format!(
+                    "Some(0) of {}: address {} (Pool), topic0 {} (tracked event signature): \
+                     decoded",
+                    second,
+                    fmt_addr(POOL),
+                    swapped
+                ),
+                format!(
+                    "Some(1) of {}: address {} (Pool), topic0 {} (unknown topic): no tracked \
+                     event signature",
+                    second,
+                    fmt_addr(POOL),
+                    B256::repeat_byte(0x99)
+                ),
+                format!(
+                    "Some(2) of {}: address {} (Untracked), topic0 {} (tracked event signature): \
+                     not from a tracked pool",
+                    second,
+                    fmt_addr(TOKEN0),
+                    swapped
+                ),
+                format!(
+                    "Some(0) of {}: address {} (Untracked), topic0 {} (tracked event signature): \
+                     no log of its transaction is from a tracked address",
+                    third,
+                    fmt_addr(TOKEN1),
+                    swapped
+                ),
+            ]
+        );
+    }
+
+    /// Hash of block `number` on a chain that replaced the stored one
+    fn reorged_hash(number: u64) -> B256 {
+        B256::from(U256::from(number) + (U256::from(1) << 128))
+    }
+
+    /// Answers for a provider serving the chain of [`reorged_hash`]es, with no receipts
+    fn reorged_chain(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => {
+                let number = u64::from_str_radix(
+                    request["params"][0].as_str().unwrap().trim_start_matches("0x"),
+                    16,
+                )
+                .unwrap();
+                let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+                    header: alloy::rpc::types::Header {
+                        hash: reorged_hash(number),
+                        inner: alloy::consensus::Header {
+                            number,
+                            parent_hash: reorged_hash(number - 1),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                json!({ "result": block })
+            }
+            _ => json!({ "result": [] }),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn reorg_deeper_than_the_limit_halts_without_rolling_back() {
+        let db_pools = test_db_pools();
+        let stored_blocks = 5_500..=5_505;
+        for number in stored_blocks.clone() {
+            insert_swaps(
+                stored_block(number),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                &mut db_pools.main.get().unwrap(),
+                None,
+            )
+            .unwrap();
+        }
+        let client = http_connection(serve_json_rpc(reorged_chain).await, None)
+            .await
+            .unwrap();
+        let mut tracking_config = tracking_config();
+        tracking_config.reorg_depth_limit = 3;
+        let mut pools = HashSet::from([POOL]);
+        let retry_config = RetryConfig::new(1, 1, 1, 1.0, false);
+        let mut tracker = LiveTracker::new(
+            &tracking_config,
+            &mut pools,
+            &retry_config,
+            None,
+            &db_pools,
+            None,
+            0,
+        )
+        .unwrap();
+        for number in stored_blocks.clone() {
+            let head = (number, B256::from(U256::from(number)));
+            remember_head(&mut tracker.processed_heads, REORG_WINDOW, head);
+        }
+
+        // every stored block's parent differs on the new chain
+        let error = tracker
+            .process_block::<Ethereum>(BlockSource::Http(&client), 5_506)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("reorg depth limit of 3"), "{}", error);
+        assert_eq!(tracker.processed_heads.len(), 6);
+        assert_eq!(tracker.deepest_reorg, 0);
+        assert!(tracker.pools.contains(&POOL));
+        let mut conn = db_pools.main.get().unwrap();
+        for number in stored_blocks {
+            assert!(BlockRaw::find_by_number(number as i64, &mut conn)
+                .unwrap()
+                .is_some());
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn reorg_of_two_blocks_rolls_back_both() {
+        let db_pools = test_db_pools();
+        let client = http_connection(serve_json_rpc(reorged_chain).await, None)
+            .await
+            .unwrap();
+        let tracking_config = tracking_config();
+        let mut pools = HashSet::new();
+        let retry_config = RetryConfig::new(1, 1, 1, 1.0, false);
+        let mut tracker = LiveTracker::new(
+            &tracking_config,
+            &mut pools,
+            &retry_config,
+            None,
+            &db_pools,
+            None,
+            0,
+        )
+        .unwrap();
+        // the pool is created in the first block and swaps in the second
+        let stored_blocks = [
+            (5_910, DEPLOYER, (FACTORY, pool_created(POOL))),
+            (5_911, POOL, (POOL, swap(10, -20))),
+        ];
+        for (number, to, log) in stored_blocks {
+            let block = BlockInfo {
+                hash: B256::from(U256::from(number)),
+                ..block_info(number)
+            };
+            let head = (number, block.hash);
+            get_and_store_events::<Ethereum>(
+                &tracking_config,
+                tracker.pools,
+                vec![receipt(&block, 0, to, vec![log])],
+                block,
+                false,
+                &db_pools,
+                None,
+            )
+            .await
+            .unwrap();
+            remember_head(&mut tracker.processed_heads, REORG_WINDOW, head);
+        }
+        assert!(tracker.pools.contains(&POOL));
+        assert_eq!(
+            SwapEventRaw::find_after_id(0, 10, Some(POOL), &mut db_pools.main.get().unwrap())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // the new head's parent and grandparent both differ from the stored blocks
+        tracker
+            .process_block::<Ethereum>(BlockSource::Http(&client), 5_912)
+            .await
+            .unwrap();
+
+        assert_eq!(tracker.deepest_reorg, 2);
+        assert!(!tracker.pools.contains(&POOL));
+        let mut conn = db_pools.main.get().unwrap();
+        for number in [5_910, 5_911] {
+            assert!(BlockRaw::find_by_number(number, &mut conn).unwrap().is_none());
+        }
+        assert!(PoolCreateEventRaw::find_by_pool(POOL.as_slice(), &mut conn)
+            .unwrap()
+            .is_none());
+        assert!(SwapEventRaw::find_after_id(0, 10, Some(POOL), &mut conn)
+            .unwrap()
+            .is_empty());
+        assert!(!find_all_pools_with_metadata(&mut conn)
+            .unwrap()
+            .iter()
+            .any(|pool| pool.pool == POOL.to_vec()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rollbacks_within_the_cooldown_wait_for_it() {
+        let mut cooldown = ReorgCooldown::new(Some(Duration::from_secs(60)));
+        assert_eq!(cooldown.remaining(), None);
+
+        cooldown.record_rollback();
+        assert_eq!(cooldown.remaining(), Some(Duration::from_secs(60)));
+        tokio::time::advance(Duration::from_secs(45)).await;
+        assert_eq!(cooldown.remaining(), Some(Duration::from_secs(15)));
+        tokio::time::advance(Duration::from_secs(15)).await;
+        assert_eq!(cooldown.remaining(), None);
+
+        // a second reorg right after the next rollback waits the full cooldown again
+        cooldown.record_rollback();
+        assert_eq!(cooldown.remaining(), Some(Duration::from_secs(60)));
+
+        let mut unlimited = ReorgCooldown::new(None);
+        unlimited.record_rollback();
+        assert_eq!(unlimited.remaining(), None);
+    }
+
+    /// Blocks live tracking processes, in order, for heads arriving with `head_numbers`
+    fn blocks_processed_for_heads(confirmations: u64, head_numbers: &[u64]) -> Vec<u64> {
+        let mut tracking_config = tracking_config();
+        tracking_config.confirmations = confirmations;
+        let mut pools = HashSet::new();
+        let retry_config = RetryConfig::default();
+        // never connects, the tracker only needs the database to process blocks
+        let db_pools = DbPools {
+            main: Pool::builder()
+                .build_unchecked(ConnectionManager::new("postgres://localhost/unused")),
+            swap: None,
+        };
+        let mut tracker = LiveTracker::new(
+            &tracking_config,
+            &mut pools,
+            &retry_config,
+            None,
+            &db_pools,
+            None,
+            128,
+        )
+        .unwrap();
+
+        let mut processed = Vec::new();
+        for &head_number in head_numbers {
+            let missed_blocks = tracker.missed_blocks(head_number);
+            if tracker.is_redelivered(head_number, B256::with_last_byte(head_number as u8)) {
+                continue;
+            }
+            for number in tracker.confirmed_blocks(missed_blocks, head_number) {
+                remember_head(
+                    &mut tracker.processed_heads,
+                    REORG_WINDOW,
+                    (number, B256::with_last_byte(number as u8)),
+                );
+                processed.push(number);
+            }
+        }
+        processed
+    }
+
+    #[test]
+    fn blocks_skipped_by_the_head_stream_are_fetched_before_the_head() {
+        assert_eq!(
+            blocks_processed_for_heads(0, &[100, 101, 104]),
+            [100, 101, 102, 103, 104]
+        );
+    }
+
+    #[test]
+    fn skipped_blocks_wait_for_confirmations_like_the_head() {
+        assert_eq!(
+            blocks_processed_for_heads(2, &[100, 101, 104]),
+            [100, 101, 102]
+        );
+    }
+
+    #[test]
+    fn redelivered_heads_are_skipped() {
+        assert_eq!(
+            blocks_processed_for_heads(0, &[100, 101, 101, 102, 101]),
+            [100, 101, 102]
+        );
+        // a head waiting for confirmations is only queued once
+        assert_eq!(
+            blocks_processed_for_heads(2, &[100, 101, 101, 102, 102]),
+            [100]
+        );
+    }
+
+    #[tokio::test]
+    async fn live_mode_only_needs_http_for_receipts_over_http() {
+        let tracking_config = tracking_config();
+        let mut pools = HashSet::new();
+        let db_pools = DbPools {
+            main: Pool::builder()
+                .build_unchecked(ConnectionManager::new("postgres://localhost/unused")),
+            swap: None,
+        };
+        let wss_url = closed_endpoint().replace("http://", "ws://");
+
+        // receipts over the websocket only need the subscription, which fails to connect here
+        for (receipt_transport, expected_error) in [
+            (ReceiptTransport::Http, "HTTP_URL is required"),
+            (ReceiptTransport::Ws, "Failed to connect to provider"),
+        ] {
+            let error = live_blocks::<Ethereum>(
+                None,
+                wss_url.clone(),
+                receipt_transport,
+                0,
+                &tracking_config,
+                &mut pools,
+                RetryConfig::new(1, 1, 1, 1.0, false),
+                None,
+                &db_pools,
+            )
+            .await
+            .unwrap_err();
+
+            assert!(error.to_string().contains(expected_error), "{}", error);
+        }
+    }
+
+    #[test]
+    fn head_redelivered_with_a_new_hash_is_processed_again() {
+        let tracking_config = tracking_config();
+        let mut pools = HashSet::new();
+        let retry_config = RetryConfig::default();
+        let db_pools = DbPools {
+            main: Pool::builder()
+                .build_unchecked(ConnectionManager::new("postgres://localhost/unused")),
+            swap: None,
+        };
+        let mut tracker = LiveTracker::new(
+            &tracking_config,
+            &mut pools,
+            &retry_config,
+            None,
+            &db_pools,
+            None,
+            2,
+        )
+        .unwrap();
+
+        assert!(!tracker.is_redelivered(100, B256::with_last_byte(1)));
+        assert!(tracker.is_redelivered(100, B256::with_last_byte(1)));
+        assert!(!tracker.is_redelivered(100, B256::with_last_byte(2)));
+        assert!(tracker.is_redelivered(100, B256::with_last_byte(2)));
+
+        // heads older than the window are forgotten
+        assert!(!tracker.is_redelivered(101, B256::with_last_byte(3)));
+        assert!(!tracker.is_redelivered(102, B256::with_last_byte(4)));
+        assert!(!tracker.is_redelivered(100, B256::with_last_byte(2)));
+    }
+
+    #[test]
+    fn swap_sampling_keeps_about_the_rate_of_swaps() {
+        let swaps: Vec<(TxHash, u64)> = (0..2_000_u64)
+            .map(|i| (TxHash::from(U256::from(i / 4)), i % 4))
+            .collect();
+        let kept = |sample_rate| {
+            swaps
+                .iter()
+                .filter(|(hash, log_index)| keep_sampled_swap(*hash, *log_index, sample_rate))
+                .count()
+        };
+
+        assert_eq!(kept(1.0), swaps.len());
+        assert_eq!(kept(0.0), 0);
+        let sampled = kept(0.25);
+        assert!((400..600).contains(&sampled), "kept {} of 2000", sampled);
+
+        // a higher rate keeps every swap a lower one did
+        for (hash, log_index) in &swaps {
+            if keep_sampled_swap(*hash, *log_index, 0.25) {
+                assert!(keep_sampled_swap(*hash, *log_index, 0.5));
+            }
+        }
+    }
+
+    /// Block of the [`timestamped_chain`], committed by the timestamp backfill test
+    const UNTIMESTAMPED_BLOCK: u64 = 4_000_000_001;
+
+    /// Answers for a provider whose blocks are at `1_800_000_000 + number` with a base fee of 7,
+    /// and whose only receipt is a swap of `POOL` in [`UNTIMESTAMPED_BLOCK`]
+    fn timestamped_chain(request: &Value) -> Value {
+        let number = request["params"][0].as_str().unwrap();
+        let number = u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap();
+        match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => {
+                let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+                    header: alloy::rpc::types::Header {
+                        inner: alloy::consensus::Header {
+                            number,
+                            timestamp: 1_800_000_000 + number,
+                            base_fee_per_gas: Some(7),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                json!({ "result": block })
+            }
+            "eth_getBlockReceipts" if number == UNTIMESTAMPED_BLOCK => {
+                let block = block_info(number);
+                json!({ "result": [receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))])] })
+            }
+            _ => json!({ "result": [] }),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn skipped_timestamps_are_backfilled() {
+        // `backfill_timestamps` reads from its own connection, so the block is committed
+        migrate_once();
+        let _cleanup = CommittedBlock(UNTIMESTAMPED_BLOCK);
+        let database_url = std::env::var("DATABASE_URL").unwrap();
+        let db_pools = DbPools {
+            main: Pool::builder()
+                .max_size(1)
+                .build(ConnectionManager::new(database_url))
+                .unwrap(),
+            swap: None,
+        };
+        let url = serve_json_rpc(timestamped_chain).await;
+        let client = http_connection(url.clone(), None).await.unwrap();
+        let mut tracking_config = tracking_config();
+        tracking_config.skip_timestamp_fetch = true;
+        let mut pools = HashSet::from([POOL]);
+        let retry_config = RetryConfig::new(1, 1, 1, 1.0, false);
+
+        let batch_block = fetch_blocks_info::<Ethereum>(
+            &client,
+            &[UNTIMESTAMPED_BLOCK],
+            usize::MAX,
+            &retry_config,
+            &tracking_config,
+            &pools,
+        )
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+        assert_eq!(batch_block.block.timestamp, MISSING_BLOCK_TIMESTAMP);
+        get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut pools,
+            batch_block.receipts.unwrap(),
+            batch_block.block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+        let mut conn = db_pools.main.get().unwrap();
+        let stored = BlockRaw::find_by_number(UNTIMESTAMPED_BLOCK as i64, &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.block_timestamp, MISSING_BLOCK_TIMESTAMP as i64);
+
+        let batch_config = BatchConfig {
+            blocks_per_batch: 10,
+            max_request_bytes: usize::MAX,
+            delay: Duration::ZERO,
+        };
+        let filled = backfill_timestamps::<Ethereum>(url, None, retry_config, batch_config)
+            .await
+            .unwrap();
+        assert!(filled >= 1);
+        let stored = BlockRaw::find_by_number(UNTIMESTAMPED_BLOCK as i64, &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (stored.block_timestamp, stored.base_fee_per_gas),
+            (
+                1_800_000_000 + UNTIMESTAMPED_BLOCK as i64,
+                Some(BigDecimal::from(7))
+            )
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn block_is_stored_after_the_database_connection_drops() {
+        use diesel::{
+            dsl::sql,
+            prelude::*,
+            sql_types::Integer,
+        };
+
+        // the retry reconnects through the pool, so nothing here runs in a test transaction
+        migrate_once();
+        let number = 4_000_000_002;
+        let _cleanup = CommittedBlock(number);
+        let database_url = std::env::var("DATABASE_URL").unwrap();
+        // a dropped connection is only noticed when it is used
+        let db_pools = DbPools {
+            main: Pool::builder()
+                .max_size(2)
+                .min_idle(Some(0))
+                .test_on_check_out(false)
+                .build(ConnectionManager::new(database_url))
+                .unwrap(),
+            swap: None,
+        };
+        let backend_pid: i32 = diesel::select(sql::<Integer>("pg_backend_pid()"))
+            .get_result(&mut db_pools.main.get().unwrap())
+            .unwrap();
+        let terminated: bool = diesel::select(sql::<diesel::sql_types::Bool>(&format!(
+            "pg_terminate_backend({})",
+            backend_pid
+        )))
+        .get_result(&mut establish_connection().unwrap())
+        .unwrap();
+        assert!(terminated);
+
+        let mut tracking_config = tracking_config();
+        tracking_config.db_retry = Some(RetryConfig::new(3, 1, 1, 1.0, false));
+        let block = block_info(number);
+        let recorded = RecordedSpans::start();
+        let counts = get_and_store_events::<Ethereum>(
+            &tracking_config,
+            &mut HashSet::from([POOL]),
+            vec![receipt(&block, 0, POOL, vec![(POOL, swap(10, -20))])],
+            block,
+            false,
+            &db_pools,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((counts.inserted, counts.total), (1, 1));
+        let store_attempts = recorded
+            .finished()
+            .iter()
+            .filter(|span| span.name == "store")
+            .count();
+        assert_eq!(store_attempts, 2);
+        let mut conn = db_pools.main.get().unwrap();
+        assert!(BlockRaw::find_by_number(number as i64, &mut conn)
+            .unwrap()
+            .is_some());
+    }
+
+    /// Answers for a provider of chain 1 whose blocks have no receipts
+    fn empty_chain(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_chainId" => json!({ "result": "0x1" }),
+            "eth_getBlockByNumber" => {
+                let number = request["params"][0].as_str().unwrap();
+                let number = u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap();
+                let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+                    header: alloy::rpc::types::Header {
+                        hash: B256::with_last_byte(number as u8),
+                        inner: alloy::consensus::Header {
+                            number,
+                            parent_hash: B256::with_last_byte(number as u8 - 1),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                json!({ "result": block })
+            }
+            _ => json!({ "result": [] }),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn backfill_stops_once_its_max_runtime_has_passed() {
+        let db_pools = test_db_pools();
+        let mut tracking_config = tracking_config();
+        // the paused clock jumps to the HTTP client's pending timers while it waits for the
+        // provider, those are minutes at most, so the deadline only passes in the batch delay
+        tracking_config.max_runtime = Some(Duration::from_secs(60 * 60));
+        let batch_config = BatchConfig {
+            blocks_per_batch: 1,
+            max_request_bytes: usize::MAX,
+            delay: Duration::from_secs(60 * 60),
+        };
+
+        blocks_from::<Ethereum>(
+            serve_json_rpc(empty_chain).await,
+            5_987,
+            5_990,
+            &tracking_config,
+            &mut HashSet::new(),
+            BackfillDirection::Forward,
+            RetryConfig::new(1, 1, 1, 1.0, false),
+            batch_config,
+            BackfillReporting {
+                quiet: true,
+                pushgateway: None,
+                push_every_blocks: None,
+            },
+            &db_pools,
+        )
+        .await
+        .unwrap();
+
+        let mut conn = db_pools.main.get().unwrap();
+        assert_eq!(get_last_processed_block(1, &mut conn).unwrap(), Some(5_987));
+    }
 }
@@ -0,0 +1,179 @@
+use std::fmt::Write;
+
+use alloy::transports::http::reqwest;
+use eyre::{
+    bail,
+    Result,
+    WrapErr,
+};
+
+use crate::{
+    pool_sql::database_interactions::InsertCounts,
+    rpc::rpc_call_counts,
+};
+
+/// Progress of a `blocks_from` run, pushed as gauges
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BackfillMetrics {
+    pub blocks_processed: u64,
+    pub blocks_total: u64,
+    pub last_block: u64,
+    pub events: InsertCounts,
+}
+
+/// Pushes metrics of short lived runs to a Prometheus Pushgateway.
+///
+/// Each push replaces the metrics of the run's group, which is keyed by the job name and the
+/// labels passed to `new`.
+pub struct Pushgateway {
+    client: reqwest::Client,
+    group_url: String,
+}
+
+impl Pushgateway {
+    pub fn new(url: &str, labels: &[(&str, String)]) -> Self {
+        let mut group_url = format!("{}/metrics/job/token_tracking", url.trim_end_matches('/'));
+        for (name, value) in labels {
+            // a `/` in a label value would split the grouping path
+            let _ = write!(group_url, "/{}/{}", name, value.replace('/', "_"));
+        }
+
+        Self {
+            client: reqwest::Client::new(),
+            group_url,
+        }
+    }
+
+    pub async fn push(&self, metrics: &BackfillMetrics) -> Result<()> {
+        let mut body = format!(
+            "# TYPE token_tracking_blocks_processed gauge\ntoken_tracking_blocks_processed {}\n# \
+             TYPE token_tracking_blocks_total gauge\ntoken_tracking_blocks_total {}\n# TYPE \
+             token_tracking_last_block gauge\ntoken_tracking_last_block {}\n# TYPE \
+             token_tracking_events_inserted gauge\ntoken_tracking_events_inserted {}\n# TYPE \
+             token_tracking_events_skipped gauge\ntoken_tracking_events_skipped {}\n",
+            metrics.blocks_processed,
+            metrics.blocks_total,
+            metrics.last_block,
+            metrics.events.inserted,
+            metrics.events.skipped(),
+        );
+        body.push_str("# TYPE token_tracking_rpc_calls gauge\n");
+        for (method, calls) in rpc_call_counts() {
+            let _ = writeln!(
+                body,
+                "token_tracking_rpc_calls{{method=\"{}\"}} {}",
+                method, calls
+            );
+        }
+
+        let response = self
+            .client
+            .put(&self.group_url)
+            .body(body)
+            .send()
+            .await
+            .wrap_err("failed to reach pushgateway")?;
+        if !response.status().is_success() {
+            bail!("pushgateway returned {}", response.status());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    /// Accept one request on a local port and answer it with `status`. Returns the URL and a
+    /// handle resolving to the request's head and body.
+    async fn serve_one_push(status: &'static str) -> (String, JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let request = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let (head, body) = loop {
+                let mut buf = [0; 4096];
+                let read = stream.read(&mut buf).await.unwrap();
+                assert_ne!(read, 0, "connection closed before the request ended");
+                request.extend_from_slice(&buf[..read]);
+                let Some(head_end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    continue;
+                };
+                let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+                let length: usize = head
+                    .to_lowercase()
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map_or(0, |length| length.trim().parse().unwrap());
+                if request.len() >= head_end + 4 + length {
+                    let body = &request[head_end + 4..head_end + 4 + length];
+                    break (head, String::from_utf8_lossy(body).to_string());
+                }
+            };
+            let reply = format!(
+                "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            );
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            (head, body)
+        });
+        (url, request)
+    }
+
+    #[tokio::test]
+    async fn metrics_are_pushed_to_the_runs_group() {
+        let (url, request) = serve_one_push("200 OK").await;
+        let pushgateway = Pushgateway::new(&url, &[("range", "10/20".to_string())]);
+        let metrics = BackfillMetrics {
+            blocks_processed: 3,
+            blocks_total: 10,
+            last_block: 12,
+            events: InsertCounts {
+                inserted: 4,
+                total: 6,
+            },
+        };
+
+        pushgateway.push(&metrics).await.unwrap();
+
+        let (head, body) = request.await.unwrap();
+        assert!(
+            head.starts_with("PUT /metrics/job/token_tracking/range/10_20 "),
+            "{}",
+            head
+        );
+        for line in [
+            "token_tracking_blocks_processed 3",
+            "token_tracking_blocks_total 10",
+            "token_tracking_last_block 12",
+            "token_tracking_events_inserted 4",
+            "token_tracking_events_skipped 2",
+        ] {
+            assert!(body.lines().any(|body_line| body_line == line), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_push_is_an_error() {
+        let (url, _) = serve_one_push("500 Internal Server Error").await;
+        let pushgateway = Pushgateway::new(&url, &[]);
+
+        let error = pushgateway
+            .push(&BackfillMetrics::default())
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("500"), "{}", error);
+    }
+}
@@ -1,14 +1,47 @@
 use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt,
     future::Future,
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{
+            AtomicU32,
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
 };
 
 use alloy::{
+    consensus::{
+        BlockHeader,
+        TxReceipt,
+    },
     network::{
         AnyNetwork,
-        AnyReceiptEnvelope,
+        BlockResponse,
+        Ethereum,
         Network,
+        ReceiptResponse,
+    },
+    primitives::{
+        Address,
+        TxHash,
+        B256,
+        U64,
     },
     providers::{
         ProviderBuilder,
@@ -18,18 +51,35 @@ use alloy::{
     pubsub::PubSubFrontend,
     rpc::{
         client::{
+            BatchRequest,
             ClientBuilder,
             RpcClient,
+            RpcClientInner,
+        },
+        json_rpc::{
+            Id,
+            Request,
+            RequestPacket,
+            Response,
+            ResponsePacket,
+            RpcError,
+            SerializedRequest,
         },
         types::{
-            serde_helpers::WithOtherFields,
+            Filter,
             Log,
-            TransactionReceipt,
         },
     },
-    transports::http::{
-        reqwest,
-        Http,
+    transports::{
+        http::{
+            reqwest,
+            Http,
+        },
+        Transport,
+        TransportError,
+        TransportErrorKind,
+        TransportFut,
+        TransportResult,
     },
 };
 use eyre::{
@@ -38,16 +88,20 @@ use eyre::{
     Result,
     WrapErr,
 };
+use rand::Rng;
 use serde_json::{
     json,
     Value,
 };
+use tower_service::Service;
 use tracing::{
     info,
     warn,
 };
 
-pub(crate) async fn websocket_connection(
+use crate::utils::redact_url;
+
+pub async fn websocket_connection(
     ws_url: String,
 ) -> Result<Arc<RootProvider<PubSubFrontend, AnyNetwork>>> {
     let ws = WsConnect::new(ws_url);
@@ -62,37 +116,474 @@ pub(crate) async fn websocket_connection(
     ))
 }
 
-pub(crate) async fn http_connection(
+/// HTTP clients for the batched block fetches
+pub type HttpClient = Arc<HttpEndpoints>;
+
+/// Connect over HTTP to each of the comma-separated endpoints in `http_url`, tagging request ids
+/// with `request_id_prefix` if given
+pub async fn http_connection(
     http_url: String,
-) -> Result<Arc<RpcClient<Http<reqwest::Client>>>> {
-    info!("Connecting to HTTP client...");
+    request_id_prefix: Option<String>,
+) -> Result<HttpClient> {
+    let urls: Vec<&str> = http_url
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .collect();
+    if urls.is_empty() {
+        bail!("HTTP_URL has no endpoints");
+    }
+    info!("Connecting to {} HTTP endpoints...", urls.len());
+
+    let prefix: Option<Arc<str>> = request_id_prefix.map(Arc::from);
+    // one counter for all endpoints, so ids stay unique across failovers
+    let next_id = Arc::new(AtomicU64::new(0));
+    let mut endpoints = Vec::with_capacity(urls.len());
+    for url in urls {
+        let http = Http::new(url.parse().context("Failed to parse HTTP URL")?);
+        let is_local = http.guess_local();
+        let transport = TaggedHttp {
+            inner: http,
+            prefix: prefix.clone(),
+            next_id: next_id.clone(),
+        };
+        endpoints.push(HttpEndpoint {
+            name: redact_url(url),
+            client: ClientBuilder::default().transport(transport, is_local),
+            failures: AtomicU64::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            opened_at: Mutex::new(None),
+        });
+    }
+    Ok(Arc::new(HttpEndpoints {
+        endpoints,
+    }))
+}
+
+/// One of the endpoints of `HTTP_URL`
+struct HttpEndpoint {
+    /// The endpoint's URL with credentials redacted, for logs
+    name: String,
+    client: RpcClient<TaggedHttp>,
+    /// Requests that ran out of retries on this endpoint
+    failures: AtomicU64,
+    /// Requests in a row that ran out of retries on this endpoint
+    consecutive_failures: AtomicU32,
+    /// When the endpoint's circuit breaker last opened or let a probe through, `None` while it is
+    /// closed
+    opened_at: Mutex<Option<tokio::time::Instant>>,
+}
+
+impl HttpEndpoint {
+    fn opened_at(&self) -> Option<tokio::time::Instant> {
+        *self.opened_at.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether a request may go to the endpoint: `None` while its breaker is open, otherwise
+    /// whether the request is the half-open probe. Only one request probes per cooldown.
+    fn admit(&self, breaker: &CircuitBreakerConfig) -> Option<bool> {
+        let mut opened_at = self.opened_at.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(opened) = *opened_at else {
+            return Some(false);
+        };
+        if opened.elapsed() < breaker.cooldown {
+            return None;
+        }
+        *opened_at = Some(tokio::time::Instant::now());
+        Some(true)
+    }
+
+    /// Close the endpoint's breaker, it answered
+    fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        let mut opened_at = self.opened_at.lock().unwrap_or_else(PoisonError::into_inner);
+        if opened_at.take().is_some() {
+            info!("Endpoint {} answered again, closing its circuit breaker", self.name);
+        }
+    }
+
+    /// Count a request that ran out of retries, opening the endpoint's breaker once enough of
+    /// them failed in a row
+    fn record_failure(&self, breaker: Option<&CircuitBreakerConfig>) -> u64 {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        let in_a_row = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(breaker) = breaker {
+            if in_a_row >= breaker.failure_threshold {
+                let mut opened_at = self.opened_at.lock().unwrap_or_else(PoisonError::into_inner);
+                if opened_at.is_none() {
+                    warn!(
+                        "Endpoint {} failed {} requests in a row, opening its circuit breaker for \
+                         {:?}",
+                        self.name, in_a_row, breaker.cooldown
+                    );
+                }
+                *opened_at = Some(tokio::time::Instant::now());
+            }
+        }
+        failures
+    }
+}
+
+/// Clients of the endpoints of `HTTP_URL`, each request going to the endpoint with the fewest
+/// failures so far and failing over to the next one when it runs out of retries. With a circuit
+/// breaker, endpoints that failed too many requests in a row are skipped until their cooldown is
+/// up, then a single request probes them.
+pub struct HttpEndpoints {
+    endpoints: Vec<HttpEndpoint>,
+}
+
+impl HttpEndpoints {
+    /// Retry `operation` with backoff on the healthiest endpoint, then on each of the others in
+    /// turn. Errors that fail the same way everywhere, like rejected requests, are returned
+    /// without failing over.
+    ///
+    /// Endpoints whose circuit breaker is open are skipped, and a half-open endpoint gets a single
+    /// attempt. When every breaker is open the endpoint that opened first is retried as usual, so
+    /// requests still go out.
+    pub async fn retry_with_failover<'a, F, Fut, T>(
+        &'a self,
+        operation: F,
+        config: &RetryConfig,
+    ) -> Result<T>
+    where
+        F: Fn(&'a RpcClient<TaggedHttp>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        // the sort is stable, so endpoints with as many failures keep their listed order
+        let mut endpoints: Vec<&HttpEndpoint> = self.endpoints.iter().collect();
+        endpoints.sort_by_key(|endpoint| endpoint.failures.load(Ordering::Relaxed));
+        let probe_config = RetryConfig {
+            max_attempts: 1,
+            ..config.clone()
+        };
+
+        let mut candidates = endpoints.iter().copied();
+        let mut tried = false;
+        // the last endpoint that ran out of retries, with its failure count and error
+        let mut failed: Option<(&HttpEndpoint, u64, Error)> = None;
+        loop {
+            // breakers are checked just before an endpoint is tried, so a half-open endpoint only
+            // spends its probe on a request that actually reaches it
+            let (endpoint, probe) = match (candidates.next(), &config.circuit_breaker) {
+                (Some(endpoint), None) => (endpoint, false),
+                (Some(endpoint), Some(breaker)) => match endpoint.admit(breaker) {
+                    Some(probe) => (endpoint, probe),
+                    None => continue,
+                },
+                (None, _) if !tried => {
+                    let endpoint = endpoints
+                        .iter()
+                        .copied()
+                        .min_by_key(|endpoint| endpoint.opened_at())
+                        .expect("there is at least one endpoint");
+                    warn!(
+                        "Every endpoint's circuit breaker is open, retrying {} anyway",
+                        endpoint.name
+                    );
+                    (endpoint, false)
+                }
+                (None, _) => break,
+            };
+            if let Some((failed_endpoint, failures, error)) = &failed {
+                warn!(
+                    "Endpoint {} ran out of retries ({} failures so far), failing over to {}: {:#}",
+                    failed_endpoint.name, failures, endpoint.name, error
+                );
+            }
+            tried = true;
+
+            let endpoint_config = if probe { &probe_config } else { config };
+            let error =
+                match retry_with_backoff(|| operation(&endpoint.client), endpoint_config).await {
+                    Ok(value) => {
+                        endpoint.record_success();
+                        return Ok(value);
+                    }
+                    Err(error) => error,
+                };
+            if !should_retry(&error) {
+                // the endpoint answered, it just rejected the request
+                endpoint.record_success();
+                return Err(error);
+            }
+            let failures = endpoint.record_failure(config.circuit_breaker.as_ref());
+            failed = Some((endpoint, failures, error));
+        }
+        let (_, _, error) = failed.expect("an endpoint was tried");
+        Err(error)
+    }
+
+    /// [`Self::retry_with_failover`] for contract calls, `operation` gets a provider of the
+    /// endpoint to call through
+    pub async fn call_with_failover<F, Fut, T>(&self, operation: F, config: &RetryConfig) -> Result<T>
+    where
+        F: Fn(RootProvider<TaggedHttp, AnyNetwork>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.retry_with_failover(
+            |client| operation(RootProvider::new(client.clone())),
+            config,
+        )
+        .await
+    }
+}
+
+/// HTTP transport that replaces the ids of outgoing JSON-RPC requests with `<prefix>-<n>`, so
+/// requests can be found in the provider's logs, and puts the original ids back on the
+/// responses. Without a prefix requests are sent as is.
+///
+/// `n` counts up across all requests of the client, so ids are unique within a batch and across
+/// batches.
+#[derive(Clone, Debug)]
+pub struct TaggedHttp {
+    inner: Http<reqwest::Client>,
+    prefix: Option<Arc<str>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl TaggedHttp {
+    /// Give `request` a tagged id, remembering its original one in `original_ids`. Requests that
+    /// can't be re-serialized keep their id.
+    fn tag(
+        &self,
+        prefix: &str,
+        request: SerializedRequest,
+        original_ids: &mut HashMap<Id, Id>,
+    ) -> SerializedRequest {
+        let tagged_id = Id::String(format!(
+            "{}-{}",
+            prefix,
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        ));
+        let method = request.method().to_string();
+        // requests without params, like `eth_chainId`, are sent without a `params` field
+        let tagged = match request.params().map(ToOwned::to_owned) {
+            Some(params) => Request::new(method, tagged_id.clone(), params).serialize(),
+            None => Request::new(method, tagged_id.clone(), ()).serialize(),
+        };
+        let Ok(tagged) = tagged else {
+            return request;
+        };
+        original_ids.insert(tagged_id, request.id().clone());
+        tagged
+    }
+}
+
+impl Service<RequestPacket> for TaggedHttp {
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+    type Response = ResponsePacket;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // `reqwest` is always ready
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let client = self.inner.client().clone();
+        let url = self.inner.url().to_string();
+        let Some(prefix) = self.prefix.clone() else {
+            return Box::pin(send_http(client, url, request));
+        };
 
-    Ok(Arc::new(ClientBuilder::default().http(
-        http_url.parse().context("Failed to parse HTTP URL")?,
-    )))
+        let mut original_ids = HashMap::new();
+        let request = match request {
+            RequestPacket::Single(request) => {
+                RequestPacket::Single(self.tag(&prefix, request, &mut original_ids))
+            }
+            RequestPacket::Batch(requests) => RequestPacket::Batch(
+                requests
+                    .into_iter()
+                    .map(|request| self.tag(&prefix, request, &mut original_ids))
+                    .collect(),
+            ),
+        };
+
+        let response = send_http(client, url, request);
+        Box::pin(async move {
+            let mut restore_id = |mut response: Response| {
+                if let Some(original_id) = original_ids.remove(&response.id) {
+                    response.id = original_id;
+                }
+                response
+            };
+            Ok(match response.await? {
+                ResponsePacket::Single(response) => ResponsePacket::Single(restore_id(response)),
+                ResponsePacket::Batch(responses) => {
+                    ResponsePacket::Batch(responses.into_iter().map(restore_id).collect())
+                }
+            })
+        })
+    }
+}
+
+/// Post `request` to `url`, like alloy's own HTTP transport does, but keeping the `Retry-After`
+/// header of 429 responses as a [`RateLimited`] error
+async fn send_http(
+    client: reqwest::Client,
+    url: String,
+    request: RequestPacket,
+) -> TransportResult<ResponsePacket> {
+    let response = client
+        .post(url)
+        .json(&request)
+        .send()
+        .await
+        .map_err(TransportErrorKind::custom)?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.bytes().await.map_err(TransportErrorKind::custom)?;
+
+    if status != reqwest::StatusCode::OK {
+        let body = String::from_utf8_lossy(&body).into_owned();
+        return Err(match retry_after {
+            Some(retry_after) if status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                TransportErrorKind::custom(RateLimited {
+                    retry_after,
+                    body,
+                })
+            }
+            _ => TransportErrorKind::http_error(status.as_u16(), body),
+        });
+    }
+
+    serde_json::from_slice(&body)
+        .map_err(|err| TransportError::deser_err(err, String::from_utf8_lossy(&body)))
+}
+
+/// Parse a `Retry-After` value, either a number of seconds or an HTTP date. Dates in the past
+/// mean no wait.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// A 429 response that said how long to wait before trying again
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+    pub body: String,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HTTP error 429 with body: {} (retry after {:?})",
+            self.body, self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// How long the server asked to wait, if `error` is a 429 response with a `Retry-After` header
+fn retry_after(error: &Error) -> Option<Duration> {
+    error
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<TransportError>()? {
+            RpcError::Transport(TransportErrorKind::Custom(custom)) => custom
+                .downcast_ref::<RateLimited>()
+                .map(|rate_limited| rate_limited.retry_after),
+            _ => None,
+        })
+}
+
+/// JSON-RPC calls sent during the run by method, each retry counted again, to estimate what a run
+/// costs on a metered provider
+static RPC_CALL_COUNTS: Mutex<BTreeMap<&str, u64>> = Mutex::new(BTreeMap::new());
+
+/// Count `count` calls of `method` as sent
+pub fn count_rpc_calls(method: &'static str, count: usize) {
+    *RPC_CALL_COUNTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(method)
+        .or_default() += count as u64;
+}
+
+/// JSON-RPC calls sent so far by method
+pub fn rpc_call_counts() -> BTreeMap<&'static str, u64> {
+    RPC_CALL_COUNTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Fetch the chain id of the connected chain
+pub async fn fetch_chain_id(client: &HttpClient, retry_config: &RetryConfig) -> Result<u64> {
+    let chain_id: U64 = client
+        .retry_with_failover(
+            |client| async move {
+                count_rpc_calls("eth_chainId", 1);
+                Ok(client.request_noparams("eth_chainId").await?)
+            },
+            retry_config,
+        )
+        .await?;
+    Ok(chain_id.to())
+}
+
+/// Fetch the number of the chain's latest block
+pub async fn fetch_block_number(client: &HttpClient, retry_config: &RetryConfig) -> Result<u64> {
+    let block_number: U64 = client
+        .retry_with_failover(
+            |client| async move {
+                count_rpc_calls("eth_blockNumber", 1);
+                Ok(client.request_noparams("eth_blockNumber").await?)
+            },
+            retry_config,
+        )
+        .await?;
+    Ok(block_number.to())
 }
 
 /// Retry configuration
 #[derive(Clone, Debug)]
-pub(crate) struct RetryConfig {
+pub struct RetryConfig {
     pub max_attempts: u32,
     pub initial_backoff: Duration,
     pub max_backoff: Duration,
     pub backoff_multiplier: f64,
+    /// Sleep a random time between half the backoff and the full backoff, so requests that failed
+    /// together don't all retry at the same moment
+    pub jitter: bool,
+    /// Skip endpoints that keep failing for a while, `None` to try every endpoint on each request
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
+/// Circuit breaker of each endpoint of `HTTP_URL`
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerConfig {
+    /// Requests in a row that have to run out of retries on an endpoint to open its breaker
+    pub failure_threshold: u32,
+    /// How long an open endpoint is skipped before one request is let through to probe it
+    pub cooldown: Duration,
 }
 
 impl RetryConfig {
-    pub(crate) fn new(
+    pub fn new(
         max_attempts: u32,
         initial_backoff: u64,
         max_backoff: u64,
         backoff_multiplier: f64,
+        jitter: bool,
     ) -> Self {
         Self {
             max_attempts,
             initial_backoff: Duration::from_millis(initial_backoff),
             max_backoff: Duration::from_millis(max_backoff),
             backoff_multiplier,
+            jitter,
+            circuit_breaker: None,
         }
     }
 }
@@ -104,20 +595,43 @@ impl Default for RetryConfig {
             initial_backoff: Duration::from_millis(100),
             max_backoff: Duration::from_secs(10),
             backoff_multiplier: 2.0,
+            jitter: true,
+            circuit_breaker: None,
         }
     }
 }
 
-/// Determine if an error should trigger a retry
+/// Determine if an error should trigger a retry.
+///
+/// Errors that fail the same way every time, like 4xx responses, rejected requests and results
+/// that don't deserialize, are returned right away. Timeouts, dropped connections, 429 and 5xx
+/// responses, and errors that didn't come from the transport are retried.
 fn should_retry(error: &Error) -> bool {
-    match error {
-        // TODO: Add more specific error handling
-        _ => true,
+    let Some(rpc_error) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<TransportError>())
+    else {
+        return true;
+    };
+    match rpc_error {
+        RpcError::Transport(TransportErrorKind::HttpError(http_error)) => {
+            http_error.status == 429 || http_error.status >= 500
+        }
+        RpcError::Transport(_) | RpcError::NullResp => true,
+        // parse error, invalid request, unknown method and invalid params
+        RpcError::ErrorResp(payload) => !matches!(payload.code, -32700 | -32600 | -32601 | -32602),
+        // a block the node doesn't have yet comes back as null
+        RpcError::DeserError {
+            text, ..
+        } => text.trim() == "null",
+        RpcError::SerError(_) | RpcError::UnsupportedFeature(_) | RpcError::LocalUsageError(_) => {
+            false
+        }
     }
 }
 
 /// Retry a future with exponential backoff
-pub(crate) async fn retry_with_backoff<F, Fut, T>(operation: F, config: &RetryConfig) -> Result<T>
+pub async fn retry_with_backoff<F, Fut, T>(operation: F, config: &RetryConfig) -> Result<T>
 where
     F: Fn() -> Fut,
     Fut: Future<Output = Result<T>>,
@@ -134,12 +648,19 @@ where
                     return Err(error);
                 }
 
+                let sleep = if config.jitter {
+                    rand::thread_rng().gen_range(backoff / 2..=backoff)
+                } else {
+                    backoff
+                };
+                // a rate limited request waits at least as long as the server asked
+                let wait = retry_after(&error).map_or(sleep, |retry_after| sleep.max(retry_after));
                 warn!(
                     "Request failed (attempt {}/{}), retrying in {:?}: {:?}",
-                    attempts, config.max_attempts, backoff, error
+                    attempts, config.max_attempts, wait, error
                 );
 
-                tokio::time::sleep(backoff).await;
+                tokio::time::sleep(wait).await;
 
                 // Calculate next backoff duration
                 backoff = Duration::from_secs_f64(
@@ -151,74 +672,1089 @@ where
     }
 }
 
-/// Fetch block from provider
-pub(crate) async fn fetch_block_data_batched(
-    client: &Arc<RpcClient<Http<reqwest::Client>>>,
+/// Network types fetched blocks and receipts are deserialized as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NetworkKind {
+    /// Any chain's transaction and receipt types, keeping fields Ethereum doesn't have
+    #[default]
+    Any,
+    /// Ethereum's types only, blocks with other transaction types (like L2 deposits) fail to
+    /// deserialize
+    Ethereum,
+}
+
+/// A network whose blocks can be processed for pool events
+pub trait TrackedNetwork: Network {
+    fn receipt_logs(receipt: &Self::ReceiptResponse) -> &[Log];
+}
+
+impl TrackedNetwork for AnyNetwork {
+    fn receipt_logs(receipt: &Self::ReceiptResponse) -> &[Log] {
+        receipt.inner.inner.inner.logs()
+    }
+}
+
+impl TrackedNetwork for Ethereum {
+    fn receipt_logs(receipt: &Self::ReceiptResponse) -> &[Log] {
+        receipt.inner.logs()
+    }
+}
+
+/// Transport live mode fetches new blocks and their receipts over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReceiptTransport {
+    /// A separate HTTP connection to `HTTP_URL`
+    #[default]
+    Http,
+    /// The websocket connection used for the block subscription, so only `WSS_URL` is needed
+    Ws,
+}
+
+/// Batching configuration for fetching ranges of blocks
+#[derive(Clone, Debug)]
+pub struct BatchConfig {
+    /// Number of blocks fetched per batch
+    pub blocks_per_batch: u64,
+    /// Cap on the serialized size of a batch request, larger batches are split into sub-batches
+    pub max_request_bytes: usize,
+    /// Delay after each batch to help with rate limiting
+    pub delay: Duration,
+}
+
+/// Params of `eth_getBlockByNumber`, with the full transactions or only their hashes
+fn block_params(block_number: u64, full_transactions: bool) -> [Value; 2] {
+    [
+        json!(format!("0x{:x}", block_number)),
+        json!(full_transactions),
+    ]
+}
+
+fn receipts_params(block_number: u64) -> [Value; 1] {
+    [Value::String(format!("0x{:x}", block_number))]
+}
+
+fn transaction_receipt_params(transaction_hash: TxHash) -> [TxHash; 1] {
+    [transaction_hash]
+}
+
+/// Serialized size of a JSON-RPC request in a batch, bounded for any id the client assigns
+fn request_size(method: &str, params: Value) -> usize {
+    // a request id of u64::MAX bounds the size of any id the client assigns, plus one byte for
+    // the separating comma
+    json!({ "method": method, "params": params, "id": u64::MAX, "jsonrpc": "2.0" })
+        .to_string()
+        .len()
+        + 1
+}
+
+/// Serialized size of the batch entries needed to fetch one block
+fn block_request_size(block_number: u64) -> usize {
+    // `false` serializes longer than `true`, so this bounds both
+    request_size(
+        "eth_getBlockByNumber",
+        json!(block_params(block_number, false)),
+    ) + request_size("eth_getBlockReceipts", json!(receipts_params(block_number)))
+}
+
+/// Split blocks into consecutive chunks whose batch request bodies stay under
+/// `max_request_bytes`. A single block is never split, even if it exceeds the cap by itself.
+fn split_by_request_size(block_numbers: &[u64], max_request_bytes: usize) -> Vec<&[u64]> {
+    let mut chunks = Vec::new();
+    let mut chunk_start = 0;
+    let mut chunk_size = 2; // enclosing `[` and `]`
+
+    for (i, block_number) in block_numbers.iter().enumerate() {
+        let request_size = block_request_size(*block_number);
+        if i > chunk_start && chunk_size + request_size > max_request_bytes {
+            chunks.push(&block_numbers[chunk_start..i]);
+            chunk_start = i;
+            chunk_size = 2;
+        }
+        chunk_size += request_size;
+    }
+    if chunk_start < block_numbers.len() {
+        chunks.push(&block_numbers[chunk_start..]);
+    }
+
+    chunks
+}
+
+/// Number of HTTP requests fetching blocks `start_block..=end_block` with `batch_config` takes,
+/// counting the batches split up for being over `max_request_bytes`
+pub fn batch_request_count(start_block: u64, end_block: u64, batch_config: &BatchConfig) -> u64 {
+    (start_block..=end_block)
+        .step_by(batch_config.blocks_per_batch as usize)
+        .map(|batch_start| {
+            let batch_end = end_block.min(batch_start + (batch_config.blocks_per_batch - 1));
+            let batch: Vec<u64> = (batch_start..=batch_end).collect();
+            split_by_request_size(&batch, batch_config.max_request_bytes).len() as u64
+        })
+        .sum()
+}
+
+/// Combine the results of a block's receipts and block calls from a batch request.
+///
+/// Fails if the provider returned a different block than requested, which happens around
+/// reorgs or with buggy providers, so the fetch is retried rather than the wrong block being
+/// stored under the requested number.
+fn block_call_results<N: Network>(
     block_number: u64,
+    receipts: TransportResult<Vec<N::ReceiptResponse>>,
+    block: TransportResult<N::BlockResponse>,
+) -> Result<(Vec<N::ReceiptResponse>, N::BlockResponse)> {
+    // TODO figure out if this is the correct way to handle the errors in the batch request
+    // the transport errors are kept as the source, for `should_retry` to classify
+    let (receipts, block) = match (receipts, block) {
+        (Ok(receipts), Ok(block)) => (receipts, block),
+        (Err(reciept_err), Ok(_)) => {
+            let message = format!(
+                "failed to grab receipts for block {}: {}",
+                block_number, reciept_err
+            );
+            warn!("{}", message);
+            return Err(Error::new(reciept_err).wrap_err(message));
+        }
+        (Ok(_), Err(block_err)) => {
+            let message = format!(
+                "failed to grab block for block {}: {}",
+                block_number, block_err
+            );
+            warn!("{}", message);
+            return Err(Error::new(block_err).wrap_err(message));
+        }
+        (Err(reciept_err), Err(block_err)) => {
+            let message = format!(
+                "failed to grab receipts and block for block {}: {}, {}",
+                block_number, reciept_err, block_err
+            );
+            warn!("{}", message);
+            return Err(Error::new(block_err).wrap_err(message));
+        }
+    };
+
+    check_block_number::<N>(block_number, &block)?;
+    check_receipts_block_number::<N>(block_number, &receipts)?;
+
+    Ok((receipts, block))
+}
+
+/// Fail if the provider returned a different block than requested
+fn check_block_number<N: Network>(block_number: u64, block: &N::BlockResponse) -> Result<()> {
+    let returned_number = block.header().number();
+    if returned_number != block_number {
+        warn!(
+            "requested block {} but the provider returned block {}",
+            block_number, returned_number
+        );
+        bail!(
+            "requested block {} but the provider returned block {}",
+            block_number,
+            returned_number
+        );
+    }
+    Ok(())
+}
+
+/// Fail if the provider returned receipts of a different block than requested
+pub fn check_receipts_block_number<N: Network>(
+    block_number: u64,
+    receipts: &[N::ReceiptResponse],
+) -> Result<()> {
+    if let Some(receipt_number) = receipts
+        .iter()
+        .filter_map(ReceiptResponse::block_number)
+        .find(|&receipt_number| receipt_number != block_number)
+    {
+        warn!(
+            "requested receipts of block {} but the provider returned receipts of block {}",
+            block_number, receipt_number
+        );
+        bail!(
+            "requested receipts of block {} but the provider returned receipts of block {}",
+            block_number,
+            receipt_number
+        );
+    }
+    Ok(())
+}
+
+/// Fetch block from provider, failing over to the other endpoints if it runs out of retries
+pub async fn fetch_block_data_batched<N: Network>(
+    client: &HttpClient,
+    block_number: u64,
+    full_transactions: bool,
     retry_config: &RetryConfig,
-) -> Result<(
-    Vec<WithOtherFields<TransactionReceipt<AnyReceiptEnvelope<Log>>>>,
-    <AnyNetwork as Network>::BlockResponse,
-)> {
-    // Execute the batch request
-    let (receipts, block) = retry_with_backoff(
-        || async {
-            // Execute the batch request
-            let mut batch_requests = client.new_batch();
-            let block_call = batch_requests.add_call(
-                "eth_getBlockByNumber",
-                &[json!(format!("0x{:x}", block_number)), json!(false)],
-            )?;
-            let receipts_call = batch_requests.add_call(
-                "eth_getBlockReceipts",
-                &[Value::String(format!("0x{:x}", block_number))],
-            )?;
-            batch_requests.await?;
-
-            // TODO figure out if this is the correct way to handle the errors in the batch request
-            match (receipts_call.await, block_call.await) {
-                (Ok(receipts), Ok(block)) => {
-                    return Ok((receipts, block));
-                }
-                (Err(reciept_err), Ok(_)) => {
-                    warn!(
-                        "failed to grab receipts for block {}: {}",
-                        block_number, reciept_err
-                    );
-                    bail!(
-                        "failed to grab receipts for block {}: {}",
-                        block_number,
-                        reciept_err
-                    );
-                }
-                (Ok(_), Err(block_err)) => {
-                    warn!(
-                        "failed to grab block for block {}: {}",
-                        block_number, block_err
-                    );
-                    bail!(
-                        "failed to grab block for block {}: {}",
-                        block_number,
-                        block_err
-                    );
+) -> Result<(Vec<N::ReceiptResponse>, N::BlockResponse)> {
+    client
+        .retry_with_failover(
+            |client| block_data_request::<N, _>(client, block_number, full_transactions),
+            retry_config,
+        )
+        .await
+}
+
+/// Fetch block from provider over a single connection, like the websocket subscription's
+pub async fn fetch_block_data_over<N: Network, T: Transport + Clone>(
+    client: &RpcClientInner<T>,
+    block_number: u64,
+    full_transactions: bool,
+    retry_config: &RetryConfig,
+) -> Result<(Vec<N::ReceiptResponse>, N::BlockResponse)> {
+    retry_with_backoff(
+        || block_data_request::<N, _>(client, block_number, full_transactions),
+        retry_config,
+    )
+    .await
+}
+
+/// Request a block and its receipts in one batch request, over any transport that supports them
+async fn block_data_request<N: Network, T: Transport + Clone>(
+    client: &RpcClientInner<T>,
+    block_number: u64,
+    full_transactions: bool,
+) -> Result<(Vec<N::ReceiptResponse>, N::BlockResponse)> {
+    let mut batch_requests = BatchRequest::new(client);
+    let block_call = batch_requests.add_call(
+        "eth_getBlockByNumber",
+        &block_params(block_number, full_transactions),
+    )?;
+    let receipts_call =
+        batch_requests.add_call("eth_getBlockReceipts", &receipts_params(block_number))?;
+    count_rpc_calls("eth_getBlockByNumber", 1);
+    count_rpc_calls("eth_getBlockReceipts", 1);
+    batch_requests.await?;
+
+    block_call_results::<N>(block_number, receipts_call.await, block_call.await)
+}
+
+/// Fetch several blocks from provider, returned in the order of `block_numbers`.
+///
+/// The blocks are requested in as few batch requests as `max_request_bytes` allows, so a large
+/// batch never has to be built as one giant request body.
+pub async fn fetch_blocks_data_batched<N: Network>(
+    client: &HttpClient,
+    block_numbers: &[u64],
+    max_request_bytes: usize,
+    full_transactions: bool,
+    retry_config: &RetryConfig,
+) -> Result<Vec<(Vec<N::ReceiptResponse>, N::BlockResponse)>> {
+    let mut blocks_data = Vec::with_capacity(block_numbers.len());
+
+    for chunk in split_by_request_size(block_numbers, max_request_bytes) {
+        let chunk_data = client
+            .retry_with_failover(
+                |client| async move {
+                    let mut batch_requests = BatchRequest::new(client);
+                    let mut calls = Vec::with_capacity(chunk.len());
+                    for &block_number in chunk {
+                        let block_call = batch_requests.add_call(
+                            "eth_getBlockByNumber",
+                            &block_params(block_number, full_transactions),
+                        )?;
+                        let receipts_call = batch_requests
+                            .add_call("eth_getBlockReceipts", &receipts_params(block_number))?;
+                        calls.push((block_number, receipts_call, block_call));
+                    }
+                    count_rpc_calls("eth_getBlockByNumber", chunk.len());
+                    count_rpc_calls("eth_getBlockReceipts", chunk.len());
+                    batch_requests.await?;
+
+                    let mut chunk_data = Vec::with_capacity(calls.len());
+                    for (block_number, receipts_call, block_call) in calls {
+                        chunk_data.push(block_call_results::<N>(
+                            block_number,
+                            receipts_call.await,
+                            block_call.await,
+                        )?);
+                    }
+                    Ok(chunk_data)
+                },
+                retry_config,
+            )
+            .await?;
+
+        blocks_data.extend(chunk_data);
+    }
+
+    Ok(blocks_data)
+}
+
+/// Fetch only the receipts of several blocks, returned in the order of `block_numbers`.
+///
+/// Chunks are sized as if the blocks were fetched too, so they only come out smaller than
+/// `max_request_bytes` allows.
+pub async fn fetch_receipts_batched<N: Network>(
+    client: &HttpClient,
+    block_numbers: &[u64],
+    max_request_bytes: usize,
+    retry_config: &RetryConfig,
+) -> Result<Vec<Vec<N::ReceiptResponse>>> {
+    let mut blocks_receipts = Vec::with_capacity(block_numbers.len());
+
+    for chunk in split_by_request_size(block_numbers, max_request_bytes) {
+        let chunk_receipts = client
+            .retry_with_failover(
+                |client| async move {
+                    let mut batch_requests = BatchRequest::new(client);
+                    let mut calls = Vec::with_capacity(chunk.len());
+                    for &block_number in chunk {
+                        let receipts_call = batch_requests
+                            .add_call("eth_getBlockReceipts", &receipts_params(block_number))?;
+                        calls.push((block_number, receipts_call));
+                    }
+                    count_rpc_calls("eth_getBlockReceipts", chunk.len());
+                    batch_requests.await?;
+
+                    let mut chunk_receipts = Vec::with_capacity(calls.len());
+                    for (block_number, receipts_call) in calls {
+                        let receipts: Vec<N::ReceiptResponse> = match receipts_call.await {
+                            Ok(receipts) => receipts,
+                            Err(e) => {
+                                let message = format!(
+                                    "failed to grab receipts for block {}: {}",
+                                    block_number, e
+                                );
+                                warn!("{}", message);
+                                return Err(Error::new(e).wrap_err(message));
+                            }
+                        };
+                        check_receipts_block_number::<N>(block_number, &receipts)?;
+                        chunk_receipts.push(receipts);
+                    }
+                    Ok(chunk_receipts)
+                },
+                retry_config,
+            )
+            .await?;
+
+        blocks_receipts.extend(chunk_receipts);
+    }
+
+    Ok(blocks_receipts)
+}
+
+/// Fetch only the headers of several blocks, returned in the order of `block_numbers`.
+///
+/// Chunks are sized as if the receipts were fetched too, so they only come out smaller than
+/// `max_request_bytes` allows.
+pub async fn fetch_blocks_batched<N: Network>(
+    client: &HttpClient,
+    block_numbers: &[u64],
+    max_request_bytes: usize,
+    retry_config: &RetryConfig,
+) -> Result<Vec<N::BlockResponse>> {
+    let mut blocks = Vec::with_capacity(block_numbers.len());
+
+    for chunk in split_by_request_size(block_numbers, max_request_bytes) {
+        let chunk_blocks = client
+            .retry_with_failover(
+                |client| async move {
+                    let mut batch_requests = BatchRequest::new(client);
+                    let mut calls = Vec::with_capacity(chunk.len());
+                    for &block_number in chunk {
+                        let block_call = batch_requests
+                            .add_call("eth_getBlockByNumber", &block_params(block_number, false))?;
+                        calls.push((block_number, block_call));
+                    }
+                    count_rpc_calls("eth_getBlockByNumber", chunk.len());
+                    batch_requests.await?;
+
+                    let mut chunk_blocks = Vec::with_capacity(calls.len());
+                    for (block_number, block_call) in calls {
+                        let block: N::BlockResponse = match block_call.await {
+                            Ok(block) => block,
+                            Err(e) => {
+                                let message = format!(
+                                    "failed to grab block for block {}: {}",
+                                    block_number, e
+                                );
+                                warn!("{}", message);
+                                return Err(Error::new(e).wrap_err(message));
+                            }
+                        };
+                        check_block_number::<N>(block_number, &block)?;
+                        chunk_blocks.push(block);
+                    }
+                    Ok(chunk_blocks)
+                },
+                retry_config,
+            )
+            .await?;
+
+        blocks.extend(chunk_blocks);
+    }
+
+    Ok(blocks)
+}
+
+/// Fetch the logs of blocks `from_block` to `to_block`, both included, whose first topic is one
+/// of `topics`. Only logs emitted by `addresses` are fetched, or by any address if `None`.
+pub async fn fetch_logs(
+    client: &HttpClient,
+    from_block: u64,
+    to_block: u64,
+    addresses: Option<Vec<Address>>,
+    topics: Vec<B256>,
+    retry_config: &RetryConfig,
+) -> Result<Vec<Log>> {
+    let mut filter = Filter::new()
+        .from_block(from_block)
+        .to_block(to_block)
+        .event_signature(topics);
+    if let Some(addresses) = addresses {
+        filter = filter.address(addresses);
+    }
+    let filter = &filter;
+    client
+        .retry_with_failover(
+            |client| async move {
+                count_rpc_calls("eth_getLogs", 1);
+                Ok(client.request("eth_getLogs", (filter,)).await?)
+            },
+            retry_config,
+        )
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "failed to grab logs of blocks {} to {}",
+                from_block, to_block
+            )
+        })
+}
+
+/// Fetch the receipts of single transactions, returned in the order of `transaction_hashes`
+pub async fn fetch_transaction_receipts_batched<N: Network>(
+    client: &HttpClient,
+    transaction_hashes: &[TxHash],
+    max_request_bytes: usize,
+    retry_config: &RetryConfig,
+) -> Result<Vec<N::ReceiptResponse>> {
+    let mut receipts = Vec::with_capacity(transaction_hashes.len());
+
+    // every request has the same size, and a single one is never split
+    let chunk_len = (max_request_bytes.saturating_sub(2)
+        / request_size(
+            "eth_getTransactionReceipt",
+            json!(transaction_receipt_params(TxHash::ZERO)),
+        ))
+    .max(1);
+    for chunk in transaction_hashes.chunks(chunk_len) {
+        let chunk_receipts = client
+            .retry_with_failover(
+                |client| async move {
+                    let mut batch_requests = BatchRequest::new(client);
+                    let mut calls = Vec::with_capacity(chunk.len());
+                    for &transaction_hash in chunk {
+                        let receipt_call = batch_requests.add_call(
+                            "eth_getTransactionReceipt",
+                            &transaction_receipt_params(transaction_hash),
+                        )?;
+                        calls.push((transaction_hash, receipt_call));
+                    }
+                    count_rpc_calls("eth_getTransactionReceipt", chunk.len());
+                    batch_requests.await?;
+
+                    let mut chunk_receipts = Vec::with_capacity(calls.len());
+                    for (transaction_hash, receipt_call) in calls {
+                        let receipt: Option<N::ReceiptResponse> = match receipt_call.await {
+                            Ok(receipt) => receipt,
+                            Err(e) => {
+                                let message = format!(
+                                    "failed to grab the receipt of transaction {}: {}",
+                                    transaction_hash, e
+                                );
+                                warn!("{}", message);
+                                return Err(Error::new(e).wrap_err(message));
+                            }
+                        };
+                        // the transaction was reorged out since its logs were fetched
+                        let Some(receipt) = receipt else {
+                            bail!("no receipt for transaction {}", transaction_hash);
+                        };
+                        chunk_receipts.push(receipt);
+                    }
+                    Ok(chunk_receipts)
+                },
+                retry_config,
+            )
+            .await?;
+
+        receipts.extend(chunk_receipts);
+    }
+
+    Ok(receipts)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        cell::RefCell,
+        collections::HashSet,
+    };
+
+    use alloy::rpc::json_rpc::ErrorPayload;
+    use eyre::eyre;
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// Run `retry_with_backoff` with an operation that fails `failures` times before succeeding,
+    /// returning its result and the time of each attempt
+    async fn attempt_times(failures: usize, config: &RetryConfig) -> (Result<usize>, Vec<Instant>) {
+        let attempts = RefCell::new(Vec::new());
+        let result = retry_with_backoff(
+            || {
+                let mut attempts = attempts.borrow_mut();
+                attempts.push(Instant::now());
+                let attempt = attempts.len();
+                async move {
+                    if attempt > failures {
+                        Ok(attempt)
+                    } else {
+                        Err(eyre!("attempt {} failed", attempt))
+                    }
                 }
-                (Err(reciept_err), Err(block_err)) => {
-                    warn!(
-                        "failed to grab receipts and block for block {}: {}, {}",
-                        block_number, reciept_err, block_err
-                    );
-                    bail!(
-                        "failed to grab receipts and block for block {}: {}, {}",
-                        block_number,
-                        reciept_err,
-                        block_err
-                    );
+            },
+            config,
+        )
+        .await;
+        (result, attempts.into_inner())
+    }
+
+    /// Serve JSON-RPC requests on a local port for the rest of the test, answering each, batched
+    /// or not, with the `result` or `error` that `respond` returns for it. Returns the endpoint's
+    /// URL.
+    pub(crate) async fn serve_json_rpc(respond: fn(&Value) -> Value) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let body = loop {
+                    let mut buf = [0; 4096];
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                    let Some(header_end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        continue;
+                    };
+                    let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                    let length: usize = headers
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map_or(0, |length| length.trim().parse().unwrap());
+                    let body = header_end + 4..header_end + 4 + length;
+                    if request.len() >= body.end {
+                        break Some(request[body].to_vec());
+                    }
+                };
+                let Some(body) = body else {
+                    continue;
+                };
+
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let answer = |request: &Value| {
+                    let mut response = respond(request);
+                    response["jsonrpc"] = json!("2.0");
+                    response["id"] = request["id"].clone();
+                    response
+                };
+                // a batch request gets a batch of answers
+                let response = match request.as_array() {
+                    Some(requests) => Value::Array(requests.iter().map(answer).collect()),
+                    None => answer(&request),
                 }
+                .to_string();
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: \
+                     {}\r\nconnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
             }
-        },
-        retry_config,
-    )
-    .await?;
+        });
+        url
+    }
 
-    Ok((receipts, block))
+    /// URL of a local port nothing listens on, requests to it fail to connect
+    pub(crate) fn closed_endpoint() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    /// Time slept between consecutive attempts
+    fn sleeps(attempts: &[Instant]) -> Vec<Duration> {
+        attempts
+            .windows(2)
+            .map(|pair| pair[1].duration_since(pair[0]))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn first_success_returns_without_sleeping() {
+        let start = Instant::now();
+        let (result, attempts) = attempt_times(0, &RetryConfig::default()).await;
+
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts_with_the_last_error() {
+        let config = RetryConfig::new(4, 100, 10_000, 2.0, false);
+        let (result, attempts) = attempt_times(usize::MAX, &config).await;
+
+        assert_eq!(attempts.len(), 4);
+        assert_eq!(result.unwrap_err().to_string(), "attempt 4 failed");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_grows_by_the_multiplier_up_to_max_backoff() {
+        let config = RetryConfig::new(6, 100, 500, 2.0, false);
+        let (result, attempts) = attempt_times(5, &config).await;
+
+        assert_eq!(result.unwrap(), 6);
+        assert_eq!(
+            sleeps(&attempts),
+            [100, 200, 400, 500, 500].map(Duration::from_millis)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jittered_sleeps_stay_between_half_and_the_full_backoff() {
+        let config = RetryConfig::new(6, 100, 500, 2.0, true);
+        let (result, attempts) = attempt_times(5, &config).await;
+
+        assert_eq!(result.unwrap(), 6);
+        let backoffs = [100, 200, 400, 500, 500].map(Duration::from_millis);
+        for (sleep, backoff) in sleeps(&attempts).into_iter().zip(backoffs) {
+            assert!(
+                backoff / 2 <= sleep && sleep <= backoff,
+                "slept {:?} for a backoff of {:?}",
+                sleep,
+                backoff
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_endpoint() {
+        let endpoint = serve_json_rpc(|_| json!({ "result": "0x2a" })).await;
+        let client = http_connection(format!("{},{}", closed_endpoint(), endpoint), None)
+            .await
+            .unwrap();
+        let config = RetryConfig::new(1, 1, 1, 1.0, false);
+
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+        // the endpoint that failed is tried last from now on
+        assert_eq!(client.endpoints[0].failures.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_are_not_failed_over() {
+        let endpoint = serve_json_rpc(|_| {
+            json!({ "error": { "code": -32601, "message": "method not found" } })
+        })
+        .await;
+        let client = http_connection(format!("{},{}", endpoint, closed_endpoint()), None)
+            .await
+            .unwrap();
+        let config = RetryConfig::new(3, 1, 1, 1.0, false);
+
+        assert!(fetch_chain_id(&client, &config).await.is_err());
+        assert!(client
+            .endpoints
+            .iter()
+            .all(|endpoint| endpoint.failures.load(Ordering::Relaxed) == 0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn circuit_breaker_opens_and_recovers_after_a_probe() {
+        let client = http_connection(closed_endpoint(), None).await.unwrap();
+        let endpoint = &client.endpoints[0];
+        let breaker = CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(30),
+        };
+
+        endpoint.record_failure(Some(&breaker));
+        assert_eq!(endpoint.admit(&breaker), Some(false));
+        endpoint.record_failure(Some(&breaker));
+        assert_eq!(endpoint.admit(&breaker), None);
+
+        // a failed probe keeps it open for another cooldown
+        tokio::time::advance(breaker.cooldown).await;
+        assert_eq!(endpoint.admit(&breaker), Some(true));
+        assert_eq!(endpoint.admit(&breaker), None);
+        endpoint.record_failure(Some(&breaker));
+        tokio::time::advance(breaker.cooldown / 2).await;
+        assert_eq!(endpoint.admit(&breaker), None);
+
+        tokio::time::advance(breaker.cooldown / 2).await;
+        assert_eq!(endpoint.admit(&breaker), Some(true));
+        endpoint.record_success();
+        assert_eq!(endpoint.admit(&breaker), Some(false));
+        assert_eq!(endpoint.consecutive_failures.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn open_endpoints_are_skipped() {
+        let endpoint = serve_json_rpc(|_| json!({ "result": "0x2a" })).await;
+        let client = http_connection(format!("{},{}", endpoint, closed_endpoint()), None)
+            .await
+            .unwrap();
+        let mut config = RetryConfig::new(1, 1, 1, 1.0, false);
+        config.circuit_breaker = Some(CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown: Duration::from_secs(3600),
+        });
+        // the healthy endpoint has failed more requests, but the other one's breaker is open
+        client.endpoints[0].failures.store(5, Ordering::Relaxed);
+        client.endpoints[1].record_failure(config.circuit_breaker.as_ref());
+
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+        assert_eq!(client.endpoints[1].failures.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn half_open_endpoints_keep_their_probe_until_a_request_reaches_them() {
+        let endpoint = serve_json_rpc(|_| json!({ "result": "0x2a" })).await;
+        let client = http_connection(format!("{},{}", endpoint, closed_endpoint()), None)
+            .await
+            .unwrap();
+        let breaker = CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown: Duration::from_millis(200),
+        };
+        let mut config = RetryConfig::new(1, 1, 1, 1.0, false);
+        config.circuit_breaker = Some(breaker.clone());
+        client.endpoints[1].record_failure(Some(&breaker));
+        tokio::time::sleep(breaker.cooldown).await;
+
+        // the healthy endpoint answers, the half-open one is never tried
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+        assert_eq!(client.endpoints[1].admit(&breaker), Some(true));
+    }
+
+    /// Block `number` as a provider returns it
+    fn block_json(number: u64) -> Value {
+        let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+            header: alloy::rpc::types::Header {
+                inner: alloy::consensus::Header {
+                    number,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        json!(block)
+    }
+
+    /// Number of the block an `eth_getBlockByNumber` request asks for
+    fn requested_block(request: &Value) -> u64 {
+        let number = request["params"][0].as_str().unwrap();
+        u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap()
+    }
+
+    /// Ids of the requests `record_ids` answered
+    static RECEIVED_IDS: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+
+    fn record_ids(request: &Value) -> Value {
+        RECEIVED_IDS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(request["id"].clone());
+        match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => json!({ "result": block_json(requested_block(request)) }),
+            _ => json!({ "result": "0x2a" }),
+        }
+    }
+
+    #[tokio::test]
+    async fn request_ids_carry_the_prefix_and_are_unique() {
+        let client = http_connection(serve_json_rpc(record_ids).await, Some("backfill".into()))
+            .await
+            .unwrap();
+        let config = RetryConfig::new(1, 1, 1, 1.0, false);
+
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+        let blocks = fetch_blocks_batched::<Ethereum>(&client, &[7, 8, 9], usize::MAX, &config)
+            .await
+            .unwrap();
+        assert_eq!(fetch_chain_id(&client, &config).await.unwrap(), 42);
+
+        assert_eq!(blocks.len(), 3);
+        let ids = RECEIVED_IDS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        assert_eq!(ids.len(), 5);
+        for id in &ids {
+            let id = id.as_str().unwrap();
+            assert!(id.starts_with("backfill-"), "{}", id);
+        }
+        let unique: HashSet<&Value> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len(), "{:?}", ids);
+    }
+
+    /// A provider answering every block request with the next block
+    fn off_by_one_blocks(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => json!({ "result": block_json(requested_block(request) + 1) }),
+            _ => json!({ "result": [] }),
+        }
+    }
+
+    #[tokio::test]
+    async fn block_other_than_the_requested_one_is_rejected() {
+        let client = http_connection(serve_json_rpc(off_by_one_blocks).await, None)
+            .await
+            .unwrap();
+        let config = RetryConfig::new(1, 1, 1, 1.0, false);
+
+        let single = fetch_block_data_batched::<Ethereum>(&client, 7, false, &config)
+            .await
+            .unwrap_err();
+        let batched = fetch_blocks_batched::<Ethereum>(&client, &[7, 8], usize::MAX, &config)
+            .await
+            .unwrap_err();
+
+        for error in [single, batched] {
+            assert!(
+                format!("{:#}", error)
+                    .contains("requested block 7 but the provider returned block 8"),
+                "{:#}",
+                error
+            );
+        }
+    }
+
+    /// A provider answering with a block holding an L2 deposit, whose receipt type Ethereum
+    /// doesn't have
+    fn deposit_block(request: &Value) -> Value {
+        match request["method"].as_str().unwrap() {
+            "eth_getBlockByNumber" => json!({ "result": block_json(requested_block(request)) }),
+            _ => json!({ "result": [{
+                "type": "0x7e",
+                "status": "0x1",
+                "cumulativeGasUsed": "0xb4d5",
+                "logsBloom": format!("0x{}", "00".repeat(256)),
+                "logs": [{
+                    "address": "0x1000000000000000000000000000000000000001",
+                    "topics": [format!("0x{}", "11".repeat(32))],
+                    "data": "0x",
+                    "blockHash": format!("0x{}", "33".repeat(32)),
+                    "blockNumber": "0x7",
+                    "blockTimestamp": null,
+                    "transactionHash": format!("0x{}", "22".repeat(32)),
+                    "transactionIndex": "0x0",
+                    "logIndex": "0x0",
+                    "removed": false,
+                }],
+                "transactionHash": format!("0x{}", "22".repeat(32)),
+                "transactionIndex": "0x0",
+                "blockHash": format!("0x{}", "33".repeat(32)),
+                "blockNumber": "0x7",
+                "gasUsed": "0xb4d5",
+                "effectiveGasPrice": "0x0",
+                "from": "0x4200000000000000000000000000000000000042",
+                "to": "0x4200000000000000000000000000000000000015",
+                "contractAddress": null,
+                "depositNonce": "0x5",
+            }] }),
+        }
+    }
+
+    #[tokio::test]
+    async fn any_network_reads_receipts_ethereum_does_not_have() {
+        let client = http_connection(serve_json_rpc(deposit_block).await, None)
+            .await
+            .unwrap();
+        let config = RetryConfig::new(1, 1, 1, 1.0, false);
+
+        let (receipts, block) = fetch_block_data_batched::<AnyNetwork>(&client, 7, false, &config)
+            .await
+            .unwrap();
+        assert_eq!(block.header().number(), 7);
+        assert_eq!(receipts.len(), 1);
+        let logs = AnyNetwork::receipt_logs(&receipts[0]);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(
+            logs[0].address(),
+            "0x1000000000000000000000000000000000000001"
+                .parse::<Address>()
+                .unwrap()
+        );
+        let deposit_nonce = receipts[0].other.get_deserialized::<U64>("depositNonce");
+        assert_eq!(deposit_nonce.unwrap().unwrap(), U64::from(5));
+
+        let error = fetch_block_data_batched::<Ethereum>(&client, 7, false, &config)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("failed to grab receipts for block 7"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn calls_are_counted_by_method_and_attempt() {
+        let endpoint = serve_json_rpc(|request| match request["method"].as_str().unwrap() {
+            "eth_getLogs" => json!({ "result": [] }),
+            _ => json!({ "result": "0x2a" }),
+        })
+        .await;
+        let client = http_connection(format!("{},{}", closed_endpoint(), endpoint), None)
+            .await
+            .unwrap();
+        let config = RetryConfig::new(1, 1, 1, 1.0, false);
+        // the counts are shared by the whole process, no other test calls these methods
+        let count = |method| rpc_call_counts().get(method).copied().unwrap_or(0);
+        let block_number_calls = count("eth_blockNumber");
+        let get_logs_calls = count("eth_getLogs");
+
+        // the first request fails on the closed endpoint and is sent again to the other one
+        assert_eq!(fetch_block_number(&client, &config).await.unwrap(), 42);
+        assert_eq!(fetch_block_number(&client, &config).await.unwrap(), 42);
+        for from_block in [10, 20] {
+            fetch_logs(
+                &client,
+                from_block,
+                from_block + 9,
+                None,
+                Vec::new(),
+                &config,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(count("eth_blockNumber") - block_number_calls, 3);
+        assert_eq!(count("eth_getLogs") - get_logs_calls, 2);
+    }
+
+    /// `error` as it comes out of a request, wrapped like the fetches wrap theirs
+    fn transport_error(error: TransportError) -> Error {
+        Error::new(error).wrap_err("failed to fetch block 1")
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let retried = [
+            transport_error(TransportErrorKind::http_error(429, String::new())),
+            transport_error(TransportErrorKind::http_error(503, String::new())),
+            transport_error(TransportErrorKind::backend_gone()),
+            transport_error(RpcError::NullResp),
+            transport_error(TransportError::ErrorResp(ErrorPayload {
+                code: -32000,
+                message: "header not found".into(),
+                data: None,
+            })),
+            // a block the node doesn't have yet
+            transport_error(TransportError::deser_err(
+                serde_json::from_str::<u64>("null").unwrap_err(),
+                "null",
+            )),
+            eyre!("block 1 not found"),
+        ];
+        for error in &retried {
+            assert!(should_retry(error), "{:?} should be retried", error);
+        }
+    }
+
+    #[test]
+    fn errors_that_fail_the_same_way_are_not_retried() {
+        let not_retried = [
+            transport_error(TransportErrorKind::http_error(400, String::new())),
+            transport_error(TransportErrorKind::http_error(413, String::new())),
+            transport_error(TransportError::ErrorResp(ErrorPayload {
+                code: -32602,
+                message: "invalid params".into(),
+                data: None,
+            })),
+            transport_error(TransportError::deser_err(
+                serde_json::from_str::<u64>("{}").unwrap_err(),
+                "{}",
+            )),
+        ];
+        for error in &not_retried {
+            assert!(!should_retry(error), "{:?} should not be retried", error);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_seconds_or_an_http_date() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = parse_retry_after(&in_a_minute).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limited_requests_wait_as_long_as_asked() {
+        let config = RetryConfig::new(2, 100, 500, 2.0, true);
+        let attempts = RefCell::new(Vec::new());
+        let result = retry_with_backoff(
+            || {
+                attempts.borrow_mut().push(Instant::now());
+                let first = attempts.borrow().len() == 1;
+                async move {
+                    if first {
+                        Err(transport_error(TransportErrorKind::custom(RateLimited {
+                            retry_after: Duration::from_secs(3),
+                            body: String::new(),
+                        })))
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            &config,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(sleeps(&attempts.into_inner()), [Duration::from_secs(3)]);
+    }
+
+    #[test]
+    fn jitter_is_on_by_default() {
+        assert!(RetryConfig::default().jitter);
+    }
+
+    #[test]
+    fn blocks_are_split_to_stay_under_the_request_size() {
+        let blocks: Vec<u64> = (100..110).collect();
+        let block_size = block_request_size(100);
+
+        assert_eq!(split_by_request_size(&blocks, usize::MAX), [&blocks[..]]);
+        assert_eq!(
+            split_by_request_size(&blocks, 2 + 3 * block_size),
+            [&blocks[..3], &blocks[3..6], &blocks[6..9], &blocks[9..]]
+        );
+        // a block over the cap by itself still goes out, on its own
+        assert_eq!(split_by_request_size(&blocks[..2], 1), [&blocks[..1], &blocks[1..2]]);
+        assert!(split_by_request_size(&[], 1).is_empty());
+    }
 }
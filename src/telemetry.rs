@@ -0,0 +1,134 @@
+//! OpenTelemetry traces of block processing, exported over OTLP.
+//!
+//! Each processed block gets a `block` span, with the block's number as the `block.number`
+//! attribute, and `fetch`, `decode` and `store` child spans for the stages of its processing.
+//! Blocks from mode fetches blocks a batch at a time, so there the batch's `fetch` span comes
+//! before its blocks' spans instead of under them.
+//!
+//! The spans have the [`PIPELINE_TARGET`] target. Only the layer returned by [`otlp_layer`] is
+//! interested in it, so without an OTLP endpoint the spans are disabled at their callsite and cost
+//! nothing, and they never show up in the log output.
+
+use eyre::{
+    Result,
+    WrapErr,
+};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{
+    SpanExporter,
+    WithExportConfig,
+};
+use opentelemetry_sdk::{
+    trace::SdkTracerProvider,
+    Resource,
+};
+use tracing::{
+    Level,
+    Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    registry::LookupSpan,
+    Layer,
+};
+
+/// Target of the block processing spans
+pub const PIPELINE_TARGET: &str = "pipeline";
+
+/// Name traces are exported under
+const SERVICE_NAME: &str = "token_tracking";
+
+/// Flushes the spans that haven't been exported yet when dropped, keep it until the end of `main`
+pub struct OtlpGuard {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush the OpenTelemetry traces: {}", e);
+        }
+    }
+}
+
+/// Layer exporting the block processing spans to the OTLP/HTTP collector at `endpoint`, e.g.
+/// `http://localhost:4318/v1/traces`
+pub fn otlp_layer<S>(endpoint: &str) -> Result<(impl Layer<S>, OtlpGuard)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .wrap_err("failed to build the OTLP exporter")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    Ok((pipeline_layer(&provider), OtlpGuard { provider }))
+}
+
+/// Layer sending the block processing spans to `provider`, and nothing else
+fn pipeline_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(SERVICE_NAME))
+        .with_filter(Targets::new().with_target(PIPELINE_TARGET, Level::INFO))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use opentelemetry_sdk::trace::{
+        InMemorySpanExporter,
+        SpanData,
+    };
+    use tracing::subscriber::DefaultGuard;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Records the block processing spans of the current thread until dropped
+    pub(crate) struct RecordedSpans {
+        exporter: InMemorySpanExporter,
+        provider: SdkTracerProvider,
+        _guard: DefaultGuard,
+    }
+
+    impl RecordedSpans {
+        pub(crate) fn start() -> Self {
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            let subscriber = tracing_subscriber::registry().with(pipeline_layer(&provider));
+            Self {
+                exporter,
+                provider,
+                _guard: tracing::subscriber::set_default(subscriber),
+            }
+        }
+
+        /// Spans closed so far, in the order they closed
+        pub(crate) fn finished(&self) -> Vec<SpanData> {
+            self.provider.force_flush().unwrap();
+            self.exporter.get_finished_spans().unwrap()
+        }
+    }
+
+    #[test]
+    fn only_pipeline_spans_are_exported() {
+        let recorded = RecordedSpans::start();
+        tracing::info_span!(target: PIPELINE_TARGET, "block", block.number = 7_i64).in_scope(|| {
+            tracing::info_span!(target: PIPELINE_TARGET, "fetch").in_scope(|| {});
+            tracing::info_span!("unrelated").in_scope(|| {});
+        });
+
+        let spans = recorded.finished();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+        assert_eq!(names, ["fetch", "block"]);
+        assert_eq!(spans[0].parent_span_id, spans[1].span_context.span_id());
+    }
+}
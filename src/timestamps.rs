@@ -0,0 +1,249 @@
+//! Timestamps of blocks whose header isn't at hand, looked up in memory, then in the `blocks`
+//! table, then over RPC.
+
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+
+use alloy::{
+    consensus::BlockHeader,
+    network::{
+        BlockResponse,
+        Network,
+    },
+};
+use diesel::PgConnection;
+use eyre::{
+    Result,
+    WrapErr,
+};
+use tracing::debug;
+
+use crate::{
+    pool_sql::types::BlockRaw,
+    rpc::{
+        fetch_blocks_batched,
+        HttpClient,
+        RetryConfig,
+    },
+};
+
+/// Number of timestamps a cache keeps when none is configured
+pub const DEFAULT_TIMESTAMP_CACHE_SIZE: usize = 10_000;
+
+/// Timestamps of the last `capacity` blocks looked up, the least recently used is evicted first
+#[derive(Debug)]
+pub struct BlockTimestampCache {
+    capacity: usize,
+    timestamps: HashMap<u64, u64>,
+    /// Block numbers from the least to the most recently used
+    recent: VecDeque<u64>,
+}
+
+impl BlockTimestampCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            timestamps: HashMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, block_number: u64) -> Option<u64> {
+        let timestamp = *self.timestamps.get(&block_number)?;
+        self.touch(block_number);
+        Some(timestamp)
+    }
+
+    pub fn insert(&mut self, block_number: u64, timestamp: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.timestamps.insert(block_number, timestamp).is_some() {
+            self.touch(block_number);
+            return;
+        }
+        if self.recent.len() == self.capacity {
+            if let Some(evicted) = self.recent.pop_front() {
+                self.timestamps.remove(&evicted);
+            }
+        }
+        self.recent.push_back(block_number);
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    fn touch(&mut self, block_number: u64) {
+        if let Some(position) = self
+            .recent
+            .iter()
+            .position(|&number| number == block_number)
+        {
+            self.recent.remove(position);
+        }
+        self.recent.push_back(block_number);
+    }
+}
+
+impl Default for BlockTimestampCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMESTAMP_CACHE_SIZE)
+    }
+}
+
+/// Timestamp of `block_number`, from `cache`, then from its stored block, and only fetched over
+/// RPC if neither has it. Blocks stored by `--skip-timestamp-fetch` runs have no timestamp yet
+/// and are fetched.
+pub async fn timestamp_for_block<N: Network>(
+    block_number: u64,
+    conn: &mut PgConnection,
+    client: &HttpClient,
+    retry_config: &RetryConfig,
+    cache: &mut BlockTimestampCache,
+) -> Result<u64> {
+    if let Some(timestamp) = cache.get(block_number) {
+        return Ok(timestamp);
+    }
+
+    let timestamp = match BlockRaw::find_timestamp(block_number as i64, conn)? {
+        Some(timestamp) => timestamp,
+        None => {
+            debug!("Fetching the timestamp of block {}", block_number);
+            let blocks =
+                fetch_blocks_batched::<N>(client, &[block_number], usize::MAX, retry_config)
+                    .await
+                    .wrap_err_with(|| {
+                        format!("failed to fetch the header of block {}", block_number)
+                    })?;
+            blocks[0].header().timestamp()
+        }
+    };
+    cache.insert(block_number, timestamp);
+    Ok(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::network::Ethereum;
+    use serde_json::{
+        json,
+        Value,
+    };
+
+    use super::*;
+    use crate::{
+        pool_sql::database_interactions::tests::{
+            block,
+            insert_swaps,
+            test_connection,
+        },
+        rpc::{
+            http_connection,
+            tests::{
+                closed_endpoint,
+                serve_json_rpc,
+            },
+        },
+    };
+
+    #[test]
+    fn least_recently_used_timestamp_is_evicted() {
+        let mut cache = BlockTimestampCache::new(2);
+        cache.insert(1, 100);
+        cache.insert(2, 200);
+        assert_eq!(cache.get(1), Some(100));
+
+        cache.insert(3, 300);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some(100));
+        assert_eq!(cache.get(3), Some(300));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn stored_timestamp_is_not_fetched() {
+        let mut conn = test_connection();
+        insert_swaps(
+            block(5_920),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            &mut conn,
+            None,
+        )
+        .unwrap();
+        // any request fails, so the lookup only succeeds without one
+        let client = http_connection(closed_endpoint(), None).await.unwrap();
+        let mut cache = BlockTimestampCache::default();
+
+        let timestamp = timestamp_for_block::<Ethereum>(
+            5_920,
+            &mut conn,
+            &client,
+            &RetryConfig::new(1, 1, 1, 1.0, false),
+            &mut cache,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(timestamp, 1_700_000_000 + 5_920 * 12);
+        assert_eq!(cache.get(5_920), Some(timestamp));
+    }
+
+    /// Serves every block with a timestamp of 1000 times its number
+    fn timestamped_blocks(request: &Value) -> Value {
+        let number = u64::from_str_radix(
+            request["params"][0]
+                .as_str()
+                .unwrap()
+                .trim_start_matches("0x"),
+            16,
+        )
+        .unwrap();
+        let block: alloy::rpc::types::Block = alloy::rpc::types::Block {
+            header: alloy::rpc::types::Header {
+                inner: alloy::consensus::Header {
+                    number,
+                    timestamp: number * 1_000,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        json!({ "result": block })
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn unknown_timestamp_is_fetched_once() {
+        let mut conn = test_connection();
+        let client = http_connection(serve_json_rpc(timestamped_blocks).await, None)
+            .await
+            .unwrap();
+        let retry_config = RetryConfig::new(1, 1, 1, 1.0, false);
+        let mut cache = BlockTimestampCache::default();
+
+        let timestamp =
+            timestamp_for_block::<Ethereum>(5_921, &mut conn, &client, &retry_config, &mut cache)
+                .await
+                .unwrap();
+        assert_eq!(timestamp, 5_921_000);
+
+        // the cached timestamp is used even with the provider gone
+        let client = http_connection(closed_endpoint(), None).await.unwrap();
+        let timestamp =
+            timestamp_for_block::<Ethereum>(5_921, &mut conn, &client, &retry_config, &mut cache)
+                .await
+                .unwrap();
+        assert_eq!(timestamp, 5_921_000);
+    }
+}
@@ -0,0 +1,192 @@
+//! ERC-20 metadata of the tokens of created pools, read with contract calls.
+
+use std::{
+    collections::HashMap,
+    future::IntoFuture,
+    sync::{
+        Mutex,
+        MutexGuard,
+        PoisonError,
+    },
+};
+
+use alloy::{
+    contract::Error as ContractError,
+    primitives::Address,
+    rpc::json_rpc::RpcError,
+};
+use diesel::PgConnection;
+use eyre::{
+    eyre,
+    Result,
+    WrapErr,
+};
+use tracing::debug;
+
+use crate::{
+    abi::IERC20,
+    pool_sql::types::{
+        Token,
+        TokenRaw,
+    },
+    rpc::{
+        count_rpc_calls,
+        HttpClient,
+        RetryConfig,
+    },
+    utils::fmt_addr,
+};
+
+/// Reads the metadata of tokens once per run, starting from the tokens already stored
+pub struct TokenMetadataCache {
+    client: HttpClient,
+    retry_config: RetryConfig,
+    tokens: Mutex<HashMap<Address, Token>>,
+}
+
+impl TokenMetadataCache {
+    pub fn new(
+        client: HttpClient,
+        retry_config: RetryConfig,
+        conn: &mut PgConnection,
+    ) -> Result<Self> {
+        let mut tokens = HashMap::new();
+        for token_raw in TokenRaw::find_all(conn)? {
+            let token =
+                Token::try_from(token_raw).map_err(|e| eyre!("failed to convert token: {}", e))?;
+            tokens.insert(token.address, token);
+        }
+        debug!("Loaded the metadata of {} stored tokens", tokens.len());
+
+        Ok(Self {
+            client,
+            retry_config,
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    /// Metadata of each of `addresses`, read from the chain for tokens that aren't known yet
+    pub async fn tokens(
+        &self,
+        addresses: impl IntoIterator<Item = Address>,
+    ) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        for address in addresses {
+            if tokens.iter().any(|token: &Token| token.address == address) {
+                continue;
+            }
+            let known = self.lock().get(&address).cloned();
+            let token = match known {
+                Some(token) => token,
+                None => {
+                    let token = self.fetch_token(address).await?;
+                    debug!("Read token metadata {:?}", token);
+                    self.lock().insert(address, token.clone());
+                    token
+                }
+            };
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Address, Token>> {
+        self.tokens.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn fetch_token(&self, address: Address) -> Result<Token> {
+        self.client
+            .call_with_failover(
+                |provider| async move {
+                    let token = IERC20::new(address, provider);
+                    count_rpc_calls("eth_call", 3);
+                    Ok(Token {
+                        address,
+                        decimals: optional_call(token.decimals().call())
+                            .await?
+                            .map(|decimals| decimals._0),
+                        symbol: optional_call(token.symbol().call())
+                            .await?
+                            .map(|symbol| text(symbol._0)),
+                        name: optional_call(token.name().call())
+                            .await?
+                            .map(|name| text(name._0)),
+                    })
+                },
+                &self.retry_config,
+            )
+            .await
+            .wrap_err_with(|| format!("failed to read the metadata of token {}", fmt_addr(address)))
+    }
+}
+
+/// Result of a token's call, `None` if the call reverted or returned something that doesn't
+/// decode, like the `bytes32` symbol of some older tokens. Other errors are returned to be retried.
+async fn optional_call<T>(
+    call: impl IntoFuture<Output = Result<T, ContractError>>,
+) -> Result<Option<T>> {
+    match call.await {
+        Ok(value) => Ok(Some(value)),
+        Err(ContractError::TransportError(RpcError::ErrorResp(payload)))
+            if payload.message.contains("revert") =>
+        {
+            Ok(None)
+        }
+        Err(ContractError::TransportError(error)) => Err(error.into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Postgres text can't hold NUL characters, which some tokens pad their symbol and name with
+fn text(value: String) -> String {
+    value.replace('\0', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{
+        json,
+        Value,
+    };
+
+    use super::*;
+    use crate::rpc::{
+        http_connection,
+        tests::{
+            closed_endpoint,
+            serve_json_rpc,
+        },
+    };
+
+    /// A token with 6 decimals whose `symbol()` and `name()` revert
+    fn token_without_symbol(request: &Value) -> Value {
+        let call = &request["params"][0];
+        let input = call["input"].as_str().or(call["data"].as_str()).unwrap();
+        if input.starts_with("0x313ce567") {
+            json!({ "result": format!("0x{:064x}", 6) })
+        } else {
+            json!({ "error": { "code": 3, "message": "execution reverted" } })
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata_is_read_through_the_next_endpoint_when_one_fails() {
+        let endpoint = serve_json_rpc(token_without_symbol).await;
+        let client = http_connection(format!("{},{}", closed_endpoint(), endpoint), None)
+            .await
+            .unwrap();
+        let cache = TokenMetadataCache {
+            client,
+            retry_config: RetryConfig::new(1, 1, 1, 1.0, false),
+            tokens: Mutex::default(),
+        };
+        let address = Address::repeat_byte(0x21);
+
+        let tokens = cache.tokens([address, address]).await.unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].address, address);
+        assert_eq!(tokens[0].decimals, Some(6));
+        assert_eq!(tokens[0].symbol, None);
+        assert_eq!(tokens[0].name, None);
+    }
+}